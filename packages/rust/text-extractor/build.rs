@@ -0,0 +1,81 @@
+//! Records the exact versions of the extraction backends from Cargo.lock, so provenance
+//! records can name them, and generates the gRPC service with the `grpc` feature.
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+const BACKENDS: [(&str, &str); 8] = [
+    ("pdf-extract", "PDF_EXTRACT_VERSION"),
+    ("lopdf", "LOPDF_VERSION"),
+    ("epub", "EPUB_VERSION"),
+    ("zip", "ZIP_VERSION"),
+    ("roxmltree", "ROXMLTREE_VERSION"),
+    ("encoding_rs", "ENCODING_RS_VERSION"),
+    ("whisper-rs", "WHISPER_RS_VERSION"),
+    ("symphonia", "SYMPHONIA_VERSION"),
+];
+
+fn main() {
+    let lock = match find_lock() {
+        Some(path) => {
+            println!("cargo:rerun-if-changed={}", path.display());
+            fs::read_to_string(path).unwrap_or_default()
+        }
+        None => {
+            println!("cargo:warning=Cargo.lock not found; provenance records backend versions as unknown");
+            String::new()
+        }
+    };
+
+    for (package, variable) in BACKENDS {
+        let version = locked_version(&lock, package).unwrap_or_else(|| {
+            if !lock.is_empty() {
+                println!("cargo:warning={package} is not in Cargo.lock; its version is recorded as unknown");
+            }
+            "unknown"
+        });
+        println!("cargo:rustc-env={variable}={version}");
+    }
+
+    #[cfg(feature = "grpc")]
+    compile_protos();
+}
+
+/// Generates the service in `proto/`, with the protoc that comes with protoc-bin-vendored so
+/// none has to be installed.
+#[cfg(feature = "grpc")]
+fn compile_protos() {
+    println!("cargo:rerun-if-changed=proto/to_texts.proto");
+    let protoc =
+        protoc_bin_vendored::protoc_bin_path().expect("no vendored protoc for this platform");
+    std::env::set_var("PROTOC", protoc);
+    tonic_prost_build::compile_protos("proto/to_texts.proto")
+        .expect("failed to compile proto/to_texts.proto");
+}
+
+/// The Cargo.lock of the workspace being built: above this crate when it is a member, else
+/// above the target directory, where a crate built as a dependency has no lock file of its
+/// own.
+fn find_lock() -> Option<PathBuf> {
+    ["CARGO_MANIFEST_DIR", "OUT_DIR"]
+        .into_iter()
+        .filter_map(|variable| env::var_os(variable).map(PathBuf::from))
+        .find_map(|dir| {
+            dir.ancestors()
+                .map(|ancestor| ancestor.join("Cargo.lock"))
+                .find(|lock| lock.is_file())
+        })
+}
+
+/// The version of the first `[[package]]` entry named `package`.
+fn locked_version<'a>(lock: &'a str, package: &str) -> Option<&'a str> {
+    let name_line = format!("name = \"{package}\"");
+    let mut lines = lock.lines();
+    lines.by_ref().find(|line| line.trim() == name_line)?;
+    lines
+        .next()?
+        .trim()
+        .strip_prefix("version = \"")?
+        .strip_suffix('"')
+}
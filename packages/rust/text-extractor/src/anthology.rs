@@ -0,0 +1,198 @@
+//! Finding the complete works bound into an omnibus or anthology.
+//!
+//! The outline is used when its top level groups chapters under work titles ("The Time
+//! Machine" > "Chapter 1", ...). Otherwise a new work starts wherever the chapter numbering
+//! starts over, together with the title page in front of it.
+
+use crate::document::{Document, Section};
+use crate::matter::{self, first_line};
+use crate::metadata::Metadata;
+use crate::split::{self, Part, FRONT_MATTER};
+
+/// Fewest chapters bookmarked under a top-level entry for it to count as a work.
+const MIN_CHAPTERS: usize = 2;
+
+/// Longest section, in non-blank lines, taken for a work's title page.
+const TITLE_PAGE_LINES: usize = 8;
+
+/// Top-level bookmarks that divide one book rather than open another.
+const DIVISIONS: &[&str] = &["part ", "section ", "chapter ", "act "];
+
+/// Headings that may come between a work's title page and its first chapter.
+const LEAD_INS: &[&str] = &["prologue", "preface", "foreword", "introduction"];
+
+/// Where one contained work starts, and what it is called.
+struct Work {
+    start: usize,
+    title: Option<String>,
+    author: Option<String>,
+}
+
+/// Splits an omnibus into one part per contained work, each carrying its own title and
+/// author with the collection's title kept alongside. Sections before the first work become
+/// a front matter part. Returns nothing when fewer than two works are found.
+pub fn works(doc: &Document) -> Vec<Part> {
+    let mut works = from_outline(doc);
+    if works.len() < 2 {
+        works = from_headings(doc);
+    }
+    if works.len() < 2 {
+        return Vec::new();
+    }
+
+    let mut parts = Vec::new();
+    if works[0].start > 0 {
+        parts.push(Part {
+            title: FRONT_MATTER.to_string(),
+            doc: split::slice(doc, 0..works[0].start),
+        });
+    }
+
+    for (i, work) in works.iter().enumerate() {
+        let end = works
+            .get(i + 1)
+            .map_or(doc.sections.len(), |next| next.start);
+        let mut part = split::slice(doc, work.start..end);
+        part.metadata = Metadata {
+            title: work.title.clone(),
+            author: work.author.clone().or_else(|| doc.metadata.author.clone()),
+            language: doc.metadata.language.clone(),
+            collection: doc.metadata.title.clone(),
+        };
+        parts.push(Part {
+            title: work
+                .title
+                .clone()
+                .unwrap_or_else(|| format!("Work {}", i + 1)),
+            doc: part,
+        });
+    }
+    parts
+}
+
+/// Top-level bookmarks with chapters nested under them, other than front or back matter and
+/// divisions such as "Part One".
+fn from_outline(doc: &Document) -> Vec<Work> {
+    let mut works: Vec<Work> = Vec::new();
+
+    for (i, entry) in doc.outline.iter().enumerate() {
+        if entry.level != 1
+            || entry.section >= doc.sections.len()
+            || matter::is_matter_title(&entry.title)
+            || is_division(&entry.title)
+        {
+            continue;
+        }
+        let chapters = doc.outline[i + 1..]
+            .iter()
+            .take_while(|child| child.level > 1)
+            .count();
+        // Works must follow each other through the book
+        if chapters < MIN_CHAPTERS || works.last().is_some_and(|w| w.start >= entry.section) {
+            continue;
+        }
+
+        let (title, author) = split_byline(&entry.title);
+        let author = author.or_else(|| {
+            let first = &doc.sections[entry.section];
+            is_title_page(first).then(|| byline(&first.text)).flatten()
+        });
+        works.push(Work {
+            start: entry.section,
+            title: Some(title),
+            author,
+        });
+    }
+    works
+}
+
+/// Works found from chapter headings: each "Chapter 1" (or "One", or "I") after the first
+/// opens a new work, starting at the title page and any prologue just before it.
+fn from_headings(doc: &Document) -> Vec<Work> {
+    let mut works = Vec::new();
+    // The earliest section a work's lead-in may reach back to
+    let mut floor = 0;
+
+    for (i, section) in doc.sections.iter().enumerate() {
+        if !opens_first_chapter(first_line(&section.text)) {
+            continue;
+        }
+
+        let mut start = i;
+        while start > floor && is_lead_in(&doc.sections[start - 1]) {
+            start -= 1;
+        }
+        let title_page = doc.sections[start..i]
+            .iter()
+            .find(|section| is_title_page(section));
+
+        works.push(Work {
+            start,
+            title: title_page.map(|page| first_line(&page.text).to_string()),
+            author: title_page.and_then(|page| byline(&page.text)),
+        });
+        floor = i + 1;
+    }
+    works
+}
+
+fn is_division(title: &str) -> bool {
+    let title = title.trim().to_lowercase();
+    DIVISIONS.iter().any(|division| title.starts_with(division))
+}
+
+/// Whether `heading` opens the first chapter of a work.
+fn opens_first_chapter(heading: &str) -> bool {
+    let heading = heading.to_lowercase();
+    let Some(rest) = heading.strip_prefix("chapter ") else {
+        return false;
+    };
+    let number = rest
+        .split(|c: char| c.is_whitespace() || c.is_ascii_punctuation())
+        .next()
+        .unwrap_or_default();
+    matches!(number, "1" | "one" | "i")
+}
+
+/// A short title page, or a prologue-like section, that belongs to the work after it. The
+/// chapters of the work before, however short, do not.
+fn is_lead_in(section: &Section) -> bool {
+    let heading = first_line(&section.text).to_lowercase();
+    if heading.starts_with("chapter ") {
+        return false;
+    }
+    is_title_page(section) || LEAD_INS.iter().any(|lead_in| heading.starts_with(lead_in))
+}
+
+fn is_title_page(section: &Section) -> bool {
+    let lines = section
+        .text
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .count();
+    (1..=TITLE_PAGE_LINES).contains(&lines)
+}
+
+/// The author named on a "by ..." line.
+fn byline(text: &str) -> Option<String> {
+    text.lines().map(str::trim).find_map(|line| {
+        let author = line
+            .strip_prefix("by ")
+            .or_else(|| line.strip_prefix("By "))
+            .or_else(|| line.strip_prefix("BY "))?
+            .trim();
+        (!author.is_empty()).then(|| author.to_string())
+    })
+}
+
+/// Splits a bookmark such as "The Time Machine by H. G. Wells" into title and author.
+fn split_byline(title: &str) -> (String, Option<String>) {
+    let title = title.trim();
+    if let Some(at) = title.rfind(" by ") {
+        let (work, author) = (title[..at].trim(), title[at + 4..].trim());
+        if !work.is_empty() && author.starts_with(char::is_uppercase) {
+            return (work.to_string(), Some(author.to_string()));
+        }
+    }
+    (title.to_string(), None)
+}
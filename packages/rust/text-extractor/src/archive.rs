@@ -0,0 +1,361 @@
+//! Books inside ZIP, TAR and 7z archives, as libraries are often handed out (`books.zip`).
+//!
+//! The supported files of an archive are unpacked into a folder under names of the form
+//! `books.zip!inner.pdf`, so their outputs (`books.zip!inner.txt`) say where they came from.
+//! Archives inside the archive are opened in turn, down to a depth limit, and add their names
+//! (`books.zip!part1.tar!inner.pdf`). ZIP and TAR archives, gzipped or not, are read here; 7z
+//! archives are unpacked with the `7z` command (p7zip or 7-Zip), which must then be on the
+//! `PATH`.
+
+use anyhow::{Context, Result};
+use flate2::read::GzDecoder;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use walkdir::WalkDir;
+use zip::ZipArchive;
+
+use crate::ocr;
+use crate::provenance;
+use crate::source::{self, SourceFile, SUPPORTED_EXTENSIONS};
+
+/// Archive kinds, by the (lowercase) endings of their file names.
+const ARCHIVE_SUFFIXES: &[(&str, Kind)] = &[
+    (".zip", Kind::Zip),
+    (".tar", Kind::Tar),
+    (".tar.gz", Kind::TarGz),
+    (".tgz", Kind::TarGz),
+    (".7z", Kind::SevenZip),
+];
+
+/// Size of a TAR header and the unit its file data is padded to.
+const TAR_BLOCK: usize = 512;
+
+#[derive(Debug, Clone, Copy)]
+enum Kind {
+    Zip,
+    Tar,
+    TarGz,
+    SevenZip,
+}
+
+/// How far [`unpack`] goes into an archive.
+#[derive(Debug, Clone, Copy)]
+pub struct UnpackOptions {
+    /// Levels of archives opened: 1 reads only the archive given, 2 the archives in it too
+    pub depth: usize,
+    /// Bytes an archive may expand to, those of the archives in it included; an archive that
+    /// expands to more (such as a zip bomb) fails as a whole
+    pub max_bytes: u64,
+}
+
+impl Default for UnpackOptions {
+    fn default() -> Self {
+        UnpackOptions {
+            depth: 3,
+            max_bytes: 4 << 30,
+        }
+    }
+}
+
+/// Whether `path` is an archive that [`unpack`] can read. Formats that are zip files
+/// underneath, such as EPUB or `.fb2.zip`, are books rather than archives.
+pub fn is_archive(path: &Path) -> bool {
+    kind(path).is_some()
+}
+
+fn kind(path: &Path) -> Option<Kind> {
+    if source::extension(path).is_some_and(|e| SUPPORTED_EXTENSIONS.contains(&e.as_str())) {
+        return None;
+    }
+    let name = path.file_name()?.to_str()?.to_lowercase();
+    ARCHIVE_SUFFIXES
+        .iter()
+        .find(|(suffix, _)| name.ends_with(suffix))
+        .map(|(_, kind)| *kind)
+}
+
+/// Unpacks the files of `archive`, and of the archives in it, that have an extractor into
+/// `into`, returning them in archive order.
+pub fn unpack(archive: &Path, into: &Path, options: &UnpackOptions) -> Result<Vec<SourceFile>> {
+    let kind = kind(archive).context(format!("Not an archive: {}", archive.display()))?;
+    let archive_name = archive
+        .file_name()
+        .context("Archive has no file name")?
+        .to_string_lossy()
+        .into_owned();
+    fs::create_dir_all(into).context(format!("Failed to create folder: {}", into.display()))?;
+    let mut unpacker = Unpacker {
+        into,
+        options,
+        remaining: options.max_bytes,
+        ancestors: Vec::new(),
+        files: Vec::new(),
+    };
+    unpacker
+        .archive(archive, kind, &archive_name, 1)
+        .context(format!("Failed to unpack: {}", archive.display()))?;
+    Ok(unpacker.files)
+}
+
+/// Writes the members of one archive, and of those nested in it, that have an extractor.
+struct Unpacker<'a> {
+    into: &'a Path,
+    options: &'a UnpackOptions,
+    /// Bytes the archive may still expand to
+    remaining: u64,
+    /// Digests of the archives being read, outermost first
+    ancestors: Vec<String>,
+    files: Vec<SourceFile>,
+}
+
+impl Unpacker<'_> {
+    /// Reads the archive at `path`, `depth` levels down from the one given to [`unpack`].
+    /// `name` is its name in outputs, with those of the archives it is in.
+    fn archive(&mut self, path: &Path, kind: Kind, name: &str, depth: usize) -> Result<()> {
+        // An archive that contains itself (a zip quine) would otherwise be read until the
+        // depth limit
+        let digest = provenance::sha256_file(path)?;
+        if self.ancestors.contains(&digest) {
+            return Ok(());
+        }
+        self.ancestors.push(digest);
+
+        let open = || File::open(path).context(format!("Failed to open: {}", path.display()));
+        let result = match kind {
+            Kind::Zip => open().and_then(|file| self.zip(file, name, depth)),
+            Kind::Tar => open().and_then(|file| self.tar(file, name, depth)),
+            Kind::TarGz => open().and_then(|file| self.tar(GzDecoder::new(file), name, depth)),
+            Kind::SevenZip => self.seven_zip(path, name, depth),
+        };
+        self.ancestors.pop();
+        result
+    }
+
+    fn zip(&mut self, file: File, archive: &str, depth: usize) -> Result<()> {
+        let mut zip = ZipArchive::new(file).context("Not a zip archive")?;
+        for index in 0..zip.len() {
+            let mut member = zip.by_index(index)?;
+            if member.is_file() {
+                let name = member.name().to_string();
+                self.member(archive, depth, &name, &mut member)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads a TAR stream: 512-byte headers, each followed by its file's data padded to a
+    /// whole block. GNU long names and pax `path` records name the entry after them.
+    fn tar(&mut self, mut reader: impl Read, archive: &str, depth: usize) -> Result<()> {
+        let mut header = [0u8; TAR_BLOCK];
+        let mut long_name: Option<String> = None;
+        loop {
+            if !read_block(&mut reader, &mut header)? || header.iter().all(|byte| *byte == 0) {
+                return Ok(());
+            }
+            let size = octal(&header[124..136]).context("Corrupt TAR header")?;
+            let padded = size.div_ceil(TAR_BLOCK as u64) * TAR_BLOCK as u64;
+            let mut data = (&mut reader).take(padded);
+
+            match header[156] {
+                // GNU long name, or pax extended header, for the next entry
+                b'L' | b'x' => {
+                    let mut record = Vec::new();
+                    data.read_to_end(&mut record)?;
+                    record.truncate(size as usize);
+                    long_name = if header[156] == b'L' {
+                        Some(c_string(&record))
+                    } else {
+                        pax_path(&record)
+                    };
+                    continue;
+                }
+                b'0' | 0 => {
+                    let name = long_name.take().unwrap_or_else(|| tar_name(&header));
+                    self.member(archive, depth, &name, &mut (&mut data).take(size))?;
+                }
+                _ => long_name = None,
+            }
+            // Skip what the member left unread and the padding
+            io::copy(&mut data, &mut io::sink())?;
+        }
+    }
+
+    /// Unpacks the whole archive with `7z` into a scratch folder, once its listing shows it
+    /// fits in what is left of the limit, then keeps the files that have an extractor.
+    fn seven_zip(&mut self, path: &Path, archive: &str, depth: usize) -> Result<()> {
+        let listing = ocr::run(
+            Command::new("7z").args(["l", "-slt"]).arg(path),
+            "7z (from p7zip or 7-Zip)",
+            "Reading 7z archives",
+        )?;
+        let size: u64 = String::from_utf8_lossy(&listing.stdout)
+            .lines()
+            .filter_map(|line| line.strip_prefix("Size = ")?.trim().parse::<u64>().ok())
+            .sum();
+        if size > self.remaining {
+            return Err(self.too_large(archive));
+        }
+
+        let folder = tempfile::tempdir().context("Failed to create a folder to unpack into")?;
+        let mut destination = OsString::from("-o");
+        destination.push(folder.path());
+        ocr::run(
+            Command::new("7z")
+                .args(["x", "-y"])
+                .arg(destination)
+                .arg(path),
+            "7z (from p7zip or 7-Zip)",
+            "Reading 7z archives",
+        )?;
+
+        for entry in WalkDir::new(folder.path())
+            .sort_by_file_name()
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_file())
+        {
+            let name = entry.path().strip_prefix(folder.path())?.to_string_lossy();
+            self.member(archive, depth, &name, &mut File::open(entry.path())?)?;
+        }
+        Ok(())
+    }
+
+    /// Writes one member of `archive` if its extension has an extractor, or reads it as an
+    /// archive of its own if it is one and the depth limit allows.
+    fn member(
+        &mut self,
+        archive: &str,
+        depth: usize,
+        name: &str,
+        content: &mut impl Read,
+    ) -> Result<()> {
+        let Some(file_name) = name.rsplit(['/', '\\']).next().filter(|n| !n.is_empty()) else {
+            return Ok(());
+        };
+
+        if let Some(kind) = kind(Path::new(file_name)) {
+            if depth >= self.options.depth {
+                return Ok(());
+            }
+            let nested = tempfile::NamedTempFile::new_in(self.into)
+                .context("Failed to create a file to unpack into")?;
+            self.copy(content, nested.as_file(), archive, name)?;
+            return self.archive(
+                nested.path(),
+                kind,
+                &format!("{archive}!{file_name}"),
+                depth + 1,
+            );
+        }
+
+        let supported = source::extension(Path::new(file_name))
+            .is_some_and(|extension| SUPPORTED_EXTENSIONS.contains(&extension.as_str()));
+        if !supported {
+            return Ok(());
+        }
+
+        let path = self.free_path(archive, file_name);
+        let output =
+            File::create(&path).context(format!("Failed to create: {}", path.display()))?;
+        self.copy(content, &output, archive, name)?;
+        if let Some(file) = SourceFile::from_path(&path) {
+            self.files.push(file);
+        }
+        Ok(())
+    }
+
+    /// Copies a member out, failing once the archive has expanded past the limit.
+    fn copy(
+        &mut self,
+        content: &mut impl Read,
+        mut output: &File,
+        archive: &str,
+        name: &str,
+    ) -> Result<()> {
+        let copied = io::copy(&mut content.take(self.remaining + 1), &mut output)
+            .context(format!("Failed to unpack {name}"))?;
+        if copied > self.remaining {
+            return Err(self.too_large(archive));
+        }
+        self.remaining -= copied;
+        Ok(())
+    }
+
+    fn too_large(&self, archive: &str) -> anyhow::Error {
+        anyhow::anyhow!(
+            "{archive} expands to more than the {} bytes allowed",
+            self.options.max_bytes
+        )
+    }
+
+    /// `archive.zip!file.pdf` in the unpack folder, numbered when another member of the same
+    /// name (in another folder of the archive) got there first.
+    fn free_path(&self, archive: &str, file_name: &str) -> PathBuf {
+        let path = self.into.join(format!("{archive}!{file_name}"));
+        if !path.exists() {
+            return path;
+        }
+        let stem = source::file_stem(Path::new(file_name))
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let extension = source::extension(Path::new(file_name)).unwrap_or_default();
+        (2..)
+            .map(|n| {
+                self.into
+                    .join(format!("{archive}!{stem} ({n}).{extension}"))
+            })
+            .find(|path| !path.exists())
+            .unwrap_or(path)
+    }
+}
+
+/// Fills `block`, returning false at a clean end of the stream.
+fn read_block(reader: &mut impl Read, block: &mut [u8]) -> Result<bool> {
+    let mut filled = 0;
+    while filled < block.len() {
+        match reader.read(&mut block[filled..])? {
+            0 if filled == 0 => return Ok(false),
+            0 => anyhow::bail!("TAR archive ends in the middle of a header"),
+            n => filled += n,
+        }
+    }
+    Ok(true)
+}
+
+/// A NUL- or space-padded octal number of a TAR header.
+fn octal(field: &[u8]) -> Option<u64> {
+    let text = std::str::from_utf8(field).ok()?;
+    let text = text.trim_matches(|c: char| c == '\0' || c == ' ');
+    if text.is_empty() {
+        return Some(0);
+    }
+    u64::from_str_radix(text, 8).ok()
+}
+
+/// The entry name of a header, with the ustar prefix when there is one.
+fn tar_name(header: &[u8; TAR_BLOCK]) -> String {
+    let name = c_string(&header[..100]);
+    if &header[257..262] == b"ustar" {
+        let prefix = c_string(&header[345..500]);
+        if !prefix.is_empty() {
+            return format!("{prefix}/{name}");
+        }
+    }
+    name
+}
+
+fn c_string(bytes: &[u8]) -> String {
+    let bytes = bytes.split(|byte| *byte == 0).next().unwrap_or_default();
+    String::from_utf8_lossy(bytes).into_owned()
+}
+
+/// The `path` of a pax extended header, whose records read `<length> path=<value>\n`.
+fn pax_path(records: &[u8]) -> Option<String> {
+    String::from_utf8_lossy(records).lines().find_map(|record| {
+        let (_, pair) = record.split_once(' ')?;
+        pair.strip_prefix("path=").map(str::to_string)
+    })
+}
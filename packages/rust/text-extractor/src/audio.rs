@@ -0,0 +1,479 @@
+//! Audiobook transcription with a local Whisper model, built with the `audio` feature.
+//!
+//! `.mp3` and `.m4b` files are decoded to 16 kHz mono and transcribed chapter by chapter,
+//! taking the chapters from ID3 `CHAP` frames or the MP4 `chpl` box. Audio is fed to the
+//! model in windows that never cross a chapter mark, so a whole book is never held in memory.
+
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::DecoderOptions;
+use symphonia::core::errors::Error as SymphoniaError;
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::{MetadataOptions, MetadataRevision, StandardTagKey};
+use symphonia::core::probe::Hint;
+use whisper_rs::{
+    FullParams, SamplingStrategy, WhisperContext, WhisperContextParameters, WhisperState,
+};
+
+use crate::document::{Document, Landmarks, OutlineEntry, Section, SectionKind};
+use crate::metadata::Metadata;
+
+/// Sample rate Whisper models expect.
+const WHISPER_RATE: u64 = 16_000;
+/// Longest stretch of audio transcribed at once, in samples (ten minutes).
+const WINDOW: u64 = 600 * WHISPER_RATE;
+/// Shortest window worth transcribing; whisper.cpp rejects shorter input.
+const MIN_WINDOW: usize = (WHISPER_RATE / 10) as usize;
+/// Pause between segments, in samples, that starts a new paragraph.
+const PARAGRAPH_PAUSE: u64 = 3 * WHISPER_RATE / 2;
+/// Units of the MP4 `chpl` chapter start times (100 ns) per sample.
+const CHPL_UNITS_PER_SAMPLE: u64 = 10_000_000 / WHISPER_RATE;
+
+#[derive(Debug, Clone, Default)]
+pub struct AudioOptions {
+    /// whisper.cpp model file (`ggml-*.bin`)
+    pub model: Option<PathBuf>,
+    /// Spoken language as an ISO 639-1 code; detected by the model when unset
+    pub language: Option<String>,
+}
+
+/// A chapter mark, in samples from the start of the book.
+struct Chapter {
+    start: u64,
+    title: Option<String>,
+}
+
+/// Transcript of one chapter, built up window by window.
+struct ChapterText {
+    title: Option<String>,
+    paragraphs: Vec<String>,
+    /// End of the last segment, to tell pauses that end a paragraph
+    last_end: u64,
+}
+
+/// A stretch of speech the model transcribed, in samples from the start of the book.
+struct Segment {
+    start: u64,
+    end: u64,
+    text: String,
+}
+
+/// Transcribes an audiobook into one section per chapter.
+pub fn transcribe(
+    path: &Path,
+    metadata: Option<&Metadata>,
+    options: &AudioOptions,
+) -> Result<Document> {
+    let model = options
+        .model
+        .as_deref()
+        .context("Transcribing audio needs a Whisper model (--whisper-model)")?;
+    let context = WhisperContext::new_with_params(model, WhisperContextParameters::default())
+        .context(format!("Failed to load Whisper model: {}", model.display()))?;
+    let mut state = context.create_state()?;
+
+    let file = File::open(path).context(format!("Failed to open audio: {}", path.display()))?;
+    let stream = MediaSourceStream::new(Box::new(file), Default::default());
+    let mut hint = Hint::new();
+    if let Some(extension) = path.extension().and_then(|e| e.to_str()) {
+        hint.with_extension(extension);
+    }
+    let mut probed = symphonia::default::get_probe()
+        .format(
+            &hint,
+            stream,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )
+        .context("Unrecognised audio format")?;
+
+    // ID3 tags come before the MP3 stream; MP4 keeps its tags in the container
+    let mut embedded = Metadata::default();
+    if let Some(revision) = probed.metadata.get().as_ref().and_then(|m| m.current()) {
+        embedded = tag_metadata(revision);
+    }
+    let mut format = probed.format;
+    if let Some(revision) = format.metadata().current() {
+        embedded = tag_metadata(revision).or(embedded);
+    }
+
+    let track = format.default_track().context("No audio track")?;
+    let track_id = track.id;
+    let rate = track
+        .codec_params
+        .sample_rate
+        .context("Unknown sample rate")?;
+    let mut decoder =
+        symphonia::default::get_codecs().make(&track.codec_params, &DecoderOptions::default())?;
+
+    let marks = chapters(path);
+    let mut texts: Vec<ChapterText> = marks
+        .iter()
+        .map(|mark| ChapterText {
+            title: mark.title.clone(),
+            paragraphs: Vec::new(),
+            last_end: 0,
+        })
+        .collect();
+
+    let mut resampler = Resampler::new(rate);
+    let mut window: Vec<f32> = Vec::new();
+    let mut window_start = 0;
+    let mut language = options.language.clone();
+
+    loop {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            Err(SymphoniaError::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                break
+            }
+            Err(e) => return Err(e.into()),
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            // A corrupt frame costs a few milliseconds of audio, not the book
+            Err(SymphoniaError::DecodeError(_)) => continue,
+            Err(e) => return Err(e.into()),
+        };
+
+        let spec = *decoded.spec();
+        let mut buffer = SampleBuffer::<f32>::new(decoded.capacity() as u64, spec);
+        buffer.copy_interleaved_ref(decoded);
+        resampler.push(
+            &downmix(buffer.samples(), spec.channels.count()),
+            &mut window,
+        );
+
+        // Windows end at the next chapter mark or after WINDOW samples, whichever is first
+        loop {
+            let chapter = chapter_at(&marks, window_start);
+            let end = marks
+                .get(chapter + 1)
+                .map_or(u64::MAX, |next| next.start)
+                .min(window_start + WINDOW);
+            let len = (end - window_start) as usize;
+            if window.len() < len {
+                break;
+            }
+            let rest = window.split_off(len);
+            let segments = transcribe_window(&mut state, &window, window_start, &mut language)?;
+            append(&mut texts[chapter], segments);
+            window = rest;
+            window_start = end;
+        }
+    }
+
+    let chapter = chapter_at(&marks, window_start);
+    let segments = transcribe_window(&mut state, &window, window_start, &mut language)?;
+    append(&mut texts[chapter], segments);
+
+    let outline = texts
+        .iter()
+        .enumerate()
+        .filter_map(|(section, text)| {
+            Some(OutlineEntry {
+                title: text.title.clone()?,
+                level: 1,
+                section,
+            })
+        })
+        .collect();
+    // Chapter names open their sections as headings do in EPUB chapters
+    let sections = texts
+        .into_iter()
+        .map(|text| Section {
+            kind: SectionKind::Chapter,
+            title: None,
+            text: text
+                .title
+                .into_iter()
+                .chain(text.paragraphs)
+                .collect::<Vec<_>>()
+                .join("\n"),
+            language: None,
+            page: None,
+        })
+        .collect();
+
+    Ok(Document {
+        metadata: metadata
+            .cloned()
+            .unwrap_or_default()
+            .or(embedded)
+            .or(Metadata {
+                language,
+                ..Metadata::default()
+            }),
+        sections,
+        outline,
+        landmarks: Landmarks::default(),
+    })
+}
+
+/// Title, author and language from the file's tags. Audiobooks name the book in the album
+/// and the chapter or part in the track title.
+fn tag_metadata(revision: &MetadataRevision) -> Metadata {
+    let tag = |key: StandardTagKey| {
+        revision
+            .tags()
+            .iter()
+            .find(|tag| tag.std_key == Some(key))
+            .map(|tag| tag.value.to_string().trim().to_string())
+            .filter(|value| !value.is_empty())
+    };
+    Metadata {
+        title: tag(StandardTagKey::Album).or_else(|| tag(StandardTagKey::TrackTitle)),
+        author: tag(StandardTagKey::AlbumArtist).or_else(|| tag(StandardTagKey::Artist)),
+        language: tag(StandardTagKey::Language),
+        collection: None,
+    }
+}
+
+/// Runs the model over one window and returns its segments, detecting the language from
+/// the first window when none was given.
+fn transcribe_window(
+    state: &mut WhisperState,
+    samples: &[f32],
+    offset: u64,
+    language: &mut Option<String>,
+) -> Result<Vec<Segment>> {
+    if samples.len() < MIN_WINDOW {
+        return Ok(Vec::new());
+    }
+
+    let mut params = FullParams::new(SamplingStrategy::Greedy { best_of: 1 });
+    params.set_language(Some(language.as_deref().unwrap_or("auto")));
+    params.set_print_progress(false);
+    params.set_print_realtime(false);
+    params.set_print_special(false);
+    params.set_print_timestamps(false);
+    state
+        .full(params, samples)
+        .context("Transcription failed")?;
+
+    if language.is_none() {
+        *language = whisper_rs::get_lang_str(state.full_lang_id_from_state()).map(str::to_string);
+    }
+
+    // Segment timestamps are in hundredths of a second
+    let samples_at =
+        |centiseconds: i64| offset + u64::try_from(centiseconds).unwrap_or(0) * WHISPER_RATE / 100;
+    state
+        .as_iter()
+        .map(|segment| {
+            Ok(Segment {
+                start: samples_at(segment.start_timestamp()),
+                end: samples_at(segment.end_timestamp()),
+                text: segment.to_str_lossy()?.trim().to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Adds segments to a chapter's transcript, starting a paragraph after each long pause.
+fn append(chapter: &mut ChapterText, segments: Vec<Segment>) {
+    for segment in segments {
+        if segment.text.is_empty() {
+            continue;
+        }
+        match chapter.paragraphs.last_mut() {
+            Some(paragraph) if segment.start < chapter.last_end + PARAGRAPH_PAUSE => {
+                paragraph.push(' ');
+                paragraph.push_str(&segment.text);
+            }
+            _ => chapter.paragraphs.push(segment.text),
+        }
+        chapter.last_end = segment.end;
+    }
+}
+
+/// Index of the chapter playing at `position`.
+fn chapter_at(marks: &[Chapter], position: u64) -> usize {
+    marks
+        .iter()
+        .rposition(|mark| mark.start <= position)
+        .unwrap_or(0)
+}
+
+/// Averages interleaved channels into one.
+fn downmix(samples: &[f32], channels: usize) -> Vec<f32> {
+    if channels <= 1 {
+        return samples.to_vec();
+    }
+    samples
+        .chunks(channels)
+        .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+        .collect()
+}
+
+/// Linear resampling to [`WHISPER_RATE`], carried across decoded packets.
+struct Resampler {
+    /// Input samples per output sample
+    step: f64,
+    /// Position of the next output sample in the next packet; -1 is the last sample of the
+    /// previous packet
+    position: f64,
+    previous: f32,
+}
+
+impl Resampler {
+    fn new(rate: u32) -> Self {
+        Resampler {
+            step: f64::from(rate) / WHISPER_RATE as f64,
+            position: 0.,
+            previous: 0.,
+        }
+    }
+
+    fn push(&mut self, input: &[f32], out: &mut Vec<f32>) {
+        let Some(&last) = input.last() else {
+            return;
+        };
+        let at = |i: isize| usize::try_from(i).map_or(self.previous, |i| input[i]);
+        while self.position + 1. < input.len() as f64 {
+            let floor = self.position.floor();
+            let i = floor as isize;
+            let fraction = (self.position - floor) as f32;
+            out.push(at(i) + (at(i + 1) - at(i)) * fraction);
+            self.position += self.step;
+        }
+        self.position -= input.len() as f64;
+        self.previous = last;
+    }
+}
+
+/// The chapter marks of the file, starting with one at the very beginning.
+fn chapters(path: &Path) -> Vec<Chapter> {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default()
+        .to_lowercase();
+    let mut marks = match extension.as_str() {
+        "mp3" => id3_chapters(path),
+        _ => mp4_chapters(path).unwrap_or_default(),
+    };
+    marks.sort_by_key(|mark| mark.start);
+
+    // Audio before the first mark (an opening credit) belongs to the first chapter
+    match marks.first_mut() {
+        Some(first) => first.start = 0,
+        None => marks.push(Chapter {
+            start: 0,
+            title: None,
+        }),
+    }
+    marks
+}
+
+/// Chapters from ID3 `CHAP` frames, titled by their `TIT2` subframes.
+fn id3_chapters(path: &Path) -> Vec<Chapter> {
+    let Ok(tag) = id3::Tag::read_from_path(path) else {
+        return Vec::new();
+    };
+    tag.chapters()
+        .map(|chapter| Chapter {
+            start: u64::from(chapter.start_time) * WHISPER_RATE / 1000,
+            title: chapter
+                .frames
+                .iter()
+                .find(|frame| frame.id() == "TIT2")
+                .and_then(|frame| frame.content().text())
+                .map(|title| title.trim().to_string())
+                .filter(|title| !title.is_empty()),
+        })
+        .collect()
+}
+
+/// Chapters from the Nero `chpl` box under `moov/udta`, which most `.m4b` tools write.
+fn mp4_chapters(path: &Path) -> Result<Vec<Chapter>> {
+    let mut file = File::open(path)?;
+    let Some(moov) = top_level_box(&mut file, b"moov")? else {
+        return Ok(Vec::new());
+    };
+    let chpl = child_box(&moov, b"udta").and_then(|udta| child_box(udta, b"chpl"));
+    Ok(chpl.and_then(parse_chpl).unwrap_or_default())
+}
+
+/// The body of the first top-level box named `name`, skipping over the others.
+fn top_level_box(file: &mut File, name: &[u8; 4]) -> Result<Option<Vec<u8>>> {
+    let end = file.metadata()?.len();
+    let mut at = 0;
+
+    while at + 8 <= end {
+        file.seek(SeekFrom::Start(at))?;
+        let mut header = [0u8; 8];
+        file.read_exact(&mut header)?;
+        let mut body = at + 8;
+        let size = match u32::from_be_bytes([header[0], header[1], header[2], header[3]]) {
+            // The box runs to the end of the file
+            0 => end - at,
+            // A 64-bit size follows the name
+            1 => {
+                let mut large = [0u8; 8];
+                file.read_exact(&mut large)?;
+                body += 8;
+                u64::from_be_bytes(large)
+            }
+            size => u64::from(size),
+        };
+        if at + size < body || at + size > end {
+            break;
+        }
+
+        if &header[4..] == name {
+            let mut data = vec![0; usize::try_from(at + size - body)?];
+            file.read_exact(&mut data)?;
+            return Ok(Some(data));
+        }
+        at += size;
+    }
+    Ok(None)
+}
+
+/// The body of the child box named `name` within a box body.
+fn child_box<'a>(data: &'a [u8], name: &[u8; 4]) -> Option<&'a [u8]> {
+    let mut at = 0;
+    while at + 8 <= data.len() {
+        let size = u32::from_be_bytes(data[at..at + 4].try_into().ok()?) as usize;
+        if size < 8 || at + size > data.len() {
+            return None;
+        }
+        if &data[at + 4..at + 8] == name {
+            return Some(&data[at + 8..at + size]);
+        }
+        at += size;
+    }
+    None
+}
+
+/// Parses a `chpl` body: version and flags, a chapter count, then each chapter's start in
+/// 100 ns units and its length-prefixed title.
+fn parse_chpl(chpl: &[u8]) -> Option<Vec<Chapter>> {
+    // Version 1 has four more reserved bytes before the count
+    let mut at = if *chpl.first()? == 0 { 4 } else { 8 };
+    let count = *chpl.get(at)?;
+    at += 1;
+
+    let mut chapters = Vec::with_capacity(count.into());
+    for _ in 0..count {
+        let start = u64::from_be_bytes(chpl.get(at..at + 8)?.try_into().ok()?);
+        let len = usize::from(*chpl.get(at + 8)?);
+        let title = String::from_utf8_lossy(chpl.get(at + 9..at + 9 + len)?)
+            .trim()
+            .to_string();
+        at += 9 + len;
+        chapters.push(Chapter {
+            start: start / CHPL_UNITS_PER_SAMPLE,
+            title: (!title.is_empty()).then_some(title),
+        });
+    }
+    Some(chapters)
+}
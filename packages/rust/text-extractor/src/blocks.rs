@@ -0,0 +1,130 @@
+//! Marks runs of special lines (display equations, code, tables) in assembled PDF page text.
+
+use crate::code;
+use crate::equations;
+use crate::layout::{AssembleOptions, Line, PageGlyphs};
+use crate::tables;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BlockKind {
+    Equation,
+    Code,
+    Table,
+}
+
+/// Rewrites `text` with consecutive equation lines wrapped in `[equation]` / `[/equation]`,
+/// consecutive code lines fenced with ```` ``` ```` and tables laid out as Markdown or TSV.
+pub fn mark(text: &str, lines: &[Line], page: &PageGlyphs, options: &AssembleOptions) -> String {
+    let mut kinds: Vec<Option<BlockKind>> = lines
+        .iter()
+        .map(|line| {
+            if options.fence_code && code::is_code_line(line, &page.glyphs) {
+                Some(BlockKind::Code)
+            } else if options.mark_equations && equations::is_equation_line(text, line, page.width)
+            {
+                Some(BlockKind::Equation)
+            } else if options.tables.is_some() && tables::is_table_line(line, &page.glyphs) {
+                Some(BlockKind::Table)
+            } else {
+                None
+            }
+        })
+        .collect();
+    extend_code_blocks(&mut kinds, lines, page);
+    drop_short_tables(&mut kinds);
+
+    let mut out = String::with_capacity(text.len());
+    let mut copied = 0;
+    let mut i = 0;
+
+    while i < lines.len() {
+        let Some(kind) = kinds[i] else {
+            i += 1;
+            continue;
+        };
+
+        // Multi-line displays stay in one block and a blank line starts a new one; code and
+        // tables keep their blank lines
+        let first = i;
+        i += 1;
+        while i < lines.len()
+            && kinds[i] == Some(kind)
+            && (kind != BlockKind::Equation
+                || !text[lines[i - 1].end..lines[i].start].contains("\n\n"))
+        {
+            i += 1;
+        }
+        let block = &lines[first..i];
+
+        out.push_str(text[copied..block[0].start].trim_end_matches(' '));
+        if !out.is_empty() && !out.ends_with('\n') {
+            out.push('\n');
+        }
+        match kind {
+            BlockKind::Equation => {
+                out.push_str("[equation]\n");
+                out.push_str(&text[block[0].start..block[block.len() - 1].end]);
+                out.push_str("\n[/equation]");
+            }
+            BlockKind::Code => {
+                out.push_str("```\n");
+                out.push_str(&code::render(text, block, &page.glyphs));
+                out.push_str("\n```");
+            }
+            BlockKind::Table => {
+                if let Some(layout) = options.tables {
+                    out.push_str(&tables::render_pdf(block, &page.glyphs, layout));
+                }
+            }
+        }
+        copied = block[block.len() - 1].end;
+    }
+
+    out.push_str(&text[copied..]);
+    out
+}
+
+/// Unmarks runs of table lines too short to be a table.
+fn drop_short_tables(kinds: &mut [Option<BlockKind>]) {
+    let mut i = 0;
+    while i < kinds.len() {
+        let end = i + kinds[i..]
+            .iter()
+            .take_while(|kind| **kind == Some(BlockKind::Table))
+            .count();
+        if end == i {
+            i += 1;
+            continue;
+        }
+        if !tables::is_table(end - i) {
+            kinds[i..end].fill(None);
+        }
+        i = end;
+    }
+}
+
+/// Pulls short monospaced lines next to code lines into the code block.
+fn extend_code_blocks(kinds: &mut [Option<BlockKind>], lines: &[Line], page: &PageGlyphs) {
+    for i in 1..lines.len() {
+        extend_code_block(kinds, lines, page, i - 1, i);
+    }
+    for i in (1..lines.len()).rev() {
+        extend_code_block(kinds, lines, page, i, i - 1);
+    }
+}
+
+fn extend_code_block(
+    kinds: &mut [Option<BlockKind>],
+    lines: &[Line],
+    page: &PageGlyphs,
+    from: usize,
+    to: usize,
+) {
+    if kinds[to].is_some() || kinds[from] != Some(BlockKind::Code) {
+        return;
+    }
+    let advance = code::monospace_advance(&lines[from], &page.glyphs);
+    if advance.is_some_and(|a| code::continues_block(&lines[to], &page.glyphs, a)) {
+        kinds[to] = Some(BlockKind::Code);
+    }
+}
@@ -0,0 +1,158 @@
+//! Enumerates books in a Calibre library via its `metadata.db`.
+
+use anyhow::{Context, Result};
+use rusqlite::{Connection, OpenFlags};
+use std::path::Path;
+
+use crate::metadata::Metadata;
+use crate::source::{SourceFile, SUPPORTED_EXTENSIONS};
+
+/// Formats tried first when a book has several, before the others we can extract.
+const PREFERRED_FORMATS: [&str; 2] = ["epub", "pdf"];
+
+/// Result of scanning a library: books to extract and titles with no usable format.
+pub struct CalibreLibrary {
+    pub books: Vec<SourceFile>,
+    pub unsupported: Vec<String>,
+}
+
+pub fn load_library(library_dir: &Path) -> Result<CalibreLibrary> {
+    let db_path = library_dir.join("metadata.db");
+    if !db_path.exists() {
+        anyhow::bail!(
+            "Not a Calibre library (metadata.db not found): {}",
+            library_dir.display()
+        );
+    }
+
+    // Calibre may be running against the same library, so never take a write lock
+    let conn = Connection::open_with_flags(&db_path, OpenFlags::SQLITE_OPEN_READ_ONLY).context(
+        format!("Failed to open Calibre database: {}", db_path.display()),
+    )?;
+
+    let mut library = CalibreLibrary {
+        books: Vec::new(),
+        unsupported: Vec::new(),
+    };
+
+    for row in query_books(&conn)? {
+        let formats = query_formats(&conn, row.id)?;
+
+        match pick_format(&formats) {
+            Some((format, name)) => {
+                let extension = format.to_lowercase();
+                library.books.push(SourceFile {
+                    path: library_dir
+                        .join(&row.path)
+                        .join(format!("{name}.{extension}")),
+                    extension,
+                    metadata: Some(Metadata {
+                        title: Some(row.title),
+                        author: row.authors,
+                        language: None,
+                        collection: None,
+                    }),
+                });
+            }
+            None => library.unsupported.push(row.title),
+        }
+    }
+
+    Ok(library)
+}
+
+/// Row of the `books` table joined with its authors.
+struct BookRow {
+    id: i64,
+    title: String,
+    /// Book directory relative to the library root
+    path: String,
+    authors: Option<String>,
+}
+
+fn query_books(conn: &Connection) -> Result<Vec<BookRow>> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT b.id, b.title, b.path,
+                    (SELECT group_concat(name, ' & ')
+                       FROM (SELECT a.name
+                               FROM books_authors_link bal
+                               JOIN authors a ON a.id = bal.author
+                              WHERE bal.book = b.id
+                              ORDER BY bal.id)) AS authors
+               FROM books b
+              ORDER BY b.id",
+        )
+        .context("Failed to query Calibre books table")?;
+
+    let rows = stmt
+        .query_map([], |row| {
+            Ok(BookRow {
+                id: row.get(0)?,
+                title: row.get(1)?,
+                path: row.get(2)?,
+                authors: row.get(3)?,
+            })
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    Ok(rows)
+}
+
+fn query_formats(conn: &Connection, book_id: i64) -> Result<Vec<(String, String)>> {
+    let mut stmt = conn
+        .prepare_cached("SELECT format, name FROM data WHERE book = ?1 ORDER BY format")
+        .context("Failed to query Calibre data table")?;
+
+    let rows = stmt
+        .query_map([book_id], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    Ok(rows)
+}
+
+/// The format of a book to extract: the first of [`PREFERRED_FORMATS`] it has, else the first
+/// other supported one in [`SUPPORTED_EXTENSIONS`] order.
+fn pick_format(formats: &[(String, String)]) -> Option<&(String, String)> {
+    let others = SUPPORTED_EXTENSIONS
+        .iter()
+        .filter(|extension| !PREFERRED_FORMATS.contains(extension));
+    PREFERRED_FORMATS
+        .iter()
+        .chain(others)
+        .find_map(|preferred| {
+            formats
+                .iter()
+                .find(|(format, _)| format.eq_ignore_ascii_case(preferred))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn formats(names: &[&str]) -> Vec<(String, String)> {
+        names
+            .iter()
+            .map(|format| (format.to_string(), "Book - Author".to_string()))
+            .collect()
+    }
+
+    fn picked(names: &[&str]) -> Option<String> {
+        pick_format(&formats(names)).map(|(format, _)| format.clone())
+    }
+
+    #[test]
+    fn epub_and_pdf_come_first() {
+        assert_eq!(picked(&["DOCX", "PDF", "EPUB"]).as_deref(), Some("EPUB"));
+        assert_eq!(picked(&["DJVU", "PDF"]).as_deref(), Some("PDF"));
+    }
+
+    #[test]
+    fn other_supported_formats_are_picked() {
+        assert_eq!(picked(&["MOBI", "FB2"]).as_deref(), Some("FB2"));
+        assert_eq!(picked(&["RTF", "DOCX"]).as_deref(), Some("DOCX"));
+        assert_eq!(picked(&["AZW3", "CHM"]).as_deref(), Some("CHM"));
+        assert_eq!(picked(&["MOBI", "AZW3", "TXT"]), None);
+    }
+}
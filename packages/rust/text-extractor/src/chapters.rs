@@ -0,0 +1,56 @@
+//! Choosing which EPUB chapters to extract, by position in the reading order or by their
+//! table-of-contents title, for leaving front matter, copyright pages and acknowledgements
+//! out of a corpus.
+
+use regex::{Regex, RegexBuilder};
+
+use crate::document::{Document, SectionKind};
+use crate::pages::PageSelection;
+
+/// Which chapters (EPUB content documents) to extract.
+#[derive(Debug, Clone, Default)]
+pub struct ChapterSelection {
+    /// Chapters to keep by position in the reading order, counting from 1
+    pub numbers: PageSelection,
+    /// Chapters to drop when a table-of-contents entry opening them matches
+    pub exclude_titles: Option<Regex>,
+}
+
+impl ChapterSelection {
+    pub fn is_all(&self) -> bool {
+        self.numbers.is_all() && self.exclude_titles.is_none()
+    }
+
+    /// Drops the chapters not selected. Only titles of the entries that open a chapter are
+    /// matched, so a chapter the table of contents leaves out is never dropped by title.
+    pub fn apply(&self, doc: &mut Document) {
+        if self.is_all() {
+            return;
+        }
+        let mut keep = Vec::with_capacity(doc.sections.len());
+        let mut number = 0;
+        for (index, section) in doc.sections.iter().enumerate() {
+            if section.kind != SectionKind::Chapter {
+                keep.push(true);
+                continue;
+            }
+            number += 1;
+            let excluded = self.exclude_titles.as_ref().is_some_and(|pattern| {
+                doc.outline
+                    .iter()
+                    .any(|entry| entry.section == index && pattern.is_match(&entry.title))
+            });
+            keep.push(self.numbers.contains(number) && !excluded);
+        }
+        let mut keep = keep.into_iter();
+        doc.retain_sections(|_| keep.next().unwrap_or(true));
+    }
+}
+
+/// Compiles a `--exclude-chapters` pattern, matched case-insensitively.
+pub fn parse_title_pattern(pattern: &str) -> Result<Regex, String> {
+    RegexBuilder::new(pattern)
+        .case_insensitive(true)
+        .build()
+        .map_err(|e| e.to_string())
+}
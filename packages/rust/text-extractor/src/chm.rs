@@ -0,0 +1,261 @@
+//! Compiled HTML Help (`.chm`), the format of many older technical books and manuals.
+//!
+//! A CHM file is an archive of HTML pages with a sitemap (`.hhc`) for its table of contents.
+//! It is unpacked into a scratch folder with the `7z` command (p7zip or 7-Zip), which must be
+//! on the `PATH`. The pages are read in contents order, followed by any the contents leave out,
+//! and go through the same tag stripping as EPUB chapters.
+
+use anyhow::{Context, Result};
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use walkdir::WalkDir;
+
+use crate::document::{Document, Landmarks, OutlineEntry, Section, SectionKind};
+use crate::extract::{self, HtmlOptions};
+use crate::html::{attribute, next_tag};
+use crate::metadata::Metadata;
+use crate::ocr;
+
+const HTML_EXTENSIONS: &[&str] = &["htm", "html", "xhtml"];
+
+pub fn extract(path: &Path, metadata: Option<&Metadata>, options: HtmlOptions) -> Result<Document> {
+    let folder = tempfile::tempdir().context("Failed to create a folder for the CHM's pages")?;
+    let mut destination = OsString::from("-o");
+    destination.push(folder.path());
+    ocr::run(
+        Command::new("7z")
+            .args(["x", "-y"])
+            .arg(destination)
+            .arg(path),
+        "7z (from p7zip or 7-Zip)",
+        "Reading CHM files",
+    )?;
+
+    // Names inside a CHM are case-insensitive
+    let files: HashMap<String, PathBuf> = WalkDir::new(folder.path())
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_file())
+        .filter_map(|entry| {
+            let name = entry.path().strip_prefix(folder.path()).ok()?;
+            let name = name.to_string_lossy().replace('\\', "/").to_lowercase();
+            Some((name, entry.into_path()))
+        })
+        .collect();
+
+    let system = files
+        .get("#system")
+        .and_then(|system| fs::read(system).ok())
+        .map(|system| System::parse(&system))
+        .unwrap_or_default();
+    let mut sitemaps: Vec<&String> = files.keys().filter(|n| n.ends_with(".hhc")).collect();
+    sitemaps.sort();
+    let contents = system
+        .contents
+        .map(|name| normalize(&name))
+        .filter(|name| files.contains_key(name))
+        .or_else(|| sitemaps.first().map(|name| name.to_string()));
+    let entries = match contents {
+        Some(name) => {
+            let bytes = fs::read(&files[&name])
+                .context(format!("Failed to read the CHM's contents: {name}"))?;
+            contents_entries(&extract::decode_html(&bytes))
+        }
+        None => Vec::new(),
+    };
+
+    // Pages in contents order, then those the contents leave out in name order
+    let mut seen = HashSet::new();
+    let mut pages: Vec<&String> = entries
+        .iter()
+        .filter_map(|entry| files.get_key_value(&entry.target).map(|(name, _)| name))
+        .filter(|name| seen.insert(name.as_str()))
+        .collect();
+    let mut rest: Vec<&String> = files
+        .keys()
+        .filter(|name| is_page(name) && !seen.contains(name.as_str()))
+        .collect();
+    rest.sort();
+    pages.extend(rest);
+
+    let mut sections = Vec::new();
+    let mut section_of = HashMap::new();
+    for name in pages {
+        let bytes = fs::read(&files[name]).context(format!("Failed to read CHM page: {name}"))?;
+        let text = extract::html_body_text(&extract::decode_html(&bytes), options);
+        if text.trim().is_empty() {
+            continue;
+        }
+        section_of.insert(name.as_str(), sections.len());
+        sections.push(Section {
+            kind: SectionKind::Chapter,
+            title: None,
+            text,
+            language: None,
+            page: None,
+        });
+    }
+
+    let outline = entries
+        .into_iter()
+        .filter_map(|entry| {
+            Some(OutlineEntry {
+                section: *section_of.get(entry.target.as_str())?,
+                title: entry.title?,
+                level: entry.level,
+            })
+        })
+        .collect();
+
+    let embedded = Metadata {
+        title: system.title,
+        ..Metadata::default()
+    };
+    Ok(Document {
+        metadata: metadata.cloned().unwrap_or_default().or(embedded),
+        sections,
+        outline,
+        landmarks: Landmarks::default(),
+    })
+}
+
+/// An HTML page of the book rather than one of the archive's internal `#` and `$` files.
+fn is_page(name: &str) -> bool {
+    !name.starts_with(['#', '$'])
+        && Path::new(name)
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| HTML_EXTENSIONS.contains(&extension))
+}
+
+/// The archive name a contents link points at: `ms-its:book.chm::/intro.htm#setup` and
+/// `Intro.htm` both name `intro.htm`.
+fn normalize(target: &str) -> String {
+    let target = target.rsplit("::").next().unwrap_or(target);
+    let target = target.split('#').next().unwrap_or_default();
+    target
+        .trim()
+        .trim_start_matches('/')
+        .replace('\\', "/")
+        .replace("%20", " ")
+        .to_lowercase()
+}
+
+/// The parts of the `#SYSTEM` file used here: a version, then records of a 16-bit code, a
+/// 16-bit length and that many bytes.
+#[derive(Default)]
+struct System {
+    /// Name of the `.hhc` sitemap
+    contents: Option<String>,
+    title: Option<String>,
+}
+
+impl System {
+    fn parse(system: &[u8]) -> System {
+        let mut parsed = System::default();
+        let mut at = 4;
+        while let Some(header) = system.get(at..at + 4) {
+            let code = u16::from_le_bytes([header[0], header[1]]);
+            let length = usize::from(u16::from_le_bytes([header[2], header[3]]));
+            let Some(data) = system.get(at + 4..at + 4 + length) else {
+                break;
+            };
+            at += 4 + length;
+            match code {
+                0 => parsed.contents = string(data),
+                3 => parsed.title = string(data),
+                _ => {}
+            }
+        }
+        parsed
+    }
+}
+
+/// A NUL-terminated string, in UTF-8 or else the Windows code page most CHM files use.
+fn string(data: &[u8]) -> Option<String> {
+    let data = data.split(|byte| *byte == 0).next().unwrap_or_default();
+    let text = match std::str::from_utf8(data) {
+        Ok(text) => text.to_string(),
+        Err(_) => encoding_rs::WINDOWS_1252.decode(data).0.into_owned(),
+    };
+    let text = text.trim();
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+/// An entry of the table of contents.
+struct ContentsEntry {
+    title: Option<String>,
+    /// Normalised archive name of the page it opens
+    target: String,
+    level: usize,
+}
+
+/// The entries of an `.hhc` sitemap: `<object type="text/sitemap">` elements with `Name` and
+/// `Local` parameters, nested in `<ul>` lists one level per list.
+fn contents_entries(hhc: &str) -> Vec<ContentsEntry> {
+    let mut entries = Vec::new();
+    let mut depth = 0usize;
+    // Title and target of the sitemap object being read
+    let mut object: Option<(Option<String>, Option<String>)> = None;
+    let mut at = 0;
+    while let Some((start, end, name)) = next_tag(hhc, at) {
+        at = end;
+        let tag = &hhc[start..end];
+        if name.is_empty() {
+            let name = tag[2..].trim_end_matches('>').trim().to_lowercase();
+            match name.as_str() {
+                "ul" => depth = depth.saturating_sub(1),
+                "object" => {
+                    if let Some((title, Some(target))) = object.take() {
+                        entries.push(ContentsEntry {
+                            title,
+                            target: normalize(&target),
+                            level: depth.max(1),
+                        });
+                    }
+                }
+                _ => {}
+            }
+            continue;
+        }
+
+        match name.as_str() {
+            "ul" => depth += 1,
+            "object" => {
+                let sitemap = parameter(tag, "type")
+                    .is_some_and(|kind| kind.eq_ignore_ascii_case("text/sitemap"));
+                object = sitemap.then_some((None, None));
+            }
+            "param" => {
+                let Some((title, target)) = &mut object else {
+                    continue;
+                };
+                let value = parameter(tag, "value").map(|value| {
+                    value
+                        .replace("&amp;", "&")
+                        .split_whitespace()
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                });
+                match parameter(tag, "name")
+                    .map(|name| name.to_lowercase())
+                    .as_deref()
+                {
+                    Some("name") if title.is_none() => *title = value,
+                    Some("local") => *target = value,
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+    entries
+}
+
+/// An attribute of a tag, whose name sitemaps write in either case.
+fn parameter(tag: &str, name: &str) -> Option<String> {
+    attribute(tag, name).or_else(|| attribute(tag, &name.to_uppercase()))
+}
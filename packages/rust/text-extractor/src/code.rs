@@ -0,0 +1,71 @@
+//! Code sample detection for assembled PDF lines.
+//!
+//! pdf-extract does not expose font names, so monospaced text is recognised by its glyphs all
+//! advancing by the same amount.
+
+use crate::layout::{Glyph, Line};
+
+/// Shortest line, in glyphs, whose uniform advances are taken as evidence of a monospaced font.
+const MIN_GLYPHS: usize = 4;
+/// Allowed relative difference between glyph advances on a monospaced line.
+const ADVANCE_TOLERANCE: f64 = 0.02;
+
+pub fn is_code_line(line: &Line, glyphs: &[Glyph]) -> bool {
+    line.glyphs.len() >= MIN_GLYPHS && monospace_advance(line, glyphs).is_some()
+}
+
+/// Returns the shared advance of the line's glyphs if they all advance by the same amount.
+pub fn monospace_advance(line: &Line, glyphs: &[Glyph]) -> Option<f64> {
+    let line_glyphs = &glyphs[line.glyphs.clone()];
+    let first = line_glyphs.first()?;
+
+    let uniform = first.advance > 0.
+        && line_glyphs.iter().all(|g| {
+            (g.advance - first.advance).abs() <= first.advance * ADVANCE_TOLERANCE
+                && (g.size - first.size).abs() <= first.size * ADVANCE_TOLERANCE
+        });
+    uniform.then_some(first.advance)
+}
+
+/// Whether a line too short to be recognised on its own (`}`, `end`) continues a code block
+/// whose lines advance by `advance`.
+pub fn continues_block(line: &Line, glyphs: &[Glyph], advance: f64) -> bool {
+    monospace_advance(line, glyphs)
+        .is_some_and(|a| (a - advance).abs() <= advance * ADVANCE_TOLERANCE)
+}
+
+/// Lays the block's glyphs out on a character grid so indentation and alignment survive.
+pub fn render(text: &str, block: &[Line], glyphs: &[Glyph]) -> String {
+    let left = block
+        .iter()
+        .map(|line| line.x0)
+        .fold(f64::INFINITY, f64::min);
+    let advance = glyphs[block[0].glyphs.start].advance;
+
+    let mut out = String::new();
+    for (i, line) in block.iter().enumerate() {
+        if i > 0 {
+            out.push('\n');
+            // Blank lines between statements show up as an empty line in the assembled text
+            if text[block[i - 1].end..line.start].contains("\n\n") {
+                out.push('\n');
+            }
+        }
+
+        let mut column = 0;
+        for glyph in &glyphs[line.glyphs.clone()] {
+            if glyph.text.trim().is_empty() {
+                continue;
+            }
+            let target = ((glyph.x - left) / advance).round().max(0.) as usize;
+            while column < target {
+                out.push(' ');
+                column += 1;
+            }
+            out.push_str(&glyph.text);
+            column += glyph.text.chars().count();
+        }
+    }
+
+    out
+}
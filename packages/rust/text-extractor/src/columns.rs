@@ -0,0 +1,381 @@
+//! Reading order for multi-column PDF pages.
+//!
+//! Producers that draw a page line by line across its columns leave the content stream
+//! alternating between them, so plain assembly interleaves the left and right halves of every
+//! line. A column gutter is a vertical band, away from the page edges, that almost no line
+//! crosses. Lines that do cross it (titles, full-width abstracts, figure captions) cut the
+//! page into regions, and each region is read column by column, left to right.
+
+use crate::layout::{AssembleOptions, Glyph, PageGlyphs, WIDE_GAP};
+use crate::structure;
+
+/// Fewest lines on each side of a gutter; a short run of split lines is more often a table.
+const MIN_COLUMN_LINES: usize = 5;
+/// Share of the lines that may cross a gutter: headings and captions set across the page.
+const MAX_CROSSING_SHARE: f64 = 0.25;
+/// A gutter lies within this share of the text width from either edge, so a column is never
+/// narrower than it.
+const MIN_COLUMN_SHARE: f64 = 0.2;
+/// Narrowest gutter, as a fraction of the font size.
+const MIN_GUTTER: f64 = 1.0;
+/// Widest text looked at, in page units: the largest page PDF allows. Broken files can place
+/// glyphs far off the page, and the gutter search takes one bin per unit.
+const MAX_TEXT_WIDTH: f64 = 14_400.;
+
+/// How PDF pages without a structure tree are put in reading order.
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PageLayout {
+    /// Content-stream order, as pdf-extract reads it
+    #[default]
+    Raw,
+    /// Read multi-column pages column by column
+    Columns,
+}
+
+/// Assembles a page column by column. Returns `None` for pages without a column gutter,
+/// which read fine in content-stream order.
+pub fn assemble(page: &PageGlyphs, options: &AssembleOptions) -> Option<String> {
+    let glyphs: Vec<usize> = (0..page.glyphs.len()).collect();
+    let groups = order(&page.glyphs, glyphs);
+    if groups.len() < 2 {
+        return None;
+    }
+
+    let mut text = String::from("\n");
+    for group in groups {
+        let glyphs: Vec<&Glyph> = group.glyphs.iter().map(|&i| &page.glyphs[i]).collect();
+        let part = structure::assemble_glyphs(page, &glyphs, options);
+        if part.is_empty() {
+            continue;
+        }
+        if text.len() > 1 {
+            // A column usually ends mid-paragraph; a region always ends a paragraph
+            text.push_str(if group.continues { "\n" } else { "\n\n" });
+        }
+        text.push_str(&part);
+    }
+    text.push('\n');
+    Some(text)
+}
+
+/// Glyphs read together, in content-stream order.
+struct Group {
+    glyphs: Vec<usize>,
+    /// Continues the column before it rather than starting a new region
+    continues: bool,
+    /// Lines that cross the gutter
+    crossing: bool,
+}
+
+/// Splits glyphs into groups in reading order: regions between the lines that cross the
+/// gutter, each read left column then right, and the columns split further when they have
+/// gutters of their own.
+fn order(all: &[Glyph], glyphs: Vec<usize>) -> Vec<Group> {
+    let lines = lines(all, &glyphs);
+    let Some(gutter) = gutter(all, &lines) else {
+        return vec![Group {
+            glyphs,
+            continues: false,
+            crossing: false,
+        }];
+    };
+
+    let mut groups: Vec<Group> = Vec::new();
+    let mut left = Vec::new();
+    let mut right = Vec::new();
+    let flush = |left: &mut Vec<usize>, right: &mut Vec<usize>, groups: &mut Vec<Group>| {
+        let region = groups.len();
+        for mut column in [std::mem::take(left), std::mem::take(right)] {
+            if column.is_empty() {
+                continue;
+            }
+            column.sort_unstable();
+            for mut group in order(all, column) {
+                group.continues = groups.len() > region;
+                groups.push(group);
+            }
+        }
+    };
+
+    for line in &lines {
+        if line.crosses(gutter) {
+            flush(&mut left, &mut right, &mut groups);
+            // Neighbouring lines that cross the gutter make up one block
+            match groups.last_mut() {
+                Some(last) if last.crossing => last.glyphs.extend(&line.glyphs),
+                _ => groups.push(Group {
+                    glyphs: line.glyphs.clone(),
+                    continues: false,
+                    crossing: true,
+                }),
+            }
+        } else {
+            for &i in &line.glyphs {
+                if all[i].x < gutter.0 {
+                    left.push(i);
+                } else {
+                    right.push(i);
+                }
+            }
+        }
+    }
+    flush(&mut left, &mut right, &mut groups);
+
+    for group in groups.iter_mut().filter(|group| group.crossing) {
+        group.glyphs.sort_unstable();
+    }
+    groups
+}
+
+/// Glyphs sharing a baseline, with the spans of text on it (none for blank lines).
+struct Line {
+    glyphs: Vec<usize>,
+    /// x ranges of runs of text, split at gaps wide enough to separate columns
+    spans: Vec<(f64, f64)>,
+}
+
+impl Line {
+    fn crosses(&self, gutter: (f64, f64)) -> bool {
+        self.spans
+            .iter()
+            .any(|&(start, end)| start < gutter.1 && end > gutter.0)
+    }
+}
+
+/// The glyphs grouped into lines, top to bottom.
+fn lines(all: &[Glyph], glyphs: &[usize]) -> Vec<Line> {
+    let mut sorted = glyphs.to_vec();
+    sorted.sort_by(|&a, &b| all[a].y.total_cmp(&all[b].y));
+
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    let mut top = f64::NEG_INFINITY;
+    for i in sorted {
+        let glyph = &all[i];
+        match groups.last_mut() {
+            Some(group) if glyph.y - top <= glyph.size.max(1.) * 0.5 => group.push(i),
+            _ => {
+                top = glyph.y;
+                groups.push(vec![i]);
+            }
+        }
+    }
+
+    groups
+        .into_iter()
+        .map(|glyphs| {
+            let mut by_x: Vec<&Glyph> = glyphs
+                .iter()
+                .map(|&i| &all[i])
+                .filter(|g| !g.text.trim().is_empty())
+                .collect();
+            by_x.sort_by(|a, b| a.x.total_cmp(&b.x));
+            let mut spans: Vec<(f64, f64)> = Vec::new();
+            for glyph in by_x {
+                let end = glyph.x + glyph.advance;
+                match spans.last_mut() {
+                    Some(span) if glyph.x <= span.1 + glyph.size * WIDE_GAP => {
+                        span.1 = span.1.max(end);
+                    }
+                    _ => spans.push((glyph.x, end)),
+                }
+            }
+            Line { glyphs, spans }
+        })
+        .collect()
+}
+
+/// The widest band near the middle of the text that few lines cross, with enough lines on
+/// either side to be columns.
+fn gutter(all: &[Glyph], lines: &[Line]) -> Option<(f64, f64)> {
+    let spans = || lines.iter().flat_map(|line| line.spans.iter());
+    let left = spans().map(|s| s.0).fold(f64::INFINITY, f64::min);
+    let right = spans().map(|s| s.1).fold(f64::NEG_INFINITY, f64::max);
+    let width = right - left;
+    let text_lines = lines.iter().filter(|line| !line.spans.is_empty()).count();
+    if text_lines < MIN_COLUMN_LINES * 2 || !(width > 0. && width <= MAX_TEXT_WIDTH) {
+        return None;
+    }
+
+    // Lines covering each point of the text width, one bin per unit
+    let bins = width.ceil() as usize;
+    let mut cover = vec![0usize; bins + 1];
+    for line in lines {
+        let mut covered = vec![false; bins + 1];
+        for &(start, end) in &line.spans {
+            let start = ((start - left).floor().max(0.)) as usize;
+            let end = ((end - left).ceil() as usize).min(bins);
+            covered[start..=end].fill(true);
+        }
+        for (count, covered) in cover.iter_mut().zip(covered) {
+            *count += usize::from(covered);
+        }
+    }
+
+    let crossing = (text_lines as f64 * MAX_CROSSING_SHARE) as usize;
+    let first = (width * MIN_COLUMN_SHARE) as usize;
+    let last = (width * (1. - MIN_COLUMN_SHARE)) as usize;
+    let last = last.min(bins);
+    let mut best: Option<(usize, usize)> = None;
+    let mut start = None;
+    // A closed bin after the band ends a gutter that reaches its edge
+    let band = cover[first..=last].iter().chain([&usize::MAX]);
+    for (bin, &count) in (first..).zip(band) {
+        match (count <= crossing, start) {
+            (true, None) => start = Some(bin),
+            (false, Some(from)) => {
+                if best.is_none_or(|(a, b)| bin - from > b - a) {
+                    best = Some((from, bin));
+                }
+                start = None;
+            }
+            _ => {}
+        }
+    }
+
+    let (from, to) = best?;
+    let all_glyphs: Vec<usize> = lines
+        .iter()
+        .flat_map(|l| l.glyphs.iter().copied())
+        .collect();
+    let gutter = (left + from as f64, left + to as f64);
+    if gutter.1 - gutter.0 < median_size(all, &all_glyphs) * MIN_GUTTER {
+        return None;
+    }
+
+    let side = |left_side: bool| {
+        lines
+            .iter()
+            .filter(|line| !line.crosses(gutter))
+            .filter(|line| {
+                line.spans.iter().any(|&(start, end)| {
+                    if left_side {
+                        end <= gutter.0
+                    } else {
+                        start >= gutter.1
+                    }
+                })
+            })
+            .count()
+    };
+    (side(true) >= MIN_COLUMN_LINES && side(false) >= MIN_COLUMN_LINES).then_some(gutter)
+}
+
+fn median_size(all: &[Glyph], glyphs: &[usize]) -> f64 {
+    let mut sizes: Vec<f64> = glyphs
+        .iter()
+        .map(|&i| all[i].size)
+        .filter(|size| *size > 0.)
+        .collect();
+    if sizes.is_empty() {
+        return 0.;
+    }
+    sizes.sort_by(f64::total_cmp);
+    sizes[sizes.len() / 2]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structure::ContentTag;
+
+    fn glyph(x: f64, y: f64, advance: f64, text: &str) -> Glyph {
+        Glyph {
+            x,
+            y,
+            size: 10.0,
+            advance,
+            word_start: true,
+            text: text.to_string(),
+            tag: ContentTag::Untagged,
+            lang: None,
+            turns: 0,
+        }
+    }
+
+    /// A two-column page drawn line by line across both columns, under a full-width title.
+    fn two_columns(lines: usize) -> Vec<Glyph> {
+        let mut glyphs = vec![glyph(0.0, 0.0, 500.0, "Title")];
+        for line in 0..lines {
+            let y = 20.0 + 12.0 * line as f64;
+            glyphs.push(glyph(0.0, y, 200.0, &format!("left{line}")));
+            glyphs.push(glyph(300.0, y, 200.0, &format!("right{line}")));
+        }
+        glyphs
+    }
+
+    fn texts(all: &[Glyph], group: &Group) -> Vec<String> {
+        group.glyphs.iter().map(|&i| all[i].text.clone()).collect()
+    }
+
+    #[test]
+    fn columns_are_read_one_after_the_other() {
+        let all = two_columns(10);
+        let groups = order(&all, (0..all.len()).collect());
+        assert_eq!(groups.len(), 3);
+        assert_eq!(texts(&all, &groups[0]), ["Title"]);
+        assert!(groups[0].crossing);
+        assert_eq!(
+            texts(&all, &groups[1]),
+            (0..10)
+                .map(|line| format!("left{line}"))
+                .collect::<Vec<_>>()
+        );
+        assert!(!groups[1].continues);
+        assert_eq!(texts(&all, &groups[2])[0], "right0");
+        assert!(groups[2].continues);
+
+        let page = PageGlyphs {
+            width: 600.0,
+            height: 800.0,
+            glyphs: all,
+        };
+        let text = assemble(&page, &AssembleOptions::default()).unwrap();
+        let at = |word: &str| text.find(word).unwrap();
+        assert!(at("Title") < at("left0"));
+        assert!(at("left9") < at("right0"), "{text}");
+    }
+
+    #[test]
+    fn short_runs_and_single_columns_keep_their_order() {
+        // Too few lines on each side: more likely a table
+        let all = two_columns(4);
+        assert_eq!(order(&all, (0..all.len()).collect()).len(), 1);
+
+        let all: Vec<Glyph> = (0..12)
+            .map(|line| glyph(0.0, 12.0 * line as f64, 500.0, "prose"))
+            .collect();
+        let page = PageGlyphs {
+            width: 600.0,
+            height: 800.0,
+            glyphs: all,
+        };
+        assert_eq!(assemble(&page, &AssembleOptions::default()), None);
+    }
+
+    #[test]
+    fn malformed_pages() {
+        let page = PageGlyphs::default();
+        assert_eq!(assemble(&page, &AssembleOptions::default()), None);
+
+        // Glyphs far off the page are read in stream order; ones at no position at all are
+        // left out of the gutter search. Either way every glyph is kept.
+        for (x, groups) in [(1e15, 1), (f64::INFINITY, 1), (f64::NAN, 3)] {
+            let mut all = two_columns(10);
+            all[1].x = x;
+            let order = order(&all, (0..all.len()).collect());
+            assert_eq!(order.len(), groups, "{x}");
+            let kept: usize = order.iter().map(|group| group.glyphs.len()).sum();
+            assert_eq!(kept, all.len());
+        }
+
+        // Blank glyphs and zero sizes have no spans
+        let mut all = two_columns(10);
+        all.push(glyph(250.0, 30.0, 5.0, " "));
+        for glyph in &mut all {
+            glyph.size = 0.0;
+        }
+        let groups = order(&all, (0..all.len()).collect());
+        assert!(groups.iter().all(|group| !group.glyphs.is_empty()));
+    }
+}
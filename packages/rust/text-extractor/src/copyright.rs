@@ -0,0 +1,48 @@
+//! Recognising copyright and imprint pages, which repeat near-identical boilerplate in every
+//! book.
+
+/// Phrases typical of imprint pages; each group counts once however often it appears.
+const MARKERS: &[&[&str]] = &[
+    &["all rights reserved"],
+    &["isbn"],
+    &["copyright", "©", "(c) "],
+    &[
+        "library of congress",
+        "british library",
+        "cataloging-in-publication",
+        "cataloguing in publication",
+    ],
+    &[
+        "no part of this publication",
+        "no part of this book",
+        "may not be reproduced",
+    ],
+    &[
+        "first published",
+        "first edition",
+        "published by",
+        "printed in",
+        "printing history",
+    ],
+    &["licensed under", "creative commons"],
+    // The printer's key that counts down the printing
+    &["10 9 8 7 6 5 4 3 2 1", "1 2 3 4 5 6 7 8 9 10"],
+];
+
+/// Distinct marker groups a page needs to count as an imprint page.
+const MIN_MARKERS: usize = 3;
+/// Longer sections are real content that happens to mention rights, like a licence appendix.
+const MAX_WORDS: usize = 600;
+
+pub fn is_copyright_page(text: &str) -> bool {
+    if text.split_whitespace().count() > MAX_WORDS {
+        return false;
+    }
+
+    let lower = text.to_lowercase();
+    let found = MARKERS
+        .iter()
+        .filter(|group| group.iter().any(|marker| lower.contains(marker)))
+        .count();
+    found >= MIN_MARKERS
+}
@@ -0,0 +1,188 @@
+//! State of a long-running `--daemon`: the queue of files waiting to be extracted, kept on
+//! disk so a restart picks up where the last run stopped, and the status and pause marker
+//! that `ctl` reads and writes.
+//!
+//! Everything lives in a state folder (by default [`STATE_DIR`] in the output folder), so the
+//! daemon and `ctl` need nothing but the file system to talk.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// State folder of a daemon, in its output folder unless one is given.
+pub const STATE_DIR: &str = ".to_texts-daemon";
+/// Files waiting to be extracted, one path per line, in the order they arrived.
+const QUEUE_FILE: &str = "queue.txt";
+const STATUS_FILE: &str = "status.json";
+/// Present while the daemon is paused.
+const PAUSED_FILE: &str = "paused";
+
+/// Files waiting to be extracted. A file leaves the queue once it has been extracted, so one
+/// being extracted when the daemon stops is extracted again on restart.
+pub struct Queue {
+    dir: PathBuf,
+    pending: Vec<PathBuf>,
+    /// The same files, for telling whether one is waiting
+    queued: HashSet<PathBuf>,
+}
+
+impl Queue {
+    /// Opens the queue in the state folder `dir`, creating the folder when needed.
+    pub fn open(dir: &Path) -> Result<Queue> {
+        fs::create_dir_all(dir).context(format!(
+            "Failed to create daemon state directory: {}",
+            dir.display()
+        ))?;
+        let path = dir.join(QUEUE_FILE);
+        let pending: Vec<PathBuf> = match fs::read_to_string(&path) {
+            Ok(text) => text
+                .lines()
+                .filter(|line| !line.is_empty())
+                .map(PathBuf::from)
+                .collect(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => {
+                return Err(e).context(format!("Failed to read queue: {}", path.display()));
+            }
+        };
+        Ok(Queue {
+            dir: dir.to_path_buf(),
+            queued: pending.iter().cloned().collect(),
+            pending,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Adds the files not already waiting.
+    pub fn push(&mut self, paths: impl IntoIterator<Item = PathBuf>) -> Result<()> {
+        let before = self.pending.len();
+        for path in paths {
+            if self.queued.insert(path.clone()) {
+                self.pending.push(path);
+            }
+        }
+        if self.pending.len() > before {
+            self.save()?;
+        }
+        Ok(())
+    }
+
+    /// The next `count` files, which stay queued until [`Queue::finish`].
+    pub fn next(&self, count: usize) -> Vec<PathBuf> {
+        self.pending.iter().take(count).cloned().collect()
+    }
+
+    /// Takes files that have been extracted, or have failed, off the queue.
+    pub fn finish(&mut self, paths: &[PathBuf]) -> Result<()> {
+        for path in paths {
+            self.queued.remove(path);
+        }
+        self.pending.retain(|path| self.queued.contains(path));
+        self.save()
+    }
+
+    fn save(&self) -> Result<()> {
+        let mut text = String::new();
+        for path in &self.pending {
+            text.push_str(&path.to_string_lossy());
+            text.push('\n');
+        }
+        let path = self.dir.join(QUEUE_FILE);
+        replace(&path, &text).context(format!("Failed to write queue: {}", path.display()))
+    }
+}
+
+/// What a daemon is doing, as `ctl status` shows it.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Status {
+    pub pid: u32,
+    pub paused: bool,
+    /// Files waiting, those being extracted included
+    pub pending: usize,
+    /// Files being extracted
+    pub processing: Vec<PathBuf>,
+    /// Files extracted since the daemon started
+    pub saved: usize,
+    pub failed: usize,
+    /// Seconds since the Unix epoch
+    pub started_at: u64,
+    pub updated_at: u64,
+}
+
+impl Status {
+    pub fn new() -> Status {
+        let now = now();
+        Status {
+            pid: std::process::id(),
+            started_at: now,
+            updated_at: now,
+            ..Status::default()
+        }
+    }
+
+    /// Writes the status into the state folder `dir`.
+    pub fn write(&mut self, dir: &Path) -> Result<()> {
+        self.updated_at = now();
+        let path = dir.join(STATUS_FILE);
+        replace(&path, &(serde_json::to_string_pretty(self)? + "\n"))
+            .context(format!("Failed to write status: {}", path.display()))
+    }
+
+    /// The status a daemon last wrote into the state folder `dir`.
+    pub fn read(dir: &Path) -> Result<Status> {
+        let path = dir.join(STATUS_FILE);
+        let text = fs::read_to_string(&path).context(format!(
+            "No daemon status in {} (is a daemon using it?)",
+            dir.display()
+        ))?;
+        serde_json::from_str(&text).context(format!("Failed to read status: {}", path.display()))
+    }
+}
+
+/// Whether the daemon with the state folder `dir` has been paused.
+pub fn is_paused(dir: &Path) -> bool {
+    dir.join(PAUSED_FILE).exists()
+}
+
+/// Pauses or resumes the daemon with the state folder `dir`. A paused daemon keeps queueing
+/// the files that arrive and finishes the ones it is extracting, but starts no others.
+pub fn set_paused(dir: &Path, paused: bool) -> Result<()> {
+    if !dir.is_dir() {
+        anyhow::bail!("Daemon state directory does not exist: {}", dir.display());
+    }
+    let marker = dir.join(PAUSED_FILE);
+    let result = if paused {
+        fs::write(&marker, "")
+    } else {
+        match fs::remove_file(&marker) {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            other => other,
+        }
+    };
+    result.context(format!("Failed to update: {}", marker.display()))
+}
+
+/// Writes `path` aside and renames it into place, so a crash never leaves half a file and
+/// `ctl` never reads one.
+fn replace(path: &Path, contents: &str) -> std::io::Result<()> {
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".partial");
+    fs::write(&partial, contents)?;
+    fs::rename(&partial, path)
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
@@ -0,0 +1,241 @@
+//! DjVu books, the format many scanned-book archives use.
+//!
+//! The page count, metadata and bookmarks are read with `djvused` and each page's hidden text
+//! layer with `djvutxt`, both from djvulibre and both needed on the `PATH`. Pages without a
+//! text layer come out empty, for `--ocr` to fill in.
+
+use anyhow::Result;
+use std::path::Path;
+use std::process::Command;
+
+use crate::document::{Document, Landmarks, OutlineEntry, Section, SectionKind};
+use crate::metadata::Metadata;
+use crate::ocr;
+
+const PURPOSE: &str = "Reading DjVu files";
+
+/// Text of every page, with the bookmarks and the title and author from the metadata.
+pub fn extract(path: &Path, metadata: Option<&Metadata>) -> Result<Document> {
+    let count: usize = djvused(path, "n")?
+        .trim()
+        .parse()
+        .map_err(|_| anyhow::anyhow!("djvused reported no page count for {}", path.display()))?;
+
+    let mut sections = Vec::with_capacity(count);
+    for page in 1..=count {
+        sections.push(Section {
+            kind: SectionKind::Page,
+            title: None,
+            text: page_text(path, page)?,
+            language: None,
+            page: Some(page),
+        });
+    }
+
+    let embedded = embedded_metadata(&djvused(path, "print-meta")?);
+    let mut outline = Vec::new();
+    for expression in parse(&djvused(path, "print-outline")?) {
+        if let Expression::List(items) = expression {
+            if items.first().and_then(Expression::atom) == Some("bookmarks") {
+                bookmarks(&items[1..], 1, count, &mut outline);
+            }
+        }
+    }
+
+    Ok(Document {
+        metadata: metadata.cloned().unwrap_or_default().or(embedded),
+        sections,
+        outline,
+        landmarks: Landmarks::default(),
+    })
+}
+
+/// Output of a `djvused` command run on the whole document, with text in UTF-8.
+fn djvused(path: &Path, command: &str) -> Result<String> {
+    let output = ocr::run(
+        Command::new("djvused")
+            .arg("-u")
+            .args(["-e", command])
+            .arg(path),
+        "djvused (from djvulibre)",
+        PURPOSE,
+    )?;
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// The hidden text layer of one page, empty for a page that has none.
+fn page_text(path: &Path, page: usize) -> Result<String> {
+    let output = ocr::run(
+        Command::new("djvutxt")
+            .arg(format!("--page={page}"))
+            .arg(path),
+        "djvutxt (from djvulibre)",
+        PURPOSE,
+    )?;
+    let text = String::from_utf8_lossy(&output.stdout);
+    let lines: Vec<&str> = text
+        .trim_matches(|c: char| c.is_whitespace() || c == '\u{b}' || c == '\u{c}')
+        .lines()
+        .map(str::trim_end)
+        .collect();
+    if lines.is_empty() {
+        return Ok(String::new());
+    }
+    // Page text ends with a line break, as pdf-extract leaves it
+    Ok(lines.join("\n") + "\n")
+}
+
+/// Title and author from `print-meta`, which lists one `key "value"` pair per line.
+fn embedded_metadata(meta: &str) -> Metadata {
+    let mut metadata = Metadata::default();
+    for line in meta.lines() {
+        let Some((key, value)) = line.trim().split_once(char::is_whitespace) else {
+            continue;
+        };
+        let Some(Expression::Atom(value)) = parse(value).into_iter().next() else {
+            continue;
+        };
+        let value = Some(value.trim().to_string()).filter(|v| !v.is_empty());
+        match key.to_ascii_lowercase().as_str() {
+            "title" => metadata.title = metadata.title.or(value),
+            "author" => metadata.author = metadata.author.or(value),
+            _ => {}
+        }
+    }
+    metadata
+}
+
+/// Appends the entries of an outline, `("Title" "#page" children...)` each. Entries that
+/// point at a page by name rather than number are left out; their children are kept.
+fn bookmarks(entries: &[Expression], level: usize, pages: usize, outline: &mut Vec<OutlineEntry>) {
+    for entry in entries {
+        let Expression::List(items) = entry else {
+            continue;
+        };
+        let (Some(title), Some(target)) = (
+            items.first().and_then(Expression::atom),
+            items.get(1).and_then(Expression::atom),
+        ) else {
+            continue;
+        };
+        let page = target
+            .strip_prefix('#')
+            .and_then(|page| page.parse::<usize>().ok())
+            .filter(|page| (1..=pages).contains(page));
+
+        let title = title.split_whitespace().collect::<Vec<_>>().join(" ");
+        if let Some(page) = page.filter(|_| !title.is_empty()) {
+            outline.push(OutlineEntry {
+                title,
+                level,
+                section: page - 1,
+            });
+        }
+        bookmarks(&items[2..], level + 1, pages, outline);
+    }
+}
+
+/// The s-expressions djvused prints for outlines and metadata.
+enum Expression {
+    /// A symbol or a string
+    Atom(String),
+    List(Vec<Expression>),
+}
+
+impl Expression {
+    fn atom(&self) -> Option<&str> {
+        match self {
+            Expression::Atom(atom) => Some(atom),
+            Expression::List(_) => None,
+        }
+    }
+}
+
+fn parse(text: &str) -> Vec<Expression> {
+    let mut chars = text.chars().peekable();
+    let mut stack: Vec<Vec<Expression>> = vec![Vec::new()];
+    while let Some(c) = chars.next() {
+        let expression = match c {
+            '(' => {
+                stack.push(Vec::new());
+                continue;
+            }
+            ')' => {
+                if stack.len() == 1 {
+                    continue;
+                }
+                Expression::List(stack.pop().unwrap_or_default())
+            }
+            '"' => Expression::Atom(string(&mut chars)),
+            c if c.is_whitespace() => continue,
+            c => {
+                let mut atom = c.to_string();
+                while let Some(&next) = chars.peek() {
+                    if next.is_whitespace() || next == '(' || next == ')' || next == '"' {
+                        break;
+                    }
+                    atom.push(next);
+                    chars.next();
+                }
+                Expression::Atom(atom)
+            }
+        };
+        if let Some(list) = stack.last_mut() {
+            list.push(expression);
+        }
+    }
+    // Close any lists a truncated output left open
+    while stack.len() > 1 {
+        let list = stack.pop().unwrap_or_default();
+        if let Some(parent) = stack.last_mut() {
+            parent.push(Expression::List(list));
+        }
+    }
+    stack.pop().unwrap_or_default()
+}
+
+/// The rest of a quoted string, with its C-style escapes decoded. Octal escapes are bytes
+/// of UTF-8.
+fn string(chars: &mut std::iter::Peekable<std::str::Chars>) -> String {
+    let mut bytes = Vec::new();
+    let mut buffer = [0; 4];
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => break,
+            '\\' => {
+                let Some(escaped) = chars.next() else {
+                    break;
+                };
+                let byte = match escaped {
+                    'n' => b'\n',
+                    't' => b'\t',
+                    'r' => b'\r',
+                    'f' => b'\x0c',
+                    'v' => b'\x0b',
+                    'b' => b'\x08',
+                    'a' => b'\x07',
+                    '0'..='7' => {
+                        let mut value = escaped as u32 - '0' as u32;
+                        for _ in 0..2 {
+                            match chars.peek() {
+                                Some(&digit @ '0'..='7') => {
+                                    value = value * 8 + (digit as u32 - '0' as u32);
+                                    chars.next();
+                                }
+                                _ => break,
+                            }
+                        }
+                        value as u8
+                    }
+                    other => {
+                        bytes.extend_from_slice(other.encode_utf8(&mut buffer).as_bytes());
+                        continue;
+                    }
+                };
+                bytes.push(byte);
+            }
+            c => bytes.extend_from_slice(c.encode_utf8(&mut buffer).as_bytes()),
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
@@ -0,0 +1,224 @@
+//! Format-independent representation of an extracted document, rendered by the output writers.
+
+use crate::metadata::Metadata;
+use crate::tables;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SectionKind {
+    /// A PDF page; text keeps the layout line breaks from the content stream
+    Page,
+    /// An EPUB content document or an XFA form; each line is a block of text
+    Chapter,
+}
+
+#[derive(Debug, Clone)]
+pub struct Section {
+    pub kind: SectionKind,
+    pub title: Option<String>,
+    pub text: String,
+    /// Language of the section when it differs from the document's
+    pub language: Option<String>,
+    /// Number of the PDF page the section holds, counting from 1
+    pub page: Option<usize>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Document {
+    pub metadata: Metadata,
+    pub sections: Vec<Section>,
+    /// Bookmarks into the sections, in reading order
+    pub outline: Vec<OutlineEntry>,
+    pub landmarks: Landmarks,
+}
+
+/// Where the book's own navigation places its main text, as section indexes.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Landmarks {
+    /// First section of the body
+    pub body_start: Option<usize>,
+    /// First section of the back matter
+    pub back_matter: Option<usize>,
+}
+
+#[derive(Debug, Clone)]
+pub struct OutlineEntry {
+    pub title: String,
+    /// Nesting depth, 1 for top-level entries
+    pub level: usize,
+    /// Index of the section (PDF page) the entry opens
+    pub section: usize,
+}
+
+impl Document {
+    /// Letters and digits in the text of every section, leaving out the metadata.
+    pub fn text_chars(&self) -> usize {
+        self.sections
+            .iter()
+            .flat_map(|section| section.text.chars())
+            .filter(|c| c.is_alphanumeric())
+            .count()
+    }
+
+    /// Words in the text of every section, as runs of characters between whitespace.
+    pub fn word_count(&self) -> usize {
+        self.sections
+            .iter()
+            .map(|section| section.text.split_whitespace().count())
+            .sum()
+    }
+
+    /// Keeps only the sections `keep` accepts. Bookmarks into a dropped section move to the
+    /// next section kept, or are dropped when none follows.
+    pub fn retain_sections(&mut self, mut keep: impl FnMut(&Section) -> bool) {
+        // New index of each old section, or of the next kept one for dropped sections
+        let mut new_index = Vec::with_capacity(self.sections.len());
+        let mut kept = 0;
+        let mut sections = Vec::with_capacity(self.sections.len());
+        for section in self.sections.drain(..) {
+            new_index.push(kept);
+            if keep(&section) {
+                sections.push(section);
+                kept += 1;
+            }
+        }
+        self.sections = sections;
+
+        let remap = |section: usize| {
+            let section = new_index.get(section).copied().unwrap_or(kept);
+            (section < kept).then_some(section)
+        };
+        self.outline.retain_mut(|entry| match remap(entry.section) {
+            Some(section) => {
+                entry.section = section;
+                true
+            }
+            None => false,
+        });
+        self.landmarks.body_start = self.landmarks.body_start.and_then(remap);
+        self.landmarks.back_matter = self.landmarks.back_matter.and_then(remap);
+    }
+}
+
+/// Opens and closes fenced code blocks, which are never reflowed.
+pub const CODE_FENCE: &str = "```";
+
+impl Section {
+    /// A copy of the section without the page furniture of PDF pages: lines that are only a
+    /// page number ("12", "Page 12", "- xii -") and form feeds.
+    pub fn without_page_furniture(&self) -> Section {
+        if self.kind != SectionKind::Page {
+            return self.clone();
+        }
+
+        let text = self
+            .text
+            .replace('\u{c}', "\n")
+            .lines()
+            .filter(|line| !is_page_number(line.trim()))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        Section {
+            text,
+            ..self.clone()
+        }
+    }
+
+    /// Splits the section into paragraphs, joining wrapped lines within a paragraph.
+    ///
+    /// Pages separate paragraphs with blank lines; chapters put one block per line. Fenced
+    /// code blocks are returned whole, fences included, with their lines untouched, and so
+    /// are Markdown and tab-separated tables.
+    pub fn paragraphs(&self) -> Vec<String> {
+        let mut paragraphs = Vec::new();
+        let mut prose = String::new();
+        let mut lines = self.text.lines().peekable();
+
+        while let Some(line) = lines.next() {
+            let row = |line: &&str| line.trim_start().starts_with('|');
+            if row(&line)
+                && lines
+                    .peek()
+                    .is_some_and(|next| tables::is_separator_row(next))
+            {
+                paragraphs.extend(self.prose_paragraphs(&prose));
+                prose.clear();
+
+                let mut table = vec![line.trim()];
+                while let Some(line) = lines.next_if(row) {
+                    table.push(line.trim());
+                }
+                paragraphs.push(table.join("\n"));
+                continue;
+            }
+
+            if tables::is_tsv_row(line) {
+                paragraphs.extend(self.prose_paragraphs(&prose));
+                prose.clear();
+
+                // Leading tabs are empty cells
+                let mut table = vec![line];
+                while let Some(line) = lines.next_if(|line| tables::is_tsv_row(line)) {
+                    table.push(line);
+                }
+                paragraphs.push(table.join("\n"));
+                continue;
+            }
+
+            if !line.trim_start().starts_with(CODE_FENCE) {
+                prose.push_str(line);
+                prose.push('\n');
+                continue;
+            }
+
+            paragraphs.extend(self.prose_paragraphs(&prose));
+            prose.clear();
+
+            let mut block = vec![line.trim()];
+            for line in lines.by_ref() {
+                block.push(line);
+                if line.trim_start().starts_with(CODE_FENCE) {
+                    break;
+                }
+            }
+            paragraphs.push(block.join("\n"));
+        }
+
+        paragraphs.extend(self.prose_paragraphs(&prose));
+        paragraphs
+    }
+
+    fn prose_paragraphs(&self, text: &str) -> Vec<String> {
+        match self.kind {
+            SectionKind::Page => text
+                .split("\n\n")
+                .map(|paragraph| {
+                    paragraph
+                        .lines()
+                        .map(str::trim)
+                        .filter(|line| !line.is_empty())
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                })
+                .filter(|paragraph| !paragraph.is_empty())
+                .collect(),
+            SectionKind::Chapter => text
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty())
+                .map(str::to_string)
+                .collect(),
+        }
+    }
+}
+
+fn is_page_number(line: &str) -> bool {
+    let line = line
+        .strip_prefix("Page ")
+        .or_else(|| line.strip_prefix("page "))
+        .unwrap_or(line)
+        .trim_matches(|c: char| c == '-' || c == '–' || c.is_whitespace());
+    !line.is_empty()
+        && (line.chars().all(|c| c.is_ascii_digit())
+            || (line.len() <= 6 && line.chars().all(|c| "ivxlcdm".contains(c))))
+}
@@ -0,0 +1,639 @@
+//! Word documents (`.docx`): WordprocessingML in a zip package.
+//!
+//! The text is read from `word/document.xml`, one line per paragraph. Paragraphs whose style
+//! is a heading (by the style's outline level or its built-in name, so localised style names
+//! work too) start sections and bookmarks, list items get `- ` or `1. ` markers from the
+//! numbering definitions, and tables are laid out like HTML tables. Title, author and
+//! language come from the package's core properties.
+
+use anyhow::{Context, Result};
+use roxmltree::Node;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use zip::ZipArchive;
+
+use crate::document::{Document, Landmarks, OutlineEntry, Section, SectionKind};
+use crate::extract::HtmlOptions;
+use crate::figures::{self, IMAGE};
+use crate::metadata::Metadata;
+use crate::scripts::{self, Script, ScriptMode};
+use crate::tables;
+use crate::xml::{attribute, child, elements, text};
+
+const DOCUMENT_XML: &str = "word/document.xml";
+const STYLES_XML: &str = "word/styles.xml";
+const NUMBERING_XML: &str = "word/numbering.xml";
+const CORE_XML: &str = "docProps/core.xml";
+
+pub fn extract(path: &Path, metadata: Option<&Metadata>, options: HtmlOptions) -> Result<Document> {
+    let file = File::open(path).context(format!("Failed to open: {}", path.display()))?;
+    let mut archive =
+        ZipArchive::new(file).context(format!("Not a Word document: {}", path.display()))?;
+
+    let document = entry(&mut archive, DOCUMENT_XML)?
+        .context(format!("No {DOCUMENT_XML} in {}", path.display()))?;
+    let document = roxmltree::Document::parse(&document).context(format!(
+        "Failed to parse {DOCUMENT_XML}: {}",
+        path.display()
+    ))?;
+    // Styles, numbering and properties only refine the text; a broken part is ignored
+    let styles =
+        entry(&mut archive, STYLES_XML)?.map_or_else(HashMap::new, |xml| heading_styles(&xml));
+    let numbering =
+        entry(&mut archive, NUMBERING_XML)?.map_or_else(HashMap::new, |xml| list_formats(&xml));
+    let embedded = entry(&mut archive, CORE_XML)?
+        .map(|xml| core_properties(&xml))
+        .unwrap_or_default();
+
+    let mut converter = Converter {
+        options,
+        styles,
+        numbering,
+        counters: HashMap::new(),
+        sections: vec![Part::default()],
+    };
+    if let Some(body) = document
+        .root_element()
+        .children()
+        .find(|node| node.has_tag_name("body"))
+    {
+        converter.blocks(body);
+    }
+
+    // The outermost heading level used becomes level 1
+    let top = converter
+        .sections
+        .iter()
+        .filter_map(|part| part.heading.as_ref().map(|(level, _)| *level))
+        .min()
+        .unwrap_or(1);
+    let mut outline = Vec::new();
+    let mut sections = Vec::new();
+    for part in converter.sections {
+        let mut lines = part.lines;
+        if let Some((level, title)) = part.heading {
+            outline.push(OutlineEntry {
+                title: title.clone(),
+                level: level - top + 1,
+                section: sections.len(),
+            });
+            lines.insert(0, title);
+        }
+        if lines.is_empty() {
+            continue;
+        }
+        let text = lines.join("\n");
+        sections.push(Section {
+            kind: SectionKind::Chapter,
+            title: None,
+            text: if options.image_placeholders {
+                figures::label(&text, false)
+            } else {
+                text
+            },
+            language: None,
+            page: None,
+        });
+    }
+
+    Ok(Document {
+        metadata: metadata.cloned().unwrap_or_default().or(embedded),
+        sections,
+        outline,
+        landmarks: Landmarks::default(),
+    })
+}
+
+/// The text of a package part, or `None` when the package does not have it.
+fn entry(archive: &mut ZipArchive<File>, name: &str) -> Result<Option<String>> {
+    let Ok(mut entry) = archive.by_name(name) else {
+        return Ok(None);
+    };
+    let mut xml = String::new();
+    entry
+        .read_to_string(&mut xml)
+        .context(format!("Failed to read {name}"))?;
+    Ok(Some(xml))
+}
+
+/// Heading level of each paragraph style that has one: its outline level, or the level in
+/// its built-in name ("heading 2"), inherited through `basedOn`.
+fn heading_styles(xml: &str) -> HashMap<String, usize> {
+    let Ok(doc) = roxmltree::Document::parse(xml) else {
+        return HashMap::new();
+    };
+    let mut own = HashMap::new();
+    let mut based_on = HashMap::new();
+    for style in elements(doc.root_element(), "style") {
+        if attribute(style, "type") != Some("paragraph") {
+            continue;
+        }
+        let Some(id) = attribute(style, "styleId") else {
+            continue;
+        };
+        let name = child(style, "name")
+            .and_then(|name| attribute(name, "val"))
+            .unwrap_or_default()
+            .to_lowercase();
+        let level = child(style, "pPr")
+            .and_then(outline_level)
+            .or_else(|| name.strip_prefix("heading ")?.parse().ok())
+            .or_else(|| (name == "title").then_some(1));
+        if let Some(level) = level {
+            own.insert(id.to_string(), level);
+        }
+        if let Some(parent) = child(style, "basedOn").and_then(|b| attribute(b, "val")) {
+            based_on.insert(id.to_string(), parent.to_string());
+        }
+    }
+
+    let mut levels = own.clone();
+    for id in based_on.keys() {
+        let mut at = id;
+        // Chains are short; the bound stops a cycle in a broken file
+        for _ in 0..16 {
+            if let Some(level) = own.get(at) {
+                levels.entry(id.clone()).or_insert(*level);
+                break;
+            }
+            match based_on.get(at) {
+                Some(parent) => at = parent,
+                None => break,
+            }
+        }
+    }
+    levels
+}
+
+/// Whether each list level is numbered, by numbering instance and level.
+fn list_formats(xml: &str) -> HashMap<(String, String), bool> {
+    let Ok(doc) = roxmltree::Document::parse(xml) else {
+        return HashMap::new();
+    };
+    let root = doc.root_element();
+    let mut abstract_levels: HashMap<&str, Vec<(String, bool)>> = HashMap::new();
+    for definition in elements(root, "abstractNum") {
+        let Some(id) = attribute(definition, "abstractNumId") else {
+            continue;
+        };
+        let levels = elements(definition, "lvl")
+            .filter_map(|level| {
+                let format = child(level, "numFmt").and_then(|f| attribute(f, "val"))?;
+                Some((
+                    attribute(level, "ilvl")?.to_string(),
+                    !matches!(format, "bullet" | "none"),
+                ))
+            })
+            .collect();
+        abstract_levels.insert(id, levels);
+    }
+
+    let mut formats = HashMap::new();
+    for instance in elements(root, "num") {
+        let (Some(id), Some(definition)) = (
+            attribute(instance, "numId"),
+            child(instance, "abstractNumId").and_then(|a| attribute(a, "val")),
+        ) else {
+            continue;
+        };
+        for (level, numbered) in abstract_levels.get(definition).into_iter().flatten() {
+            formats.insert((id.to_string(), level.clone()), *numbered);
+        }
+    }
+    formats
+}
+
+/// Title, author and language from `docProps/core.xml`.
+fn core_properties(xml: &str) -> Metadata {
+    let Ok(doc) = roxmltree::Document::parse(xml) else {
+        return Metadata::default();
+    };
+    let field = |name: &str| {
+        child(doc.root_element(), name)
+            .map(text)
+            .filter(|value| !value.is_empty())
+    };
+    Metadata {
+        title: field("title"),
+        author: field("creator"),
+        language: field("language"),
+        collection: None,
+    }
+}
+
+/// A heading and the lines under it, up to the next heading.
+#[derive(Default)]
+struct Part {
+    heading: Option<(usize, String)>,
+    lines: Vec<String>,
+}
+
+struct Converter {
+    options: HtmlOptions,
+    /// Heading level of each heading paragraph style
+    styles: HashMap<String, usize>,
+    /// Whether each list level is numbered, by numbering instance and level
+    numbering: HashMap<(String, String), bool>,
+    /// Last number given to each numbered list level
+    counters: HashMap<(String, String), usize>,
+    sections: Vec<Part>,
+}
+
+impl Converter {
+    fn lines(&mut self) -> &mut Vec<String> {
+        &mut self
+            .sections
+            .last_mut()
+            .expect("there is always a section")
+            .lines
+    }
+
+    /// Converts the paragraphs and tables of the body or a container within it.
+    fn blocks(&mut self, parent: Node) {
+        for node in parent.children().filter(Node::is_element) {
+            match node.tag_name().name() {
+                "p" => self.paragraph(node),
+                "tbl" => self.table(node),
+                // Content controls and custom XML wrap ordinary paragraphs
+                "sdt" | "sdtContent" | "customXml" => self.blocks(node),
+                _ => {}
+            }
+        }
+    }
+
+    fn paragraph(&mut self, paragraph: Node) {
+        let properties = child(paragraph, "pPr");
+        let text = self.inline(paragraph);
+
+        let level = properties.and_then(|p| {
+            outline_level(p).or_else(|| {
+                let style = child(p, "pStyle").and_then(|s| attribute(s, "val"))?;
+                self.styles.get(style).copied()
+            })
+        });
+        if let Some(level) = level.filter(|_| !text.is_empty()) {
+            self.sections.push(Part {
+                heading: Some((level, text)),
+                lines: Vec::new(),
+            });
+            return;
+        }
+
+        let marker = properties
+            .and_then(|p| child(p, "numPr"))
+            .and_then(|numbering| self.list_marker(numbering));
+        if !text.is_empty() {
+            let line = match marker {
+                Some(marker) => marker + &text,
+                None => text,
+            };
+            self.lines().push(line);
+        }
+        if self.options.image_placeholders {
+            for drawing in paragraph
+                .descendants()
+                .filter(|node| node.has_tag_name("docPr"))
+            {
+                let description = attribute(drawing, "descr")
+                    .or(attribute(drawing, "title"))
+                    .unwrap_or_default();
+                let line = format!("{IMAGE}{description}{IMAGE}");
+                self.lines().push(line);
+            }
+        }
+    }
+
+    /// `- ` for bulleted list items, `1. ` and on for numbered ones. Numbering restarts
+    /// below a level when an item of that level comes up again.
+    fn list_marker(&mut self, numbering: Node) -> Option<String> {
+        let id = child(numbering, "numId").and_then(|n| attribute(n, "val"))?;
+        // Instance 0 takes a paragraph out of a list its style put it in
+        if id == "0" {
+            return None;
+        }
+        let level = child(numbering, "ilvl")
+            .and_then(|l| attribute(l, "val"))
+            .unwrap_or("0");
+        let key = (id.to_string(), level.to_string());
+        if !self.numbering.get(&key).copied().unwrap_or(false) {
+            return Some("- ".to_string());
+        }
+
+        let depth: usize = level.parse().unwrap_or(0);
+        self.counters
+            .retain(|(list, other), _| list != id || other.parse::<usize>().unwrap_or(0) <= depth);
+        let counter = self.counters.entry(key).or_insert(0);
+        *counter += 1;
+        Some(format!("{counter}. "))
+    }
+
+    fn table(&mut self, table: Node) {
+        let rows: Vec<Vec<String>> = elements(table, "tr")
+            .map(|row| {
+                elements(row, "tc")
+                    .map(|cell| {
+                        elements(cell, "p")
+                            .map(|p| self.inline(p))
+                            .filter(|text| !text.is_empty())
+                            .collect::<Vec<_>>()
+                            .join(" ")
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .filter(|row| row.iter().any(|cell| !cell.is_empty()))
+            .collect();
+        if rows.is_empty() {
+            return;
+        }
+
+        if self.options.tables {
+            let table = tables::markdown(&rows);
+            self.lines().push(table);
+        } else {
+            let lines: Vec<String> = rows.into_iter().map(|row| row.join(" | ")).collect();
+            self.lines().extend(lines);
+        }
+    }
+
+    /// The text of a paragraph's runs on one line, with whitespace collapsed.
+    fn inline(&self, paragraph: Node) -> String {
+        let mut text = String::new();
+        self.append_runs(paragraph, &mut text);
+        text.split_whitespace().collect::<Vec<_>>().join(" ")
+    }
+
+    fn append_runs(&self, parent: Node, out: &mut String) {
+        for node in parent.children().filter(Node::is_element) {
+            match node.tag_name().name() {
+                "r" => self.append_run(node, out),
+                // Links, tracked insertions, fields and content controls hold runs
+                "hyperlink" | "ins" | "smartTag" | "fldSimple" | "sdt" | "sdtContent"
+                | "customXml" => self.append_runs(node, out),
+                // Tracked deletions and everything else print nothing
+                _ => {}
+            }
+        }
+    }
+
+    fn append_run(&self, run: Node, out: &mut String) {
+        let mut text = String::new();
+        for node in run.children().filter(Node::is_element) {
+            match node.tag_name().name() {
+                "t" => text.push_str(node.text().unwrap_or_default()),
+                "tab" | "br" | "cr" => text.push(' '),
+                "noBreakHyphen" => text.push('-'),
+                _ => {}
+            }
+        }
+
+        let script = child(run, "rPr")
+            .and_then(|p| child(p, "vertAlign"))
+            .and_then(|v| match attribute(v, "val") {
+                Some("superscript") => Some(Script::Super),
+                Some("subscript") => Some(Script::Sub),
+                _ => None,
+            });
+        match script {
+            Some(script) if self.options.scripts != ScriptMode::Keep => {
+                let follows_digit = out.chars().last().is_some_and(|c| c.is_ascii_digit());
+                out.push_str(&scripts::render(
+                    text.trim(),
+                    script,
+                    self.options.scripts,
+                    follows_digit,
+                ));
+            }
+            _ => out.push_str(&text),
+        }
+    }
+}
+
+/// The heading level a paragraph's `outlineLvl` gives it; level 9 is body text.
+fn outline_level(properties: Node) -> Option<usize> {
+    let level: usize = child(properties, "outlineLvl")
+        .and_then(|l| attribute(l, "val"))?
+        .parse()
+        .ok()?;
+    (level < 9).then_some(level + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use zip::write::SimpleFileOptions;
+    use zip::ZipWriter;
+
+    const W: &str = r#"xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main""#;
+
+    /// Writes a package of the given parts and extracts it.
+    fn convert(parts: &[(&str, &str)], options: HtmlOptions) -> Result<Document> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.docx");
+        let mut zip = ZipWriter::new(File::create(&path).unwrap());
+        for (name, data) in parts {
+            zip.start_file(*name, SimpleFileOptions::default()).unwrap();
+            zip.write_all(data.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap();
+        extract(&path, None, options)
+    }
+
+    fn document(body: &str) -> String {
+        format!(r#"<w:document {W}><w:body>{body}</w:body></w:document>"#)
+    }
+
+    /// A paragraph of one run, with the given paragraph properties.
+    fn p(properties: &str, text: &str) -> String {
+        format!(r#"<w:p><w:pPr>{properties}</w:pPr><w:r><w:t>{text}</w:t></w:r></w:p>"#)
+    }
+
+    fn text_of(doc: &Document) -> String {
+        doc.sections
+            .iter()
+            .map(|section| section.text.as_str())
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+
+    #[test]
+    fn heading_styles_by_name_outline_level_and_inheritance() {
+        let styles = format!(
+            r#"<w:styles {W}>
+              <w:style w:type="paragraph" w:styleId="Titre1"><w:name w:val="heading 1"/></w:style>
+              <w:style w:type="paragraph" w:styleId="Custom"><w:name w:val="Chapter"/><w:pPr><w:outlineLvl w:val="1"/></w:pPr></w:style>
+              <w:style w:type="paragraph" w:styleId="Derived"><w:name w:val="Derived"/><w:basedOn w:val="Custom"/></w:style>
+              <w:style w:type="paragraph" w:styleId="LoopA"><w:name w:val="A"/><w:basedOn w:val="LoopB"/></w:style>
+              <w:style w:type="paragraph" w:styleId="LoopB"><w:name w:val="B"/><w:basedOn w:val="LoopA"/></w:style>
+              <w:style w:type="character" w:styleId="Heading9Char"><w:name w:val="heading 9"/></w:style>
+            </w:styles>"#
+        );
+        let levels = heading_styles(&styles);
+        assert_eq!(levels.get("Titre1"), Some(&1));
+        assert_eq!(levels.get("Custom"), Some(&2));
+        assert_eq!(levels.get("Derived"), Some(&2));
+        assert_eq!(levels.get("LoopA"), None);
+        assert_eq!(levels.get("Heading9Char"), None);
+        assert!(heading_styles("<w:styles").is_empty());
+
+        let body = [
+            p("", "Preface."),
+            p(r#"<w:pStyle w:val="Titre1"/>"#, "Part"),
+            p(r#"<w:pStyle w:val="Derived"/>"#, "Chapter"),
+            p("", "Text."),
+            p(r#"<w:outlineLvl w:val="9"/>"#, "Body text"),
+            p(r#"<w:pStyle w:val="Titre1"/>"#, " "),
+        ]
+        .concat();
+        let doc = convert(
+            &[(DOCUMENT_XML, &document(&body)), (STYLES_XML, &styles)],
+            HtmlOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            text_of(&doc),
+            "Preface.\n\nPart\n\nChapter\nText.\nBody text"
+        );
+        let outline: Vec<_> = doc
+            .outline
+            .iter()
+            .map(|entry| (entry.title.as_str(), entry.level, entry.section))
+            .collect();
+        assert_eq!(outline, [("Part", 1, 1), ("Chapter", 2, 2)]);
+    }
+
+    #[test]
+    fn lists_from_numbering_definitions() {
+        let numbering = format!(
+            r#"<w:numbering {W}>
+              <w:abstractNum w:abstractNumId="0">
+                <w:lvl w:ilvl="0"><w:numFmt w:val="decimal"/></w:lvl>
+                <w:lvl w:ilvl="1"><w:numFmt w:val="lowerLetter"/></w:lvl>
+              </w:abstractNum>
+              <w:abstractNum w:abstractNumId="1"><w:lvl w:ilvl="0"><w:numFmt w:val="bullet"/></w:lvl></w:abstractNum>
+              <w:num w:numId="1"><w:abstractNumId w:val="0"/></w:num>
+              <w:num w:numId="2"><w:abstractNumId w:val="1"/></w:num>
+            </w:numbering>"#
+        );
+        let item = |id: &str, level: &str, text: &str| {
+            p(
+                &format!(r#"<w:numPr><w:ilvl w:val="{level}"/><w:numId w:val="{id}"/></w:numPr>"#),
+                text,
+            )
+        };
+        let body = [
+            item("1", "0", "One"),
+            item("1", "1", "Sub"),
+            item("1", "1", "Sub"),
+            item("1", "0", "Two"),
+            item("1", "1", "Again"),
+            item("2", "0", "Bullet"),
+            item("0", "0", "Out of the list"),
+            item("7", "0", "Unknown list"),
+        ]
+        .concat();
+        let doc = convert(
+            &[
+                (DOCUMENT_XML, &document(&body)),
+                (NUMBERING_XML, &numbering),
+            ],
+            HtmlOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            text_of(&doc),
+            "1. One\n1. Sub\n2. Sub\n2. Two\n1. Again\n- Bullet\nOut of the list\n- Unknown list"
+        );
+    }
+
+    #[test]
+    fn runs_tables_and_hidden_content() {
+        let body = r#"<w:sdt><w:sdtContent><w:p><w:r><w:t>In a control</w:t></w:r></w:p></w:sdtContent></w:sdt>
+            <w:p>
+              <w:r><w:t xml:space="preserve">Kept </w:t></w:r>
+              <w:del><w:r><w:delText>deleted</w:delText></w:r></w:del>
+              <w:ins><w:r><w:t xml:space="preserve">inserted </w:t></w:r></w:ins>
+              <w:hyperlink><w:r><w:t>link</w:t><w:tab/><w:t>well</w:t><w:noBreakHyphen/><w:t>known</w:t></w:r></w:hyperlink>
+              <w:r><w:instrText>PAGE</w:instrText></w:r>
+            </w:p>
+            <w:tbl>
+              <w:tr><w:tc><w:p><w:r><w:t>A</w:t></w:r></w:p></w:tc><w:tc><w:p><w:r><w:t>B</w:t></w:r></w:p></w:tc></w:tr>
+              <w:tr><w:tc><w:p/></w:tc><w:tc><w:p/></w:tc></w:tr>
+              <w:tr><w:tc><w:p><w:r><w:t>1</w:t></w:r></w:p><w:p><w:r><w:t>more</w:t></w:r></w:p></w:tc><w:tc/></w:tr>
+            </w:tbl>"#;
+        let doc = convert(&[(DOCUMENT_XML, &document(body))], HtmlOptions::default()).unwrap();
+        assert_eq!(
+            text_of(&doc),
+            "In a control\nKept inserted link well-known\nA | B\n1 more | "
+        );
+    }
+
+    #[test]
+    fn raised_runs_follow_the_script_mode() {
+        let body = r#"<w:p><w:r><w:t>x</w:t></w:r><w:r><w:rPr><w:vertAlign w:val="superscript"/></w:rPr><w:t>2</w:t></w:r>
+            <w:r><w:t xml:space="preserve"> H</w:t></w:r><w:r><w:rPr><w:vertAlign w:val="subscript"/></w:rPr><w:t>2</w:t></w:r><w:r><w:t>O</w:t></w:r></w:p>"#;
+        let body = document(body);
+        let parts = [(DOCUMENT_XML, body.as_str())];
+        assert_eq!(
+            text_of(&convert(&parts, HtmlOptions::default()).unwrap()),
+            "x2 H2O"
+        );
+        let options = HtmlOptions {
+            scripts: ScriptMode::Plain,
+            ..Default::default()
+        };
+        assert_eq!(text_of(&convert(&parts, options).unwrap()), "x^2 H_2O");
+    }
+
+    #[test]
+    fn metadata_from_core_properties() {
+        let core = r#"<cp:coreProperties xmlns:cp="http://schemas.openxmlformats.org/package/2006/metadata/core-properties" xmlns:dc="http://purl.org/dc/elements/1.1/">
+            <dc:title>Emma</dc:title><dc:creator>Jane Austen</dc:creator><dc:language></dc:language></cp:coreProperties>"#;
+        let body = document(&p("", "Text"));
+        let doc = convert(
+            &[(DOCUMENT_XML, &body), (CORE_XML, core)],
+            HtmlOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(doc.metadata.title.as_deref(), Some("Emma"));
+        assert_eq!(doc.metadata.author.as_deref(), Some("Jane Austen"));
+        assert_eq!(doc.metadata.language, None);
+    }
+
+    #[test]
+    fn malformed_packages() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("plain.docx");
+        std::fs::write(&path, "not a zip").unwrap();
+        let error = extract(&path, None, HtmlOptions::default()).unwrap_err();
+        assert!(error.to_string().starts_with("Not a Word document"));
+
+        let error = convert(&[(CORE_XML, "<x/>")], HtmlOptions::default()).unwrap_err();
+        assert!(error.to_string().starts_with("No word/document.xml"));
+        let error = convert(&[(DOCUMENT_XML, "<w:document")], HtmlOptions::default()).unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("Failed to parse word/document.xml"));
+
+        // Broken styles, numbering and properties only lose what they would have added
+        let body = document(&p(
+            r#"<w:pStyle w:val="Heading1"/><w:numPr><w:ilvl w:val="0"/><w:numId w:val="1"/></w:numPr>"#,
+            "Kept",
+        ));
+        let doc = convert(
+            &[
+                (DOCUMENT_XML, &body),
+                (STYLES_XML, "<w:styles"),
+                (NUMBERING_XML, "&"),
+                (CORE_XML, "<<"),
+            ],
+            HtmlOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(text_of(&doc), "- Kept");
+        assert!(doc.outline.is_empty());
+        assert_eq!(doc.metadata.title, None);
+    }
+}
@@ -0,0 +1,346 @@
+//! Text of HTML and XHTML content documents, read from the tree an HTML5 parser builds, so
+//! comments, character references and attributes holding `>` come out the way a browser
+//! would show them.
+//!
+//! XHTML is parsed as HTML, which does not know two of its habits: self-closing elements and
+//! CDATA sections. Both are rewritten first where the HTML reading would lose text: a
+//! self-closed `<script/>` or `<title/>` would otherwise swallow the rest of the document, and
+//! a CDATA section outside SVG or MathML would be dropped as a comment.
+
+use ego_tree::iter::Edge;
+use scraper::node::Node;
+use scraper::{ElementRef, Html};
+
+use crate::document::CODE_FENCE;
+use crate::extract::HtmlOptions;
+use crate::figures::{self, IMAGE};
+use crate::scripts::{self, Script, ScriptMode};
+use crate::tables;
+
+/// Elements whose content is never text of the document.
+const SKIPPED_ELEMENTS: &[&str] = &["head", "noscript", "script", "style", "template"];
+
+/// Elements whose content the parser reads as raw text up to their end tag.
+const RAW_TEXT_ELEMENTS: &[&str] = &[
+    "iframe", "noembed", "noframes", "noscript", "script", "style", "textarea", "title", "xmp",
+];
+
+/// Elements that sit on lines of their own, for markup written without line breaks.
+const BLOCK_ELEMENTS: &[&str] = &[
+    "p",
+    "div",
+    "br",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "li",
+    "dt",
+    "dd",
+    "tr",
+    "table",
+    "ul",
+    "ol",
+    "dl",
+    "blockquote",
+    "section",
+    "article",
+    "header",
+    "footer",
+    "aside",
+    "nav",
+    "figure",
+    "figcaption",
+    "hr",
+    "pre",
+    "main",
+    "address",
+    "caption",
+];
+
+/// The text of an HTML document, one line per block element, with whitespace inside `<pre>`
+/// kept and elsewhere collapsed.
+pub fn text(html: &str, options: HtmlOptions) -> String {
+    let html = expand_raw_text_elements(&escape_cdata(html));
+    let document = Html::parse_document(&html);
+    let mut walker = Walker {
+        options,
+        result: String::new(),
+        offset: None,
+        pending: String::new(),
+        pre: 0,
+    };
+    // The element whose subtree is being skipped
+    let mut skip = None;
+    for edge in document.tree.root().traverse() {
+        match edge {
+            Edge::Open(node) if skip.is_none() => match node.value() {
+                Node::Text(text) => walker.text(text),
+                Node::Element(element) => {
+                    let name = element.name();
+                    let skipped = SKIPPED_ELEMENTS.contains(&name)
+                        || ElementRef::wrap(node).is_some_and(|e| walker.replace(e, name));
+                    if skipped {
+                        skip = Some(node.id());
+                    } else {
+                        walker.open(name);
+                    }
+                }
+                _ => {}
+            },
+            Edge::Close(node) if skip == Some(node.id()) => skip = None,
+            Edge::Close(node) if skip.is_none() => {
+                if let Node::Element(element) = node.value() {
+                    walker.close(element.name());
+                }
+            }
+            _ => {}
+        }
+    }
+    walker.result
+}
+
+struct Walker {
+    options: HtmlOptions,
+    result: String,
+    /// Text inside `<sup>`/`<sub>`, rendered as a whole when the element closes
+    offset: Option<(Script, String)>,
+    /// Markdown that opens an element, written once its first text arrives
+    pending: String,
+    /// Depth of `<pre>` elements around the current node
+    pre: usize,
+}
+
+impl Walker {
+    /// Writes the lines standing in for a table or an image, when those are laid out,
+    /// returning whether it did.
+    fn replace(&mut self, element: ElementRef, name: &str) -> bool {
+        if self.offset.is_some() {
+            return false;
+        }
+        let line = match name {
+            "table" if self.options.tables => tables::html_table(element),
+            "figure" | "img" | "image" if self.options.image_placeholders => {
+                let description = figures::html_description(element);
+                Some(format!("{IMAGE}{description}{IMAGE}"))
+            }
+            _ => None,
+        };
+        let Some(line) = line else {
+            return false;
+        };
+        self.result.push('\n');
+        self.result.push_str(&line);
+        self.result.push('\n');
+        true
+    }
+
+    fn text(&mut self, text: &str) {
+        for ch in text.chars() {
+            let target = match &mut self.offset {
+                Some((_, text)) => text,
+                None => &mut self.result,
+            };
+            if self.pre == 0 && ch.is_ascii_whitespace() {
+                if !target.ends_with(|c: char| c.is_ascii_whitespace()) && !target.is_empty() {
+                    target.push(' ');
+                }
+                continue;
+            }
+            if !self.pending.is_empty() && !ch.is_whitespace() {
+                target.push_str(&self.pending);
+                self.pending.clear();
+            }
+            target.push(ch);
+        }
+    }
+
+    fn open(&mut self, name: &str) {
+        if self.offset.is_none() {
+            if BLOCK_ELEMENTS.contains(&name) {
+                self.result.push('\n');
+            } else if name == "td" || name == "th" {
+                self.result.push(' ');
+            }
+            if self.options.markup {
+                self.open_markup(name);
+            }
+        }
+        if self.options.scripts != ScriptMode::Keep {
+            match name {
+                "sup" => self.offset = Some((Script::Super, String::new())),
+                "sub" => self.offset = Some((Script::Sub, String::new())),
+                _ => {}
+            }
+        }
+        if name == "pre" {
+            self.pre += 1;
+            if self.options.fence_code {
+                self.fence();
+            }
+        }
+    }
+
+    fn close(&mut self, name: &str) {
+        if self.offset.is_none() {
+            if BLOCK_ELEMENTS.contains(&name) {
+                self.result.push('\n');
+            }
+            if self.options.markup {
+                self.close_markup(name);
+            }
+        }
+        if matches!(name, "sup" | "sub") {
+            if let Some((script, text)) = self.offset.take() {
+                let follows_digit = self.result.ends_with(|ch: char| ch.is_ascii_digit());
+                self.result.push_str(&scripts::render(
+                    text.trim(),
+                    script,
+                    self.options.scripts,
+                    follows_digit,
+                ));
+            }
+        }
+        if name == "pre" {
+            self.pre -= 1;
+            if self.options.fence_code {
+                self.fence();
+            }
+        }
+    }
+
+    /// Fences `<pre>` blocks so their whitespace survives clean-up.
+    fn fence(&mut self) {
+        self.result.push('\n');
+        self.result.push_str(CODE_FENCE);
+        self.result.push('\n');
+    }
+
+    /// Marks headings with `#`, list items with `-` and emphasis with `*` or `**`. Opening
+    /// marks wait in `pending` for the element's text, so empty elements leave none behind
+    /// and the marks hug the words they enclose.
+    fn open_markup(&mut self, name: &str) {
+        match name {
+            "li" => self.pending.push_str("- "),
+            _ => {
+                if let Some(emphasis) = emphasis(name) {
+                    self.pending.push_str(emphasis);
+                } else if let Some(level) = heading_level(name) {
+                    self.pending.push_str(&"#".repeat(level));
+                    self.pending.push(' ');
+                }
+            }
+        }
+    }
+
+    fn close_markup(&mut self, name: &str) {
+        let Some(emphasis) = emphasis(name) else {
+            if BLOCK_ELEMENTS.contains(&name) {
+                self.pending.clear();
+            }
+            return;
+        };
+        if self.pending.ends_with(emphasis) {
+            // Nothing was emphasised
+            self.pending.truncate(self.pending.len() - emphasis.len());
+        } else {
+            let text = self.result.trim_end().len();
+            let space = self.result.split_off(text);
+            self.result.push_str(emphasis);
+            self.result.push_str(&space);
+        }
+    }
+}
+
+fn emphasis(name: &str) -> Option<&'static str> {
+    match name {
+        "em" | "i" => Some("*"),
+        "strong" | "b" => Some("**"),
+        _ => None,
+    }
+}
+
+fn heading_level(name: &str) -> Option<usize> {
+    let level = name.strip_prefix('h')?.parse::<usize>().ok()?;
+    (1..=6).contains(&level).then_some(level)
+}
+
+/// The text of an element with its whitespace collapsed, for table cells and captions.
+pub fn collapsed_text(element: ElementRef) -> String {
+    element
+        .text()
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Replaces CDATA sections with their content as escaped text.
+fn escape_cdata(html: &str) -> String {
+    let mut out = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(start) = rest.find("<![CDATA[") {
+        out.push_str(&rest[..start]);
+        let content = &rest[start + "<![CDATA[".len()..];
+        let end = content.find("]]>").unwrap_or(content.len());
+        out.push_str(
+            &content[..end]
+                .replace('&', "&amp;")
+                .replace('<', "&lt;")
+                .replace('>', "&gt;"),
+        );
+        rest = content.get(end + "]]>".len()..).unwrap_or_default();
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Gives self-closed raw-text elements (`<script src="a.js"/>`) an end tag.
+fn expand_raw_text_elements(html: &str) -> String {
+    let mut out = String::with_capacity(html.len());
+    let mut copied = 0;
+    let mut from = 0;
+    while let Some(at) = html[from..].find('<') {
+        let start = from + at;
+        if !html[start + 1..].starts_with(|c: char| c.is_ascii_alphabetic()) {
+            from = start + 1;
+            continue;
+        }
+        let Some(end) = tag_end(html, start) else {
+            break;
+        };
+        from = end;
+        let name: String = html[start + 1..end]
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric())
+            .collect();
+        let name = name.to_ascii_lowercase();
+        if !html[..end].ends_with("/>") || !RAW_TEXT_ELEMENTS.contains(&name.as_str()) {
+            continue;
+        }
+        out.push_str(&html[copied..end - 2]);
+        out.push_str("></");
+        out.push_str(&name);
+        out.push('>');
+        copied = end;
+    }
+    out.push_str(&html[copied..]);
+    out
+}
+
+/// The offset just past the `>` closing the tag that starts at `start`, skipping any inside
+/// quoted attribute values.
+fn tag_end(html: &str, start: usize) -> Option<usize> {
+    let mut quote = None;
+    for (at, ch) in html[start..].char_indices() {
+        match (ch, quote) {
+            ('"' | '\'', None) => quote = Some(ch),
+            (c, Some(q)) if c == q => quote = None,
+            ('>', None) => return Some(start + at + 1),
+            _ => {}
+        }
+    }
+    None
+}
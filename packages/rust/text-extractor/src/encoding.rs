@@ -0,0 +1,165 @@
+//! Writing outputs in encodings other than UTF-8, for downstream tools that only read a
+//! legacy code page or UTF-16.
+//!
+//! Characters the encoding has no code for are written as `?` and counted, so a run can
+//! report the files that lost text on the way out.
+
+use encoding_rs::{EncoderResult, Encoding};
+
+/// Written in place of a character the output encoding cannot represent.
+const REPLACEMENT: u8 = b'?';
+
+/// The character encoding outputs are written in.
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputEncoding {
+    /// UTF-8 without a byte order mark
+    #[default]
+    #[cfg_attr(feature = "cli", value(name = "utf-8", alias = "utf8"))]
+    Utf8,
+    /// UTF-8 after a byte order mark, which some Windows tools need to tell it from ANSI
+    #[cfg_attr(feature = "cli", value(name = "utf-8-bom", alias = "utf8-bom"))]
+    Utf8Bom,
+    /// Little-endian UTF-16 after a byte order mark
+    #[cfg_attr(feature = "cli", value(name = "utf-16le", alias = "utf-16"))]
+    Utf16Le,
+    /// Big-endian UTF-16 after a byte order mark
+    #[cfg_attr(feature = "cli", value(name = "utf-16be"))]
+    Utf16Be,
+    /// Japanese, as Windows writes it (code page 932)
+    #[cfg_attr(
+        feature = "cli",
+        value(name = "shift_jis", alias = "sjis", alias = "cp932")
+    )]
+    ShiftJis,
+    /// Japanese, as Unix tools write it
+    #[cfg_attr(feature = "cli", value(name = "euc-jp"))]
+    EucJp,
+    /// Simplified Chinese (code page 936)
+    #[cfg_attr(feature = "cli", value(name = "gbk", alias = "cp936"))]
+    Gbk,
+    /// Simplified Chinese, covering all of Unicode
+    #[cfg_attr(feature = "cli", value(name = "gb18030"))]
+    Gb18030,
+    /// Traditional Chinese (code page 950)
+    #[cfg_attr(feature = "cli", value(name = "big5", alias = "cp950"))]
+    Big5,
+    /// Korean (code page 949)
+    #[cfg_attr(feature = "cli", value(name = "euc-kr", alias = "cp949"))]
+    EucKr,
+    /// Western European (code page 1252, the superset of ISO 8859-1 that browsers use)
+    #[cfg_attr(
+        feature = "cli",
+        value(name = "windows-1252", alias = "cp1252", alias = "latin1")
+    )]
+    Windows1252,
+    /// Central European (code page 1250)
+    #[cfg_attr(feature = "cli", value(name = "windows-1250", alias = "cp1250"))]
+    Windows1250,
+    /// Cyrillic (code page 1251)
+    #[cfg_attr(feature = "cli", value(name = "windows-1251", alias = "cp1251"))]
+    Windows1251,
+    /// Russian Cyrillic, as Unix tools write it
+    #[cfg_attr(feature = "cli", value(name = "koi8-r"))]
+    Koi8R,
+}
+
+impl OutputEncoding {
+    /// The encoding's name as XML declarations and the command line spell it.
+    pub fn label(self) -> &'static str {
+        match self {
+            OutputEncoding::Utf8 | OutputEncoding::Utf8Bom => "UTF-8",
+            OutputEncoding::Utf16Le | OutputEncoding::Utf16Be => "UTF-16",
+            OutputEncoding::ShiftJis => "Shift_JIS",
+            OutputEncoding::EucJp => "EUC-JP",
+            OutputEncoding::Gbk => "GBK",
+            OutputEncoding::Gb18030 => "GB18030",
+            OutputEncoding::Big5 => "Big5",
+            OutputEncoding::EucKr => "EUC-KR",
+            OutputEncoding::Windows1252 => "windows-1252",
+            OutputEncoding::Windows1250 => "windows-1250",
+            OutputEncoding::Windows1251 => "windows-1251",
+            OutputEncoding::Koi8R => "KOI8-R",
+        }
+    }
+
+    /// The byte order mark written at the start of a file in this encoding, if any.
+    pub fn bom(self) -> &'static [u8] {
+        match self {
+            OutputEncoding::Utf8Bom => &[0xEF, 0xBB, 0xBF],
+            OutputEncoding::Utf16Le => &[0xFF, 0xFE],
+            OutputEncoding::Utf16Be => &[0xFE, 0xFF],
+            _ => &[],
+        }
+    }
+
+    /// The encoding_rs encoder of a legacy encoding; the Unicode ones are written directly.
+    fn legacy(self) -> &'static Encoding {
+        match self {
+            OutputEncoding::Utf8
+            | OutputEncoding::Utf8Bom
+            | OutputEncoding::Utf16Le
+            | OutputEncoding::Utf16Be => encoding_rs::UTF_8,
+            OutputEncoding::ShiftJis => encoding_rs::SHIFT_JIS,
+            OutputEncoding::EucJp => encoding_rs::EUC_JP,
+            OutputEncoding::Gbk => encoding_rs::GBK,
+            OutputEncoding::Gb18030 => encoding_rs::GB18030,
+            OutputEncoding::Big5 => encoding_rs::BIG5,
+            OutputEncoding::EucKr => encoding_rs::EUC_KR,
+            OutputEncoding::Windows1252 => encoding_rs::WINDOWS_1252,
+            OutputEncoding::Windows1250 => encoding_rs::WINDOWS_1250,
+            OutputEncoding::Windows1251 => encoding_rs::WINDOWS_1251,
+            OutputEncoding::Koi8R => encoding_rs::KOI8_R,
+        }
+    }
+}
+
+/// Text encoded for writing.
+pub struct Encoded {
+    pub bytes: Vec<u8>,
+    /// Characters written as `?` because the encoding has no code for them
+    pub unmappable: usize,
+}
+
+/// Encodes `text`, with a byte order mark first where the encoding calls for one.
+pub fn encode(text: &str, encoding: OutputEncoding) -> Encoded {
+    let unicode = |units: Vec<u8>| {
+        let mut bytes = encoding.bom().to_vec();
+        bytes.extend(units);
+        Encoded {
+            bytes,
+            unmappable: 0,
+        }
+    };
+    match encoding {
+        OutputEncoding::Utf8 | OutputEncoding::Utf8Bom => unicode(text.as_bytes().to_vec()),
+        OutputEncoding::Utf16Le => {
+            unicode(text.encode_utf16().flat_map(u16::to_le_bytes).collect())
+        }
+        OutputEncoding::Utf16Be => {
+            unicode(text.encode_utf16().flat_map(u16::to_be_bytes).collect())
+        }
+        _ => encode_legacy(text, encoding.legacy()),
+    }
+}
+
+fn encode_legacy(text: &str, encoding: &'static Encoding) -> Encoded {
+    let mut encoder = encoding.new_encoder();
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut unmappable = 0;
+    let mut rest = text;
+    loop {
+        let (result, read) =
+            encoder.encode_from_utf8_to_vec_without_replacement(rest, &mut bytes, true);
+        rest = &rest[read..];
+        match result {
+            EncoderResult::InputEmpty => break,
+            EncoderResult::OutputFull => bytes.reserve(rest.len().max(16)),
+            EncoderResult::Unmappable(_) => {
+                unmappable += 1;
+                bytes.push(REPLACEMENT);
+            }
+        }
+    }
+    Encoded { bytes, unmappable }
+}
@@ -0,0 +1,263 @@
+//! Reading EPUB content documents straight from the zip archive, in spine order.
+//!
+//! The `epub` crate parses the package document; chapters are then inflated one at a time
+//! into a single reused buffer, so peak memory follows the largest chapter rather than the
+//! whole book, and images and fonts are never read.
+
+use anyhow::{Context, Result};
+use epub::doc::EpubDoc;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+use zip::ZipArchive;
+
+use crate::extract;
+
+/// Capacity the buffer keeps between chapters; an unusually large chapter's allocation is
+/// released once it has been processed.
+const RETAINED_BUFFER: usize = 1 << 20;
+
+/// A content document of the spine.
+pub struct ContentRef {
+    /// Path inside the archive
+    pub path: String,
+}
+
+/// The HTML content documents in reading order.
+///
+/// Spine items that are not HTML (images in fixed-layout books, for instance) are skipped. A
+/// book without a usable spine falls back to every HTML resource in path order.
+pub fn content_documents(doc: &EpubDoc<BufReader<File>>) -> Vec<ContentRef> {
+    let as_ref = |resource: &epub::doc::ResourceItem| {
+        let path = archive_path(&resource.path);
+        is_html_content(&resource.mime, &path).then_some(ContentRef { path })
+    };
+
+    let spine: Vec<ContentRef> = doc
+        .spine
+        .iter()
+        .filter_map(|item| doc.resources.get(&item.idref))
+        .filter_map(as_ref)
+        .collect();
+    if !spine.is_empty() {
+        return spine;
+    }
+
+    let mut all: Vec<ContentRef> = doc.resources.values().filter_map(as_ref).collect();
+    all.sort_by(|a, b| a.path.cmp(&b.path));
+    all
+}
+
+fn is_html_content(mime_type: &str, path_str: &str) -> bool {
+    mime_type.starts_with("application/xhtml")
+        || mime_type.starts_with("text/html")
+        || path_str.ends_with(".xhtml")
+        || path_str.ends_with(".html")
+}
+
+/// An entry of the NCX table of contents.
+pub struct TocEntry {
+    pub label: String,
+    /// Nesting depth, 1 for top-level entries
+    pub level: usize,
+    /// Normalised path of the content document it opens
+    pub path: String,
+}
+
+/// The NCX table of contents, flattened in reading order.
+pub fn toc(doc: &EpubDoc<BufReader<File>>) -> Vec<TocEntry> {
+    fn flatten(points: &[epub::doc::NavPoint], level: usize, out: &mut Vec<TocEntry>) {
+        for point in points {
+            out.push(TocEntry {
+                label: point.label.trim().to_string(),
+                level,
+                path: resolve("", &archive_path(&point.content)),
+            });
+            flatten(&point.children, level + 1, out);
+        }
+    }
+
+    let mut entries = Vec::new();
+    flatten(&doc.toc, 1, &mut entries);
+    entries
+}
+
+/// Where the book's own navigation says the body starts and the back matter begins, as
+/// normalised content document paths.
+#[derive(Default)]
+pub struct LandmarkPaths {
+    pub body_start: Option<String>,
+    pub back_matter: Option<String>,
+}
+
+/// Landmark `epub:type`s (EPUB 3) and guide reference types (EPUB 2) opening the body.
+const BODY_TYPES: [&str; 3] = ["bodymatter", "text", "start"];
+/// The same for the first back-matter document.
+const BACK_MATTER_TYPES: [&str; 3] = ["backmatter", "index", "colophon"];
+
+/// Reads landmarks from the EPUB 3 navigation document, falling back to the EPUB 2 guide.
+pub fn landmarks(reader: &mut ChapterReader, doc: &EpubDoc<BufReader<File>>) -> LandmarkPaths {
+    let nav = doc.resources.values().find(|resource| {
+        resource
+            .properties
+            .as_deref()
+            .is_some_and(|properties| properties.split_whitespace().any(|p| p == "nav"))
+    });
+    let from_nav = nav.and_then(|nav| {
+        let path = archive_path(&nav.path);
+        reader
+            .with_entry(&path, |xml| nav_landmarks(&path, xml))
+            .flatten()
+    });
+    if let Some(landmarks) = from_nav {
+        return landmarks;
+    }
+
+    let opf = archive_path(&doc.root_file);
+    reader
+        .with_entry(&opf, |xml| guide_landmarks(&opf, xml))
+        .flatten()
+        .unwrap_or_default()
+}
+
+fn nav_landmarks(nav_path: &str, xml: &str) -> Option<LandmarkPaths> {
+    const OPS: &str = "http://www.idpf.org/2007/ops";
+
+    let doc = roxmltree::Document::parse(xml).ok()?;
+    let nav = doc.descendants().find(|n| {
+        n.tag_name().name() == "nav"
+            && n.attribute((OPS, "type"))
+                .is_some_and(|t| t.split_whitespace().any(|t| t == "landmarks"))
+    })?;
+    let links = nav
+        .descendants()
+        .filter(|n| n.tag_name().name() == "a")
+        .filter_map(|a| Some((a.attribute((OPS, "type"))?, a.attribute("href")?)));
+    Some(pick_landmarks(nav_path, links))
+}
+
+fn guide_landmarks(opf_path: &str, xml: &str) -> Option<LandmarkPaths> {
+    let doc = roxmltree::Document::parse(xml).ok()?;
+    let references = doc
+        .descendants()
+        .filter(|n| n.tag_name().name() == "reference")
+        .filter_map(|r| Some((r.attribute("type")?, r.attribute("href")?)));
+    Some(pick_landmarks(opf_path, references))
+}
+
+fn pick_landmarks<'a>(
+    base: &str,
+    links: impl Iterator<Item = (&'a str, &'a str)>,
+) -> LandmarkPaths {
+    let mut landmarks = LandmarkPaths::default();
+    for (types, href) in links {
+        let has = |wanted: &[&str]| types.split_whitespace().any(|t| wanted.contains(&t));
+        if has(&BODY_TYPES) && landmarks.body_start.is_none() {
+            landmarks.body_start = Some(resolve(base, href));
+        } else if has(&BACK_MATTER_TYPES) && landmarks.back_matter.is_none() {
+            landmarks.back_matter = Some(resolve(base, href));
+        }
+    }
+    landmarks
+}
+
+/// Resolves `href` against the document at `base` into a normalised archive path: fragment
+/// dropped, percent-escapes decoded, `.` and `..` segments applied.
+pub fn resolve(base: &str, href: &str) -> String {
+    let href = href.split('#').next().unwrap_or_default();
+    let href = percent_encoding::percent_decode_str(href).decode_utf8_lossy();
+
+    let mut segments: Vec<&str> = match base.rfind('/') {
+        Some(slash) if !href.starts_with('/') => base[..slash].split('/').collect(),
+        _ => Vec::new(),
+    };
+    for segment in href.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            segment => segments.push(segment),
+        }
+    }
+    segments.join("/")
+}
+
+/// Algorithms that only obfuscate embedded fonts, which leaves the text readable.
+const FONT_OBFUSCATION: [&str; 2] = [
+    "http://www.idpf.org/2008/embedding",
+    "http://ns.adobe.com/pdf/enc#RC",
+];
+
+/// Whether `META-INF/encryption.xml` encrypts anything but fonts, as DRM does.
+pub fn is_encrypted(reader: &mut ChapterReader) -> bool {
+    reader
+        .with_entry("META-INF/encryption.xml", |xml| {
+            let Ok(tree) = roxmltree::Document::parse(xml) else {
+                // Unreadable, so assume the worst
+                return true;
+            };
+            tree.descendants()
+                .filter(|node| node.has_tag_name("EncryptionMethod"))
+                .filter_map(|node| node.attribute("Algorithm"))
+                .any(|algorithm| !FONT_OBFUSCATION.contains(&algorithm))
+        })
+        .unwrap_or(false)
+}
+
+/// Zip entry names always use forward slashes.
+fn archive_path(path: &Path) -> String {
+    path.to_string_lossy().replace('\\', "/")
+}
+
+/// Inflates content documents one at a time into a reused buffer.
+pub struct ChapterReader {
+    zip: ZipArchive<BufReader<File>>,
+    buffer: Vec<u8>,
+}
+
+impl ChapterReader {
+    pub fn open(epub_path: &Path) -> Result<Self> {
+        let file = File::open(epub_path)
+            .context(format!("Failed to open EPUB: {}", epub_path.display()))?;
+        let zip = ZipArchive::new(BufReader::new(file)).context(format!(
+            "Failed to read EPUB archive: {}",
+            epub_path.display()
+        ))?;
+        Ok(ChapterReader {
+            zip,
+            buffer: Vec::new(),
+        })
+    }
+
+    /// Hands the entry's text to `f`, or returns `None` if the archive lacks it. Entries in
+    /// other encodings than UTF-8 are transcoded (see [`extract::decode_html`]), and bytes
+    /// that still do not decode are replaced rather than dropping the chapter.
+    pub fn with_entry<T>(&mut self, path: &str, f: impl FnOnce(&str) -> T) -> Option<T> {
+        self.buffer.clear();
+        let read = read_entry(&mut self.zip, path, &mut self.buffer).or_else(|| {
+            // Manifest hrefs are percent-encoded; some archives store the decoded name
+            let decoded = percent_encoding::percent_decode_str(path)
+                .decode_utf8()
+                .ok()?;
+            read_entry(&mut self.zip, &decoded, &mut self.buffer)
+        });
+
+        let result = read.map(|()| f(&extract::decode_html(&self.buffer)));
+        if self.buffer.capacity() > RETAINED_BUFFER {
+            self.buffer = Vec::new();
+        }
+        result
+    }
+}
+
+fn read_entry(
+    zip: &mut ZipArchive<BufReader<File>>,
+    name: &str,
+    buffer: &mut Vec<u8>,
+) -> Option<()> {
+    let mut entry = zip.by_name(name).ok()?;
+    buffer.clear();
+    entry.read_to_end(buffer).ok()?;
+    Some(())
+}
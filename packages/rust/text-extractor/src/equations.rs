@@ -0,0 +1,75 @@
+//! Display equation detection for assembled PDF lines.
+//!
+//! Without font information the detector relies on layout and content: display equations sit
+//! centred on their own line, are dense in mathematical symbols, and contain hardly any prose.
+
+use crate::layout::Line;
+
+/// Centre of an equation line may drift this far from the page centre (fraction of page width).
+const CENTRE_TOLERANCE: f64 = 0.08;
+/// Lines wider than this fraction of the page are body text, however symbol-heavy.
+const MAX_WIDTH: f64 = 0.75;
+/// Minimum share of non-space characters that must be mathematical symbols.
+const MIN_SYMBOL_RATIO: f64 = 0.3;
+
+pub fn is_equation_line(text: &str, line: &Line, page_width: f64) -> bool {
+    let (body, x1) = match line.last_gap {
+        Some((gap_at, gap_x)) if is_equation_number(&text[gap_at..line.end]) => {
+            (&text[line.start..gap_at], gap_x)
+        }
+        _ => (&text[line.start..line.end], line.x1),
+    };
+
+    let centre = (line.x0 + x1) / 2.;
+    let centred = (centre - page_width / 2.).abs() < page_width * CENTRE_TOLERANCE
+        && x1 - line.x0 < page_width * MAX_WIDTH;
+
+    centred && looks_like_math(body)
+}
+
+fn looks_like_math(text: &str) -> bool {
+    let chars: Vec<char> = text.chars().filter(|c| !c.is_whitespace()).collect();
+    if chars.len() < 3 || !chars.iter().any(|&c| is_relation(c)) {
+        return false;
+    }
+
+    let symbols = chars.iter().filter(|&&c| is_math_symbol(c)).count();
+    // "where", "therefore": a single connecting word is fine, a sentence is not
+    let prose_words = text
+        .split_whitespace()
+        .filter(|word| {
+            let word = word.trim_end_matches([',', '.', ';', ':']);
+            word.chars().count() >= 4 && word.chars().all(char::is_alphabetic)
+        })
+        .count();
+
+    prose_words <= 1 && symbols as f64 >= chars.len() as f64 * MIN_SYMBOL_RATIO
+}
+
+/// Right-aligned numbers such as "(3)" or "(2.14a)".
+fn is_equation_number(text: &str) -> bool {
+    let Some(inner) = text
+        .trim()
+        .strip_prefix('(')
+        .and_then(|t| t.strip_suffix(')'))
+    else {
+        return false;
+    };
+    let inner = inner.trim_end_matches(|c: char| c.is_ascii_lowercase());
+    !inner.is_empty()
+        && inner.starts_with(|c: char| c.is_ascii_digit())
+        && inner.chars().all(|c| c.is_ascii_digit() || c == '.')
+}
+
+fn is_relation(ch: char) -> bool {
+    "=<>≤≥≠≈≡∼∝→⇒⇔∈⊂⊆".contains(ch)
+}
+
+fn is_math_symbol(ch: char) -> bool {
+    ch.is_ascii_digit()
+        || is_relation(ch)
+        || "+-−*/^_()[]{}|±×÷·⋅√′".contains(ch)
+        // Greek letters and the Mathematical Operators block
+        || ('\u{0391}'..='\u{03C9}').contains(&ch)
+        || ('\u{2200}'..='\u{22FF}').contains(&ch)
+}
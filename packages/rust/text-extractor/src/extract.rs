@@ -0,0 +1,990 @@
+//! Turning one input file into a [`Document`]: the options, the extractor for each format,
+//! and the entry points used by the command line, the ingest pipeline and the window.
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+#[cfg(feature = "audio")]
+use crate::audio;
+use crate::chapters::ChapterSelection;
+use crate::chm;
+use crate::djvu;
+use crate::document::{Document, Landmarks, OutlineEntry, Section, SectionKind, CODE_FENCE};
+use crate::docx;
+use crate::dom;
+use crate::epub_archive::{self, ChapterReader};
+use crate::fb2;
+use crate::figures;
+use crate::html;
+use crate::iwork;
+use crate::latex;
+use crate::layers::LayerSelection;
+use crate::layout::AssembleOptions;
+use crate::mathml;
+use crate::memory::{self, MemoryBudget};
+use crate::metadata::Metadata;
+use crate::ocr::{self, OcrOptions};
+use crate::odt;
+use crate::output::{self, OutputOptions, OutputRun};
+use crate::pages::PageSelection;
+use crate::pdf;
+use crate::postprocess::{self, PostProcessOptions};
+use crate::provenance::{self, Provenance};
+use crate::rtf;
+use crate::scripts::ScriptMode;
+use crate::source::{self, SourceFile};
+use crate::tables::TableLayout;
+
+/// Everything that controls how a single file is turned into output.
+pub struct ExtractOptions {
+    /// Shared by every file extracted with these options
+    pub memory: MemoryBudget,
+    pub pdf: AssembleOptions,
+    pub layers: LayerSelection,
+    /// PDF pages to extract
+    pub pages: PageSelection,
+    /// EPUB chapters to extract
+    pub chapters: ChapterSelection,
+    /// Passwords to try on encrypted PDFs after the empty one
+    pub pdf_passwords: Vec<String>,
+    /// OCR for PDF and DjVu pages without a text layer
+    pub ocr: OcrOptions,
+    pub html: HtmlOptions,
+    pub postprocess: PostProcessOptions,
+    pub output: OutputOptions,
+    #[cfg(feature = "audio")]
+    pub audio: audio::AudioOptions,
+}
+
+impl Default for ExtractOptions {
+    /// The command line's defaults: tagged PDFs read in structure order, letter-spaced
+    /// titles joined, PDF tables as tab-separated rows, plain text output.
+    fn default() -> Self {
+        ExtractOptions {
+            memory: MemoryBudget::new(None),
+            pdf: AssembleOptions {
+                use_structure: true,
+                join_letter_spacing: true,
+                tables: Some(TableLayout::Tsv),
+                ..AssembleOptions::default()
+            },
+            layers: LayerSelection::default(),
+            pages: PageSelection::default(),
+            chapters: ChapterSelection::default(),
+            pdf_passwords: Vec::new(),
+            ocr: OcrOptions::default(),
+            html: HtmlOptions::default(),
+            postprocess: PostProcessOptions::default(),
+            output: OutputOptions::default(),
+            #[cfg(feature = "audio")]
+            audio: audio::AudioOptions::default(),
+        }
+    }
+}
+
+impl ExtractOptions {
+    /// Digest of the options that change what is written for a document, its sidecars
+    /// included, for provenance records; memory, passwords and where outputs go do not count.
+    pub fn options_hash(&self) -> String {
+        let fingerprint = format!(
+            "{:?}|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}",
+            self.pdf,
+            self.layers,
+            self.pages,
+            self.chapters,
+            self.ocr,
+            self.html,
+            self.postprocess,
+            self.output.format,
+            self.output.wrap,
+            self.output.page_separator,
+            self.output.ssml,
+            self.output.split,
+            self.output.split_level,
+            self.output.provenance,
+            self.output.stamps,
+            self.output.metadata_sidecar,
+            self.output.encoding,
+            self.output.newline,
+            self.output.document_separator,
+        );
+        #[cfg(feature = "audio")]
+        let fingerprint = format!("{fingerprint}|{:?}", self.audio);
+        provenance::options_hash(&fingerprint)
+    }
+}
+
+/// How EPUB content documents are turned into text.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HtmlOptions {
+    pub scripts: ScriptMode,
+    pub fence_code: bool,
+    pub image_placeholders: bool,
+    /// Lay `<table>` elements out as Markdown
+    pub tables: bool,
+    /// Keep headings, emphasis and list items as Markdown
+    pub markup: bool,
+    pub whitespace: WhitespaceMode,
+}
+
+/// How the blank lines between the blocks of EPUB, HTML and CHM documents are kept.
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WhitespaceMode {
+    /// Keep every blank line the markup leaves between blocks
+    Preserve,
+    /// Collapse runs of blank lines into one, so paragraphs stay a blank line apart
+    Compact,
+    /// Drop blank lines, leaving one block per line
+    #[default]
+    Aggressive,
+}
+
+/// Reads one family of input formats into a [`Document`].
+pub trait Extractor: Sync {
+    /// Lowercase extensions of the files it reads.
+    fn extensions(&self) -> &'static [&'static str];
+
+    /// Reads `path`. `metadata` holds canonical values from a catalog (e.g. Calibre) that win
+    /// over those embedded in the file.
+    fn extract(
+        &self,
+        path: &Path,
+        metadata: Option<&Metadata>,
+        options: &ExtractOptions,
+    ) -> Result<Document>;
+}
+
+struct PdfExtractor;
+struct EpubExtractor;
+struct HtmlExtractor;
+struct ChmExtractor;
+struct PagesExtractor;
+struct IbooksExtractor;
+struct LatexExtractor;
+struct DjvuExtractor;
+struct Fb2Extractor;
+struct DocxExtractor;
+struct OdtExtractor;
+struct RtfExtractor;
+#[cfg(feature = "audio")]
+struct AudioExtractor;
+
+impl Extractor for PdfExtractor {
+    fn extensions(&self) -> &'static [&'static str] {
+        &["pdf"]
+    }
+
+    fn extract(
+        &self,
+        path: &Path,
+        metadata: Option<&Metadata>,
+        options: &ExtractOptions,
+    ) -> Result<Document> {
+        let mut document = extract_pdf_text(
+            path,
+            metadata,
+            &options.pdf,
+            &options.layers,
+            &options.pages,
+            &options.pdf_passwords,
+        )?;
+        ocr::apply(path, &mut document, &options.ocr)?;
+        Ok(document)
+    }
+}
+
+impl Extractor for EpubExtractor {
+    fn extensions(&self) -> &'static [&'static str] {
+        &["epub"]
+    }
+
+    fn extract(
+        &self,
+        path: &Path,
+        metadata: Option<&Metadata>,
+        options: &ExtractOptions,
+    ) -> Result<Document> {
+        let mut doc = extract_epub_text(path, metadata, options.html)?;
+        options.chapters.apply(&mut doc);
+        Ok(doc)
+    }
+}
+
+impl Extractor for HtmlExtractor {
+    fn extensions(&self) -> &'static [&'static str] {
+        &["html", "xhtml", "htm"]
+    }
+
+    fn extract(
+        &self,
+        path: &Path,
+        metadata: Option<&Metadata>,
+        options: &ExtractOptions,
+    ) -> Result<Document> {
+        extract_html_text(path, metadata, options.html)
+    }
+}
+
+impl Extractor for ChmExtractor {
+    fn extensions(&self) -> &'static [&'static str] {
+        &["chm"]
+    }
+
+    fn extract(
+        &self,
+        path: &Path,
+        metadata: Option<&Metadata>,
+        options: &ExtractOptions,
+    ) -> Result<Document> {
+        chm::extract(path, metadata, options.html)
+    }
+}
+
+impl Extractor for PagesExtractor {
+    fn extensions(&self) -> &'static [&'static str] {
+        &["pages"]
+    }
+
+    fn extract(
+        &self,
+        path: &Path,
+        metadata: Option<&Metadata>,
+        options: &ExtractOptions,
+    ) -> Result<Document> {
+        extract_pages_text(path, metadata, options)
+    }
+}
+
+impl Extractor for IbooksExtractor {
+    fn extensions(&self) -> &'static [&'static str] {
+        &["ibooks"]
+    }
+
+    fn extract(
+        &self,
+        path: &Path,
+        metadata: Option<&Metadata>,
+        options: &ExtractOptions,
+    ) -> Result<Document> {
+        extract_ibooks_text(path, metadata, options)
+    }
+}
+
+impl Extractor for LatexExtractor {
+    fn extensions(&self) -> &'static [&'static str] {
+        &["tex"]
+    }
+
+    fn extract(
+        &self,
+        path: &Path,
+        metadata: Option<&Metadata>,
+        options: &ExtractOptions,
+    ) -> Result<Document> {
+        latex::extract(path, metadata, options.html.tables)
+    }
+}
+
+impl Extractor for DjvuExtractor {
+    fn extensions(&self) -> &'static [&'static str] {
+        &["djvu", "djv"]
+    }
+
+    fn extract(
+        &self,
+        path: &Path,
+        metadata: Option<&Metadata>,
+        options: &ExtractOptions,
+    ) -> Result<Document> {
+        let mut document = extract_djvu_text(path, metadata)?;
+        ocr::apply(path, &mut document, &options.ocr)?;
+        Ok(document)
+    }
+}
+
+impl Extractor for Fb2Extractor {
+    fn extensions(&self) -> &'static [&'static str] {
+        &["fb2", "fb2.zip"]
+    }
+
+    fn extract(
+        &self,
+        path: &Path,
+        metadata: Option<&Metadata>,
+        options: &ExtractOptions,
+    ) -> Result<Document> {
+        fb2::extract(path, metadata, options.html)
+    }
+}
+
+impl Extractor for DocxExtractor {
+    fn extensions(&self) -> &'static [&'static str] {
+        &["docx"]
+    }
+
+    fn extract(
+        &self,
+        path: &Path,
+        metadata: Option<&Metadata>,
+        options: &ExtractOptions,
+    ) -> Result<Document> {
+        docx::extract(path, metadata, options.html)
+    }
+}
+
+impl Extractor for OdtExtractor {
+    fn extensions(&self) -> &'static [&'static str] {
+        &["odt"]
+    }
+
+    fn extract(
+        &self,
+        path: &Path,
+        metadata: Option<&Metadata>,
+        options: &ExtractOptions,
+    ) -> Result<Document> {
+        odt::extract(path, metadata, options.html)
+    }
+}
+
+impl Extractor for RtfExtractor {
+    fn extensions(&self) -> &'static [&'static str] {
+        &["rtf"]
+    }
+
+    fn extract(
+        &self,
+        path: &Path,
+        metadata: Option<&Metadata>,
+        options: &ExtractOptions,
+    ) -> Result<Document> {
+        rtf::extract(path, metadata, options.html.tables)
+    }
+}
+
+#[cfg(feature = "audio")]
+impl Extractor for AudioExtractor {
+    fn extensions(&self) -> &'static [&'static str] {
+        &["mp3", "m4b"]
+    }
+
+    fn extract(
+        &self,
+        path: &Path,
+        metadata: Option<&Metadata>,
+        options: &ExtractOptions,
+    ) -> Result<Document> {
+        audio::transcribe(path, metadata, &options.audio)
+    }
+}
+
+const EXTRACTORS: &[&dyn Extractor] = &[
+    &PdfExtractor,
+    &EpubExtractor,
+    &HtmlExtractor,
+    &ChmExtractor,
+    &PagesExtractor,
+    &IbooksExtractor,
+    &LatexExtractor,
+    &DjvuExtractor,
+    &Fb2Extractor,
+    &DocxExtractor,
+    &OdtExtractor,
+    &RtfExtractor,
+    #[cfg(feature = "audio")]
+    &AudioExtractor,
+];
+
+/// The extractor for files with this (lowercase) extension.
+pub fn extractor_for(extension: &str) -> Option<&'static dyn Extractor> {
+    EXTRACTORS
+        .iter()
+        .copied()
+        .find(|extractor| extractor.extensions().contains(&extension))
+}
+
+/// A document read from a file, ready to be rendered.
+#[derive(Debug, Clone)]
+pub struct ExtractedDocument {
+    pub path: PathBuf,
+    pub document: Document,
+}
+
+impl ExtractedDocument {
+    /// The document as text, SSML, Markdown or JSON, as it would be written to a single file.
+    pub fn render(&self, options: &OutputOptions) -> String {
+        output::render(&self.document, &self.path, options)
+    }
+
+    /// The document as plain text with its metadata header.
+    pub fn text(&self) -> String {
+        self.render(&OutputOptions::default())
+    }
+}
+
+/// Extracts `path` with the default options.
+pub fn extract_file(path: &Path) -> Result<ExtractedDocument> {
+    extract_file_with(path, &ExtractOptions::default())
+}
+
+/// Extracts `path`, picking the extractor from its extension.
+pub fn extract_file_with(path: &Path, options: &ExtractOptions) -> Result<ExtractedDocument> {
+    let extension =
+        source::extension(path).context(format!("No file extension: {}", path.display()))?;
+    extract_source(&SourceFile::new(path.to_path_buf(), &extension), options)
+}
+
+/// Extracts a file, with the catalog metadata it came with, and applies the clean-up options.
+pub fn extract_source(file: &SourceFile, options: &ExtractOptions) -> Result<ExtractedDocument> {
+    let _reservation = options.memory.reserve(memory::estimate(file));
+    let extractor = extractor_for(&file.extension)
+        .context(format!("Unsupported file type: {}", file.extension))?;
+
+    let mut document = extractor.extract(&file.path, file.metadata.as_ref(), options)?;
+    postprocess::apply(&mut document, &options.postprocess);
+    Ok(ExtractedDocument {
+        path: file.path.clone(),
+        document,
+    })
+}
+
+/// Whether `file` already has an up-to-date output in `output`, named as `run` names it. With
+/// a provenance sidecar the record must name the same options and source hash, so edited
+/// options or sources are extracted again; without one the output must be newer than the
+/// source.
+pub fn is_up_to_date(
+    file: &SourceFile,
+    output: &Path,
+    options: &ExtractOptions,
+    run: &OutputRun,
+) -> bool {
+    let Some(output_path) = output::existing_output(&file.path, output, &options.output, run)
+    else {
+        return false;
+    };
+    if let Some(matches) = provenance::sidecar_matches(&output_path, file, &options.options_hash())
+    {
+        return matches;
+    }
+
+    let modified = |path: &Path| fs::metadata(path).and_then(|m| m.modified()).ok();
+    match (modified(&file.path), modified(&output_path)) {
+        (Some(source), Some(output)) => output >= source,
+        _ => false,
+    }
+}
+
+/// An output written by [`write_source`].
+#[derive(Debug, Clone)]
+pub struct WrittenOutput {
+    /// The output file, or the folder of parts when splitting
+    pub path: PathBuf,
+    pub provenance: Provenance,
+    /// Letters and digits in the extracted text; next to none usually means a scanned PDF
+    pub text_chars: usize,
+    /// Characters the output encoding could not represent, written as `?`
+    pub unmappable: usize,
+    /// The document as extracted
+    pub document: Document,
+}
+
+/// Extracts one file into `output` as part of `run`, returning where it went and how it was
+/// produced. A file at `position` in [`OutputRun::reserve`] waits for its turn at a shared
+/// output.
+pub fn write_source(
+    file: &SourceFile,
+    output: &Path,
+    options: &ExtractOptions,
+    run: &OutputRun,
+    position: Option<usize>,
+) -> Result<WrittenOutput> {
+    let extracted = extract_source(file, options)?;
+    let provenance = Provenance::of(file, &options.options_hash())?;
+    let written = output::write_document(
+        &extracted.document,
+        &file.path,
+        output,
+        &options.output,
+        &provenance,
+        run,
+        position,
+    )?;
+    Ok(WrittenOutput {
+        path: written.path,
+        unmappable: written.unmappable,
+        text_chars: extracted.document.text_chars(),
+        provenance,
+        document: extracted.document,
+    })
+}
+
+/// Extracts one file and writes it to `out` as its output file would hold it, for streaming
+/// to standard output; no sidecars are written. Returns the characters the output encoding
+/// could not represent.
+pub fn stream_source(
+    file: &SourceFile,
+    out: &mut dyn Write,
+    options: &ExtractOptions,
+) -> Result<usize> {
+    let extracted = extract_source(file, options)?;
+    let provenance = Provenance::of(file, &options.options_hash())?;
+    write_encoded(&extracted.document, &file.path, &provenance, out, options)
+}
+
+/// Extracts a document of type `extension` (as [`SourceFile::extension`]) read from `input`,
+/// such as standard input, and writes it to `out` as [`stream_source`] does. Outputs that
+/// name their source name it `-`.
+pub fn stream_reader(
+    input: &mut dyn Read,
+    extension: &str,
+    out: &mut dyn Write,
+    options: &ExtractOptions,
+) -> Result<usize> {
+    let (extracted, provenance) = extract_reader(input, extension, options)?;
+    write_encoded(
+        &extracted.document,
+        Path::new("-"),
+        &provenance,
+        out,
+        options,
+    )
+}
+
+/// Extracts a document of type `extension` read from `input`, with the provenance of the
+/// bytes read.
+pub(crate) fn extract_reader(
+    input: &mut dyn Read,
+    extension: &str,
+    options: &ExtractOptions,
+) -> Result<(ExtractedDocument, Provenance)> {
+    // Extractors read files, so the document is spooled to one first
+    let mut spooled = spool_file(extension)?;
+    io::copy(input, &mut spooled).context("Failed to read the document")?;
+    spooled.flush()?;
+    extract_spooled(&spooled, extension, options)
+}
+
+/// A temporary file to spool a document of type `extension` to before extracting it.
+pub(crate) fn spool_file(extension: &str) -> Result<tempfile::NamedTempFile> {
+    tempfile::Builder::new()
+        .suffix(&format!(".{extension}"))
+        .tempfile()
+        .context("Failed to create a file for the document read")
+}
+
+/// Extracts a document spooled to a [`spool_file`], with the provenance of its bytes.
+pub(crate) fn extract_spooled(
+    spooled: &tempfile::NamedTempFile,
+    extension: &str,
+    options: &ExtractOptions,
+) -> Result<(ExtractedDocument, Provenance)> {
+    let file = SourceFile::new(spooled.path().to_path_buf(), extension);
+    let extracted = extract_source(&file, options)?;
+    let provenance = Provenance::of(&file, &options.options_hash())?;
+    Ok((extracted, provenance))
+}
+
+fn write_encoded(
+    document: &Document,
+    source: &Path,
+    provenance: &Provenance,
+    out: &mut dyn Write,
+    options: &ExtractOptions,
+) -> Result<usize> {
+    let encoded = output::encode_document(document, source, &options.output, provenance);
+    out.write_all(&encoded.bytes)?;
+    out.flush()?;
+    Ok(encoded.unmappable)
+}
+
+/// Text of the selected pages of a PDF, with its bookmarks and language. Encrypted PDFs are
+/// opened with the empty password or one of `passwords`, failing with [`pdf::LockedPdf`]
+/// when none fits.
+pub fn extract_pdf_text(
+    pdf_path: &Path,
+    metadata: Option<&Metadata>,
+    options: &AssembleOptions,
+    layers: &LayerSelection,
+    pages: &PageSelection,
+    passwords: &[String],
+) -> Result<Document> {
+    // Extract text using pdf-extract which properly handles encodings
+    let pdf = pdf::extract_pages(pdf_path, options, layers, pages, passwords)?;
+    Ok(pdf_document(pdf, metadata, pages))
+}
+
+/// Text of every page of a DjVu book from its hidden text layer, with its bookmarks. Pages
+/// without one are left empty for OCR.
+pub fn extract_djvu_text(djvu_path: &Path, metadata: Option<&Metadata>) -> Result<Document> {
+    djvu::extract(djvu_path, metadata)
+}
+
+/// Text of a Pages document from its IWA archives, or from the PDF preview saved with it.
+fn extract_pages_text(
+    path: &Path,
+    metadata: Option<&Metadata>,
+    options: &ExtractOptions,
+) -> Result<Document> {
+    match iwork::pages_text(path)? {
+        Some(text) => Ok(Document {
+            metadata: metadata.cloned().unwrap_or_default(),
+            sections: vec![Section {
+                kind: SectionKind::Chapter,
+                title: None,
+                text,
+                language: None,
+                page: None,
+            }],
+            outline: Vec::new(),
+            landmarks: Landmarks::default(),
+        }),
+        None => extract_preview_text(path, metadata, options),
+    }
+}
+
+/// Text of an iBooks Author book, read as the EPUB it is built on, or from its PDF preview
+/// when the EPUB reader cannot open it.
+fn extract_ibooks_text(
+    path: &Path,
+    metadata: Option<&Metadata>,
+    options: &ExtractOptions,
+) -> Result<Document> {
+    extract_epub_text(path, metadata, options.html)
+        .or_else(|e| extract_preview_text(path, metadata, options).map_err(|_| e))
+}
+
+fn extract_preview_text(
+    path: &Path,
+    metadata: Option<&Metadata>,
+    options: &ExtractOptions,
+) -> Result<Document> {
+    let preview = iwork::preview_pdf(path)?;
+    let pdf = pdf::extract_embedded_pages(
+        &preview,
+        path,
+        &options.pdf,
+        &options.layers,
+        &options.pages,
+        &options.pdf_passwords,
+    )?;
+    Ok(pdf_document(pdf, metadata, &options.pages))
+}
+
+fn pdf_document(pdf: pdf::PdfText, metadata: Option<&Metadata>, pages: &PageSelection) -> Document {
+    // Only the caller's canonical title and author (e.g. from Calibre) are used for PDFs
+    let embedded = Metadata {
+        language: pdf.language,
+        ..Metadata::default()
+    };
+
+    // A dynamic XFA form's pages only ask for a viewer that can render it
+    let (sections, outline) = match pdf.form {
+        Some(form) => (
+            vec![Section {
+                kind: SectionKind::Chapter,
+                title: None,
+                text: form,
+                language: None,
+                page: None,
+            }],
+            Vec::new(),
+        ),
+        None => (
+            pdf.pages
+                .into_iter()
+                .enumerate()
+                .map(|(index, page)| Section {
+                    kind: SectionKind::Page,
+                    title: None,
+                    text: page.text,
+                    language: page.language,
+                    page: Some(index + 1),
+                })
+                .collect(),
+            pdf.outline,
+        ),
+    };
+
+    let mut document = Document {
+        metadata: metadata.cloned().unwrap_or_default().or(embedded),
+        sections,
+        outline,
+        landmarks: Landmarks::default(),
+    };
+    if !pages.is_all() {
+        document.retain_sections(|section| section.page.is_none_or(|page| pages.contains(page)));
+    }
+    document
+}
+
+/// Text of every content document of an EPUB in reading order, with its table of contents
+/// and landmarks.
+pub fn extract_epub_text(
+    epub_path: &Path,
+    metadata: Option<&Metadata>,
+    options: HtmlOptions,
+) -> Result<Document> {
+    let doc = epub::doc::EpubDoc::new(epub_path)
+        .context(format!("Failed to open EPUB: {}", epub_path.display()))?;
+
+    // Extract metadata, preferring the caller's canonical values
+    let embedded = epub_metadata(&doc);
+    let metadata = match metadata {
+        Some(metadata) => metadata.clone().or(embedded),
+        None => embedded,
+    };
+
+    // Extract text from all resources
+    let mut reader = ChapterReader::open(epub_path)?;
+    let (mut sections, paths) = extract_resources(&mut reader, &doc, options);
+    let section_of = |path: &str| paths.iter().position(|p| p == path);
+
+    // Only chapters in a language other than the book's are worth flagging
+    for section in &mut sections {
+        if section.language.is_some() && section.language == metadata.language {
+            section.language = None;
+        }
+    }
+
+    // Bookmarks to documents outside the spine have nothing to point at
+    let outline = epub_archive::toc(&doc)
+        .into_iter()
+        .filter_map(|entry| {
+            Some(OutlineEntry {
+                section: section_of(&entry.path)?,
+                title: entry.label,
+                level: entry.level,
+            })
+        })
+        .collect();
+    let landmark_paths = epub_archive::landmarks(&mut reader, &doc);
+    let landmarks = Landmarks {
+        body_start: landmark_paths.body_start.and_then(|p| section_of(&p)),
+        back_matter: landmark_paths.back_matter.and_then(|p| section_of(&p)),
+    };
+
+    Ok(Document {
+        metadata,
+        sections,
+        outline,
+        landmarks,
+    })
+}
+
+/// Text of a loose HTML or XHTML file, with its `<title>` as the title.
+pub fn extract_html_text(
+    html_path: &Path,
+    metadata: Option<&Metadata>,
+    options: HtmlOptions,
+) -> Result<Document> {
+    let bytes =
+        fs::read(html_path).context(format!("Failed to read HTML: {}", html_path.display()))?;
+    let html = decode_html(&bytes);
+
+    let embedded = Metadata {
+        title: html_title(&html),
+        language: html_language(&html),
+        ..Metadata::default()
+    };
+    Ok(Document {
+        metadata: metadata.cloned().unwrap_or_default().or(embedded),
+        sections: vec![Section {
+            kind: SectionKind::Chapter,
+            title: None,
+            text: html_body_text(&html, options),
+            language: None,
+            page: None,
+        }],
+        outline: Vec::new(),
+        landmarks: Landmarks::default(),
+    })
+}
+
+/// The text of a whole HTML file but its `<head>`, whose title is metadata rather than the
+/// first line of the text.
+pub(crate) fn html_body_text(html: &str, options: HtmlOptions) -> String {
+    strip_html_tags(html, options)
+}
+
+/// The file as text, in the encoding its byte order mark, `<meta charset>` or XML declaration
+/// names. Files that name none, or claim UTF-8 without being valid UTF-8, are decoded in the
+/// encoding their bytes look most like (Windows-1252, Shift_JIS, GBK...).
+pub(crate) fn decode_html(bytes: &[u8]) -> String {
+    if let Some((bom, _)) = encoding_rs::Encoding::for_bom(bytes) {
+        return bom.decode(bytes).0.into_owned();
+    }
+
+    let head = String::from_utf8_lossy(&bytes[..bytes.len().min(1024)]).to_ascii_lowercase();
+    let declared = ["charset=", "encoding="].iter().find_map(|name| {
+        let value = head[head.find(name)? + name.len()..].trim_start_matches(['"', '\'']);
+        let end = value
+            .find(|c: char| c == '"' || c == '\'' || c == ';' || c == '>' || c.is_whitespace())
+            .unwrap_or(value.len());
+        encoding_rs::Encoding::for_label(&value.as_bytes()[..end])
+    });
+    let encoding = match declared {
+        Some(encoding) if encoding != encoding_rs::UTF_8 => encoding,
+        _ if std::str::from_utf8(bytes).is_ok() => encoding_rs::UTF_8,
+        _ => {
+            let mut detector = chardetng::EncodingDetector::new();
+            detector.feed(bytes, true);
+            detector.guess(None, false)
+        }
+    };
+    encoding.decode_without_bom_handling(bytes).0.into_owned()
+}
+
+/// The text of the document's `<title>` element.
+fn html_title(html: &str) -> Option<String> {
+    let start = html::find_ignore_case(html, 0, "<title")?;
+    let start = start + html[start..].find('>')? + 1;
+    let end = html::find_ignore_case(html, start, "</title")?;
+    let title = html::strip_tags(&html[start..end]);
+    (!title.is_empty()).then_some(title)
+}
+
+pub(crate) fn epub_metadata(
+    doc: &epub::doc::EpubDoc<std::io::BufReader<std::fs::File>>,
+) -> Metadata {
+    Metadata {
+        title: doc.mdata("title").map(|item| item.value.clone()),
+        author: doc.mdata("creator").map(|item| item.value.clone()),
+        language: doc.mdata("language").map(|item| item.value.clone()),
+        collection: None,
+    }
+}
+
+/// Extracts the book's content documents in spine order, with the normalised archive path
+/// of each section.
+fn extract_resources(
+    reader: &mut ChapterReader,
+    doc: &epub::doc::EpubDoc<std::io::BufReader<std::fs::File>>,
+    options: HtmlOptions,
+) -> (Vec<Section>, Vec<String>) {
+    let mut sections = Vec::new();
+    let mut paths = Vec::new();
+
+    for content in epub_archive::content_documents(doc) {
+        let section = reader.with_entry(&content.path, |html| Section {
+            kind: SectionKind::Chapter,
+            title: None,
+            text: strip_html_tags(html, options),
+            language: html_language(html),
+            page: None,
+        });
+        if let Some(section) = section {
+            sections.push(section);
+            paths.push(epub_archive::resolve("", &content.path));
+        }
+    }
+
+    (sections, paths)
+}
+
+/// The `lang`/`xml:lang` attribute of a content document's root element.
+fn html_language(html: &str) -> Option<String> {
+    let start = html.find("<html")?;
+    let tag = &html[start..start + html[start..].find('>')?];
+
+    ["xml:lang=", " lang="].iter().find_map(|attribute| {
+        let value = &tag[tag.find(attribute)? + attribute.len()..];
+        let quote = value.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+        let value = &value[1..];
+        let language = value[..value.find(quote)?].trim();
+        (!language.is_empty()).then(|| language.to_string())
+    })
+}
+
+fn strip_html_tags(html: &str, options: HtmlOptions) -> String {
+    let html = mathml::replace_math(html);
+    let text = clean_whitespace(&dom::text(&html, options), options.whitespace);
+    if options.image_placeholders {
+        figures::label(&text, false)
+    } else {
+        text
+    }
+}
+
+/// Trims every line but those of fenced code, and keeps or collapses the blank lines between
+/// them as `mode` asks.
+fn clean_whitespace(text: &str, mode: WhitespaceMode) -> String {
+    let mut lines: Vec<&str> = Vec::new();
+    let mut in_code = false;
+
+    for line in text.split('\n') {
+        if line.trim() == CODE_FENCE {
+            if in_code {
+                // Drop the blank lines the markup left at the end of the block
+                while lines.last().is_some_and(|l| l.is_empty()) {
+                    lines.pop();
+                }
+            }
+            in_code = !in_code;
+            lines.push(CODE_FENCE);
+        } else if in_code {
+            // Keep indentation and blank lines, skipping those straight after the opening fence
+            if !(line.trim().is_empty() && lines.last() == Some(&CODE_FENCE)) {
+                lines.push(line.trim_end());
+            }
+        } else if !line.trim().is_empty() {
+            lines.push(line.trim());
+        } else if !lines.is_empty()
+            && match mode {
+                WhitespaceMode::Preserve => true,
+                WhitespaceMode::Compact => lines.last() != Some(&""),
+                WhitespaceMode::Aggressive => false,
+            }
+        {
+            lines.push("");
+        }
+    }
+
+    while lines.last() == Some(&"") {
+        lines.pop();
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn options_hash_is_stable() {
+        assert_eq!(
+            ExtractOptions::default().options_hash(),
+            ExtractOptions::default().options_hash()
+        );
+    }
+
+    #[test]
+    fn options_hash_covers_what_is_written() {
+        let plain = ExtractOptions::default().options_hash();
+        let mut header = ExtractOptions::default();
+        header.output.provenance.header = true;
+        assert_ne!(header.options_hash(), plain);
+        let mut stamps = ExtractOptions::default();
+        stamps.output.stamps = true;
+        assert_ne!(stamps.options_hash(), plain);
+        let mut markdown = ExtractOptions::default();
+        markdown.output.format = output::OutputFormat::Markdown;
+        assert_ne!(markdown.options_hash(), plain);
+    }
+
+    #[test]
+    fn options_hash_ignores_where_outputs_go() {
+        let plain = ExtractOptions::default().options_hash();
+        let mut options = ExtractOptions {
+            memory: MemoryBudget::new(Some(1 << 30)),
+            pdf_passwords: vec!["secret".to_string()],
+            ..ExtractOptions::default()
+        };
+        options.output.mirror_tree = vec![PathBuf::from("books")];
+        options.output.name_template = Some("{author} - {title}".to_string());
+        assert_eq!(options.options_hash(), plain);
+    }
+}
@@ -0,0 +1,554 @@
+//! FictionBook 2 books (`.fb2`, and `.fb2.zip` as most libraries hand them out).
+//!
+//! An FB2 file is a single XML document: `<description>` carries the bibliographic data and
+//! each `<body>` holds nested `<section>`s of paragraphs, poems and citations. The main body
+//! comes first; later bodies hold the notes and comments that the text links to. Files are
+//! often saved in a legacy code page such as windows-1251, named in the XML declaration.
+
+use anyhow::{Context, Result};
+use roxmltree::Node;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::Path;
+use zip::ZipArchive;
+
+use crate::document::{Document, Landmarks, OutlineEntry, Section, SectionKind};
+use crate::extract::HtmlOptions;
+use crate::figures::{self, IMAGE};
+use crate::metadata::Metadata;
+use crate::scripts::{self, Script, ScriptMode};
+use crate::tables;
+use crate::xml::{elements, text};
+
+/// Elements whose text is part of the paragraph they sit in.
+const INLINE_ELEMENTS: &[&str] = &[
+    "emphasis",
+    "strong",
+    "strikethrough",
+    "code",
+    "style",
+    "a",
+    "sup",
+    "sub",
+];
+
+pub fn extract(path: &Path, metadata: Option<&Metadata>, options: HtmlOptions) -> Result<Document> {
+    let bytes = read(path)?;
+    let xml = decode(&bytes);
+    let xml = roxmltree::Document::parse(&xml)
+        .context(format!("Failed to parse FB2 XML: {}", path.display()))?;
+    let root = xml.root_element();
+
+    let embedded = root
+        .children()
+        .find(|node| node.has_tag_name("description"))
+        .map(description)
+        .unwrap_or_default();
+
+    let mut book = Book {
+        options,
+        sections: Vec::new(),
+        outline: Vec::new(),
+        landmarks: Landmarks::default(),
+    };
+    for (index, body) in elements(root, "body").enumerate() {
+        if index > 0 && book.landmarks.back_matter.is_none() {
+            book.landmarks.back_matter = Some(book.sections.len());
+        }
+        book.body(body, index == 0);
+    }
+
+    let embedded_language = embedded.language.clone();
+    for section in &mut book.sections {
+        if section.language.is_some() && section.language == embedded_language {
+            section.language = None;
+        }
+    }
+
+    Ok(Document {
+        metadata: metadata.cloned().unwrap_or_default().or(embedded),
+        sections: book.sections,
+        outline: book.outline,
+        landmarks: book.landmarks,
+    })
+}
+
+/// The FB2 document of a `.fb2` file, or of the first `.fb2` entry of a zip archive.
+fn read(path: &Path) -> Result<Vec<u8>> {
+    let zipped = path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("zip"));
+    if !zipped {
+        return fs::read(path).context(format!("Failed to read FB2 file: {}", path.display()));
+    }
+
+    let file = File::open(path).context(format!("Failed to open: {}", path.display()))?;
+    let mut archive =
+        ZipArchive::new(file).context(format!("Not a zip archive: {}", path.display()))?;
+    let name = archive
+        .file_names()
+        .find(|name| name.to_lowercase().ends_with(".fb2"))
+        .map(str::to_string)
+        .context(format!("No .fb2 file in {}", path.display()))?;
+    let mut bytes = Vec::new();
+    archive.by_name(&name)?.read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// The document as text, in the encoding its byte order mark or XML declaration names
+/// (UTF-8 when neither does).
+fn decode(bytes: &[u8]) -> String {
+    let head = String::from_utf8_lossy(&bytes[..bytes.len().min(200)]);
+    let declared = head
+        .find("encoding=")
+        .and_then(|at| {
+            let value = &head[at + "encoding=".len()..];
+            let quote = value.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+            value[1..].split(quote).next()
+        })
+        .and_then(|label| encoding_rs::Encoding::for_label(label.trim().as_bytes()))
+        .unwrap_or(encoding_rs::UTF_8);
+    let (text, _, _) = declared.decode(bytes);
+    text.into_owned()
+}
+
+/// Title, authors and language from `<title-info>`.
+fn description(description: Node) -> Metadata {
+    let Some(info) = elements(description, "title-info").next() else {
+        return Metadata::default();
+    };
+    let authors: Vec<String> = elements(info, "author").filter_map(author_name).collect();
+
+    Metadata {
+        title: elements(info, "book-title")
+            .next()
+            .map(text)
+            .filter(|t| !t.is_empty()),
+        author: (!authors.is_empty()).then(|| authors.join(" & ")),
+        language: elements(info, "lang")
+            .next()
+            .map(text)
+            .filter(|l| !l.is_empty()),
+        collection: None,
+    }
+}
+
+/// "First Middle Last", or the nickname of an author known by no other name.
+fn author_name(author: Node) -> Option<String> {
+    let parts: Vec<String> = ["first-name", "middle-name", "last-name"]
+        .iter()
+        .filter_map(|name| elements(author, name).next().map(text))
+        .filter(|part| !part.is_empty())
+        .collect();
+    if parts.is_empty() {
+        elements(author, "nickname")
+            .next()
+            .map(text)
+            .filter(|nickname| !nickname.is_empty())
+    } else {
+        Some(parts.join(" "))
+    }
+}
+
+/// Sections and bookmarks collected from the bodies.
+struct Book {
+    options: HtmlOptions,
+    sections: Vec<Section>,
+    outline: Vec<OutlineEntry>,
+    landmarks: Landmarks,
+}
+
+impl Book {
+    /// Reads one `<body>`. The main body's own title and epigraphs (usually the title page)
+    /// become a section of their own, before the body proper starts.
+    fn body(&mut self, body: Node, main: bool) {
+        let language = language(body);
+        let mut lines = Vec::new();
+        let mut title = None;
+        for child in body.children().filter(Node::is_element) {
+            match child.tag_name().name() {
+                "title" => title = Some(heading(child)).filter(|t| !t.is_empty()),
+                "section" => break,
+                _ => self.block(child, &mut lines),
+            }
+        }
+
+        if main {
+            if let Some(title) = &title {
+                lines.insert(0, title.clone());
+            }
+            self.push(lines, language.clone());
+            self.landmarks.body_start = Some(self.sections.len());
+            for section in elements(body, "section") {
+                self.section(section, 1, language.as_deref());
+            }
+            return;
+        }
+
+        // Notes and comments: the body's title ("Notes") heads its sections
+        let level = match title {
+            Some(title) => {
+                self.outline.push(OutlineEntry {
+                    title: title.clone(),
+                    level: 1,
+                    section: self.sections.len(),
+                });
+                lines.insert(0, title);
+                2
+            }
+            None => 1,
+        };
+        self.push(lines, language.clone());
+        for section in elements(body, "section") {
+            self.section(section, level, language.as_deref());
+        }
+    }
+
+    /// Reads a `<section>` into a section of the document, up to its first nested section;
+    /// the nested sections follow as sections of their own, one level down.
+    fn section(&mut self, section: Node, level: usize, inherited: Option<&str>) {
+        let language = language(section).or_else(|| inherited.map(str::to_string));
+        let mut lines = Vec::new();
+        let index = self.sections.len();
+        let mut nested = Vec::new();
+
+        for child in section.children().filter(Node::is_element) {
+            match child.tag_name().name() {
+                "title" => {
+                    let title = heading(child);
+                    if !title.is_empty() {
+                        self.outline.push(OutlineEntry {
+                            title: title.clone(),
+                            level,
+                            section: index,
+                        });
+                        lines.push(title);
+                    }
+                }
+                "section" => nested.push(child),
+                _ if nested.is_empty() => self.block(child, &mut lines),
+                // Text after a nested section is rare; it stays with the section it follows
+                _ => {}
+            }
+        }
+
+        self.push(lines, language.clone());
+        for child in nested {
+            self.section(child, level + 1, language.as_deref());
+        }
+    }
+
+    fn push(&mut self, lines: Vec<String>, language: Option<String>) {
+        if lines.is_empty() {
+            return;
+        }
+        let text = lines.join("\n");
+        let text = if self.options.image_placeholders {
+            figures::label(&text, false)
+        } else {
+            text
+        };
+        self.sections.push(Section {
+            kind: SectionKind::Chapter,
+            title: None,
+            text,
+            language,
+            page: None,
+        });
+    }
+
+    /// Appends the lines of a block element: one line per paragraph, verse line or row.
+    fn block(&self, node: Node, lines: &mut Vec<String>) {
+        match node.tag_name().name() {
+            "p" | "v" | "subtitle" | "text-author" => {
+                let line = self.inline(node);
+                if !line.is_empty() {
+                    lines.push(line);
+                }
+            }
+            "title" => {
+                let title = heading(node);
+                if !title.is_empty() {
+                    lines.push(title);
+                }
+            }
+            "image" if self.options.image_placeholders => {
+                let description = node
+                    .attribute("alt")
+                    .or(node.attribute("title"))
+                    .unwrap_or_default();
+                lines.push(format!("{IMAGE}{description}{IMAGE}"));
+            }
+            "table" => self.table(node, lines),
+            "epigraph" | "cite" | "poem" | "stanza" | "annotation" => {
+                for child in node.children().filter(Node::is_element) {
+                    self.block(child, lines);
+                }
+            }
+            // "empty-line" and anything unknown print nothing
+            _ => {}
+        }
+    }
+
+    fn table(&self, table: Node, lines: &mut Vec<String>) {
+        let rows: Vec<Vec<String>> = elements(table, "tr")
+            .map(|row| {
+                row.children()
+                    .filter(|cell| cell.has_tag_name("th") || cell.has_tag_name("td"))
+                    .map(|cell| self.inline(cell))
+                    .collect::<Vec<_>>()
+            })
+            .filter(|row| row.iter().any(|cell| !cell.is_empty()))
+            .collect();
+        if rows.is_empty() {
+            return;
+        }
+
+        if self.options.tables {
+            lines.push(tables::markdown(&rows));
+        } else {
+            lines.extend(rows.into_iter().map(|row| row.join(" | ")));
+        }
+    }
+
+    /// The text of a paragraph-like element on one line, with whitespace collapsed.
+    fn inline(&self, node: Node) -> String {
+        let mut text = String::new();
+        self.append_inline(node, &mut text);
+        text.split_whitespace().collect::<Vec<_>>().join(" ")
+    }
+
+    fn append_inline(&self, node: Node, out: &mut String) {
+        for child in node.children() {
+            if child.is_text() {
+                out.push_str(child.text().unwrap_or_default());
+                continue;
+            }
+            let name = child.tag_name().name();
+            if !INLINE_ELEMENTS.contains(&name) {
+                continue;
+            }
+            let script = match name {
+                "sup" => Some(Script::Super),
+                "sub" => Some(Script::Sub),
+                _ => None,
+            };
+            match script {
+                Some(script) if self.options.scripts != ScriptMode::Keep => {
+                    let mut inner = String::new();
+                    self.append_inline(child, &mut inner);
+                    let follows_digit = out.chars().last().is_some_and(|c| c.is_ascii_digit());
+                    out.push_str(&scripts::render(
+                        inner.trim(),
+                        script,
+                        self.options.scripts,
+                        follows_digit,
+                    ));
+                }
+                _ => self.append_inline(child, out),
+            }
+        }
+    }
+}
+
+/// A `<title>` on one line: its paragraphs joined, with a full stop between them unless
+/// one already ends in punctuation ("Chapter One. The Boy Who Lived").
+fn heading(title: Node) -> String {
+    let mut heading = String::new();
+    for paragraph in elements(title, "p").map(text).filter(|p| !p.is_empty()) {
+        if !heading.is_empty() {
+            if !heading.ends_with(|c: char| c.is_ascii_punctuation() || c == '…') {
+                heading.push('.');
+            }
+            heading.push(' ');
+        }
+        heading.push_str(&paragraph);
+    }
+    heading
+}
+
+/// The `xml:lang` of an element.
+fn language(node: Node) -> Option<String> {
+    node.attribute(("http://www.w3.org/XML/1998/namespace", "lang"))
+        .map(str::trim)
+        .filter(|language| !language.is_empty())
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use zip::write::SimpleFileOptions;
+    use zip::ZipWriter;
+
+    const BOOK: &str = r##"<?xml version="1.0" encoding="utf-8"?>
+<FictionBook xmlns="http://www.gribuser.ru/xml/fictionbook/2.0" xmlns:l="http://www.w3.org/1999/xlink">
+  <description><title-info>
+    <author><first-name>Lev</first-name><middle-name/><last-name>Tolstoy</last-name></author>
+    <author><nickname>Anon</nickname></author>
+    <author><first-name> </first-name></author>
+    <book-title>War and Peace</book-title><lang>ru</lang>
+  </title-info></description>
+  <body xml:lang="ru">
+    <title><p>War</p><p>and Peace</p></title>
+    <epigraph><p>Quote</p><text-author>Someone</text-author></epigraph>
+    <section>
+      <title><p>Part One</p></title>
+      <p>Text <emphasis>here</emphasis><a l:href="#n1">[1]</a>.</p>
+      <section>
+        <title><p>Chapter 1!</p><p>Begins</p></title>
+        <poem><stanza><v>Line one</v><v>Line two</v></stanza></poem>
+        <empty-line/>
+        <p>x<sup>2</sup> <unknown>dropped</unknown></p>
+      </section>
+      <section xml:lang="fr"><title><p>Chapitre 2</p></title><p>Bonjour</p></section>
+    </section>
+  </body>
+  <body name="notes">
+    <title><p>Notes</p></title>
+    <section id="n1"><title><p>1</p></title><p>A note</p></section>
+  </body>
+</FictionBook>"##;
+
+    fn convert(name: &str, bytes: &[u8], options: HtmlOptions) -> Result<Document> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(name);
+        fs::write(&path, bytes).unwrap();
+        extract(&path, None, options)
+    }
+
+    fn texts(doc: &Document) -> Vec<&str> {
+        doc.sections
+            .iter()
+            .map(|section| section.text.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn bodies_sections_and_notes() {
+        let doc = convert("book.fb2", BOOK.as_bytes(), HtmlOptions::default()).unwrap();
+        assert_eq!(
+            texts(&doc),
+            [
+                "War. and Peace\nQuote\nSomeone",
+                "Part One\nText here[1].",
+                "Chapter 1! Begins\nLine one\nLine two\nx2",
+                "Chapitre 2\nBonjour",
+                "Notes",
+                "1\nA note",
+            ]
+        );
+        let outline: Vec<_> = doc
+            .outline
+            .iter()
+            .map(|entry| (entry.title.as_str(), entry.level, entry.section))
+            .collect();
+        assert_eq!(
+            outline,
+            [
+                ("Part One", 1, 1),
+                ("Chapter 1! Begins", 2, 2),
+                ("Chapitre 2", 2, 3),
+                ("Notes", 1, 4),
+                ("1", 2, 5),
+            ]
+        );
+        assert_eq!(doc.landmarks.body_start, Some(1));
+        assert_eq!(doc.landmarks.back_matter, Some(4));
+
+        // Only a language that differs from the book's is kept on a section
+        let languages: Vec<_> = doc
+            .sections
+            .iter()
+            .map(|section| section.language.as_deref())
+            .collect();
+        assert_eq!(languages, [None, None, None, Some("fr"), None, None]);
+
+        assert_eq!(doc.metadata.title.as_deref(), Some("War and Peace"));
+        assert_eq!(doc.metadata.author.as_deref(), Some("Lev Tolstoy & Anon"));
+        assert_eq!(doc.metadata.language.as_deref(), Some("ru"));
+    }
+
+    #[test]
+    fn options_apply_to_scripts_tables_and_images() {
+        let book = r#"<FictionBook><body><section>
+            <p>x<sup>2</sup></p>
+            <image alt="A map"/>
+            <table><tr><th>A</th><th>B</th></tr><tr><td/><td/></tr><tr><td>1</td><td>2</td></tr></table>
+          </section></body></FictionBook>"#;
+        let doc = convert("book.fb2", book.as_bytes(), HtmlOptions::default()).unwrap();
+        assert_eq!(texts(&doc), ["x2\nA | B\n1 | 2"]);
+
+        let options = HtmlOptions {
+            scripts: ScriptMode::Unicode,
+            image_placeholders: true,
+            tables: true,
+            ..Default::default()
+        };
+        let doc = convert("book.fb2", book.as_bytes(), options).unwrap();
+        let text = texts(&doc)[0];
+        assert!(text.starts_with("x²\n[Figure: A map]\n"), "{text}");
+        assert!(text.contains("| A | B |"), "{text}");
+    }
+
+    #[test]
+    fn declared_code_pages_are_decoded() {
+        let (bytes, _, _) = encoding_rs::WINDOWS_1251.encode("Война и мир");
+        let mut book =
+            b"<?xml version=\"1.0\" encoding='windows-1251'?><FictionBook><body><section><p>"
+                .to_vec();
+        book.extend_from_slice(&bytes);
+        book.extend_from_slice(b"</p></section></body></FictionBook>");
+        let doc = convert("book.fb2", &book, HtmlOptions::default()).unwrap();
+        assert_eq!(texts(&doc), ["Война и мир"]);
+
+        assert_eq!(
+            decode(b"<?xml encoding=\"no-such\"?><a/>"),
+            "<?xml encoding=\"no-such\"?><a/>"
+        );
+        assert_eq!(
+            decode(b"<?xml encoding=unquoted?>"),
+            "<?xml encoding=unquoted?>"
+        );
+        assert_eq!(decode(b""), "");
+    }
+
+    #[test]
+    fn zipped_books() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("book.fb2.zip");
+        let mut zip = ZipWriter::new(File::create(&path).unwrap());
+        zip.start_file("cover.jpg", SimpleFileOptions::default())
+            .unwrap();
+        zip.start_file("Book.FB2", SimpleFileOptions::default())
+            .unwrap();
+        zip.write_all(BOOK.as_bytes()).unwrap();
+        zip.finish().unwrap();
+        let doc = extract(&path, None, HtmlOptions::default()).unwrap();
+        assert_eq!(doc.sections.len(), 6);
+    }
+
+    #[test]
+    fn malformed_books() {
+        let error =
+            convert("book.fb2", b"<FictionBook><body>", HtmlOptions::default()).unwrap_err();
+        assert!(error.to_string().starts_with("Failed to parse FB2 XML"));
+        let error = convert("book.fb2.zip", BOOK.as_bytes(), HtmlOptions::default()).unwrap_err();
+        assert!(error.to_string().starts_with("Not a zip archive"));
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("empty.fb2.zip");
+        let mut zip = ZipWriter::new(File::create(&path).unwrap());
+        zip.start_file("readme.txt", SimpleFileOptions::default())
+            .unwrap();
+        zip.finish().unwrap();
+        let error = extract(&path, None, HtmlOptions::default()).unwrap_err();
+        assert!(error.to_string().starts_with("No .fb2 file in"));
+
+        // A book without a description or bodies is empty, not an error
+        let doc = convert("book.fb2", b"<FictionBook/>", HtmlOptions::default()).unwrap();
+        assert!(doc.sections.is_empty());
+        assert_eq!(doc.metadata.title, None);
+    }
+}
@@ -0,0 +1,253 @@
+//! Placeholders where images occurred, so references to figures in the text still have
+//! something to point at.
+//!
+//! While text is assembled each image becomes a line starting with [`IMAGE`]: PDF image
+//! XObjects announce themselves through a marker path injected before their `Do`, the same
+//! way [`crate::structure`] announces marked content, and EPUB `<img>` and `<figure>`
+//! elements are replaced as the document tree is read. [`label`] then turns each one into
+//! `[Figure 3.1: caption]`, taking the caption from a numbered caption line next to it or
+//! from the figure's own caption or alt text.
+
+use anyhow::Result;
+use pdf_extract::content::{Content, Operation};
+use pdf_extract::{Document, Object, ObjectId, Path, PathOp};
+
+use scraper::ElementRef;
+
+use crate::dom::collapsed_text;
+use crate::layers;
+
+/// Stands in for an image in assembled text. EPUB images carry their description up to a
+/// second one: "\u{fffc}A map of the harbour\u{fffc}".
+pub const IMAGE: char = '\u{fffc}';
+
+/// x coordinate of the injected marker path; see [`crate::structure`] for the others.
+const IMAGE_MARKER_X: f64 = -986_000.0;
+
+/// Images smaller than this in both directions, in points, are decoration such as bullets,
+/// rules and icons.
+const MIN_IMAGE_SIZE: f64 = 24.0;
+
+/// Openings of numbered captions, with the label the placeholder uses.
+const CAPTION_LABELS: &[(&str, &str)] = &[
+    ("figure ", "Figure"),
+    ("fig. ", "Figure"),
+    ("fig ", "Figure"),
+    ("plate ", "Plate"),
+    ("illustration ", "Illustration"),
+    ("chart ", "Chart"),
+    ("diagram ", "Diagram"),
+    ("map ", "Map"),
+    ("exhibit ", "Exhibit"),
+];
+
+/// Lines a PDF caption may run over, its numbered first line included.
+const MAX_CAPTION_LINES: usize = 3;
+
+/// Announces every image XObject drawn on the page with an empty fill at the marker
+/// position, under the image's own transform.
+pub fn mark_images(doc: &mut Document, page_id: ObjectId) -> Result<()> {
+    let content = Content::decode(&doc.get_page_content(page_id)?)?;
+    let mut operations = Vec::with_capacity(content.operations.len());
+
+    for operation in content.operations {
+        if is_image(doc, page_id, &operation) {
+            operations.push(Operation::new(
+                "m",
+                vec![Object::Real(IMAGE_MARKER_X as f32), Object::Integer(0)],
+            ));
+            operations.push(Operation::new("f", Vec::new()));
+        }
+        operations.push(operation);
+    }
+
+    doc.change_page_content(page_id, Content { operations }.encode()?)?;
+    Ok(())
+}
+
+fn is_image(doc: &Document, page_id: ObjectId, operation: &Operation) -> bool {
+    if operation.operator != "Do" {
+        return false;
+    }
+    operation
+        .operands
+        .first()
+        .and_then(|o| o.as_name().ok())
+        .and_then(|name| layers::resource(doc, page_id, b"XObject", name))
+        .and_then(|xobject| doc.dereference(xobject).ok())
+        .and_then(|(_, xobject)| xobject.as_stream().ok())
+        .and_then(|stream| stream.dict.get(b"Subtype").and_then(Object::as_name).ok())
+        == Some(b"Image".as_slice())
+}
+
+/// Recognises the marker path injected by [`mark_images`].
+pub fn is_image_marker(path: &Path) -> bool {
+    matches!(path.ops.as_slice(), [PathOp::MoveTo(x, _)] if (*x - IMAGE_MARKER_X).abs() < 1.0)
+}
+
+/// Whether an image spanning `width` by `height` points is large enough to mention.
+pub fn is_figure(width: f64, height: f64) -> bool {
+    width >= MIN_IMAGE_SIZE || height >= MIN_IMAGE_SIZE
+}
+
+/// The description of a `<figure>` element, its `<figcaption>` or else the alt text of its
+/// first image, or of an `<img>`/`<image>` element, its alt text.
+pub fn html_description(element: ElementRef) -> String {
+    let alt = |element: ElementRef| {
+        element
+            .value()
+            .attr("alt")
+            .map(|alt| alt.trim().to_string())
+    };
+    if element.value().name() != "figure" {
+        return alt(element).unwrap_or_default();
+    }
+    let mut descendants = element.descendants().filter_map(ElementRef::wrap);
+    if let Some(caption) = descendants
+        .clone()
+        .find(|e| e.value().name() == "figcaption")
+    {
+        return collapsed_text(caption);
+    }
+    descendants
+        .find(|e| matches!(e.value().name(), "img" | "image"))
+        .and_then(alt)
+        .unwrap_or_default()
+}
+
+/// Replaces image lines with `[Figure ...]` placeholders. A numbered caption on the line
+/// after the image, or else just before it, is folded into the placeholder.
+///
+/// `paragraphs` is set for PDF pages, where blank lines separate paragraphs: captions may
+/// run over several lines, and placeholders are set off by blank lines.
+pub fn label(text: &str, paragraphs: bool) -> String {
+    let lines: Vec<&str> = text.lines().collect();
+    let mut captions = vec![false; lines.len()];
+    let mut labels: Vec<Option<(String, &str)>> = vec![None; lines.len()];
+
+    for (i, line) in lines.iter().enumerate() {
+        let Some(image) = line.trim().strip_prefix(IMAGE) else {
+            continue;
+        };
+        let (description, rest) = image.split_once(IMAGE).unwrap_or(("", image));
+        let description = description.trim();
+
+        let label = numbered_caption(description)
+            .or_else(|| take_caption(&lines, &mut captions, i, paragraphs))
+            .unwrap_or_else(|| {
+                if description.is_empty() {
+                    "[Figure]".to_string()
+                } else {
+                    format!("[Figure: {description}]")
+                }
+            });
+        labels[i] = Some((label, rest.trim()));
+    }
+
+    let mut out: Vec<&str> = Vec::with_capacity(lines.len());
+    // Set while the last line out is the blank line after a placeholder
+    let mut set_off = false;
+    for (i, line) in lines.iter().enumerate() {
+        if captions[i] {
+            continue;
+        }
+        let Some((label, rest)) = &labels[i] else {
+            if !(set_off && line.trim().is_empty()) {
+                out.push(line);
+                set_off = false;
+            }
+            continue;
+        };
+
+        if paragraphs && out.last().is_some_and(|last| !last.trim().is_empty()) {
+            out.push("");
+        }
+        out.push(label);
+        set_off = paragraphs;
+        if paragraphs {
+            out.push("");
+        }
+        if !rest.is_empty() {
+            out.push(rest);
+            set_off = false;
+        }
+    }
+
+    let mut labelled = out.join("\n");
+    if text.ends_with('\n') {
+        labelled.push('\n');
+    }
+    labelled
+}
+
+/// Finds a numbered caption next to the image on line `image`, marks its lines as used and
+/// returns the placeholder built from it.
+fn take_caption(
+    lines: &[&str],
+    captions: &mut [bool],
+    image: usize,
+    paragraphs: bool,
+) -> Option<String> {
+    let usable = |i: &usize| {
+        let line = lines[*i].trim();
+        !line.is_empty() && !line.starts_with(IMAGE) && !captions[*i]
+    };
+
+    if let Some(first) = (image + 1..lines.len()).find(usable) {
+        if numbered_caption(lines[first].trim()).is_some() {
+            let mut last = first;
+            if paragraphs {
+                while last + 1 < lines.len()
+                    && last - first + 1 < MAX_CAPTION_LINES
+                    && usable(&(last + 1))
+                    && numbered_caption(lines[last + 1].trim()).is_none()
+                {
+                    last += 1;
+                }
+            }
+            captions[first..=last]
+                .iter_mut()
+                .for_each(|used| *used = true);
+            let caption: Vec<&str> = lines[first..=last].iter().map(|l| l.trim()).collect();
+            return numbered_caption(&caption.join(" "));
+        }
+    }
+
+    let before = (0..image).rev().find(usable)?;
+    let label = numbered_caption(lines[before].trim())?;
+    captions[before] = true;
+    Some(label)
+}
+
+/// `[Figure 3.1: caption text]` from a caption such as "Fig. 3.1. Caption text".
+fn numbered_caption(caption: &str) -> Option<String> {
+    let (opening, label) = CAPTION_LABELS.iter().find(|(opening, _)| {
+        caption
+            .get(..opening.len())
+            .is_some_and(|start| start.eq_ignore_ascii_case(opening))
+    })?;
+
+    let rest = caption[opening.len()..].trim_start();
+    let number = rest
+        .split_whitespace()
+        .next()?
+        .trim_end_matches(['.', ':', ',']);
+    let numbered = number.chars().any(|c| c.is_ascii_digit())
+        && number
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '–'));
+    if !numbered {
+        return None;
+    }
+
+    let text = rest
+        .split_once(char::is_whitespace)
+        .map_or("", |(_, text)| text)
+        .trim_start_matches([':', '.', '-', '–', '—', ' '])
+        .trim();
+    Some(if text.is_empty() {
+        format!("[{label} {number}]")
+    } else {
+        format!("[{label} {number}: {text}]")
+    })
+}
@@ -0,0 +1,288 @@
+//! Choosing which files under `--target` are extracted by glob patterns or regular
+//! expressions on their paths, so part of a library can be processed without moving folders
+//! around, and by their size and modification time.
+
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use glob::{MatchOptions, Pattern};
+use regex::Regex;
+
+/// Patterns match case-insensitively, and `*` stops at folder separators where `**` does not.
+const MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: false,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+/// Glob patterns matched against paths relative to the folder searched, e.g. `**/*.pdf`, and
+/// regular expressions matched against whole paths as found, e.g. `_(draft|old)\.pdf$`.
+#[derive(Debug, Clone)]
+pub struct PathFilter {
+    /// Documents must match one of these or of `include_regex`, when there are any
+    pub include: Vec<Pattern>,
+    /// Files and folders matching any of these are left out
+    pub exclude: Vec<Pattern>,
+    pub include_regex: Vec<Regex>,
+    pub exclude_regex: Vec<Regex>,
+    /// Leave out what `.gitignore` and `.toignore` files in the folder searched ignore
+    pub ignore_files: bool,
+    /// Levels of folders searched, 1 being only the files directly in the folder searched
+    pub max_depth: Option<usize>,
+    /// Search through symbolic links, which are otherwise left out
+    pub follow_symlinks: bool,
+    /// Documents smaller than this many bytes are left out
+    pub min_size: Option<u64>,
+    /// Documents larger than this many bytes are left out
+    pub max_size: Option<u64>,
+    /// Documents last modified before this are left out
+    pub modified_after: Option<SystemTime>,
+    /// Documents last modified at or after this are left out
+    pub modified_before: Option<SystemTime>,
+}
+
+impl Default for PathFilter {
+    fn default() -> Self {
+        PathFilter {
+            include: Vec::new(),
+            exclude: Vec::new(),
+            include_regex: Vec::new(),
+            exclude_regex: Vec::new(),
+            ignore_files: true,
+            max_depth: None,
+            follow_symlinks: false,
+            min_size: None,
+            max_size: None,
+            modified_after: None,
+            modified_before: None,
+        }
+    }
+}
+
+impl PathFilter {
+    /// Whether the document at `path`, found at `relative` under the folder searched, is
+    /// extracted.
+    pub fn accepts(&self, path: &Path, relative: &Path) -> bool {
+        if self.excluded(path, relative) || !self.metadata_accepted(path) {
+            return false;
+        }
+        if self.include.is_empty() && self.include_regex.is_empty() {
+            return true;
+        }
+        let full = path.to_string_lossy();
+        self.include
+            .iter()
+            .any(|p| p.matches_path_with(relative, MATCH_OPTIONS))
+            || self.include_regex.iter().any(|r| r.is_match(&full))
+    }
+
+    /// Whether the size and modification time of the file at `path` are within the limits;
+    /// files whose metadata cannot be read are left to extraction to report.
+    fn metadata_accepted(&self, path: &Path) -> bool {
+        if self.min_size.is_none()
+            && self.max_size.is_none()
+            && self.modified_after.is_none()
+            && self.modified_before.is_none()
+        {
+            return true;
+        }
+        let Ok(metadata) = fs::metadata(path) else {
+            return true;
+        };
+        let size = metadata.len();
+        if self.min_size.is_some_and(|min| size < min)
+            || self.max_size.is_some_and(|max| size > max)
+        {
+            return false;
+        }
+        let Ok(modified) = metadata.modified() else {
+            return true;
+        };
+        self.modified_after.is_none_or(|after| modified >= after)
+            && self.modified_before.is_none_or(|before| modified < before)
+    }
+
+    /// Whether the file or folder at `path`, found at `relative` under the folder searched,
+    /// matches an exclude pattern. Folders excluded are not searched, and archives are only
+    /// held to these patterns, since include patterns name the documents wanted.
+    pub fn excluded(&self, path: &Path, relative: &Path) -> bool {
+        let full = path.to_string_lossy();
+        self.exclude
+            .iter()
+            .any(|p| p.matches_path_with(relative, MATCH_OPTIONS))
+            || self.exclude_regex.iter().any(|r| r.is_match(&full))
+    }
+}
+
+/// Compiles an `--include` or `--exclude` pattern.
+pub fn parse_pattern(pattern: &str) -> Result<Pattern, String> {
+    Pattern::new(pattern).map_err(|e| format!("invalid glob pattern {pattern}: {e}"))
+}
+
+/// Compiles an `--include-regex` or `--exclude-regex` expression, case-sensitive unless it
+/// starts with `(?i)`.
+pub fn parse_regex(pattern: &str) -> Result<Regex, String> {
+    Regex::new(pattern).map_err(|e| e.to_string())
+}
+
+/// Parses the times of `--newer-than` and `--older-than`: a date (`2024-01-01`), a date and
+/// time (`2024-01-01T18:30`, seconds optional), both in UTC, or an age before now such as
+/// `36h`, `7d` or `2w`.
+pub fn parse_time(text: &str) -> Result<SystemTime, String> {
+    let text = text.trim();
+    if let Some(age) = parse_age(text) {
+        return SystemTime::now()
+            .checked_sub(age)
+            .ok_or_else(|| format!("age too large: {text}"));
+    }
+    let invalid = || format!("invalid date: {text} (use e.g. 2024-01-01, 2024-01-01T18:30 or 7d)");
+
+    let (date, time) = match text.split_once(['T', ' ']) {
+        Some((date, time)) => (date, Some(time)),
+        None => (text, None),
+    };
+    let mut fields = date.splitn(3, '-').map(str::parse::<i64>);
+    let (Some(Ok(year)), Some(Ok(month)), Some(Ok(day))) =
+        (fields.next(), fields.next(), fields.next())
+    else {
+        return Err(invalid());
+    };
+    // Years past 9999 would overflow the seconds counted below
+    if !(0..=9999).contains(&year)
+        || !(1..=12).contains(&month)
+        || day < 1
+        || day > days_in_month(year, month)
+    {
+        return Err(invalid());
+    }
+    let mut seconds = days_from_civil(year, month, day) * 86_400;
+    if let Some(time) = time {
+        // Unsigned, so `-5` is refused rather than counted back
+        let fields: Vec<i64> = time
+            .split(':')
+            .map(|field| field.parse::<u32>().map(i64::from))
+            .collect::<Result<_, _>>()
+            .map_err(|_| invalid())?;
+        let limits = [24, 60, 60];
+        if !(2..=3).contains(&fields.len())
+            || fields
+                .iter()
+                .zip(limits)
+                .any(|(&field, limit)| field >= limit)
+        {
+            return Err(invalid());
+        }
+        seconds += fields
+            .iter()
+            .zip([3_600, 60, 1])
+            .map(|(field, unit)| field * unit)
+            .sum::<i64>();
+    }
+
+    let offset = Duration::from_secs(seconds.unsigned_abs());
+    let time = if seconds >= 0 {
+        UNIX_EPOCH.checked_add(offset)
+    } else {
+        UNIX_EPOCH.checked_sub(offset)
+    };
+    time.ok_or_else(invalid)
+}
+
+/// An age such as `30m`, `36h`, `7d` or `2w`.
+fn parse_age(text: &str) -> Option<Duration> {
+    let unit = match text.chars().last()? {
+        'm' => 60,
+        'h' => 3_600,
+        'd' => 86_400,
+        'w' => 7 * 86_400,
+        _ => return None,
+    };
+    let number: u64 = text[..text.len() - 1].parse().ok()?;
+    Some(Duration::from_secs(number.checked_mul(unit)?))
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Days from 1970-01-01 to a date of the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    // Counted in years starting in March, so the leap day falls at the end
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seconds(text: &str) -> u64 {
+        parse_time(text)
+            .unwrap()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    }
+
+    #[test]
+    fn dates_and_times() {
+        assert_eq!(seconds("1970-01-01"), 0);
+        assert_eq!(seconds("2024-01-01"), 1_704_067_200);
+        assert_eq!(seconds("2024-02-29T18:30"), 1_709_231_400);
+        assert_eq!(seconds("2024-02-29 18:30:15"), 1_709_231_415);
+        assert_eq!(seconds(" 2000-03-01 "), 951_868_800);
+        assert_eq!(
+            parse_time("1969-12-31").unwrap(),
+            UNIX_EPOCH - Duration::from_secs(86_400)
+        );
+    }
+
+    #[test]
+    fn invalid_dates() {
+        for text in [
+            "2023-02-29",
+            "1900-02-29",
+            "2024-13-01",
+            "2024-04-31",
+            "2024-01-00",
+            "2024-01",
+            "2024-01-01T24:00",
+            "2024-01-01T18",
+            "2024-01-01T18:30:00:00",
+            "2024-01-01T-5:-3",
+            "2024-01-01T18:-30",
+            "2024--1-01",
+            "100000000000000000-01-01",
+            "-100000000000000000-01-01",
+            "10000-01-01",
+            "yesterday",
+            "",
+        ] {
+            assert!(parse_time(text).is_err(), "{text}");
+        }
+    }
+
+    #[test]
+    fn ages() {
+        assert_eq!(parse_age("30m"), Some(Duration::from_secs(1_800)));
+        assert_eq!(parse_age("2w"), Some(Duration::from_secs(1_209_600)));
+        assert_eq!(parse_age("7"), None);
+        assert_eq!(parse_age("d"), None);
+        assert_eq!(parse_age("7é"), None);
+        assert_eq!(parse_age("99999999999999999999d"), None);
+        let week_ago = parse_time("7d").unwrap();
+        let elapsed = SystemTime::now().duration_since(week_ago).unwrap();
+        assert!(elapsed >= Duration::from_secs(7 * 86_400));
+        assert!(elapsed < Duration::from_secs(7 * 86_400 + 60));
+    }
+}
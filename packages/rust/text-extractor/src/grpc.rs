@@ -0,0 +1,274 @@
+//! Extraction over gRPC, for microservices that would rather speak protobuf than upload forms
+//! to [`crate::server`]. The service is described in `proto/to_texts.proto`.
+//!
+//! `Extract` reads one document sent as a stream of chunks and answers with its sections,
+//! metadata, outline and rendered text, as [`crate::extract_file_with`] returns them.
+//! `ExtractBatch` reads documents one after another, each ending with a chunk marked `last`,
+//! and streams a result back for each, so one unreadable document does not end the batch.
+
+use anyhow::Result;
+use std::io::Write;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio::sync::{mpsc, Semaphore};
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::Stream;
+use tonic::{Request, Response, Status, Streaming};
+
+use crate::document::{Document, SectionKind};
+use crate::extract::{self, ExtractOptions};
+use crate::output::{self, OutputFormat};
+use crate::provenance::Provenance;
+use crate::server::ServeOptions;
+use crate::source;
+
+/// Messages and service stubs generated from `proto/to_texts.proto`.
+#[allow(clippy::large_enum_variant)]
+pub mod proto {
+    tonic::include_proto!("to_texts.v1");
+}
+
+use proto::extractor_server::{Extractor, ExtractorServer};
+
+/// Serves extraction with `extract` until the listener fails.
+pub fn serve(options: &ServeOptions, extract: ExtractOptions) -> Result<()> {
+    let address = tokio::net::lookup_host(&options.listen);
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?;
+    runtime.block_on(async {
+        let address = address
+            .await
+            .ok()
+            .and_then(|mut addresses| addresses.next())
+            .ok_or_else(|| anyhow::anyhow!("Failed to listen on {}", options.listen))?;
+        let service = Service {
+            extract: Arc::new(extract),
+            slots: Arc::new(Semaphore::new(options.workers.max(1))),
+            max_upload: options.max_upload,
+        };
+        println!("Listening for gRPC on {address}");
+        tonic::transport::Server::builder()
+            .add_service(ExtractorServer::new(service))
+            .serve(address)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to listen on {}: {e}", options.listen))
+    })
+}
+
+#[derive(Clone)]
+struct Service {
+    extract: Arc<ExtractOptions>,
+    /// One permit per document that may be extracted at once
+    slots: Arc<Semaphore>,
+    max_upload: u64,
+}
+
+/// A document received in full, spooled to a file.
+struct Upload {
+    name: String,
+    extension: String,
+    format: Option<OutputFormat>,
+    spooled: tempfile::NamedTempFile,
+}
+
+#[tonic::async_trait]
+impl Extractor for Service {
+    async fn extract(
+        &self,
+        request: Request<Streaming<proto::Chunk>>,
+    ) -> Result<Response<proto::ExtractedDocument>, Status> {
+        let mut chunks = request.into_inner();
+        let upload = receive(&mut chunks, self.max_upload)
+            .await?
+            .ok_or_else(|| Status::invalid_argument("no document sent"))?;
+        let document = self
+            .extract_upload(upload)
+            .await
+            .map_err(|e| Status::invalid_argument(format!("{e:#}")))?;
+        Ok(Response::new(document))
+    }
+
+    type ExtractBatchStream =
+        Pin<Box<dyn Stream<Item = Result<proto::BatchResult, Status>> + Send>>;
+
+    async fn extract_batch(
+        &self,
+        request: Request<Streaming<proto::Chunk>>,
+    ) -> Result<Response<Self::ExtractBatchStream>, Status> {
+        let mut chunks = request.into_inner();
+        let (sender, results) = mpsc::channel(1);
+        let service = self.clone();
+        tokio::spawn(async move {
+            loop {
+                let upload = match receive(&mut chunks, service.max_upload).await {
+                    Ok(Some(upload)) => upload,
+                    Ok(None) => break,
+                    Err(status) => {
+                        // The stream cannot be followed past a broken document
+                        let _ = sender.send(Err(status)).await;
+                        break;
+                    }
+                };
+                let name = upload.name.clone();
+                let result = match service.extract_upload(upload).await {
+                    Ok(document) => proto::batch_result::Result::Document(document),
+                    Err(e) => proto::batch_result::Result::Error(format!("{e:#}")),
+                };
+                let answer = proto::BatchResult {
+                    name,
+                    result: Some(result),
+                };
+                if sender.send(Ok(answer)).await.is_err() {
+                    // The client went away
+                    break;
+                }
+            }
+        });
+        Ok(Response::new(Box::pin(ReceiverStream::new(results))))
+    }
+}
+
+impl Service {
+    /// Extracts `upload` once a slot is free.
+    async fn extract_upload(&self, upload: Upload) -> Result<proto::ExtractedDocument> {
+        let _slot = self.slots.acquire().await?;
+        let options = self.extract.clone();
+        tokio::task::spawn_blocking(move || extract_document(upload, &options)).await?
+    }
+}
+
+/// Reads the chunks of the next document and spools them to a file. Returns `None` when the
+/// stream ends before another document starts.
+async fn receive(
+    chunks: &mut Streaming<proto::Chunk>,
+    max_upload: u64,
+) -> Result<Option<Upload>, Status> {
+    let Some(first) = chunks.message().await? else {
+        return Ok(None);
+    };
+    let extension = if first.r#type.is_empty() {
+        source::extension(Path::new(&first.name))
+            .filter(|extension| source::SUPPORTED_EXTENSIONS.contains(&extension.as_str()))
+            .ok_or_else(|| {
+                Status::invalid_argument(format!(
+                    "unsupported type: {} (use a supported extension or set type)",
+                    first.name
+                ))
+            })?
+    } else {
+        source::parse_extension(&first.r#type).map_err(Status::invalid_argument)?
+    };
+    let format = match first.format() {
+        proto::Format::Unspecified => None,
+        proto::Format::Text => Some(OutputFormat::Text),
+        proto::Format::Markdown => Some(OutputFormat::Markdown),
+        proto::Format::Json => Some(OutputFormat::Json),
+    };
+    // Documents sent without a name are named `-`, as those read from standard input are
+    let name = match first.name.as_str() {
+        "" => "-".to_string(),
+        name => name.to_string(),
+    };
+    let mut spooled =
+        extract::spool_file(&extension).map_err(|e| Status::internal(format!("{e:#}")))?;
+
+    let mut size = 0;
+    let mut chunk = first;
+    loop {
+        size += chunk.data.len() as u64;
+        if size > max_upload {
+            let limit = crate::memory::format_size(max_upload);
+            return Err(Status::resource_exhausted(format!(
+                "document larger than {limit}"
+            )));
+        }
+        spooled
+            .write_all(&chunk.data)
+            .map_err(|e| Status::internal(format!("failed to spool the document: {e}")))?;
+        if chunk.last {
+            break;
+        }
+        match chunks.message().await? {
+            Some(next) => chunk = next,
+            None => break,
+        }
+    }
+    spooled
+        .flush()
+        .map_err(|e| Status::internal(format!("failed to spool the document: {e}")))?;
+
+    Ok(Some(Upload {
+        name,
+        extension,
+        format,
+        spooled,
+    }))
+}
+
+fn extract_document(upload: Upload, options: &ExtractOptions) -> Result<proto::ExtractedDocument> {
+    let (extracted, provenance) =
+        extract::extract_spooled(&upload.spooled, &upload.extension, options)?;
+    let mut output_options = options.output.clone();
+    if let Some(format) = upload.format {
+        output_options.format = format;
+    }
+    let source = Path::new(&upload.name);
+    let text = output::render(&extracted.document, source, &output_options);
+    Ok(document_message(
+        &extracted.document,
+        upload.name,
+        output_options.newline.apply(&text),
+        &provenance,
+    ))
+}
+
+fn document_message(
+    document: &Document,
+    name: String,
+    text: String,
+    provenance: &Provenance,
+) -> proto::ExtractedDocument {
+    let metadata = &document.metadata;
+    proto::ExtractedDocument {
+        name,
+        metadata: Some(proto::Metadata {
+            title: metadata.title.clone(),
+            author: metadata.author.clone(),
+            language: metadata.language.clone(),
+            collection: metadata.collection.clone(),
+        }),
+        sections: document
+            .sections
+            .iter()
+            .map(|section| proto::Section {
+                kind: match section.kind {
+                    SectionKind::Page => proto::section::Kind::Page,
+                    SectionKind::Chapter => proto::section::Kind::Chapter,
+                }
+                .into(),
+                title: section.title.clone(),
+                text: section.text.clone(),
+                language: section.language.clone(),
+                page: section.page.map(|page| page as u32),
+            })
+            .collect(),
+        outline: document
+            .outline
+            .iter()
+            .map(|entry| proto::OutlineEntry {
+                title: entry.title.clone(),
+                level: entry.level as u32,
+                section: entry.section as u32,
+            })
+            .collect(),
+        text,
+        provenance: Some(proto::Provenance {
+            tool: provenance.tool.to_string(),
+            backend: provenance.backend.to_string(),
+            options: provenance.options.clone(),
+            source_sha256: provenance.source_sha256.clone(),
+        }),
+    }
+}
@@ -0,0 +1,317 @@
+//! A small desktop window for running extractions without a terminal: pick an input and an
+//! output folder (or drop files onto the window), press Extract and watch the progress.
+//!
+//! Extraction runs on a worker thread with the options given on the command line, reporting
+//! each finished file back over a channel; the window only draws what it has been told.
+
+use anyhow::Result;
+use eframe::egui;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
+use std::thread;
+
+use crate::output::OutputRun;
+use crate::source::SourceFile;
+
+/// Folders the window opens with, from `--target` and `--output`.
+pub struct GuiOptions {
+    pub target: Option<PathBuf>,
+    pub output: Option<PathBuf>,
+}
+
+/// Progress reported by the worker thread.
+enum Progress {
+    /// Files were found and extraction is starting
+    Started(usize),
+    /// A file is being extracted
+    Extracting(PathBuf),
+    Saved,
+    Failed(PathBuf, String),
+    Finished,
+}
+
+/// Opens the window and blocks until it is closed. `discover` lists the supported files in a
+/// folder and `extract` writes one file's output into the output folder, returning its path;
+/// the files of one press of Extract share an [`OutputRun`].
+pub fn run<D, F>(options: GuiOptions, discover: D, extract: F) -> Result<()>
+where
+    D: Fn(&Path) -> Vec<SourceFile> + Send + Sync + 'static,
+    F: Fn(&SourceFile, &Path, &OutputRun) -> Result<PathBuf> + Send + Sync + 'static,
+{
+    let app = App {
+        target: options.target,
+        output: options.output,
+        dropped: Vec::new(),
+        discover: Arc::new(discover),
+        extract: Arc::new(extract),
+        run: None,
+    };
+    let native = eframe::NativeOptions {
+        viewport: egui::ViewportBuilder::default()
+            .with_inner_size([640.0, 480.0])
+            .with_min_inner_size([420.0, 320.0])
+            .with_drag_and_drop(true),
+        ..Default::default()
+    };
+
+    eframe::run_native("to_texts", native, Box::new(|_| Ok(Box::new(app))))
+        .map_err(|e| anyhow::anyhow!("Failed to open the window: {e}"))
+}
+
+type Discover = dyn Fn(&Path) -> Vec<SourceFile> + Send + Sync;
+type Extract = dyn Fn(&SourceFile, &Path, &OutputRun) -> Result<PathBuf> + Send + Sync;
+
+struct App {
+    target: Option<PathBuf>,
+    output: Option<PathBuf>,
+    /// Files dropped onto the window, extracted along with the input folder
+    dropped: Vec<PathBuf>,
+    discover: Arc<Discover>,
+    extract: Arc<Extract>,
+    /// The extraction in progress or last finished
+    run: Option<Run>,
+}
+
+/// State of one press of Extract, as reported by its worker.
+struct Run {
+    progress: Receiver<Progress>,
+    total: usize,
+    saved: usize,
+    current: Option<PathBuf>,
+    errors: Vec<(PathBuf, String)>,
+    finished: bool,
+}
+
+impl Run {
+    fn done(&self) -> usize {
+        self.saved + self.errors.len()
+    }
+}
+
+impl App {
+    fn running(&self) -> bool {
+        self.run.as_ref().is_some_and(|run| !run.finished)
+    }
+
+    /// Starts a worker extracting everything selected into the output folder.
+    fn start(&mut self, ctx: &egui::Context) {
+        let Some(output) = self.output.clone() else {
+            return;
+        };
+        let (sender, receiver) = mpsc::channel();
+        let target = self.target.clone();
+        let dropped = self.dropped.clone();
+        let discover = Arc::clone(&self.discover);
+        let extract = Arc::clone(&self.extract);
+        let ctx = ctx.clone();
+
+        thread::spawn(move || {
+            let send = |progress: Progress| {
+                // The window is gone when nobody listens; there is nothing left to report to
+                let _ = sender.send(progress);
+                ctx.request_repaint();
+            };
+            let mut files = target.as_deref().map(|t| discover(t)).unwrap_or_default();
+            files.extend(
+                dropped
+                    .iter()
+                    .filter_map(|path| {
+                        if path.is_dir() {
+                            Some(discover(path))
+                        } else {
+                            SourceFile::from_path(path).map(|file| vec![file])
+                        }
+                    })
+                    .flatten(),
+            );
+
+            send(Progress::Started(files.len()));
+            if let Err(e) = std::fs::create_dir_all(&output) {
+                send(Progress::Failed(output.clone(), e.to_string()));
+                send(Progress::Finished);
+                return;
+            }
+            let run = OutputRun::default();
+            for file in &files {
+                send(Progress::Extracting(file.path.clone()));
+                match extract(file, &output, &run) {
+                    Ok(_) => send(Progress::Saved),
+                    Err(e) => send(Progress::Failed(file.path.clone(), format!("{e:#}"))),
+                }
+            }
+            send(Progress::Finished);
+        });
+
+        self.run = Some(Run {
+            progress: receiver,
+            total: 0,
+            saved: 0,
+            current: None,
+            errors: Vec::new(),
+            finished: false,
+        });
+    }
+
+    /// Applies what the worker has reported since the last frame.
+    fn poll(&mut self) {
+        let Some(run) = &mut self.run else {
+            return;
+        };
+        for progress in run.progress.try_iter() {
+            match progress {
+                Progress::Started(total) => run.total = total,
+                Progress::Extracting(path) => run.current = Some(path),
+                Progress::Saved => run.saved += 1,
+                Progress::Failed(path, error) => run.errors.push((path, error)),
+                Progress::Finished => {
+                    run.finished = true;
+                    run.current = None;
+                }
+            }
+        }
+    }
+
+    fn folders(&mut self, ui: &mut egui::Ui) {
+        let enabled = !self.running();
+        egui::Grid::new("folders").num_columns(3).show(ui, |ui| {
+            ui.label("Input folder:");
+            ui.label(display(self.target.as_deref()));
+            if ui
+                .add_enabled(enabled, egui::Button::new("Choose…"))
+                .clicked()
+            {
+                if let Some(folder) = rfd::FileDialog::new().pick_folder() {
+                    self.target = Some(folder);
+                }
+            }
+            ui.end_row();
+
+            ui.label("Output folder:");
+            ui.label(display(self.output.as_deref()));
+            if ui
+                .add_enabled(enabled, egui::Button::new("Choose…"))
+                .clicked()
+            {
+                if let Some(folder) = rfd::FileDialog::new().pick_folder() {
+                    self.output = Some(folder);
+                }
+            }
+            ui.end_row();
+        });
+    }
+
+    fn dropped_files(&mut self, ui: &mut egui::Ui) {
+        if self.dropped.is_empty() {
+            ui.weak("Drop files or folders here to extract them too.");
+            return;
+        }
+        ui.horizontal(|ui| {
+            ui.label(format!("Dropped: {} item(s)", self.dropped.len()));
+            if ui
+                .add_enabled(!self.running(), egui::Button::new("Clear"))
+                .clicked()
+            {
+                self.dropped.clear();
+            }
+        });
+        egui::ScrollArea::vertical()
+            .id_salt("dropped")
+            .max_height(80.0)
+            .show(ui, |ui| {
+                for path in &self.dropped {
+                    ui.monospace(path.display().to_string());
+                }
+            });
+    }
+
+    fn progress(&self, ui: &mut egui::Ui) {
+        let Some(run) = &self.run else {
+            return;
+        };
+        let fraction = if run.total == 0 {
+            if run.finished {
+                1.0
+            } else {
+                0.0
+            }
+        } else {
+            run.done() as f32 / run.total as f32
+        };
+        ui.add(egui::ProgressBar::new(fraction).text(format!("{} / {}", run.done(), run.total)));
+
+        if let Some(current) = &run.current {
+            ui.label(format!("Extracting {}", current.display()));
+        } else if run.finished {
+            ui.label(format!(
+                "Done: {} saved, {} failed",
+                run.saved,
+                run.errors.len()
+            ));
+        }
+
+        if !run.errors.is_empty() {
+            ui.separator();
+            ui.strong("Errors");
+            egui::ScrollArea::vertical()
+                .id_salt("errors")
+                .show(ui, |ui| {
+                    for (path, error) in &run.errors {
+                        ui.colored_label(ui.visuals().error_fg_color, path.display().to_string());
+                        ui.label(error);
+                    }
+                });
+        }
+    }
+}
+
+impl eframe::App for App {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        self.poll();
+
+        if !self.running() {
+            let dropped: Vec<PathBuf> = ctx.input(|i| {
+                i.raw
+                    .dropped_files
+                    .iter()
+                    .filter_map(|file| file.path.clone())
+                    .collect()
+            });
+            for path in dropped {
+                if !self.dropped.contains(&path) {
+                    self.dropped.push(path);
+                }
+            }
+        }
+        let hovering = ctx.input(|i| !i.raw.hovered_files.is_empty());
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.heading("Extract text from books and documents");
+            ui.add_space(8.0);
+            self.folders(ui);
+            ui.add_space(8.0);
+            if hovering {
+                ui.strong("Release to add the files");
+            } else {
+                self.dropped_files(ui);
+            }
+            ui.add_space(8.0);
+
+            let ready = self.output.is_some()
+                && (self.target.is_some() || !self.dropped.is_empty())
+                && !self.running();
+            if ui
+                .add_enabled(ready, egui::Button::new("Extract"))
+                .clicked()
+            {
+                self.start(ctx);
+            }
+            ui.add_space(8.0);
+            self.progress(ui);
+        });
+    }
+}
+
+fn display(folder: Option<&Path>) -> String {
+    folder.map_or_else(|| "(none)".to_string(), |f| f.display().to_string())
+}
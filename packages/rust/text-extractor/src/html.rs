@@ -0,0 +1,65 @@
+//! Small helpers for scanning EPUB markup without parsing it, which content documents in
+//! the wild do not always allow.
+
+/// The next start tag at or after `from`: its start and end offsets and lower-cased local
+/// name. End tags have an empty name.
+pub fn next_tag(html: &str, from: usize) -> Option<(usize, usize, String)> {
+    let start = from + html[from..].find('<')?;
+    let end = start + html[start..].find('>')? + 1;
+    let name: String = html[start + 1..end - 1]
+        .chars()
+        .take_while(|c| !c.is_whitespace() && *c != '/' && *c != '>')
+        .collect();
+    // Drop any namespace prefix (svg:image)
+    let name = name.rsplit(':').next().unwrap_or_default().to_lowercase();
+    Some((start, end, name))
+}
+
+/// Offset of `needle`, which must be lower case, at or after `from`, ignoring ASCII case.
+pub fn find_ignore_case(html: &str, from: usize, needle: &str) -> Option<usize> {
+    html[from..]
+        .to_ascii_lowercase()
+        .find(needle)
+        .map(|offset| from + offset)
+}
+
+/// The value of a quoted attribute in a start tag.
+pub fn attribute(tag: &str, name: &str) -> Option<String> {
+    let mut rest = tag;
+    while let Some(at) = rest.find(name) {
+        let preceded = rest[..at].ends_with(char::is_whitespace);
+        let after = rest[at + name.len()..].trim_start();
+        rest = &rest[at + name.len()..];
+        let Some(value) = after.strip_prefix('=').map(str::trim_start) else {
+            continue;
+        };
+        let Some(quote) = value.chars().next().filter(|c| *c == '"' || *c == '\'') else {
+            continue;
+        };
+        if !preceded {
+            continue;
+        }
+        let value = &value[1..];
+        let value = value[..value.find(quote)?].trim();
+        return (!value.is_empty()).then(|| value.to_string());
+    }
+    None
+}
+
+/// The text of a fragment of markup on one line, with runs of whitespace collapsed.
+pub fn strip_tags(html: &str) -> String {
+    let mut text = String::new();
+    let mut in_tag = false;
+    for ch in html.chars() {
+        match ch {
+            '<' => in_tag = true,
+            '>' => {
+                in_tag = false;
+                text.push(' ');
+            }
+            _ if !in_tag => text.push(ch),
+            _ => {}
+        }
+    }
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
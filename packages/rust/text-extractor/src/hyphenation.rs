@@ -0,0 +1,144 @@
+//! Rejoining words that typesetting broke across line ends ("exam-" / "ple").
+//!
+//! A line ending in a letter and a hyphen, followed by a line starting with a lowercase
+//! letter, holds a broken word: the rest of the word moves up and the hyphen goes, unless the
+//! word is a compound whose hyphen belongs to it. Compounds are told apart by the capital,
+//! digit or further hyphen after the break, and by short lists of prefixes and endings for
+//! the document's language. Soft hyphens, which only mark where a word may break, are removed.
+
+use crate::document::CODE_FENCE;
+
+const SOFT_HYPHEN: char = '\u{ad}';
+
+/// Prefixes that keep their hyphen ("self-" / "aware").
+const ENGLISH_PREFIXES: &[&str] = &[
+    "all", "cross", "e", "ex", "great", "half", "mid", "non", "quasi", "self", "vice", "well",
+];
+const FRENCH_PREFIXES: &[&str] = &[
+    "après", "arrière", "avant", "demi", "ex", "grand", "mi", "non", "semi", "sous", "vice",
+];
+/// Endings that keep the hyphen before them: inverted pronouns ("dit-" / "il") and "-ci"/"-là".
+const FRENCH_ENDINGS: &[&str] = &[
+    "ci", "elle", "elles", "il", "ils", "je", "là", "leur", "lui", "moi", "même", "nous", "on",
+    "t", "toi", "tu", "vous",
+];
+/// Words after a hyphen that leaves out the end of a compound ("Ein-" / "und Ausgang"); the
+/// line break stays.
+const GERMAN_CONJUNCTIONS: &[&str] = &["bis", "bzw", "oder", "sowie", "und"];
+
+/// Rejoins the words `text` breaks across lines. `language` is a BCP 47 tag such as `en-GB`;
+/// without one only the rules that hold in every language apply. Fenced code and table rows
+/// are left alone.
+pub fn dehyphenate(text: &str, language: Option<&str>) -> String {
+    let language = language
+        .and_then(|tag| tag.split(['-', '_']).next())
+        .map(str::to_ascii_lowercase);
+    let rules = Rules::for_language(language.as_deref());
+
+    let mut lines: Vec<String> = Vec::new();
+    let mut in_code = false;
+    // Whether the last line is prose that may continue into this one, and whether it ended
+    // in a soft hyphen
+    let mut joinable = false;
+    let mut soft = false;
+    for line in text.split('\n') {
+        let fence = line.trim_start().starts_with(CODE_FENCE);
+        if fence {
+            in_code = !in_code;
+        }
+        if fence || in_code || is_table_row(line) {
+            lines.push(line.to_string());
+            joinable = false;
+            continue;
+        }
+
+        let ends_soft = line.trim_end().ends_with(SOFT_HYPHEN);
+        let line = line.replace(SOFT_HYPHEN, "");
+        if joinable {
+            if let Some(previous) = lines.last_mut() {
+                if let Some(rest) = rules.join(previous, &line, soft) {
+                    if !rest.trim().is_empty() {
+                        lines.push(rest.to_string());
+                    }
+                    soft = ends_soft;
+                    continue;
+                }
+            }
+        }
+        joinable = true;
+        soft = ends_soft;
+        lines.push(line);
+    }
+    lines.join("\n")
+}
+
+fn is_table_row(line: &str) -> bool {
+    line.contains('\t') || line.trim_start().starts_with('|')
+}
+
+struct Rules {
+    prefixes: &'static [&'static str],
+    endings: &'static [&'static str],
+    conjunctions: &'static [&'static str],
+}
+
+impl Rules {
+    fn for_language(language: Option<&str>) -> Rules {
+        let (prefixes, endings, conjunctions) = match language {
+            Some("en") => (ENGLISH_PREFIXES, &[][..], &[][..]),
+            Some("fr") => (FRENCH_PREFIXES, FRENCH_ENDINGS, &[][..]),
+            Some("de") => (&[][..], &[][..], GERMAN_CONJUNCTIONS),
+            _ => (&[][..], &[][..], &[][..]),
+        };
+        Rules {
+            prefixes,
+            endings,
+            conjunctions,
+        }
+    }
+
+    /// Moves the end of a word broken at the end of `previous` (by a hyphen, or by a soft
+    /// hyphen when `soft` is set) up from `next`, returning what is left of `next`. Returns
+    /// `None` when `previous` does not end in a broken word.
+    fn join<'a>(&self, previous: &mut String, next: &'a str, soft: bool) -> Option<&'a str> {
+        let trimmed = previous.trim_end();
+        let head = if soft {
+            trimmed
+        } else {
+            trimmed.strip_suffix(['-', '\u{2010}'])?
+        };
+        let start = head
+            .char_indices()
+            .rev()
+            .find(|(_, c)| !(c.is_alphabetic() || *c == '-' || *c == '\''))
+            .map_or(0, |(at, c)| at + c.len_utf8());
+        let fragment = &head[start..];
+        if !fragment.chars().last().is_some_and(char::is_alphabetic) {
+            return None;
+        }
+
+        let next = next.trim_start();
+        let end = next.find(char::is_whitespace).unwrap_or(next.len());
+        let word = &next[..end];
+        let first = word.chars().next()?;
+        if !first.is_alphanumeric() {
+            return None;
+        }
+        let bare = word.trim_end_matches(|c: char| !c.is_alphanumeric());
+        let listed = |list: &[&str], word: &str| list.iter().any(|w| w.eq_ignore_ascii_case(word));
+        if !soft && listed(self.conjunctions, bare) {
+            return None;
+        }
+
+        let compound = !soft
+            && (!first.is_lowercase()
+                || fragment.contains('-')
+                || bare.contains('-')
+                || listed(self.prefixes, fragment)
+                || listed(self.endings, bare.split('-').next().unwrap_or(bare)));
+        let keep = if compound { trimmed.len() } else { head.len() };
+        previous.truncate(keep);
+        previous.push_str(word);
+        Some(next[end..].trim_start())
+    }
+}
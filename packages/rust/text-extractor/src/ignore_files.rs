@@ -0,0 +1,129 @@
+//! `.gitignore`-style ignore files in the tree being searched, so files a source repository
+//! ignores (vendored PDFs, build output) are not extracted. `.toignore` files take the same
+//! rules for trees that are not repositories.
+//!
+//! The rules follow git's: blank lines and `#` comments are skipped, `!` brings back what an
+//! earlier rule ignored, a trailing `/` only matches folders, a pattern with a `/` elsewhere
+//! is taken from the folder of the ignore file and one without matches at any depth below it.
+//! The last rule that matches decides, and rules of deeper files come after those above them.
+//! Ignore files above the folder searched, and git's global excludes, are not read.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use glob::{MatchOptions, Pattern};
+
+/// Files read for ignore rules in each folder, in this order.
+pub const IGNORE_FILES: &[&str] = &[".gitignore", ".toignore"];
+
+/// Git's own folder, never searched when ignore files are honored.
+const GIT_DIR: &str = ".git";
+
+/// As git matches: case-sensitively, with `*` stopping at folder separators.
+const MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+struct Rule {
+    pattern: Pattern,
+    /// Brings back what an earlier rule ignored
+    negated: bool,
+    /// Only matches folders
+    dir_only: bool,
+}
+
+/// The rules of the ignore files of one folder.
+struct Rules {
+    dir: PathBuf,
+    /// Depth of `dir` in the walk
+    depth: usize,
+    rules: Vec<Rule>,
+}
+
+/// The rules that apply to the entries of a depth-first walk, from the ignore files of the
+/// folders above them.
+#[derive(Default)]
+pub struct IgnoreStack {
+    levels: Vec<Rules>,
+}
+
+impl IgnoreStack {
+    /// Whether the entry at `path`, `depth` levels into the walk, is ignored. Entries must
+    /// come in depth-first order, each folder before what is in it; the ignore files of
+    /// folders not ignored are read as they come.
+    pub fn ignored(&mut self, path: &Path, depth: usize, is_dir: bool) -> bool {
+        // Folders the walk has left no longer apply
+        while self.levels.last().is_some_and(|level| level.depth >= depth) {
+            self.levels.pop();
+        }
+
+        let git_dir = is_dir && depth > 0 && path.file_name() == Some(GIT_DIR.as_ref());
+        let ignored = git_dir || self.decide(path, is_dir);
+        if is_dir && !ignored {
+            let rules = read_rules(path);
+            if !rules.is_empty() {
+                self.levels.push(Rules {
+                    dir: path.to_path_buf(),
+                    depth,
+                    rules,
+                });
+            }
+        }
+        ignored
+    }
+
+    fn decide(&self, path: &Path, is_dir: bool) -> bool {
+        let mut ignored = false;
+        for level in &self.levels {
+            let Ok(relative) = path.strip_prefix(&level.dir) else {
+                continue;
+            };
+            for rule in &level.rules {
+                if (is_dir || !rule.dir_only)
+                    && rule.pattern.matches_path_with(relative, MATCH_OPTIONS)
+                {
+                    ignored = !rule.negated;
+                }
+            }
+        }
+        ignored
+    }
+}
+
+/// The rules of the ignore files in `dir`; unreadable files and bad patterns are skipped.
+fn read_rules(dir: &Path) -> Vec<Rule> {
+    IGNORE_FILES
+        .iter()
+        .filter_map(|name| fs::read_to_string(dir.join(name)).ok())
+        .flat_map(|text| text.lines().filter_map(parse_rule).collect::<Vec<_>>())
+        .collect()
+}
+
+fn parse_rule(line: &str) -> Option<Rule> {
+    let line = line.trim_end();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+    let (negated, line) = match line.strip_prefix('!') {
+        Some(rest) => (true, rest),
+        None => (false, line),
+    };
+    // `\#` and `\!` start patterns with the character itself
+    let line = line.strip_prefix('\\').unwrap_or(line);
+    let (dir_only, line) = match line.strip_suffix('/') {
+        Some(rest) => (true, rest),
+        None => (false, line),
+    };
+    let pattern = if line.contains('/') {
+        line.trim_start_matches('/').to_string()
+    } else {
+        format!("**/{line}")
+    };
+    Some(Rule {
+        pattern: Pattern::new(&pattern).ok()?,
+        negated,
+        dir_only,
+    })
+}
@@ -0,0 +1,200 @@
+//! A full-text index of a library, for `index` and `search --index`: documents are extracted
+//! once into a [tantivy](https://github.com/quickwit-oss/tantivy) index of their title,
+//! author, text and source path, and searched there in milliseconds.
+//!
+//! Documents are keyed by their path, so indexing a folder again replaces the documents that
+//! changed since and leaves the others alone.
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use tantivy::collector::TopDocs;
+use tantivy::directory::MmapDirectory;
+use tantivy::query::{QueryParser, TermQuery};
+use tantivy::schema::{Field, IndexRecordOption, Schema, Value, STORED, STRING, TEXT};
+use tantivy::snippet::SnippetGenerator;
+use tantivy::{Index, IndexReader, IndexWriter, TantivyDocument, Term};
+
+use crate::document::Document;
+
+/// Memory the index writer buffers documents in before writing them out.
+const WRITER_MEMORY: usize = 100_000_000;
+/// Characters of text shown around the best match.
+const SNIPPET_CHARS: usize = 160;
+
+/// A document that matched.
+#[derive(Debug, Clone, Serialize)]
+pub struct Hit {
+    /// The file the document was extracted from
+    pub path: PathBuf,
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub score: f32,
+    pub snippet: String,
+}
+
+#[derive(Clone, Copy)]
+struct Fields {
+    title: Field,
+    author: Field,
+    body: Field,
+    path: Field,
+    /// Modification time of the file when it was indexed, in seconds since the epoch
+    modified: Field,
+}
+
+impl Fields {
+    fn schema() -> (Schema, Fields) {
+        let mut builder = Schema::builder();
+        let fields = Fields {
+            title: builder.add_text_field("title", TEXT | STORED),
+            author: builder.add_text_field("author", TEXT | STORED),
+            body: builder.add_text_field("body", TEXT | STORED),
+            path: builder.add_text_field("path", STRING | STORED),
+            modified: builder.add_u64_field("modified", STORED),
+        };
+        (builder.build(), fields)
+    }
+}
+
+/// An index opened for adding documents. Documents added become searchable once
+/// [`LibraryIndex::commit`] is called.
+pub struct LibraryIndex {
+    fields: Fields,
+    reader: IndexReader,
+    writer: IndexWriter,
+}
+
+impl LibraryIndex {
+    /// Opens the index in `dir`, creating the folder and an empty index when there is none.
+    pub fn open(dir: &Path) -> Result<LibraryIndex> {
+        let (index, fields) = open_index(dir, true)?;
+        let writer = index
+            .writer(WRITER_MEMORY)
+            .context(format!("Failed to lock index: {}", dir.display()))?;
+        Ok(LibraryIndex {
+            fields,
+            reader: index.reader()?,
+            writer,
+        })
+    }
+
+    /// Whether `path` is indexed as it is on disk, so extracting it again can be skipped.
+    pub fn is_current(&self, path: &Path) -> bool {
+        let Some(modified) = modified(path) else {
+            return false;
+        };
+        let searcher = self.reader.searcher();
+        let query = TermQuery::new(self.path_term(path), IndexRecordOption::Basic);
+        let Ok(found) = searcher.search(&query, &TopDocs::with_limit(1)) else {
+            return false;
+        };
+        found.first().is_some_and(|(_, address)| {
+            searcher
+                .doc::<TantivyDocument>(*address)
+                .ok()
+                .and_then(|doc| doc.get_first(self.fields.modified).and_then(|v| v.as_u64()))
+                == Some(modified)
+        })
+    }
+
+    /// Adds `document`, extracted from `path`, in place of what was indexed for `path` before.
+    pub fn add(&self, path: &Path, document: &Document) -> Result<()> {
+        let fields = self.fields;
+        let mut doc = TantivyDocument::default();
+        doc.add_text(fields.path, path.to_string_lossy());
+        doc.add_u64(fields.modified, modified(path).unwrap_or(0));
+        if let Some(title) = &document.metadata.title {
+            doc.add_text(fields.title, title);
+        }
+        if let Some(author) = &document.metadata.author {
+            doc.add_text(fields.author, author);
+        }
+        let body: Vec<&str> = document
+            .sections
+            .iter()
+            .map(|section| section.text.as_str())
+            .collect();
+        doc.add_text(fields.body, body.join("\n\n"));
+
+        self.writer.delete_term(self.path_term(path));
+        self.writer.add_document(doc)?;
+        Ok(())
+    }
+
+    /// Writes out the documents added so far.
+    pub fn commit(&mut self) -> Result<()> {
+        self.writer.commit().context("Failed to write the index")?;
+        Ok(())
+    }
+
+    fn path_term(&self, path: &Path) -> Term {
+        Term::from_field_text(self.fields.path, &path.to_string_lossy())
+    }
+}
+
+/// Searches the index in `dir` for `query`, best first, returning at most `limit` hits.
+///
+/// Queries use tantivy's syntax: words match any field, `"quoted words"` match a phrase, and
+/// `title:`, `author:` or `body:` limit a word to one field. Titles weigh double.
+pub fn search(dir: &Path, query: &str, limit: usize) -> Result<Vec<Hit>> {
+    let (index, fields) = open_index(dir, false)?;
+    let searcher = index.reader()?.searcher();
+    let mut parser = QueryParser::for_index(&index, vec![fields.title, fields.author, fields.body]);
+    parser.set_field_boost(fields.title, 2.0);
+    let query = parser
+        .parse_query(query)
+        .map_err(|e| anyhow::anyhow!("Invalid query: {e}"))?;
+
+    let mut snippets = SnippetGenerator::create(&searcher, &*query, fields.body)?;
+    snippets.set_max_num_chars(SNIPPET_CHARS);
+    let mut hits = Vec::new();
+    for (score, address) in searcher.search(&query, &TopDocs::with_limit(limit))? {
+        let doc: TantivyDocument = searcher.doc(address)?;
+        let text = |field| {
+            doc.get_first(field)
+                .and_then(|value| value.as_str())
+                .map(str::to_string)
+        };
+        let snippet = snippets.snippet_from_doc(&doc);
+        hits.push(Hit {
+            path: PathBuf::from(text(fields.path).unwrap_or_default()),
+            title: text(fields.title),
+            author: text(fields.author),
+            score,
+            snippet: snippet
+                .fragment()
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" "),
+        });
+    }
+    Ok(hits)
+}
+
+fn open_index(dir: &Path, create: bool) -> Result<(Index, Fields)> {
+    let (schema, fields) = Fields::schema();
+    let index = if create {
+        fs::create_dir_all(dir).context(format!(
+            "Failed to create index directory: {}",
+            dir.display()
+        ))?;
+        let directory =
+            MmapDirectory::open(dir).context(format!("Failed to open index: {}", dir.display()))?;
+        Index::open_or_create(directory, schema.clone())
+    } else {
+        Index::open_in_dir(dir)
+    };
+    let index = index.context(format!("Failed to open index: {}", dir.display()))?;
+    if index.schema() != schema {
+        anyhow::bail!("Not an index built by `index`: {}", dir.display());
+    }
+    Ok((index, fields))
+}
+
+fn modified(path: &Path) -> Option<u64> {
+    let modified = fs::metadata(path).ok()?.modified().ok()?;
+    Some(modified.duration_since(UNIX_EPOCH).ok()?.as_secs())
+}
@@ -0,0 +1,105 @@
+//! What a document is, for `info`: its type, size, metadata, page or chapter count and
+//! whether it is encrypted.
+//!
+//! PDFs and EPUBs are only opened, so even large or locked files answer quickly. Other
+//! formats keep their metadata where only their extractor reads it, so they are extracted
+//! and their text is thrown away.
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::document::SectionKind;
+use crate::epub_archive::{self, ChapterReader};
+use crate::extract::{self, ExtractOptions};
+use crate::metadata::Metadata;
+use crate::pdf;
+use crate::source::{self, SourceFile};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DocumentInfo {
+    pub path: PathBuf,
+    /// Name of the format, e.g. "EPUB"
+    #[serde(rename = "type")]
+    pub format: &'static str,
+    pub extension: String,
+    /// Bytes
+    pub size: u64,
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub language: Option<String>,
+    pub publisher: Option<String>,
+    pub page_count: Option<usize>,
+    pub chapter_count: Option<usize>,
+    /// Password-protected PDFs and DRM-protected EPUBs
+    pub encrypted: bool,
+}
+
+/// Reads what `file` is. `options` supply the passwords of encrypted PDFs, and extract the
+/// formats whose metadata only their extractor reads.
+pub fn read(file: &SourceFile, options: &ExtractOptions) -> Result<DocumentInfo> {
+    let size = fs::metadata(&file.path)
+        .context(format!("Failed to read: {}", file.path.display()))?
+        .len();
+    let mut info = DocumentInfo {
+        path: file.path.clone(),
+        format: source::format_name(&file.extension),
+        extension: file.extension.clone(),
+        size,
+        title: None,
+        author: None,
+        language: None,
+        publisher: None,
+        page_count: None,
+        chapter_count: None,
+        encrypted: false,
+    };
+
+    let embedded = match file.extension.as_str() {
+        "pdf" => {
+            let pdf = pdf::metadata(&file.path, &options.pdf_passwords)?;
+            info.page_count = Some(pdf.pages);
+            info.encrypted = pdf.encrypted;
+            pdf.metadata
+        }
+        "epub" => {
+            let doc = epub::doc::EpubDoc::new(&file.path)
+                .context(format!("Failed to open EPUB: {}", file.path.display()))?;
+            info.publisher = doc.mdata("publisher").map(|item| item.value.clone());
+            info.chapter_count = Some(epub_archive::content_documents(&doc).len());
+            info.encrypted = epub_archive::is_encrypted(&mut ChapterReader::open(&file.path)?);
+            extract::epub_metadata(&doc)
+        }
+        _ => {
+            let document = extract::extract_source(file, options)?.document;
+            let count = |kind: SectionKind| {
+                let count = document
+                    .sections
+                    .iter()
+                    .filter(|section| section.kind == kind)
+                    .count();
+                (count > 0).then_some(count)
+            };
+            info.page_count = count(SectionKind::Page);
+            info.chapter_count = count(SectionKind::Chapter);
+            document.metadata
+        }
+    };
+
+    // Canonical metadata from a catalog wins, as it does when extracting
+    let metadata = match &file.metadata {
+        Some(metadata) => metadata.clone().or(embedded),
+        None => embedded,
+    };
+    let Metadata {
+        title,
+        author,
+        language,
+        ..
+    } = metadata;
+    info.title = title;
+    info.author = author;
+    info.language = language;
+    Ok(info)
+}
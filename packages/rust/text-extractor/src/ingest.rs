@@ -0,0 +1,186 @@
+//! Picks up finished downloads (e.g. from the bundled zlibrary-downloader), extracts them,
+//! archives the originals and appends the mapping to a ledger.
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::provenance::Provenance;
+use crate::source::SourceFile;
+
+pub struct IngestOptions {
+    pub downloads: PathBuf,
+    pub output: PathBuf,
+    /// Where originals are moved after successful extraction
+    pub archive: PathBuf,
+    /// JSON Lines file recording one entry per ingested download
+    pub ledger: PathBuf,
+    /// Files modified more recently than this are assumed to still be downloading
+    pub settle: Duration,
+}
+
+/// One ledger line.
+#[derive(Serialize)]
+struct LedgerEntry<'a> {
+    source: &'a Path,
+    archived: &'a Path,
+    output: &'a Path,
+    ingested_at: u64,
+    #[serde(flatten)]
+    provenance: &'a Provenance,
+}
+
+pub struct IngestSummary {
+    pub processed: usize,
+    pub errors: usize,
+    /// Downloads left alone because they were modified too recently
+    pub pending: usize,
+}
+
+/// Runs one ingest pass, extracting with `extract` which returns the written output path and
+/// its provenance.
+pub fn ingest<F>(options: &IngestOptions, extract: F) -> Result<IngestSummary>
+where
+    F: Fn(&SourceFile) -> Result<(PathBuf, Provenance)>,
+{
+    fs::create_dir_all(&options.archive).context(format!(
+        "Failed to create archive directory: {}",
+        options.archive.display()
+    ))?;
+
+    let mut summary = IngestSummary {
+        processed: 0,
+        errors: 0,
+        pending: 0,
+    };
+
+    for file in completed_downloads(options, &mut summary.pending)? {
+        println!(
+            "Processing {}: {}",
+            file.extension.to_uppercase(),
+            file.path.display()
+        );
+
+        let result = extract(&file).and_then(|(output_path, provenance)| {
+            println!("  -> Saved to: {}", output_path.display());
+            record(options, &file.path, &output_path, &provenance)
+        });
+
+        match result {
+            Ok(archived) => {
+                println!("  -> Archived to: {}", archived.display());
+                summary.processed += 1;
+            }
+            Err(e) => {
+                eprintln!("  -> Error: {e:#}");
+                summary.errors += 1;
+            }
+        }
+    }
+
+    Ok(summary)
+}
+
+fn completed_downloads(options: &IngestOptions, pending: &mut usize) -> Result<Vec<SourceFile>> {
+    let entries = fs::read_dir(&options.downloads).context(format!(
+        "Failed to read downloads directory: {}",
+        options.downloads.display()
+    ))?;
+
+    let now = SystemTime::now();
+    let mut files = Vec::new();
+
+    for entry in entries.filter_map(|e| e.ok()) {
+        let path = entry.path();
+        if !path.is_file() {
+            continue;
+        }
+        let Some(file) = SourceFile::from_path(&path) else {
+            continue;
+        };
+
+        let modified = entry.metadata().and_then(|m| m.modified()).unwrap_or(now);
+        if now.duration_since(modified).unwrap_or_default() < options.settle {
+            println!("Still downloading, skipping for now: {}", path.display());
+            *pending += 1;
+            continue;
+        }
+
+        files.push(file);
+    }
+
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(files)
+}
+
+/// Moves the original into the archive and appends a ledger entry.
+fn record(
+    options: &IngestOptions,
+    source: &Path,
+    output_path: &Path,
+    provenance: &Provenance,
+) -> Result<PathBuf> {
+    let archived = move_to_archive(source, &options.archive)?;
+
+    let entry = LedgerEntry {
+        source,
+        archived: &archived,
+        output: output_path,
+        ingested_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs(),
+        provenance,
+    };
+
+    let mut ledger = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&options.ledger)
+        .context(format!(
+            "Failed to open ledger: {}",
+            options.ledger.display()
+        ))?;
+    writeln!(ledger, "{}", serde_json::to_string(&entry)?).context(format!(
+        "Failed to write ledger: {}",
+        options.ledger.display()
+    ))?;
+
+    Ok(archived)
+}
+
+fn move_to_archive(source: &Path, archive: &Path) -> Result<PathBuf> {
+    let file_name = source.file_name().context("Failed to get file name")?;
+    let mut target = archive.join(file_name);
+
+    // Never overwrite an earlier download of the same name
+    let mut counter = 1;
+    while target.exists() {
+        let stem = source
+            .file_stem()
+            .context("Failed to get file stem")?
+            .to_string_lossy();
+        let extension = source
+            .extension()
+            .map(|e| format!(".{}", e.to_string_lossy()))
+            .unwrap_or_default();
+        target = archive.join(format!("{stem} ({counter}){extension}"));
+        counter += 1;
+    }
+
+    // Renaming fails across filesystems, so fall back to copy-and-delete
+    if fs::rename(source, &target).is_err() {
+        fs::copy(source, &target).context(format!(
+            "Failed to copy {} to {}",
+            source.display(),
+            target.display()
+        ))?;
+        fs::remove_file(source)
+            .context(format!("Failed to remove original: {}", source.display()))?;
+    }
+
+    Ok(target)
+}
@@ -0,0 +1,269 @@
+//! Apple iWork packages: Pages documents (`.pages`) and iBooks Author books (`.ibooks`).
+//!
+//! Pages keeps its text in IWA archives: Snappy-compressed streams of protobuf messages.
+//! The body text is read from the `TSWP.StorageArchive` messages of `Index/Document.iwa`;
+//! documents saved in another layout fall back to the PDF preview stored in the package.
+//! iBooks Author books are EPUB packages underneath and only need the preview when the
+//! EPUB reader cannot make sense of them.
+
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use zip::ZipArchive;
+
+/// Archive holding the main text of a Pages document.
+const DOCUMENT_IWA: &str = "Index/Document.iwa";
+
+/// Paths, lowercased, at which iWork saves a PDF preview of the document, best first.
+const PREVIEW_PATHS: &[&str] = &["quicklook/preview.pdf", "preview.pdf"];
+
+/// Message types registered for `TSWP.StorageArchive`, the text storage of a flow of text.
+const STORAGE_TYPES: &[u64] = &[2001, 2005];
+
+/// `TSWP.StorageArchive.kind` of the document body; headers, footnotes, text boxes and
+/// table cells have their own kinds.
+const BODY_KIND: u64 = 0;
+
+/// The body text of a Pages document, one paragraph per line, or `None` when the package
+/// has no IWA document to read it from.
+pub fn pages_text(path: &Path) -> Result<Option<String>> {
+    let mut archive = open(path)?;
+    let Ok(mut entry) = archive.by_name(DOCUMENT_IWA) else {
+        return Ok(None);
+    };
+    let mut iwa = Vec::new();
+    entry.read_to_end(&mut iwa)?;
+
+    let Some(stream) = decompress_iwa(&iwa) else {
+        return Ok(None);
+    };
+    let paragraphs: Vec<String> = body_storages(&stream)
+        .iter()
+        .flat_map(|text| text.split(['\n', '\r', '\u{2028}', '\u{2029}']))
+        .map(clean_paragraph)
+        .filter(|paragraph| !paragraph.is_empty())
+        .collect();
+    Ok((!paragraphs.is_empty()).then(|| paragraphs.join("\n")))
+}
+
+/// The PDF preview saved inside an iWork package.
+pub fn preview_pdf(path: &Path) -> Result<Vec<u8>> {
+    let mut archive = open(path)?;
+    let name = PREVIEW_PATHS
+        .iter()
+        .find_map(|preview| {
+            archive
+                .file_names()
+                .find(|name| name.eq_ignore_ascii_case(preview))
+                .map(str::to_string)
+        })
+        .context(format!(
+            "No PDF preview in {} (save it with a preview, or export it to PDF)",
+            path.display()
+        ))?;
+
+    let mut pdf = Vec::new();
+    archive.by_name(&name)?.read_to_end(&mut pdf)?;
+    Ok(pdf)
+}
+
+fn open(path: &Path) -> Result<ZipArchive<File>> {
+    let file = File::open(path).context(format!("Failed to open: {}", path.display()))?;
+    ZipArchive::new(file).context(format!("Not an iWork package: {}", path.display()))
+}
+
+/// Strips the control characters and attachment marks (images, footnote references)
+/// that stand in the text for objects anchored there, and the spaces left around them.
+fn clean_paragraph(paragraph: &str) -> String {
+    paragraph
+        .chars()
+        .filter(|&c| !(c.is_control() && c != '\t') && c != '\u{fffc}')
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// The text of every body storage in the message stream, in archive order.
+fn body_storages(stream: &[u8]) -> Vec<String> {
+    let mut texts = Vec::new();
+    let mut at = 0;
+
+    // Each archive is a length-prefixed ArchiveInfo followed by its messages
+    while let Some((info_len, start)) = varint(stream, at) {
+        let Some(info) = slice(stream, start, info_len) else {
+            break;
+        };
+        at = start + info.len();
+
+        for message_info in fields(info).filter_map(|(field, value)| (field == 2).then_some(value))
+        {
+            let Value::Bytes(message_info) = message_info else {
+                continue;
+            };
+            let number = |wanted: u64| {
+                fields(message_info).find_map(|(field, value)| match value {
+                    Value::Varint(n) if field == wanted => Some(n),
+                    _ => None,
+                })
+            };
+            // Without a length the rest of the stream cannot be found
+            let (Some(kind), Some(len)) = (number(1), number(3)) else {
+                return texts;
+            };
+            let Some(payload) = slice(stream, at, len) else {
+                return texts;
+            };
+            at += payload.len();
+
+            if STORAGE_TYPES.contains(&kind) {
+                texts.extend(body_text(payload));
+            }
+        }
+    }
+    texts
+}
+
+/// The text of a `TSWP.StorageArchive` if it holds the document body.
+fn body_text(storage: &[u8]) -> Option<String> {
+    let mut kind = BODY_KIND;
+    let mut text = String::new();
+    for (field, value) in fields(storage) {
+        match (field, value) {
+            (1, Value::Varint(n)) => kind = n,
+            (3, Value::Bytes(bytes)) => text.push_str(&String::from_utf8_lossy(bytes)),
+            _ => {}
+        }
+    }
+    (kind == BODY_KIND && !text.is_empty()).then_some(text)
+}
+
+/// A protobuf field value; fixed-width values are skipped over.
+enum Value<'a> {
+    Varint(u64),
+    Bytes(&'a [u8]),
+    Fixed,
+}
+
+/// The fields of a protobuf message, stopping at the first malformed one.
+fn fields(message: &[u8]) -> impl Iterator<Item = (u64, Value<'_>)> {
+    let mut at = 0;
+    std::iter::from_fn(move || {
+        let (key, next) = varint(message, at)?;
+        let (value, next) = match key & 7 {
+            0 => {
+                let (n, next) = varint(message, next)?;
+                (Value::Varint(n), next)
+            }
+            1 => (Value::Fixed, next + 8),
+            2 => {
+                let (len, start) = varint(message, next)?;
+                let bytes = slice(message, start, len)?;
+                (Value::Bytes(bytes), start + bytes.len())
+            }
+            5 => (Value::Fixed, next + 4),
+            _ => return None,
+        };
+        if next > message.len() {
+            return None;
+        }
+        at = next;
+        Some((key >> 3, value))
+    })
+}
+
+/// `len` bytes of `data` from `start`, if there are that many.
+fn slice(data: &[u8], start: usize, len: u64) -> Option<&[u8]> {
+    data.get(start..start.checked_add(usize::try_from(len).ok()?)?)
+}
+
+/// A base-128 varint starting at `at`, and the offset just past it.
+fn varint(data: &[u8], mut at: usize) -> Option<(u64, usize)> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let byte = *data.get(at)?;
+        at += 1;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Some((value, at));
+        }
+    }
+    None
+}
+
+/// Joins the chunks of an IWA file: each is a zero byte, a 24-bit little-endian length and
+/// a block of raw Snappy data.
+fn decompress_iwa(iwa: &[u8]) -> Option<Vec<u8>> {
+    let mut stream = Vec::new();
+    let mut at = 0;
+    while at < iwa.len() {
+        let header = iwa.get(at..at + 4)?;
+        if header[0] != 0 {
+            return None;
+        }
+        let len =
+            usize::from(header[1]) | usize::from(header[2]) << 8 | usize::from(header[3]) << 16;
+        let chunk = iwa.get(at + 4..at + 4 + len)?;
+        snappy(chunk, &mut stream)?;
+        at += 4 + len;
+    }
+    Some(stream)
+}
+
+/// Decompresses one raw Snappy block onto the end of `out`.
+fn snappy(block: &[u8], out: &mut Vec<u8>) -> Option<()> {
+    let (len, mut at) = varint(block, 0)?;
+    let start = out.len();
+    out.reserve(usize::try_from(len).ok()?);
+
+    while at < block.len() {
+        let tag = block[at];
+        at += 1;
+        let (copy_len, offset) = match tag & 3 {
+            // Literal; lengths past 60 take the next one to four bytes
+            0 => {
+                let mut literal = usize::from(tag >> 2);
+                if literal >= 60 {
+                    let bytes = literal - 59;
+                    literal = block
+                        .get(at..at + bytes)?
+                        .iter()
+                        .rev()
+                        .fold(0, |n, &b| n << 8 | usize::from(b));
+                    at += bytes;
+                }
+                out.extend_from_slice(block.get(at..at + literal + 1)?);
+                at += literal + 1;
+                continue;
+            }
+            1 => {
+                let offset = usize::from(tag >> 5) << 8 | usize::from(*block.get(at)?);
+                at += 1;
+                (4 + usize::from((tag >> 2) & 7), offset)
+            }
+            2 => {
+                let offset =
+                    usize::from(u16::from_le_bytes(block.get(at..at + 2)?.try_into().ok()?));
+                at += 2;
+                (1 + usize::from(tag >> 2), offset)
+            }
+            _ => {
+                let offset =
+                    usize::try_from(u32::from_le_bytes(block.get(at..at + 4)?.try_into().ok()?))
+                        .ok()?;
+                at += 4;
+                (1 + usize::from(tag >> 2), offset)
+            }
+        };
+
+        // Copies may overlap the bytes they produce, so go byte by byte
+        if offset == 0 || offset > out.len() - start {
+            return None;
+        }
+        for _ in 0..copy_len {
+            out.push(out[out.len() - offset]);
+        }
+    }
+    Some(())
+}
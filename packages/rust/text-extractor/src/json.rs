@@ -0,0 +1,115 @@
+//! JSON rendering: the metadata and one text block per page or chapter, for pipelines that
+//! would otherwise parse page separators back out of text output, and single-line records
+//! for JSON Lines corpora.
+
+use serde::Serialize;
+use std::path::Path;
+
+use crate::document::{Document, SectionKind};
+
+#[derive(Serialize)]
+struct JsonDocument<'a> {
+    /// The input file, as it was given
+    source: String,
+    title: Option<&'a str>,
+    author: Option<&'a str>,
+    language: Option<&'a str>,
+    collection: Option<&'a str>,
+    page_count: usize,
+    chapter_count: usize,
+    blocks: Vec<Block<'a>>,
+    outline: Vec<Bookmark<'a>>,
+}
+
+/// The text of one page or chapter.
+#[derive(Serialize)]
+struct Block<'a> {
+    kind: &'static str,
+    /// Page number, counting from 1, for pages
+    #[serde(skip_serializing_if = "Option::is_none")]
+    page: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title: Option<&'a str>,
+    /// Set when it differs from the document's
+    #[serde(skip_serializing_if = "Option::is_none")]
+    language: Option<&'a str>,
+    text: &'a str,
+}
+
+#[derive(Serialize)]
+struct Bookmark<'a> {
+    title: &'a str,
+    level: usize,
+    /// Index into `blocks` of the block it opens
+    block: usize,
+}
+
+/// One line of a JSON Lines corpus.
+#[derive(Serialize)]
+struct Record<'a> {
+    source: String,
+    title: Option<&'a str>,
+    author: Option<&'a str>,
+    language: Option<&'a str>,
+    collection: Option<&'a str>,
+    text: &'a str,
+}
+
+/// The document as a single-line JSON object ending in a line break, with `text` the body
+/// of its text output.
+pub fn record(doc: &Document, source: &Path, text: &str) -> String {
+    let record = Record {
+        source: source.display().to_string(),
+        title: doc.metadata.title.as_deref(),
+        author: doc.metadata.author.as_deref(),
+        language: doc.metadata.language.as_deref(),
+        collection: doc.metadata.collection.as_deref(),
+        text: text.trim(),
+    };
+    // Plain data with string keys always serialises
+    let mut rendered = serde_json::to_string(&record).unwrap_or_default();
+    rendered.push('\n');
+    rendered
+}
+
+/// The document as a pretty-printed JSON object, its text as extracted (line wrapping does not
+/// apply).
+pub fn render(doc: &Document, source: &Path) -> String {
+    let count = |kind| doc.sections.iter().filter(|s| s.kind == kind).count();
+    let json = JsonDocument {
+        source: source.display().to_string(),
+        title: doc.metadata.title.as_deref(),
+        author: doc.metadata.author.as_deref(),
+        language: doc.metadata.language.as_deref(),
+        collection: doc.metadata.collection.as_deref(),
+        page_count: count(SectionKind::Page),
+        chapter_count: count(SectionKind::Chapter),
+        blocks: doc
+            .sections
+            .iter()
+            .map(|section| Block {
+                kind: match section.kind {
+                    SectionKind::Page => "page",
+                    SectionKind::Chapter => "chapter",
+                },
+                page: section.page,
+                title: section.title.as_deref(),
+                language: section.language.as_deref(),
+                text: section.text.trim_end(),
+            })
+            .collect(),
+        outline: doc
+            .outline
+            .iter()
+            .map(|entry| Bookmark {
+                title: &entry.title,
+                level: entry.level,
+                block: entry.section,
+            })
+            .collect(),
+    };
+    // Plain data with string keys always serialises
+    let mut rendered = serde_json::to_string_pretty(&json).unwrap_or_default();
+    rendered.push('\n');
+    rendered
+}
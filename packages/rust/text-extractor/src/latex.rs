@@ -0,0 +1,1628 @@
+//! LaTeX sources turned into text, for preprint source dumps converted alongside their PDFs.
+//!
+//! `\input`, `\include` and friends are resolved first, and `\bibliography` pulls in the
+//! compiled `.bbl` when one sits next to the main file. Commands are then dropped or replaced
+//! by what they print: sectioning commands start sections and bookmarks, lists become `- `
+//! and `1. ` lines, verbatim blocks are fenced, and math is kept as `$...$` and `$$...$$`,
+//! the way MathML in EPUBs is rendered.
+
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use crate::document::{Document, Landmarks, OutlineEntry, Section, SectionKind, CODE_FENCE};
+use crate::metadata::Metadata;
+use crate::tables;
+
+/// Deepest nesting of included files followed, which also stops inclusion cycles.
+const MAX_INPUT_DEPTH: usize = 16;
+/// Deepest nesting of user macro expansions, which also stops recursive definitions.
+const MAX_EXPANSION_DEPTH: usize = 32;
+
+/// Commands that include another file, by the number of path arguments they take.
+const INPUT_COMMANDS: &[(&str, usize)] = &[
+    ("input", 1),
+    ("include", 1),
+    ("subfile", 1),
+    ("import", 2),
+    ("subimport", 2),
+];
+
+/// Environments whose content is printed as typed.
+const VERBATIM_ENVIRONMENTS: &[&str] = &["verbatim", "Verbatim", "lstlisting", "minted", "alltt"];
+
+/// Environments whose content is never printed.
+const HIDDEN_ENVIRONMENTS: &[&str] = &["comment", "tikzpicture", "pspicture", "picture"];
+
+/// Display math environments, kept as `$$...$$`; those marked `true` align their rows.
+const DISPLAY_MATH: &[(&str, bool)] = &[
+    ("equation", false),
+    ("equation*", false),
+    ("displaymath", false),
+    ("gather", false),
+    ("gather*", false),
+    ("multline", false),
+    ("multline*", false),
+    ("align", true),
+    ("align*", true),
+    ("flalign", true),
+    ("flalign*", true),
+    ("alignat", true),
+    ("alignat*", true),
+    ("eqnarray", true),
+    ("eqnarray*", true),
+];
+
+/// Environments laid out as tables, with the number of arguments before their rows.
+const TABLE_ENVIRONMENTS: &[(&str, usize)] = &[
+    ("tabular", 1),
+    ("tabular*", 2),
+    ("tabularx", 2),
+    ("tabulary", 2),
+    ("longtable", 1),
+];
+
+/// Mandatory arguments taken by other environments, skipped along with `\begin`.
+const ENVIRONMENT_ARGUMENTS: &[(&str, usize)] = &[
+    ("minipage", 1),
+    ("wrapfigure", 2),
+    ("multicols", 1),
+    ("thebibliography", 1),
+    ("subfigure", 1),
+    ("adjustbox", 1),
+];
+
+/// Theorem-like environments defined by most classes or by convention, with the name
+/// printed in front of them; `\newtheorem` adds to these.
+const THEOREMS: &[(&str, &str)] = &[
+    ("theorem", "Theorem"),
+    ("lemma", "Lemma"),
+    ("corollary", "Corollary"),
+    ("proposition", "Proposition"),
+    ("definition", "Definition"),
+    ("remark", "Remark"),
+    ("example", "Example"),
+    ("conjecture", "Conjecture"),
+    ("claim", "Claim"),
+    ("exercise", "Exercise"),
+    ("proof", "Proof"),
+];
+
+/// Sectioning commands with their depth; depths are renumbered so the outermost used
+/// becomes level 1.
+const SECTIONS: &[(&str, usize)] = &[
+    ("part", 1),
+    ("chapter", 2),
+    ("section", 3),
+    ("subsection", 4),
+    ("subsubsection", 5),
+];
+
+/// Commands that print nothing, with the number of mandatory arguments they swallow.
+/// Optional arguments are skipped wherever they occur.
+const DROPPED: &[(&str, usize)] = &[
+    ("label", 1),
+    ("index", 1),
+    ("glossary", 1),
+    ("vspace", 1),
+    ("hspace", 1),
+    ("includegraphics", 1),
+    ("bibliographystyle", 1),
+    ("nocite", 1),
+    ("usepackage", 1),
+    ("RequirePackage", 1),
+    ("documentclass", 1),
+    ("setlength", 2),
+    ("addtolength", 2),
+    ("setcounter", 2),
+    ("addtocounter", 2),
+    ("stepcounter", 1),
+    ("pagestyle", 1),
+    ("thispagestyle", 1),
+    ("pagenumbering", 1),
+    ("addcontentsline", 3),
+    ("hypersetup", 1),
+    ("graphicspath", 1),
+    ("markboth", 2),
+    ("markright", 1),
+    ("color", 1),
+    ("definecolor", 3),
+    ("geometry", 1),
+    ("title", 1),
+    ("author", 1),
+    ("date", 1),
+    ("thanks", 1),
+    ("affiliation", 1),
+    ("email", 1),
+    ("keywords", 1),
+    ("linespread", 1),
+    ("fontsize", 2),
+    ("newenvironment", 3),
+    ("renewenvironment", 3),
+    ("newtheorem", 2),
+    ("DeclareMathOperator", 2),
+    ("cline", 1),
+    ("cmidrule", 1),
+    ("bibliography", 1),
+    ("input", 1),
+    ("include", 1),
+    ("includeonly", 1),
+    ("setdefaultlanguage", 1),
+    ("setotherlanguage", 1),
+    ("selectlanguage", 1),
+];
+
+/// Commands whose last argument is printed and the ones before it dropped.
+const LAST_ARGUMENT: &[(&str, usize)] = &[
+    ("href", 2),
+    ("textcolor", 2),
+    ("colorbox", 2),
+    ("fcolorbox", 3),
+    ("multicolumn", 3),
+    ("multirow", 3),
+    ("parbox", 2),
+    ("raisebox", 2),
+    ("resizebox", 3),
+    ("scalebox", 2),
+    ("foreignlanguage", 2),
+];
+
+/// Commands that print a fixed text.
+const SYMBOLS: &[(&str, &str)] = &[
+    ("ldots", "…"),
+    ("dots", "…"),
+    ("textellipsis", "…"),
+    ("LaTeX", "LaTeX"),
+    ("LaTeXe", "LaTeX2e"),
+    ("TeX", "TeX"),
+    ("BibTeX", "BibTeX"),
+    ("textendash", "–"),
+    ("textemdash", "—"),
+    ("S", "§"),
+    ("P", "¶"),
+    ("copyright", "©"),
+    ("textcopyright", "©"),
+    ("textregistered", "®"),
+    ("texttrademark", "™"),
+    ("textdegree", "°"),
+    ("degree", "°"),
+    ("euro", "€"),
+    ("pounds", "£"),
+    ("ss", "ß"),
+    ("ae", "æ"),
+    ("AE", "Æ"),
+    ("oe", "œ"),
+    ("OE", "Œ"),
+    ("o", "ø"),
+    ("O", "Ø"),
+    ("aa", "å"),
+    ("AA", "Å"),
+    ("l", "ł"),
+    ("L", "Ł"),
+    ("i", "ı"),
+    ("textbackslash", "\\"),
+    ("textasciitilde", "~"),
+    ("textasciicircum", "^"),
+    ("textbar", "|"),
+    ("textless", "<"),
+    ("textgreater", ">"),
+    ("textbullet", "•"),
+    ("guillemotleft", "«"),
+    ("guillemotright", "»"),
+    ("textquoteleft", "‘"),
+    ("textquoteright", "’"),
+    ("textquotedblleft", "“"),
+    ("textquotedblright", "”"),
+    ("quad", " "),
+    ("qquad", " "),
+    ("enspace", " "),
+    ("space", " "),
+];
+
+/// Accent commands, the letters they combine with and the accented letters, with the
+/// combining mark used for any other letter.
+const ACCENTS: &[(&str, &str, &str, char)] = &[
+    (
+        "'",
+        "aeiouyAEIOUYcnszCNSZ",
+        "áéíóúýÁÉÍÓÚÝćńśźĆŃŚŹ",
+        '\u{301}',
+    ),
+    ("`", "aeiouAEIOU", "àèìòùÀÈÌÒÙ", '\u{300}'),
+    ("^", "aeiouAEIOU", "âêîôûÂÊÎÔÛ", '\u{302}'),
+    ("\"", "aeiouyAEIOUY", "äëïöüÿÄËÏÖÜŸ", '\u{308}'),
+    ("~", "anoANO", "ãñõÃÑÕ", '\u{303}'),
+    ("=", "aeiouAEIOU", "āēīōūĀĒĪŌŪ", '\u{304}'),
+    (".", "zeZEI", "żėŻĖİ", '\u{307}'),
+    ("c", "cCsStT", "çÇşŞţŢ", '\u{327}'),
+    ("v", "csznrecdCSZNRECD", "čšžňřěčďČŠŽŇŘĚČĎ", '\u{30c}'),
+    ("u", "gaGA", "ğăĞĂ", '\u{306}'),
+    ("H", "oOuU", "őŐűŰ", '\u{30b}'),
+    ("k", "aeAE", "ąęĄĘ", '\u{328}'),
+    ("r", "auAU", "åůÅŮ", '\u{30a}'),
+];
+
+/// Babel and polyglossia language names with their ISO 639-1 codes.
+const LANGUAGES: &[(&str, &str)] = &[
+    ("english", "en"),
+    ("american", "en"),
+    ("british", "en"),
+    ("usenglish", "en"),
+    ("ukenglish", "en"),
+    ("german", "de"),
+    ("ngerman", "de"),
+    ("austrian", "de"),
+    ("french", "fr"),
+    ("francais", "fr"),
+    ("spanish", "es"),
+    ("italian", "it"),
+    ("portuguese", "pt"),
+    ("brazilian", "pt"),
+    ("brazil", "pt"),
+    ("dutch", "nl"),
+    ("russian", "ru"),
+    ("ukrainian", "uk"),
+    ("polish", "pl"),
+    ("czech", "cs"),
+    ("slovak", "sk"),
+    ("hungarian", "hu"),
+    ("swedish", "sv"),
+    ("danish", "da"),
+    ("norsk", "no"),
+    ("norwegian", "no"),
+    ("nynorsk", "nn"),
+    ("finnish", "fi"),
+    ("greek", "el"),
+    ("turkish", "tr"),
+    ("catalan", "ca"),
+    ("japanese", "ja"),
+    ("chinese", "zh"),
+    ("korean", "ko"),
+];
+
+/// Whether the `.tex` file is a document of its own rather than a chapter that another
+/// file pulls in with `\input` or `\include`.
+pub fn is_document(path: &Path) -> bool {
+    let Ok(source) = fs::read_to_string(path) else {
+        return false;
+    };
+    let source = strip_comments(&source);
+    source.find("\\documentclass").is_some_and(|at| {
+        // Subfiles of a larger document declare themselves with the subfiles class
+        let class = skip_arguments(&source, at + "\\documentclass".len(), 0);
+        !group(&source, class)
+            .is_some_and(|(class, _)| matches!(class.trim(), "subfiles" | "standalone"))
+    })
+}
+
+/// Converts a LaTeX document, with the files it includes, into text.
+pub fn extract(path: &Path, metadata: Option<&Metadata>, tables: bool) -> Result<Document> {
+    let root = path.parent().unwrap_or(Path::new("."));
+    let source = fs::read_to_string(path)
+        .context(format!("Failed to read LaTeX source: {}", path.display()))?;
+    let bibliography = path.with_extension("bbl");
+    let source = resolve_inputs(&strip_comments(&source), root, &bibliography, 0);
+
+    let (preamble, body) = match source.find("\\begin{document}") {
+        Some(at) => {
+            let body = &source[at + "\\begin{document}".len()..];
+            (
+                &source[..at],
+                &body[..body.find("\\end{document}").unwrap_or(body.len())],
+            )
+        }
+        None => ("", source.as_str()),
+    };
+
+    let definitions = Definitions::read(&source);
+    let mut converter = Converter {
+        definitions: &definitions,
+        tables,
+        lists: Vec::new(),
+        environments: Vec::new(),
+        figures: 0,
+        tables_seen: 0,
+        expanding: 0,
+    };
+
+    let mut embedded = Metadata {
+        title: argument_of(&source, "title").map(|title| converter.inline(&title)),
+        author: author(&source, &mut converter),
+        language: language(preamble),
+        collection: None,
+    };
+    embedded.title = embedded.title.filter(|title| !title.is_empty());
+
+    let mut out = Output::default();
+    converter.convert(body, &mut out);
+    out.end_section();
+
+    // The outermost sectioning command used becomes level 1
+    let top = out
+        .sections
+        .iter()
+        .filter_map(|section| section.heading.as_ref().map(|(depth, _)| *depth))
+        .min()
+        .unwrap_or(1);
+    let mut outline = Vec::new();
+    let mut sections = Vec::new();
+    for (index, section) in out.sections.into_iter().enumerate() {
+        let mut lines = section.blocks;
+        if let Some((depth, title)) = section.heading {
+            outline.push(OutlineEntry {
+                title: title.clone(),
+                level: depth - top + 1,
+                section: index,
+            });
+            lines.insert(0, title);
+        }
+        sections.push(Section {
+            kind: SectionKind::Chapter,
+            title: None,
+            text: lines.join("\n"),
+            language: None,
+            page: None,
+        });
+    }
+
+    Ok(Document {
+        metadata: metadata.cloned().unwrap_or_default().or(embedded),
+        sections,
+        outline,
+        landmarks: Landmarks::default(),
+    })
+}
+
+/// Removes `%` comments, keeping escaped `\%` and the content of verbatim environments.
+/// Lines holding nothing but a comment go entirely, so they cannot end a paragraph.
+fn strip_comments(source: &str) -> String {
+    let mut out = String::with_capacity(source.len());
+    let mut verbatim: Option<&str> = None;
+
+    for line in source.lines() {
+        if let Some(environment) = verbatim {
+            out.push_str(line);
+            out.push('\n');
+            if line.contains(&format!("\\end{{{environment}}}")) {
+                verbatim = None;
+            }
+            continue;
+        }
+        verbatim = VERBATIM_ENVIRONMENTS
+            .iter()
+            .find(|environment| line.contains(&format!("\\begin{{{environment}}}")))
+            .copied();
+
+        let mut escaped = false;
+        let cut = line.char_indices().find_map(|(i, c)| {
+            let comment = c == '%' && !escaped;
+            escaped = c == '\\' && !escaped;
+            comment.then_some(i)
+        });
+        match cut {
+            Some(0) => {}
+            Some(at) if line[..at].trim().is_empty() => {}
+            Some(at) => {
+                out.push_str(&line[..at]);
+                out.push('\n');
+            }
+            None => {
+                out.push_str(line);
+                out.push('\n');
+            }
+        }
+    }
+    out
+}
+
+/// Replaces `\input`, `\include` and similar commands with the files they name, and
+/// `\bibliography` with the compiled bibliography. Missing files are left out.
+fn resolve_inputs(source: &str, root: &Path, bibliography: &Path, depth: usize) -> String {
+    let mut out = String::with_capacity(source.len());
+    let mut copied = 0;
+    let mut from = 0;
+
+    while let Some(found) = source[from..].find('\\') {
+        let start = from + found;
+        let (name, mut at) = command_name(source, start + 1);
+        from = at.max(start + 1);
+
+        let included = if name == "bibliography" {
+            let Some((_, end)) = group(source, at) else {
+                continue;
+            };
+            at = end;
+            fs::read_to_string(bibliography)
+                .ok()
+                .map(|bbl| strip_comments(&bbl))
+        } else if let Some(&(_, arguments)) = INPUT_COMMANDS.iter().find(|(n, _)| *n == name) {
+            let mut path = root.to_path_buf();
+            for _ in 0..arguments {
+                let Some((argument, end)) = group(source, at) else {
+                    break;
+                };
+                path.push(argument.trim());
+                at = end;
+            }
+            (depth < MAX_INPUT_DEPTH)
+                .then(|| read_input(&path, root, bibliography, depth + 1))
+                .flatten()
+        } else {
+            continue;
+        };
+
+        out.push_str(&source[copied..start]);
+        if let Some(included) = included {
+            out.push('\n');
+            out.push_str(&included);
+            out.push('\n');
+        }
+        copied = at;
+        from = at;
+    }
+
+    out.push_str(&source[copied..]);
+    out
+}
+
+/// The body of an included file, its own inclusions resolved. Paths are relative to the
+/// main file, and `.tex` is added when the name has no extension.
+fn read_input(path: &Path, root: &Path, bibliography: &Path, depth: usize) -> Option<String> {
+    let source = fs::read_to_string(path.with_extension("tex"))
+        .or_else(|_| fs::read_to_string(path))
+        .ok()?;
+    let source = strip_comments(&source);
+    // Subfiles are complete documents; only their body belongs to the main one
+    let body = match source.find("\\begin{document}") {
+        Some(at) => {
+            let body = &source[at + "\\begin{document}".len()..];
+            &body[..body.find("\\end{document}").unwrap_or(body.len())]
+        }
+        None => source.as_str(),
+    };
+    Some(resolve_inputs(body, root, bibliography, depth))
+}
+
+/// User macros without parameters and theorem names defined anywhere in the source.
+#[derive(Default)]
+struct Definitions {
+    macros: HashMap<String, String>,
+    theorems: HashMap<String, String>,
+}
+
+impl Definitions {
+    fn read(source: &str) -> Self {
+        let mut definitions = Definitions::default();
+        for (name, printed) in THEOREMS {
+            definitions
+                .theorems
+                .insert((*name).to_string(), (*printed).to_string());
+        }
+
+        let mut from = 0;
+        while let Some(found) = source[from..].find('\\') {
+            let (name, at) = command_name(source, from + found + 1);
+            from = at.max(from + found + 1);
+            match name {
+                "newcommand" | "renewcommand" | "providecommand" => {
+                    let Some((macro_name, at)) = group(source, at) else {
+                        continue;
+                    };
+                    let (parameters, at) = optional(source, at).unwrap_or(("", at));
+                    if let Some((body, end)) = group(source, at) {
+                        if parameters.trim().is_empty() || parameters.trim() == "0" {
+                            definitions.define(macro_name, body);
+                        }
+                        from = end;
+                    }
+                }
+                "def" => {
+                    let (macro_name, at) = command_name(source, skip_spaces(source, at) + 1);
+                    // Only definitions without parameters go straight to their body
+                    if let Some((body, end)) = group(source, at) {
+                        if source[at..].trim_start().starts_with('{') {
+                            definitions.define(&format!("\\{macro_name}"), body);
+                        }
+                        from = end;
+                    }
+                }
+                "newtheorem" => {
+                    let Some((environment, at)) = group(source, at) else {
+                        continue;
+                    };
+                    let at = optional(source, at).map_or(at, |(_, end)| end);
+                    if let Some((printed, end)) = group(source, at) {
+                        definitions
+                            .theorems
+                            .insert(environment.trim().to_string(), printed.trim().to_string());
+                        from = end;
+                    }
+                }
+                _ => {}
+            }
+        }
+        definitions
+    }
+
+    fn define(&mut self, name: &str, body: &str) {
+        let name = name.trim().trim_start_matches('\\');
+        if !name.is_empty() && !body.contains('#') {
+            self.macros.insert(name.to_string(), body.to_string());
+        }
+    }
+}
+
+/// The first argument of the first `\command`, skipping an optional short form.
+fn argument_of(source: &str, command: &str) -> Option<String> {
+    arguments_of(source, command).into_iter().next()
+}
+
+/// The first argument of every `\command`, in order.
+fn arguments_of(source: &str, command: &str) -> Vec<String> {
+    let mut found = Vec::new();
+    let mut from = 0;
+    while let Some(at) = source[from..].find('\\') {
+        let (name, after) = command_name(source, from + at + 1);
+        from = after.max(from + at + 1);
+        if name == command {
+            let after = optional(source, after).map_or(after, |(_, end)| end);
+            if let Some((argument, end)) = group(source, after) {
+                found.push(argument.to_string());
+                from = end;
+            }
+        }
+    }
+    found
+}
+
+/// The authors from every `\author`, those sharing one split at `\and`.
+fn author(source: &str, converter: &mut Converter) -> Option<String> {
+    let authors: Vec<String> = arguments_of(source, "author")
+        .iter()
+        .flat_map(|authors| {
+            authors
+                .split("\\and")
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        })
+        .map(|author| converter.inline(&author))
+        .filter(|author| !author.is_empty())
+        .collect();
+    (!authors.is_empty()).then(|| authors.join(", "))
+}
+
+/// The main language from babel's options (the last one given) or polyglossia.
+fn language(preamble: &str) -> Option<String> {
+    let code = |name: &str| {
+        let name = name.trim().to_lowercase();
+        LANGUAGES
+            .iter()
+            .find(|(language, _)| *language == name)
+            .map(|(_, code)| (*code).to_string())
+    };
+
+    if let Some(language) = argument_of(preamble, "setdefaultlanguage").and_then(|l| code(&l)) {
+        return Some(language);
+    }
+    let mut from = 0;
+    let mut found = None;
+    while let Some(at) = preamble[from..].find("\\usepackage") {
+        let after = from + at + "\\usepackage".len();
+        from = after;
+        let Some((options, end)) = optional(preamble, after) else {
+            continue;
+        };
+        if group(preamble, end).is_some_and(|(package, _)| package.trim() == "babel") {
+            found = options.split(',').rev().find_map(code).or(found);
+        }
+    }
+    found
+}
+
+/// Text converted so far: finished sections, and the lines and paragraph of the current one.
+#[derive(Default)]
+struct Output {
+    sections: Vec<OutputSection>,
+    heading: Option<(usize, String)>,
+    blocks: Vec<String>,
+    /// Text of the paragraph being built; `\n` marks forced line breaks
+    paragraph: String,
+}
+
+struct OutputSection {
+    /// Depth of the sectioning command and the title
+    heading: Option<(usize, String)>,
+    blocks: Vec<String>,
+}
+
+impl Output {
+    fn push(&mut self, text: &str) {
+        self.paragraph.push_str(text);
+    }
+
+    fn end_paragraph(&mut self) {
+        for line in self.paragraph.split('\n') {
+            let line = line.split_whitespace().collect::<Vec<_>>().join(" ");
+            if !line.is_empty() {
+                self.blocks.push(line);
+            }
+        }
+        self.paragraph.clear();
+    }
+
+    /// Adds a line of its own, such as a display equation or a fenced code block.
+    fn block(&mut self, block: String) {
+        self.end_paragraph();
+        if !block.trim().is_empty() {
+            self.blocks.push(block);
+        }
+    }
+
+    fn heading(&mut self, depth: usize, title: String) {
+        self.end_section();
+        self.heading = Some((depth, title));
+    }
+
+    fn end_section(&mut self) {
+        self.end_paragraph();
+        if self.heading.is_some() || !self.blocks.is_empty() {
+            self.sections.push(OutputSection {
+                heading: self.heading.take(),
+                blocks: std::mem::take(&mut self.blocks),
+            });
+        }
+    }
+
+    /// Everything converted, on one line.
+    fn into_inline(mut self) -> String {
+        self.end_section();
+        self.sections
+            .into_iter()
+            .flat_map(|section| {
+                section
+                    .heading
+                    .map(|(_, title)| title)
+                    .into_iter()
+                    .chain(section.blocks)
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+enum List {
+    Bullets,
+    Numbered(usize),
+    Description,
+}
+
+struct Converter<'a> {
+    definitions: &'a Definitions,
+    /// Lay tables out as Markdown rather than as ` | `-separated lines
+    tables: bool,
+    /// Open lists, innermost last
+    lists: Vec<List>,
+    /// Open environments, innermost last
+    environments: Vec<String>,
+    figures: usize,
+    tables_seen: usize,
+    /// User macros being expanded
+    expanding: usize,
+}
+
+impl Converter<'_> {
+    /// Converts a fragment that prints as running text, such as a title or a caption.
+    fn inline(&mut self, source: &str) -> String {
+        let mut out = Output::default();
+        self.convert(source, &mut out);
+        out.into_inline()
+    }
+
+    fn convert(&mut self, source: &str, out: &mut Output) {
+        let mut at = 0;
+        while let Some(c) = source[at..].chars().next() {
+            at = match c {
+                '\\' => self.command(source, at, out),
+                '{' | '}' => at + 1,
+                '$' => self.math(source, at, out),
+                '~' => {
+                    out.push(" ");
+                    at + 1
+                }
+                '\n' => {
+                    let next = skip_blank(source, at + 1);
+                    if source[next..].starts_with('\n') {
+                        out.end_paragraph();
+                        skip_all_whitespace(source, next)
+                    } else {
+                        out.push(" ");
+                        next
+                    }
+                }
+                '`' if source[at..].starts_with("``") => {
+                    out.push("“");
+                    at + 2
+                }
+                '`' => {
+                    out.push("‘");
+                    at + 1
+                }
+                '\'' if source[at..].starts_with("''") => {
+                    out.push("”");
+                    at + 2
+                }
+                '-' if source[at..].starts_with("---") => {
+                    out.push("—");
+                    at + 3
+                }
+                '-' if source[at..].starts_with("--") => {
+                    out.push("–");
+                    at + 2
+                }
+                _ => {
+                    out.paragraph.push(c);
+                    at + c.len_utf8()
+                }
+            };
+        }
+    }
+
+    /// Handles the command at `at` (a backslash) and returns where the text resumes.
+    fn command(&mut self, source: &str, at: usize, out: &mut Output) -> usize {
+        let (name, after) = command_name(source, at + 1);
+
+        // Control symbols: a backslash and one other character
+        if !name.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            return self.control_symbol(source, name, after, out);
+        }
+        // TeX drops the spaces after a control word
+        let next = skip_spaces(source, after);
+
+        if let Some(body) = self.definitions.macros.get(name) {
+            if self.expanding < MAX_EXPANSION_DEPTH {
+                self.expanding += 1;
+                self.convert(body, out);
+                self.expanding -= 1;
+            }
+            return next;
+        }
+        if let Some((_, text)) = SYMBOLS.iter().find(|(symbol, _)| *symbol == name) {
+            out.push(text);
+            return next;
+        }
+        if let Some(&(_, arguments)) = DROPPED.iter().find(|(command, _)| *command == name) {
+            return skip_arguments(source, after, arguments);
+        }
+        if let Some(&(_, arguments)) = LAST_ARGUMENT.iter().find(|(command, _)| *command == name) {
+            let at = skip_arguments(source, after, arguments - 1);
+            let at = optional(source, at).map_or(at, |(_, end)| end);
+            return match group(source, at) {
+                Some((last, end)) => {
+                    self.convert(last, out);
+                    end
+                }
+                None => at,
+            };
+        }
+        if let Some(&(_, depth)) = SECTIONS.iter().find(|(command, _)| *command == name) {
+            return self.section(source, after, depth, out);
+        }
+        if let Some((_, letters, accented, mark)) =
+            ACCENTS.iter().find(|(accent, ..)| *accent == name)
+        {
+            // Letter accents such as \c and \v take their letter as an argument
+            return accent(source, after, letters, accented, *mark, out);
+        }
+
+        match name {
+            "begin" => self.begin(source, after, out),
+            "end" => self.end(source, after, out),
+            "item" => self.item(source, after, out),
+            "bibitem" => {
+                let (label, at) = optional(source, after).unwrap_or(("", after));
+                let Some((key, end)) = group(source, at) else {
+                    return at;
+                };
+                out.end_paragraph();
+                let label = if label.is_empty() { key } else { label };
+                out.push(&format!("[{}] ", label.trim()));
+                end
+            }
+            "paragraph" | "subparagraph" => {
+                let at = skip_star(source, after);
+                let at = optional(source, at).map_or(at, |(_, end)| end);
+                match group(source, at) {
+                    Some((title, end)) => {
+                        let title = self.inline(title);
+                        out.block(title);
+                        end
+                    }
+                    None => at,
+                }
+            }
+            "caption" => {
+                let at = optional(source, after).map_or(after, |(_, end)| end);
+                let Some((caption, end)) = group(source, at) else {
+                    return at;
+                };
+                let caption = self.inline(caption);
+                let label = if self.in_environment(&["figure", "figure*", "wrapfigure"]) {
+                    self.figures += 1;
+                    format!("Figure {}: ", self.figures)
+                } else if self.in_environment(&["table", "table*"]) {
+                    self.tables_seen += 1;
+                    format!("Table {}: ", self.tables_seen)
+                } else {
+                    String::new()
+                };
+                out.block(format!("{label}{caption}"));
+                end
+            }
+            "footnote" | "footnotetext" => {
+                let at = optional(source, after).map_or(after, |(_, end)| end);
+                let Some((note, end)) = group(source, at) else {
+                    return at;
+                };
+                let note = self.inline(note);
+                out.push(&format!(" ({note})"));
+                end
+            }
+            "cite" | "citep" | "citet" | "parencite" | "textcite" | "autocite" | "ref"
+            | "eqref" | "pageref" | "autoref" | "cref" | "Cref" | "nameref" => {
+                let at = skip_star(source, after);
+                let at = skip_optionals(source, at);
+                let Some((keys, end)) = group(source, at) else {
+                    return at;
+                };
+                let keys: Vec<&str> = keys.split(',').map(str::trim).collect();
+                out.push(&format!("[{}]", keys.join(", ")));
+                end
+            }
+            "url" | "path" => match group(source, after) {
+                Some((url, end)) => {
+                    out.push(url.trim());
+                    end
+                }
+                None => next,
+            },
+            "verb" => {
+                // \verb|text| takes any delimiter
+                let Some(delimiter) = source[after..].chars().next() else {
+                    return after;
+                };
+                let start = after + delimiter.len_utf8();
+                let end = source[start..]
+                    .find(delimiter)
+                    .map_or(source.len(), |i| start + i);
+                out.push(&source[start..end]);
+                (end + delimiter.len_utf8()).min(source.len())
+            }
+            "enquote" => match group(source, after) {
+                Some((quote, end)) => {
+                    out.push("“");
+                    self.convert(quote, out);
+                    out.push("”");
+                    end
+                }
+                None => next,
+            },
+            "newcommand" | "renewcommand" | "providecommand" => {
+                let at = group(source, after).map_or(after, |(_, end)| end);
+                skip_arguments(source, at, 1)
+            }
+            "def" => {
+                let (_, at) = command_name(source, skip_spaces(source, after) + 1);
+                let body = source[at..].find('{').map_or(at, |i| at + i);
+                group(source, body).map_or(body, |(_, end)| end)
+            }
+            "par" => {
+                out.end_paragraph();
+                next
+            }
+            "newline" | "linebreak" => {
+                out.push("\n");
+                next
+            }
+            "today" => next,
+            // Font switches, spacing and page layout print nothing; an unknown command's
+            // argument, if any, is printed as plain text
+            _ => skip_optionals(source, after).max(next),
+        }
+    }
+
+    fn control_symbol(
+        &mut self,
+        source: &str,
+        symbol: &str,
+        after: usize,
+        out: &mut Output,
+    ) -> usize {
+        if let Some((_, letters, accented, mark)) =
+            ACCENTS.iter().find(|(accent, ..)| *accent == symbol)
+        {
+            return accent(source, after, letters, accented, *mark, out);
+        }
+        match symbol {
+            "\\" => {
+                out.push("\n");
+                // \\* and \\[2pt] only adjust the spacing
+                skip_optionals(source, skip_star(source, after))
+            }
+            "(" => {
+                let end = source[after..]
+                    .find("\\)")
+                    .map_or(source.len(), |i| after + i);
+                let math = self.expand_math(&source[after..end]);
+                out.push(&format!("${}$", math.trim()));
+                (end + 2).min(source.len())
+            }
+            "[" => {
+                let end = source[after..]
+                    .find("\\]")
+                    .map_or(source.len(), |i| after + i);
+                out.block(self.display(&source[after..end], false));
+                (end + 2).min(source.len())
+            }
+            "%" | "&" | "$" | "#" | "_" | "{" | "}" => {
+                out.push(symbol);
+                after
+            }
+            " " | "," | ";" | ":" | "\n" => {
+                out.push(" ");
+                after
+            }
+            // Discretionary hyphens, italic corrections and spacing hints
+            _ => after,
+        }
+    }
+
+    /// A sectioning command: a new section, titled by the mandatory argument.
+    fn section(&mut self, source: &str, after: usize, depth: usize, out: &mut Output) -> usize {
+        let at = skip_star(source, after);
+        let at = optional(source, at).map_or(at, |(_, end)| end);
+        let Some((title, end)) = group(source, at) else {
+            return at;
+        };
+        let title = self.inline(title);
+        out.heading(depth, title);
+        end
+    }
+
+    fn begin(&mut self, source: &str, after: usize, out: &mut Output) -> usize {
+        let Some((name, at)) = group(source, after) else {
+            return after;
+        };
+        let name = name.trim();
+
+        if VERBATIM_ENVIRONMENTS.contains(&name) {
+            let at = skip_optionals(source, at);
+            let at = if name == "minted" {
+                group(source, at).map_or(at, |(_, end)| end)
+            } else {
+                at
+            };
+            let (body, end) = environment_body(source, at, name);
+            let code: Vec<&str> = body.trim_matches('\n').lines().map(str::trim_end).collect();
+            out.block(format!("{CODE_FENCE}\n{}\n{CODE_FENCE}", code.join("\n")));
+            return end;
+        }
+        if HIDDEN_ENVIRONMENTS.contains(&name) {
+            return environment_body(source, at, name).1;
+        }
+        if let Some(&(_, aligned)) = DISPLAY_MATH.iter().find(|(math, _)| *math == name) {
+            let at = if name.starts_with("alignat") {
+                group(source, at).map_or(at, |(_, end)| end)
+            } else {
+                at
+            };
+            let (body, end) = environment_body(source, at, name);
+            out.block(self.display(body, aligned));
+            return end;
+        }
+        if let Some(&(_, arguments)) = TABLE_ENVIRONMENTS.iter().find(|(table, _)| *table == name) {
+            let at = skip_arguments(source, at, arguments);
+            let (body, end) = environment_body(source, at, name);
+            self.table(body, out);
+            return end;
+        }
+
+        out.end_paragraph();
+        let theorem = self.definitions.theorems.get(name);
+        let mut at = if theorem.is_some() {
+            at
+        } else {
+            skip_optionals(source, at)
+        };
+        if let Some(&(_, arguments)) = ENVIRONMENT_ARGUMENTS.iter().find(|(e, _)| *e == name) {
+            at = skip_arguments(source, at, arguments);
+        }
+        match name {
+            "itemize" => self.lists.push(List::Bullets),
+            "enumerate" => self.lists.push(List::Numbered(0)),
+            "description" => self.lists.push(List::Description),
+            "abstract" => out.block("Abstract".to_string()),
+            "thebibliography" => out.block("References".to_string()),
+            _ => {
+                if let Some(printed) = theorem {
+                    // An optional argument names the theorem: "Theorem (Fermat). ..."
+                    let (title, end) = match optional(source, at) {
+                        Some((title, end)) => (format!(" ({})", self.inline(title)), end),
+                        None => (String::new(), at),
+                    };
+                    out.push(&format!("{}{title}. ", capitalised(printed)));
+                    at = end;
+                }
+            }
+        }
+        self.environments.push(name.to_string());
+        at
+    }
+
+    fn end(&mut self, source: &str, after: usize, out: &mut Output) -> usize {
+        let Some((name, end)) = group(source, after) else {
+            return after;
+        };
+        out.end_paragraph();
+        if matches!(name.trim(), "itemize" | "enumerate" | "description") {
+            self.lists.pop();
+        }
+        if let Some(open) = self.environments.iter().rposition(|e| e == name.trim()) {
+            self.environments.truncate(open);
+        }
+        end
+    }
+
+    fn item(&mut self, source: &str, after: usize, out: &mut Output) -> usize {
+        let (label, at) = match optional(source, after) {
+            Some((label, end)) => (Some(self.inline(label)), end),
+            None => (None, after),
+        };
+        out.end_paragraph();
+        let marker = match (self.lists.last_mut(), label) {
+            (Some(List::Description), Some(label)) => format!("{label}: "),
+            (_, Some(label)) => format!("{label} "),
+            (Some(List::Numbered(n)), None) => {
+                *n += 1;
+                format!("{n}. ")
+            }
+            _ => "- ".to_string(),
+        };
+        out.push(&marker);
+        skip_spaces(source, at)
+    }
+
+    /// Lays out a tabular's rows, cells converted as running text.
+    fn table(&mut self, body: &str, out: &mut Output) {
+        let rows: Vec<Vec<String>> = split_top_level(body, "\\\\")
+            .into_iter()
+            .map(|row| {
+                split_top_level(row, "&")
+                    .into_iter()
+                    .map(|cell| self.inline(cell))
+                    .collect::<Vec<_>>()
+            })
+            .filter(|row| row.iter().any(|cell| !cell.is_empty()))
+            .collect();
+        if rows.is_empty() {
+            return;
+        }
+
+        if self.tables {
+            out.block(tables::markdown(&rows));
+        } else {
+            out.end_paragraph();
+            for row in rows {
+                out.block(row.join(" | "));
+            }
+        }
+    }
+
+    fn in_environment(&self, names: &[&str]) -> bool {
+        self.environments
+            .iter()
+            .any(|e| names.contains(&e.as_str()))
+    }
+
+    /// Inline math `$...$`, or display math `$$...$$` on a line of its own.
+    fn math(&self, source: &str, at: usize, out: &mut Output) -> usize {
+        if source[at..].starts_with("$$") {
+            let start = at + 2;
+            let end = source[start..]
+                .find("$$")
+                .map_or(source.len(), |i| start + i);
+            out.block(self.display(&source[start..end], false));
+            return (end + 2).min(source.len());
+        }
+
+        let start = at + 1;
+        let mut escaped = false;
+        let end = source[start..]
+            .char_indices()
+            .find_map(|(i, c)| {
+                let closes = c == '$' && !escaped;
+                escaped = c == '\\' && !escaped;
+                closes.then_some(start + i)
+            })
+            .unwrap_or(source.len());
+        let math = self.expand_math(&source[start..end]);
+        out.push(&format!("${}$", math.trim()));
+        (end + 1).min(source.len())
+    }
+
+    /// A display equation as `$$...$$`, without labels and numbering switches. The rows of
+    /// alignment environments are kept together in an `aligned` block.
+    fn display(&self, math: &str, aligned: bool) -> String {
+        let mut math = self.expand_math(math);
+        while let Some(at) = math.find("\\label") {
+            let end =
+                group(&math, at + "\\label".len()).map_or(at + "\\label".len(), |(_, end)| end);
+            math.replace_range(at..end, "");
+        }
+        let math = math.replace("\\nonumber", "").replace("\\notag", "");
+        let math = math.split_whitespace().collect::<Vec<_>>().join(" ");
+        if math.is_empty() {
+            String::new()
+        } else if aligned {
+            format!("$$\\begin{{aligned}} {math} \\end{{aligned}}$$")
+        } else {
+            format!("$${math}$$")
+        }
+    }
+
+    /// Math with the user's macros replaced by their definitions, which may be written for
+    /// either mode, such as `\\R` for `\\mathbb{R}`.
+    fn expand_math(&self, math: &str) -> String {
+        let mut math = math.to_string();
+        for _ in 0..MAX_EXPANSION_DEPTH {
+            let mut expanded = String::with_capacity(math.len());
+            let mut changed = false;
+            let mut at = 0;
+            while let Some(found) = math[at..].find('\\') {
+                let start = at + found;
+                let (name, end) = command_name(&math, start + 1);
+                expanded.push_str(&math[at..start]);
+                match self.definitions.macros.get(name) {
+                    Some(body) => {
+                        expanded.push_str(body);
+                        changed = true;
+                    }
+                    None => expanded.push_str(&math[start..end]),
+                }
+                at = end;
+            }
+            expanded.push_str(&math[at..]);
+            math = expanded;
+            if !changed {
+                break;
+            }
+        }
+        math
+    }
+}
+
+/// An accented letter, given as `\'e`, `\'{e}` or `\c{c}`.
+fn accent(
+    source: &str,
+    after: usize,
+    letters: &str,
+    accented: &str,
+    mark: char,
+    out: &mut Output,
+) -> usize {
+    let (letter, end) = match group(source, after) {
+        Some((letter, end)) => (letter.trim().trim_start_matches('\\'), end),
+        None => return after,
+    };
+    let Some(base) = letter.chars().next() else {
+        return end;
+    };
+    match letters.chars().position(|c| c == base) {
+        Some(i) => out.paragraph.extend(accented.chars().nth(i)),
+        None => {
+            out.paragraph.push(base);
+            out.paragraph.push(mark);
+        }
+    }
+    end
+}
+
+fn capitalised(name: &str) -> String {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .map(|first| first.to_uppercase().chain(chars).collect())
+        .unwrap_or_default()
+}
+
+/// The command name starting at `at` (just past the backslash) and the offset after it:
+/// a run of letters with an optional star, or a single other character.
+fn command_name(source: &str, at: usize) -> (&str, usize) {
+    let rest = &source[at.min(source.len())..];
+    let letters = rest
+        .find(|c: char| !c.is_ascii_alphabetic())
+        .unwrap_or(rest.len());
+    if letters > 0 {
+        return (&rest[..letters], at + letters);
+    }
+    match rest.chars().next() {
+        Some(c) => (&rest[..c.len_utf8()], at + c.len_utf8()),
+        None => ("", at),
+    }
+}
+
+/// A mandatory argument: a braced group, a control sequence or a single character.
+fn group(source: &str, at: usize) -> Option<(&str, usize)> {
+    let at = skip_spaces(source, at);
+    let rest = source.get(at..)?;
+    match rest.chars().next()? {
+        '{' => {
+            let end = matching(rest, '{', '}')?;
+            Some((&rest[1..end], at + end + 1))
+        }
+        '\\' => {
+            let (_, end) = command_name(source, at + 1);
+            Some((&source[at..end], end))
+        }
+        '}' | '[' | ']' => None,
+        c => Some((&rest[..c.len_utf8()], at + c.len_utf8())),
+    }
+}
+
+/// An optional argument in brackets.
+fn optional(source: &str, at: usize) -> Option<(&str, usize)> {
+    let at = skip_spaces(source, at);
+    let rest = source.get(at..)?;
+    if !rest.starts_with('[') {
+        return None;
+    }
+    let end = matching(rest, '[', ']')?;
+    Some((&rest[1..end], at + end + 1))
+}
+
+/// Offset of the bracket closing the one `text` starts with, skipping escaped brackets and
+/// those inside braces.
+fn matching(text: &str, open: char, close: char) -> Option<usize> {
+    let mut depth = 0usize;
+    let mut braces = 0usize;
+    let mut escaped = false;
+    for (i, c) in text.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match c {
+            '\\' => escaped = true,
+            '{' if open != '{' => braces += 1,
+            '}' if open != '{' => braces = braces.saturating_sub(1),
+            c if c == open && braces == 0 => depth += 1,
+            c if c == close && braces == 0 => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Skips `count` mandatory arguments and any optional ones around them.
+fn skip_arguments(source: &str, mut at: usize, count: usize) -> usize {
+    at = skip_optionals(source, skip_star(source, at));
+    for _ in 0..count {
+        match group(source, at) {
+            Some((_, end)) => at = skip_optionals(source, end),
+            None => break,
+        }
+    }
+    at
+}
+
+fn skip_optionals(source: &str, mut at: usize) -> usize {
+    while let Some((_, end)) = optional(source, at) {
+        at = end;
+    }
+    at
+}
+
+fn skip_star(source: &str, at: usize) -> usize {
+    if source[at..].starts_with('*') {
+        at + 1
+    } else {
+        at
+    }
+}
+
+/// Skips spaces and tabs, and one line break unless a blank line follows it.
+fn skip_spaces(source: &str, at: usize) -> usize {
+    let at = skip_blank(source, at);
+    if source[at..].starts_with('\n') {
+        let next = skip_blank(source, at + 1);
+        if !source[next..].starts_with('\n') {
+            return next;
+        }
+    }
+    at
+}
+
+fn skip_blank(source: &str, at: usize) -> usize {
+    source[at..]
+        .find(|c: char| c != ' ' && c != '\t')
+        .map_or(source.len(), |i| at + i)
+}
+
+fn skip_all_whitespace(source: &str, at: usize) -> usize {
+    source[at..]
+        .find(|c: char| !c.is_whitespace())
+        .map_or(source.len(), |i| at + i)
+}
+
+/// The body of the environment opened just before `at`, up to its matching `\end`, and the
+/// offset after that.
+fn environment_body<'a>(source: &'a str, at: usize, name: &str) -> (&'a str, usize) {
+    let begin = format!("\\begin{{{name}}}");
+    let end = format!("\\end{{{name}}}");
+    let mut depth = 1;
+    let mut from = at;
+
+    loop {
+        let next_end = source[from..].find(&end).map(|i| from + i);
+        let next_begin = source[from..].find(&begin).map(|i| from + i);
+        match (next_begin, next_end) {
+            (Some(b), Some(e)) if b < e => {
+                depth += 1;
+                from = b + begin.len();
+            }
+            (_, Some(e)) => {
+                depth -= 1;
+                if depth == 0 {
+                    return (&source[at..e], e + end.len());
+                }
+                from = e + end.len();
+            }
+            (_, None) => return (&source[at..], source.len()),
+        }
+    }
+}
+
+/// Splits at `separator` where it is not inside braces or escaped.
+fn split_top_level<'a>(text: &'a str, separator: &str) -> Vec<&'a str> {
+    let mut parts = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+    let mut at = 0;
+
+    while let Some(c) = text[at..].chars().next() {
+        if depth == 0 && text[at..].starts_with(separator) {
+            parts.push(&text[start..at]);
+            at += separator.len();
+            start = at;
+            continue;
+        }
+        match c {
+            // An escaped character, `\&` or `\\` included, is never a separator
+            '\\' if separator != "\\\\" || !text[at..].starts_with("\\\\") => {
+                at += 1;
+                at += text[at..].chars().next().map_or(0, char::len_utf8);
+                continue;
+            }
+            '{' => depth += 1,
+            '}' => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+        at += c.len_utf8();
+    }
+    parts.push(&text[start..]);
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The converted document of the LaTeX `source`, written as `main.tex` with the other
+    /// `files` next to it.
+    fn convert(source: &str, files: &[(&str, &str)]) -> Document {
+        let folder = tempfile::tempdir().unwrap();
+        for (name, content) in files {
+            fs::write(folder.path().join(name), content).unwrap();
+        }
+        let path = folder.path().join("main.tex");
+        fs::write(&path, source).unwrap();
+        extract(&path, None, false).unwrap()
+    }
+
+    fn text_of(source: &str) -> String {
+        convert(source, &[])
+            .sections
+            .iter()
+            .map(|section| section.text.as_str())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn body(body: &str) -> String {
+        text_of(&format!(
+            "\\documentclass{{article}}\n\\begin{{document}}\n{body}\n\\end{{document}}\n"
+        ))
+    }
+
+    #[test]
+    fn user_macros_are_expanded() {
+        let text = text_of(
+            "\\newcommand{\\name}{World}\n\\def\\greeting{Hello}\n\
+             \\begin{document}\n\\greeting, \\name!\n\\end{document}\n",
+        );
+        assert_eq!(text, "Hello, World!");
+    }
+
+    #[test]
+    fn macros_in_math_are_expanded() {
+        let text = text_of(
+            "\\newcommand{\\R}{\\mathbb{R}}\n\\begin{document}\nLet $x \\in \\R$.\n\
+             \\begin{equation}\\label{eq:1} y = \\R \\end{equation}\n\\end{document}\n",
+        );
+        assert_eq!(text, "Let $x \\in \\mathbb{R}$.\n$$y = \\mathbb{R}$$");
+    }
+
+    #[test]
+    fn macros_with_parameters_are_not_expanded() {
+        let text = text_of(
+            "\\newcommand{\\pair}[2]{(#1, #2)}\n\\begin{document}\n\\pair{a}{b}\n\\end{document}\n",
+        );
+        assert!(!text.contains('#'), "{text}");
+    }
+
+    #[test]
+    fn recursive_macros_stop() {
+        let text = text_of(
+            "\\newcommand{\\again}{x\\again}\n\\begin{document}\n\\again\n\\end{document}\n",
+        );
+        assert_eq!(text, "x".repeat(MAX_EXPANSION_DEPTH));
+    }
+
+    #[test]
+    fn comments_are_removed() {
+        assert_eq!(
+            strip_comments("a % note\n% a line of its own\nb \\% c\n"),
+            "a \nb \\% c\n"
+        );
+        assert_eq!(
+            strip_comments("\\begin{verbatim}\n50% off\n\\end{verbatim}\n% gone\n"),
+            "\\begin{verbatim}\n50% off\n\\end{verbatim}\n"
+        );
+        // A comment line does not end the paragraph it sits in
+        assert_eq!(body("one\n% aside\ntwo"), "one two");
+    }
+
+    #[test]
+    fn lists_and_theorems() {
+        assert_eq!(
+            body(
+                "\\begin{itemize}\\item apples\n\\item pears\\end{itemize}\n\
+                 \\begin{enumerate}\\item first\\item second\\end{enumerate}\n\
+                 \\begin{description}\\item[Term] meaning\\end{description}"
+            ),
+            "- apples\n- pears\n1. first\n2. second\nTerm: meaning"
+        );
+        assert_eq!(
+            body("\\begin{theorem}[Fermat] No solutions.\\end{theorem}"),
+            "Theorem (Fermat). No solutions."
+        );
+        assert_eq!(
+            text_of(
+                "\\newtheorem{conj}{Guess}\n\\begin{document}\n\
+                 \\begin{conj}It holds.\\end{conj}\n\\end{document}\n"
+            ),
+            "Guess. It holds."
+        );
+    }
+
+    #[test]
+    fn verbatim_hidden_and_table_environments() {
+        assert_eq!(
+            body("\\begin{verbatim}\nfn main() {} % kept\n\\end{verbatim}"),
+            format!("{CODE_FENCE}\nfn main() {{}} % kept\n{CODE_FENCE}")
+        );
+        assert_eq!(
+            body("Before\n\\begin{tikzpicture}\\node{hidden};\\end{tikzpicture}\nafter"),
+            "Before after"
+        );
+        assert_eq!(
+            body("\\begin{tabular}{ll}\nA & B \\\\\n\\textbf{1} & 2\\&3 \\\\\n\\end{tabular}"),
+            "A | B\n1 | 2&3"
+        );
+    }
+
+    #[test]
+    fn sections_become_the_outline() {
+        let document = convert(
+            "\\begin{document}\n\\section{Intro}\nText.\n\\subsection*{Detail}\nMore.\n\
+             \\end{document}\n",
+            &[],
+        );
+        let outline: Vec<(&str, usize)> = document
+            .outline
+            .iter()
+            .map(|entry| (entry.title.as_str(), entry.level))
+            .collect();
+        assert_eq!(outline, [("Intro", 1), ("Detail", 2)]);
+        assert_eq!(document.sections[0].text, "Intro\nText.");
+    }
+
+    #[test]
+    fn accents_quotes_and_dashes() {
+        assert_eq!(
+            body("caf\\'e, gar\\c{c}on, \\v{C}apek, \\'x"),
+            "café, garçon, Čapek, x\u{301}"
+        );
+        assert_eq!(body("``quoted'' --- pages 1--2"), "“quoted” — pages 1–2");
+    }
+
+    #[test]
+    fn included_files_are_resolved() {
+        let text = convert(
+            "\\begin{document}\nStart.\n\\input{chapter}\n\\include{missing}\nEnd.\n\
+             \\end{document}\n",
+            &[("chapter.tex", "Middle \\input{chapter}")],
+        )
+        .sections
+        .iter()
+        .map(|section| section.text.clone())
+        .collect::<Vec<_>>()
+        .join("\n");
+        // The chapter includes itself; that stops at the inclusion depth limit
+        assert!(text.starts_with("Start.\nMiddle Middle"), "{text}");
+        assert!(text.ends_with("End."), "{text}");
+        assert_eq!(text.matches("Middle").count(), MAX_INPUT_DEPTH);
+    }
+
+    #[test]
+    fn bibliography_comes_from_the_bbl() {
+        let document = convert(
+            "\\begin{document}\nSee \\cite{knuth, lamport}.\n\\bibliography{refs}\n\\end{document}\n",
+            &[(
+                "main.bbl",
+                "\\begin{thebibliography}{9}\n\\bibitem{knuth} D. Knuth, \\emph{TAOCP}.\n\
+                 \\end{thebibliography}\n",
+            )],
+        );
+        assert_eq!(
+            document.sections[0].text,
+            "See [knuth, lamport].\nReferences\n[knuth] D. Knuth, TAOCP."
+        );
+    }
+
+    #[test]
+    fn malformed_sources_do_not_panic() {
+        for source in [
+            "\\textbf{unclosed",
+            "stray } braces {",
+            "$unclosed math",
+            "\\verb|no end",
+            "ends in a backslash \\",
+            "\\begin{itemize}\\item never closed",
+            "\\end{enumerate} without a begin",
+            "\\section",
+            "\\'",
+            "\\newcommand{\\x}",
+            "\\begin{tabular}{",
+            "Ünïcödé \\'{é} \\c{}",
+        ] {
+            body(source);
+            text_of(source);
+        }
+        assert_eq!(body("\\textbf{unclosed"), "unclosed");
+    }
+
+    #[test]
+    fn top_level_splits() {
+        assert_eq!(
+            split_top_level("a & {b & c} & d\\&e", "&"),
+            ["a ", " {b & c} ", " d\\&e"]
+        );
+        assert_eq!(split_top_level("1 \\\\ 2 \\\\", "\\\\"), ["1 ", " 2 ", ""]);
+    }
+}
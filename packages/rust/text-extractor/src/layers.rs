@@ -0,0 +1,239 @@
+//! Optional content groups (layers) in PDFs.
+//!
+//! pdf-extract shows every layer, so content in deselected layers is removed from the page
+//! content streams before extraction. Only text and XObjects are removed; the rest of the
+//! stream is kept so the graphics and text state stay intact for what follows.
+
+use anyhow::Result;
+use pdf_extract::content::{Content, Operation};
+use pdf_extract::{Dictionary, Document, Object, ObjectId};
+
+use crate::structure::text_string;
+
+/// An optional content group as listed in the document catalog.
+pub struct Layer {
+    pub id: ObjectId,
+    pub name: String,
+    /// Visible under the document's default configuration
+    pub visible: bool,
+}
+
+/// The layers of the document, in catalog order.
+pub fn list(doc: &Document) -> Vec<Layer> {
+    let Some(properties) = doc
+        .catalog()
+        .ok()
+        .and_then(|catalog| catalog.get_deref(b"OCProperties", doc).ok())
+        .and_then(|o| o.as_dict().ok())
+    else {
+        return Vec::new();
+    };
+    let config = properties
+        .get_deref(b"D", doc)
+        .and_then(Object::as_dict)
+        .ok();
+    let listed = |key: &[u8], id: ObjectId| {
+        config
+            .and_then(|config| config.get_deref(key, doc).and_then(Object::as_array).ok())
+            .is_some_and(|ids| ids.iter().any(|o| o.as_reference().ok() == Some(id)))
+    };
+    let base_off = config
+        .and_then(|config| config.get(b"BaseState").and_then(Object::as_name).ok())
+        == Some(b"OFF".as_slice());
+
+    let ocgs = properties
+        .get_deref(b"OCGs", doc)
+        .and_then(Object::as_array)
+        .map(|ocgs| ocgs.as_slice())
+        .unwrap_or_default();
+    ocgs.iter()
+        .filter_map(|o| o.as_reference().ok())
+        .map(|id| {
+            let name = doc
+                .get_dictionary(id)
+                .ok()
+                .and_then(|ocg| ocg.get(b"Name").ok())
+                .and_then(text_string)
+                .unwrap_or_else(|| format!("{} {} R", id.0, id.1));
+            let visible = if base_off {
+                listed(b"ON", id)
+            } else {
+                !listed(b"OFF", id)
+            };
+            Layer { id, name, visible }
+        })
+        .collect()
+}
+
+/// Which layers to extract, by name. Content outside any layer is always extracted.
+#[derive(Debug, Clone, Default)]
+pub struct LayerSelection {
+    /// Extract only these layers; empty means all of them
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+}
+
+impl LayerSelection {
+    pub fn is_all(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    fn shows(&self, name: &str) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|n| n == name))
+            && !self.exclude.iter().any(|n| n == name)
+    }
+
+    /// Object ids of the layers this selection leaves out.
+    pub fn hidden(&self, layers: &[Layer]) -> Vec<ObjectId> {
+        layers
+            .iter()
+            .filter(|layer| !self.shows(&layer.name))
+            .map(|layer| layer.id)
+            .collect()
+    }
+}
+
+/// Removes text and XObjects drawn in hidden layers from a page's content stream.
+pub fn hide_content(doc: &mut Document, page_id: ObjectId, hidden: &[ObjectId]) -> Result<()> {
+    let content = Content::decode(&doc.get_page_content(page_id)?)?;
+    let mut operations = Vec::with_capacity(content.operations.len());
+    let mut stack: Vec<bool> = Vec::new();
+
+    for operation in content.operations {
+        let hiding = stack.last().copied().unwrap_or(false);
+        match operation.operator.as_str() {
+            "BMC" => stack.push(hiding),
+            "BDC" => {
+                let layer_hidden = optional_content(doc, page_id, &operation)
+                    .is_some_and(|oc| is_hidden(doc, oc, hidden));
+                stack.push(hiding || layer_hidden);
+            }
+            "EMC" => {
+                stack.pop();
+            }
+            _ => {}
+        }
+
+        if hiding || is_hidden_xobject(doc, page_id, &operation, hidden) {
+            operations.extend(without_text(operation));
+        } else {
+            operations.push(operation);
+        }
+    }
+
+    doc.change_page_content(page_id, Content { operations }.encode()?)?;
+    Ok(())
+}
+
+/// Replaces text-showing and XObject operations with the state changes they imply.
+fn without_text(operation: Operation) -> Vec<Operation> {
+    match operation.operator.as_str() {
+        "Tj" | "TJ" | "Do" => Vec::new(),
+        "'" => vec![Operation::new("T*", Vec::new())],
+        "\"" => {
+            let mut operands = operation.operands.into_iter();
+            let word_spacing = operands.next().unwrap_or(Object::Integer(0));
+            let char_spacing = operands.next().unwrap_or(Object::Integer(0));
+            vec![
+                Operation::new("Tw", vec![word_spacing]),
+                Operation::new("Tc", vec![char_spacing]),
+                Operation::new("T*", Vec::new()),
+            ]
+        }
+        _ => vec![operation],
+    }
+}
+
+/// The optional content group or membership dictionary a `BDC /OC` operation refers to.
+fn optional_content<'a>(
+    doc: &'a Document,
+    page_id: ObjectId,
+    operation: &'a Operation,
+) -> Option<&'a Object> {
+    if operation.operands.first()?.as_name().ok()? != b"OC" {
+        return None;
+    }
+    match operation.operands.get(1)? {
+        Object::Name(name) => resource(doc, page_id, b"Properties", name),
+        inline => Some(inline),
+    }
+}
+
+fn is_hidden_xobject(
+    doc: &Document,
+    page_id: ObjectId,
+    operation: &Operation,
+    hidden: &[ObjectId],
+) -> bool {
+    if operation.operator != "Do" {
+        return false;
+    }
+    operation
+        .operands
+        .first()
+        .and_then(|o| o.as_name().ok())
+        .and_then(|name| resource(doc, page_id, b"XObject", name))
+        .and_then(|xobject| doc.dereference(xobject).ok())
+        .and_then(|(_, xobject)| xobject.as_stream().ok())
+        .and_then(|stream| stream.dict.get(b"OC").ok())
+        .is_some_and(|oc| is_hidden(doc, oc, hidden))
+}
+
+/// Whether an optional content group, or a membership dictionary over several, is hidden.
+fn is_hidden(doc: &Document, oc: &Object, hidden: &[ObjectId]) -> bool {
+    if let Ok(id) = oc.as_reference() {
+        if hidden.contains(&id) {
+            return true;
+        }
+    }
+    let Ok((_, Object::Dictionary(dict))) = doc.dereference(oc) else {
+        return false;
+    };
+    if dict.get(b"Type").and_then(Object::as_name).ok() != Some(b"OCMD".as_slice()) {
+        return false;
+    }
+    membership_hidden(dict, hidden)
+}
+
+fn membership_hidden(membership: &Dictionary, hidden: &[ObjectId]) -> bool {
+    let groups: Vec<ObjectId> = match membership.get(b"OCGs") {
+        Ok(Object::Array(ids)) => ids.iter().filter_map(|o| o.as_reference().ok()).collect(),
+        Ok(Object::Reference(id)) => vec![*id],
+        _ => return false,
+    };
+    if groups.is_empty() {
+        return false;
+    }
+    let shown = |id: &ObjectId| !hidden.contains(id);
+
+    match membership.get(b"P").and_then(Object::as_name) {
+        Ok(b"AllOn") => !groups.iter().all(shown),
+        Ok(b"AnyOff") => groups.iter().all(shown),
+        Ok(b"AllOff") => groups.iter().any(shown),
+        _ => !groups.iter().any(shown),
+    }
+}
+
+/// Looks up a named resource of the page, including resources inherited from the page tree.
+pub fn resource<'a>(
+    doc: &'a Document,
+    page_id: ObjectId,
+    category: &[u8],
+    name: &[u8],
+) -> Option<&'a Object> {
+    let (inline, inherited) = doc.get_page_resources(page_id).ok()?;
+    inline
+        .into_iter()
+        .chain(
+            inherited
+                .into_iter()
+                .filter_map(|id| doc.get_dictionary(id).ok()),
+        )
+        .find_map(|resources| {
+            resources
+                .get_deref(category, doc)
+                .and_then(Object::as_dict)
+                .and_then(|names| names.get(name))
+                .ok()
+        })
+}
@@ -0,0 +1,518 @@
+//! Positioned glyphs collected from PDF content streams, and the text assembly built on them.
+//!
+//! The assembler follows the same spacing and line-break rules as pdf-extract's
+//! `PlainTextOutput`, so plain output is unchanged; having the glyphs lets us recognise
+//! things like raised footnote markers and sub/superscripts that plain text loses.
+
+use pdf_extract::{ColorSpace, MediaBox, OutputDev, OutputError, Path, Transform};
+use std::ops::Range;
+
+use crate::blocks;
+use crate::columns::PageLayout;
+use crate::figures;
+use crate::scripts::{self, Script, ScriptMode};
+use crate::structure::{self, ContentTag, Marker};
+use crate::tables::TableLayout;
+use crate::tracking;
+
+/// A single shown character in page space, with y growing downwards from the top edge.
+#[derive(Debug, Clone)]
+pub struct Glyph {
+    pub x: f64,
+    pub y: f64,
+    /// Font size after the text and page transforms
+    pub size: f64,
+    /// Horizontal advance in page units
+    pub advance: f64,
+    /// First glyph of a text-showing operation
+    pub word_start: bool,
+    pub text: String,
+    /// Marked-content sequence the glyph was shown in, for tagged PDFs
+    pub tag: ContentTag,
+    /// Index of the marked-content language the glyph was shown in
+    pub lang: Option<usize>,
+    /// Baseline direction in quarter turns clockwise from left-to-right
+    pub turns: u8,
+}
+
+/// The glyphs of one page in content-stream order.
+#[derive(Debug, Default)]
+pub struct PageGlyphs {
+    pub width: f64,
+    pub height: f64,
+    pub glyphs: Vec<Glyph>,
+}
+
+/// Collects glyphs for a single page.
+pub struct GlyphCollector {
+    pub page: PageGlyphs,
+    /// Flips PDF's bottom-up y axis so text reads top to bottom
+    flip_ctm: Transform,
+    word_start: bool,
+    tag: ContentTag,
+    lang: Option<usize>,
+}
+
+impl GlyphCollector {
+    pub fn new() -> Self {
+        GlyphCollector {
+            page: PageGlyphs::default(),
+            flip_ctm: Transform::identity(),
+            word_start: false,
+            tag: ContentTag::Untagged,
+            lang: None,
+        }
+    }
+}
+
+impl GlyphCollector {
+    /// Records an image drawn in the unit square under `ctm` as a zero-sized glyph at its
+    /// top-left corner, so it keeps its place in the text.
+    fn image(&mut self, ctm: &Transform) {
+        let t = ctm.post_transform(&self.flip_ctm);
+        let corners = [(0., 0.), (1., 0.), (0., 1.), (1., 1.)].map(|(x, y): (f64, f64)| {
+            (x * t.m11 + y * t.m21 + t.m31, x * t.m12 + y * t.m22 + t.m32)
+        });
+        let x0 = corners.iter().map(|c| c.0).fold(f64::INFINITY, f64::min);
+        let x1 = corners
+            .iter()
+            .map(|c| c.0)
+            .fold(f64::NEG_INFINITY, f64::max);
+        let y0 = corners.iter().map(|c| c.1).fold(f64::INFINITY, f64::min);
+        let y1 = corners
+            .iter()
+            .map(|c| c.1)
+            .fold(f64::NEG_INFINITY, f64::max);
+        if !figures::is_figure(x1 - x0, y1 - y0) {
+            return;
+        }
+
+        self.page.glyphs.push(Glyph {
+            x: x0,
+            y: y0,
+            size: 0.,
+            advance: 0.,
+            word_start: true,
+            text: figures::IMAGE.to_string(),
+            tag: self.tag,
+            lang: self.lang,
+            turns: 0,
+        });
+    }
+}
+
+impl OutputDev for GlyphCollector {
+    fn begin_page(
+        &mut self,
+        _page_num: u32,
+        media_box: &MediaBox,
+        _art_box: Option<(f64, f64, f64, f64)>,
+    ) -> Result<(), OutputError> {
+        self.flip_ctm = Transform::row_major(1., 0., 0., -1., 0., media_box.ury - media_box.lly);
+        self.page.width = media_box.urx - media_box.llx;
+        self.page.height = media_box.ury - media_box.lly;
+        Ok(())
+    }
+
+    fn end_page(&mut self) -> Result<(), OutputError> {
+        Ok(())
+    }
+
+    fn output_character(
+        &mut self,
+        trm: &Transform,
+        width: f64,
+        _spacing: f64,
+        font_size: f64,
+        char: &str,
+    ) -> Result<(), OutputError> {
+        let position = trm.post_transform(&self.flip_ctm);
+        // Side of the square with the same area as the transformed font box
+        let size = font_size * (trm.m11 * trm.m22 - trm.m12 * trm.m21).abs().sqrt();
+
+        self.page.glyphs.push(Glyph {
+            x: position.m31,
+            y: position.m32,
+            size,
+            advance: width * size,
+            word_start: self.word_start,
+            text: char.to_string(),
+            tag: self.tag,
+            lang: self.lang,
+            // The text space x axis, with y flipped like the position
+            turns: quarter_turns(trm.m11, -trm.m12),
+        });
+        self.word_start = false;
+        Ok(())
+    }
+
+    fn begin_word(&mut self) -> Result<(), OutputError> {
+        self.word_start = true;
+        Ok(())
+    }
+
+    fn end_word(&mut self) -> Result<(), OutputError> {
+        Ok(())
+    }
+
+    fn end_line(&mut self) -> Result<(), OutputError> {
+        Ok(())
+    }
+
+    fn fill(
+        &mut self,
+        ctm: &Transform,
+        _colorspace: &ColorSpace,
+        _color: &[f64],
+        path: &Path,
+    ) -> Result<(), OutputError> {
+        match structure::marker(path) {
+            Some(Marker::Tag(tag)) => self.tag = tag,
+            Some(Marker::Language(lang)) => self.lang = lang,
+            None if figures::is_image_marker(path) => self.image(ctm),
+            None => {}
+        }
+        Ok(())
+    }
+}
+
+/// What to do with superscript footnote reference markers ("result³").
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FootnoteMarkers {
+    /// Leave markers inline as extracted
+    #[default]
+    Keep,
+    /// Render markers as `[3]`
+    Bracket,
+    /// Remove markers
+    Strip,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AssembleOptions {
+    pub footnotes: FootnoteMarkers,
+    pub scripts: ScriptMode,
+    /// Wrap display equations in `[equation]` blocks
+    pub mark_equations: bool,
+    /// Fence monospaced lines as code blocks
+    pub fence_code: bool,
+    /// Follow the structure tree of tagged PDFs
+    pub use_structure: bool,
+    /// Join letter-spaced titles ("I N T R O") back into words
+    pub join_letter_spacing: bool,
+    /// Keep a placeholder line where each image was drawn
+    pub image_placeholders: bool,
+    /// Lay out tables, in this layout
+    pub tables: Option<TableLayout>,
+    /// Reading order of pages without a structure tree
+    pub layout: PageLayout,
+}
+
+/// Where one output line sits in the assembled text and on the page.
+#[derive(Debug, Clone)]
+pub struct Line {
+    /// Byte range of the line in the assembled text
+    pub start: usize,
+    pub end: usize,
+    /// Glyphs shown on the line
+    pub glyphs: Range<usize>,
+    pub x0: f64,
+    pub x1: f64,
+    /// Byte offset and x position where the last wide gap on the line starts, which is where
+    /// a right-aligned equation number would begin
+    pub last_gap: Option<(usize, f64)>,
+}
+
+/// A `[start, end)` range of glyphs set off from the baseline of the text before it.
+#[derive(Debug, Clone, Copy)]
+struct Run {
+    start: usize,
+    end: usize,
+    kind: RunKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RunKind {
+    Footnote,
+    Script(Script),
+}
+
+/// Turns a page's glyphs into text.
+/// Rotates the page's coordinates so its dominant text direction reads left to right.
+///
+/// Rotated pages are usually drawn with rotated text matrices, so the glyphs' own direction
+/// decides; the page's `/Rotate` (degrees clockwise) only breaks ties in favour of the
+/// direction that is horizontal on screen.
+pub fn orient(page: &mut PageGlyphs, rotate: i64) {
+    let displayed = |turns: u8| (i64::from(turns) + rotate.div_euclid(90)).rem_euclid(4);
+    let mut counts = [0usize; 4];
+    for glyph in &page.glyphs {
+        counts[usize::from(glyph.turns)] += 1;
+    }
+    let Some(turns) = (0..4u8).max_by_key(|&t| (counts[usize::from(t)], displayed(t) == 0)) else {
+        return;
+    };
+    if turns == 0 || counts[usize::from(turns)] == 0 {
+        return;
+    }
+
+    let (dx, dy) = match turns {
+        1 => (0., 1.),
+        2 => (-1., 0.),
+        _ => (0., -1.),
+    };
+    // Reading "down" is a quarter turn clockwise from the baseline
+    let rotate_point = |x: f64, y: f64| (x * dx + y * dy, y * dx - x * dy);
+    let corners = [
+        (0., 0.),
+        (page.width, 0.),
+        (0., page.height),
+        (page.width, page.height),
+    ]
+    .map(|(x, y)| rotate_point(x, y));
+    let left = corners.iter().map(|c| c.0).fold(f64::INFINITY, f64::min);
+    let top = corners.iter().map(|c| c.1).fold(f64::INFINITY, f64::min);
+
+    for glyph in &mut page.glyphs {
+        let (x, y) = rotate_point(glyph.x, glyph.y);
+        glyph.x = x - left;
+        glyph.y = y - top;
+        glyph.turns = (glyph.turns + 4 - turns) % 4;
+    }
+    if turns % 2 == 1 {
+        std::mem::swap(&mut page.width, &mut page.height);
+    }
+}
+
+fn quarter_turns(dx: f64, dy: f64) -> u8 {
+    if dx.abs() >= dy.abs() {
+        if dx >= 0. {
+            0
+        } else {
+            2
+        }
+    } else if dy > 0. {
+        1
+    } else {
+        3
+    }
+}
+
+pub fn assemble(page: &PageGlyphs, options: &AssembleOptions) -> String {
+    let glyphs = &page.glyphs;
+    let runs = if options.footnotes == FootnoteMarkers::Keep && options.scripts == ScriptMode::Keep
+    {
+        Vec::new()
+    } else {
+        offset_runs(glyphs, options)
+    };
+    let joined = if options.join_letter_spacing {
+        tracking::joined(glyphs)
+    } else {
+        Vec::new()
+    };
+
+    let mut out = String::new();
+    let mut last_end = 100_000.;
+    let mut last_y = 0.;
+    let mut lines: Vec<Line> = Vec::new();
+    let mut i = 0;
+
+    while i < glyphs.len() {
+        let glyph = &glyphs[i];
+
+        // Runs are attached to the preceding text, so they never start a new word or line
+        if let Some(run) = runs.iter().find(|run| run.start == i) {
+            let text: String = glyphs[run.start..run.end]
+                .iter()
+                .map(|g| g.text.as_str())
+                .collect();
+            match run.kind {
+                RunKind::Footnote => {
+                    if options.footnotes == FootnoteMarkers::Bracket {
+                        out.push('[');
+                        out.push_str(&text);
+                        out.push(']');
+                    }
+                }
+                RunKind::Script(script) => {
+                    let follows_digit = out.chars().last().is_some_and(|ch| ch.is_ascii_digit());
+                    out.push_str(&scripts::render(
+                        &text,
+                        script,
+                        options.scripts,
+                        follows_digit,
+                    ));
+                }
+            }
+            // Spacing continues from the text the run is attached to
+            let last = &glyphs[run.end - 1];
+            last_end = last.x + last.advance;
+            if let Some(line) = lines.last_mut() {
+                line.end = out.len();
+                line.glyphs.end = run.end;
+                line.x1 = line.x1.max(last_end);
+            }
+            i = run.end;
+            continue;
+        }
+
+        let mut new_line = lines.is_empty();
+        if glyph.word_start {
+            if (glyph.y - last_y).abs() > glyph.size * 1.5 {
+                out.push('\n');
+                new_line = true;
+            }
+
+            // We've moved to the left and down
+            if glyph.x < last_end && (glyph.y - last_y).abs() > glyph.size * 0.5 {
+                out.push('\n');
+                new_line = true;
+            }
+
+            if !new_line && glyph.x > last_end + glyph.size * WIDE_GAP {
+                if let Some(line) = lines.last_mut() {
+                    line.last_gap = Some((out.len(), last_end));
+                }
+            }
+
+            if glyph.x > last_end + glyph.size * 0.1 && joined.get(i) != Some(&true) {
+                out.push(' ');
+            }
+        }
+
+        if new_line {
+            lines.push(Line {
+                start: out.len(),
+                end: out.len(),
+                glyphs: i..i,
+                x0: glyph.x,
+                x1: glyph.x,
+                last_gap: None,
+            });
+        }
+
+        out.push_str(&glyph.text);
+        last_y = glyph.y;
+        last_end = glyph.x + glyph.advance;
+        if let Some(line) = lines.last_mut() {
+            line.end = out.len();
+            line.glyphs.end = i + 1;
+            line.x0 = line.x0.min(glyph.x);
+            line.x1 = line.x1.max(last_end);
+        }
+        i += 1;
+    }
+
+    if options.mark_equations || options.fence_code || options.tables.is_some() {
+        blocks::mark(&out, &lines, page, options)
+    } else {
+        out
+    }
+}
+
+/// Horizontal gap, as a fraction of the font size, that separates columns of a line rather
+/// than words.
+pub const WIDE_GAP: f64 = 2.0;
+
+/// Largest font size ratio (run / surrounding text) still treated as a super- or subscript.
+const SCRIPT_SIZE_RATIO: f64 = 0.85;
+/// Minimum baseline rise, as a fraction of the surrounding font size.
+const SUPERSCRIPT_RISE: f64 = 0.2;
+/// Minimum baseline drop, as a fraction of the surrounding font size.
+const SUBSCRIPT_DROP: f64 = 0.1;
+/// Footnote markers are short: "3", "12", "*", "†".
+const MAX_MARKER_LEN: usize = 3;
+/// Longer raised or lowered runs are more likely separate text than an exponent or index.
+const MAX_SCRIPT_LEN: usize = 12;
+
+/// Finds raised footnote markers and super/subscript runs hanging off the preceding text.
+fn offset_runs(glyphs: &[Glyph], options: &AssembleOptions) -> Vec<Run> {
+    let mut runs = Vec::new();
+    let mut i = 1;
+
+    while i < glyphs.len() {
+        let anchor = &glyphs[i - 1];
+
+        // A run hangs off the end of a word: it follows the word closely
+        let attached = anchor.x + anchor.advance + anchor.size * 0.5 >= glyphs[i].x
+            && !anchor.text.trim().is_empty();
+
+        let raised_end = superscript_run_end(glyphs, i, anchor);
+        let lowered_end = subscript_run_end(glyphs, i, anchor);
+
+        let is_marker = raised_end > i
+            && raised_end - i <= MAX_MARKER_LEN
+            && glyphs[i..raised_end]
+                .iter()
+                .all(|g| g.text.chars().all(is_footnote_char));
+
+        let run = if !attached {
+            None
+        } else if is_marker && options.footnotes != FootnoteMarkers::Keep {
+            Some((raised_end, RunKind::Footnote))
+        } else if options.scripts == ScriptMode::Keep {
+            None
+        } else if raised_end > i && raised_end - i <= MAX_SCRIPT_LEN {
+            Some((raised_end, RunKind::Script(Script::Super)))
+        } else if lowered_end > i && lowered_end - i <= MAX_SCRIPT_LEN {
+            Some((lowered_end, RunKind::Script(Script::Sub)))
+        } else {
+            None
+        };
+
+        if let Some((end, kind)) = run {
+            runs.push(Run {
+                start: i,
+                end,
+                kind,
+            });
+            i = end;
+        } else {
+            i += 1;
+        }
+    }
+
+    runs
+}
+
+/// Returns the end of the run of glyphs starting at `start` that sit raised above `anchor`'s
+/// baseline in a smaller font, or `start` if there is none.
+pub fn superscript_run_end(glyphs: &[Glyph], start: usize, anchor: &Glyph) -> usize {
+    let mut end = start;
+    while end < glyphs.len() && is_raised(&glyphs[end], anchor) {
+        end += 1;
+    }
+    end
+}
+
+/// Like [`superscript_run_end`], for glyphs dropped below `anchor`'s baseline.
+pub fn subscript_run_end(glyphs: &[Glyph], start: usize, anchor: &Glyph) -> usize {
+    let mut end = start;
+    while end < glyphs.len() && is_lowered(&glyphs[end], anchor) {
+        end += 1;
+    }
+    end
+}
+
+fn is_raised(glyph: &Glyph, anchor: &Glyph) -> bool {
+    // Images are zero-sized
+    glyph.size > 0.
+        && glyph.size < anchor.size * SCRIPT_SIZE_RATIO
+        && anchor.y - glyph.y > anchor.size * SUPERSCRIPT_RISE
+        // Still on the anchor's line rather than a new, higher line
+        && anchor.y - glyph.y < anchor.size
+}
+
+fn is_lowered(glyph: &Glyph, anchor: &Glyph) -> bool {
+    glyph.size > 0.
+        && glyph.size < anchor.size * SCRIPT_SIZE_RATIO
+        && glyph.y - anchor.y > anchor.size * SUBSCRIPT_DROP
+        // Still on the anchor's line rather than the next one
+        && glyph.y - anchor.y < anchor.size * 0.6
+}
+
+fn is_footnote_char(ch: char) -> bool {
+    ch.is_ascii_digit() || matches!(ch, '*' | '†' | '‡' | '§' | '¶' | '‖' | ',')
+}
@@ -0,0 +1,111 @@
+//! Text extraction from PDF, EPUB, HTML and XHTML, CHM, DjVu, FictionBook, Word (DOCX),
+//! OpenDocument (ODT), RTF, Pages, iBooks Author and LaTeX files (and audiobooks with the
+//! `audio` feature).
+//!
+//! [`extract_file`] reads one file with the command line's default options and returns
+//! the [`Document`] it holds, which can be rendered as text, SSML, Markdown or JSON. The
+//! `text-extractor` binary is a thin command-line front end over the same calls.
+//!
+//! The default `cli` and `server` features bring in the command line's and the HTTP
+//! service's dependencies; a library that needs neither can depend on the crate with
+//! `default-features = false`.
+//!
+//! ```no_run
+//! let extracted = to_texts::extract_file(std::path::Path::new("book.epub"))?;
+//! println!("{}", extracted.text());
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+mod anthology;
+pub mod archive;
+#[cfg(feature = "audio")]
+pub mod audio;
+mod blocks;
+pub mod calibre;
+pub mod chapters;
+mod chm;
+mod code;
+mod columns;
+mod copyright;
+pub mod daemon;
+mod djvu;
+pub mod document;
+mod docx;
+mod dom;
+mod encoding;
+mod epub_archive;
+mod equations;
+mod extract;
+mod fb2;
+mod figures;
+pub mod filter;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+#[cfg(feature = "gui")]
+pub mod gui;
+mod html;
+mod hyphenation;
+pub mod ignore_files;
+#[cfg(feature = "index")]
+pub mod index;
+pub mod info;
+pub mod ingest;
+mod iwork;
+mod json;
+mod latex;
+pub mod layers;
+mod layout;
+pub mod manifest;
+mod markdown;
+mod mathml;
+mod matter;
+pub mod mcp;
+pub mod memory;
+pub mod metadata;
+pub mod naming;
+pub mod ocr;
+mod odt;
+pub mod opds;
+mod outline;
+pub mod output;
+pub mod pages;
+#[cfg(feature = "parquet")]
+mod parquet_file;
+pub mod pdf;
+pub mod postprocess;
+pub mod provenance;
+mod punctuation;
+pub mod push;
+pub mod rpc;
+mod rtf;
+mod running_heads;
+pub mod scripts;
+pub mod search;
+#[cfg(feature = "server")]
+pub mod server;
+pub mod source;
+pub mod split;
+mod sqlite;
+pub mod ssml;
+mod stamps;
+mod structure;
+mod tables;
+mod tracking;
+pub mod validate;
+pub mod watch;
+pub mod wrap;
+mod xfa;
+mod xml;
+
+pub use columns::PageLayout;
+pub use document::Document;
+pub use encoding::OutputEncoding;
+pub use extract::{
+    extract_djvu_text, extract_epub_text, extract_file, extract_file_with, extract_html_text,
+    extract_pdf_text, extract_source, extractor_for, is_up_to_date, stream_reader, stream_source,
+    write_source, ExtractOptions, ExtractedDocument, Extractor, HtmlOptions, WhitespaceMode,
+    WrittenOutput,
+};
+pub use layout::{AssembleOptions, FootnoteMarkers};
+pub use metadata::Metadata;
+pub use tables::{TableLayout, TableMode};
@@ -1,260 +1,2418 @@
+//! Command-line front end: parses the options, finds the input files and reports progress,
+//! leaving the extraction itself to the `to_texts` library.
+
 use anyhow::{Context, Result};
-use clap::Parser;
+use clap::{ArgGroup, Parser, Subcommand};
+use glob::Pattern;
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+use rayon::prelude::*;
+use regex::Regex;
+use std::collections::HashSet;
 use std::fs;
-use std::panic;
+use std::io::{self, IsTerminal, Read, Write};
+use std::panic::{self, AssertUnwindSafe};
 use std::path::{Path, PathBuf};
-use walkdir::WalkDir;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+use tempfile::TempDir;
+#[cfg(feature = "audio")]
+use to_texts::audio;
+use to_texts::chapters::{self, ChapterSelection};
+use to_texts::daemon::{self, Queue, Status};
+use to_texts::filter::{self, PathFilter};
+#[cfg(feature = "grpc")]
+use to_texts::grpc;
+#[cfg(feature = "gui")]
+use to_texts::gui;
+use to_texts::ignore_files::IgnoreStack;
+#[cfg(feature = "index")]
+use to_texts::index::{self, LibraryIndex};
+use to_texts::info::{self, DocumentInfo};
+use to_texts::layers::LayerSelection;
+use to_texts::manifest::{self, Manifest};
+use to_texts::mcp;
+use to_texts::memory::{self, MemoryBudget};
+use to_texts::naming;
+use to_texts::ocr::{OcrMode, OcrOptions};
+use to_texts::output::{
+    self, Newline, OnConflict, OutputExists, OutputFormat, OutputOptions, OutputRun,
+};
+use to_texts::pages::{self, PageSelection};
+use to_texts::postprocess::PostProcessOptions;
+use to_texts::provenance::ProvenanceOptions;
+use to_texts::push::{Engine, PushOptions, Pusher};
+use to_texts::rpc;
+use to_texts::scripts::ScriptMode;
+use to_texts::search;
+#[cfg(feature = "server")]
+use to_texts::server::{self, ServeOptions};
+use to_texts::source::{self, SourceFile};
+use to_texts::split::SplitMode;
+use to_texts::ssml::SsmlOptions;
+use to_texts::validate::{self, Check, Status as FileStatus};
+use to_texts::wrap::Wrap;
+use to_texts::{
+    archive, calibre, ingest, is_up_to_date, opds, pdf, write_source, AssembleOptions,
+    ExtractOptions, FootnoteMarkers, HtmlOptions, OutputEncoding, PageLayout, TableLayout,
+    TableMode, WhitespaceMode,
+};
+use walkdir::{DirEntry, WalkDir};
 
 #[derive(Parser, Debug)]
 #[command(name = "to_texts")]
 #[command(about = "Extract text from PDF and EPUB files recursively", long_about = None)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    args: Args,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Extract completed downloads, move originals to an archive folder and record them in a ledger
+    Ingest(Box<IngestArgs>),
+    /// Extract a document read from standard input, e.g. `curl -s URL | to_texts extract
+    /// --stdin --type pdf`, and write its text to standard output
+    Extract(Box<ExtractArgs>),
+    /// Answer newline-delimited JSON-RPC requests (extract, info, list) on standard input,
+    /// for editors and agents that run the extractor as a subprocess
+    Rpc(Box<RpcArgs>),
+    /// Serve the extract_document and get_metadata tools to LLM agents over the Model Context
+    /// Protocol, on standard input and output
+    Mcp(Box<McpArgs>),
+    /// Report on, pause or resume a daemon started with --daemon
+    Ctl(CtlArgs),
+    /// Serve extraction over HTTP: POST /extract with a multipart upload, GET /healthz
+    #[cfg(feature = "server")]
+    Serve(Box<ServeArgs>),
+    /// Serve extraction over gRPC: the Extract and ExtractBatch calls of proto/to_texts.proto
+    #[cfg(feature = "grpc")]
+    Grpc(Box<ServeArgs>),
+    /// Show a document's title, author, language, publisher, page or chapter count, encryption
+    /// and size without writing any text
+    Info(Box<InfoArgs>),
+    /// Show what a PDF contains (page count, optional content layers) without extracting it
+    Inspect(InspectArgs),
+    /// Search the text and JSON files written to an output folder, or an index built with
+    /// `index`, best matches first
+    Search(SearchArgs),
+    /// Extract the files under --target into a full-text index for `search --index`
+    #[cfg(feature = "index")]
+    Index(Box<IndexArgs>),
+    /// Open every file under --target and report those that are encrypted, corrupt, image-only
+    /// or unsupported, before a long extraction run
+    Validate(Box<ValidateArgs>),
+    /// List the files a run over --target would extract, with their sizes and types, without
+    /// extracting them
+    List(ListArgs),
+    /// Open a window to pick folders, drop files and follow extraction progress
+    #[cfg(feature = "gui")]
+    Gui(Box<GuiArgs>),
+}
+
+#[derive(clap::Args, Debug)]
+#[command(group(ArgGroup::new("source").required(true).args(["target", "calibre_library", "opds", "files_from"])))]
 struct Args {
     /// Target path to search recursively for PDF or EPUB files
     #[arg(short, long)]
-    target: PathBuf,
+    target: Option<PathBuf>,
+
+    /// Calibre library directory; books are read from its metadata.db instead of walking --target
+    #[arg(long, value_name = "DIR")]
+    calibre_library: Option<PathBuf>,
+
+    /// File listing the documents to extract, one path per line ("-" for standard input),
+    /// instead of searching --target
+    #[arg(long, value_name = "FILE", conflicts_with = "scan_archives")]
+    files_from: Option<PathBuf>,
+
+    /// Paths in the --files-from list end in NUL characters instead of line breaks, as
+    /// find -print0 writes them
+    #[arg(long, requires = "files_from")]
+    null: bool,
+
+    /// OPDS catalog URL to crawl; supported acquisition links are downloaded and extracted
+    #[arg(long, value_name = "URL")]
+    opds: Option<String>,
+
+    /// Delay between OPDS requests in milliseconds
+    #[arg(long, value_name = "MS", default_value_t = 1000)]
+    opds_delay: u64,
+
+    /// Directory for downloaded OPDS books (default: <output>/.opds-cache)
+    #[arg(long, value_name = "DIR")]
+    opds_cache: Option<PathBuf>,
+
+    /// Maximum number of OPDS feed pages to visit
+    #[arg(long, value_name = "N", default_value_t = 500)]
+    opds_max_feeds: usize,
+
+    /// Output path to save extracted texts
+    #[arg(short, long, required = true)]
+    output: Option<PathBuf>,
+
+    /// Reproduce the folders of --target (or the Calibre library) in the output, so a/book.pdf
+    /// and b/book.pdf are written to a/book.txt and b/book.txt instead of the same file
+    #[arg(long, conflicts_with = "combine")]
+    mirror_tree: bool,
+
+    /// Skip files whose output is already up to date (same options and source per the
+    /// provenance sidecar, else an output newer than the source)
+    #[arg(long, visible_alias = "incremental")]
+    skip_existing: bool,
+
+    /// Flag outputs with fewer than N letters and digits of text, which usually means a
+    /// scanned PDF or DjVu book without a text layer (0 turns the check off)
+    #[arg(long, value_name = "N", default_value_t = 1)]
+    min_chars: usize,
+
+    /// Move flagged outputs, with their sidecars, into a quarantine/ folder of the output
+    #[arg(long)]
+    quarantine: bool,
+
+    /// Do not write manifest.csv, the row per file of where its output went and how it went,
+    /// to the output folder
+    #[arg(long)]
+    no_manifest: bool,
+
+    /// Write what the summary shows, with the result of each file, how long the run took and
+    /// the tool version, to FILE as JSON
+    #[arg(long, value_name = "FILE", conflicts_with = "daemon")]
+    summary_json: Option<PathBuf>,
+
+    /// Also extract the supported files inside ZIP, TAR (.tar, .tar.gz, .tgz) and 7z archives
+    /// under --target; their outputs are named after both, e.g. books.zip!inner.txt
+    #[arg(long)]
+    scan_archives: bool,
+
+    /// Levels of archives that --scan-archives opens: 1 for only the archives found, 2 for the
+    /// archives in them too, and so on
+    #[arg(long, value_name = "N", default_value_t = 3,
+          value_parser = clap::value_parser!(u16).range(1..))]
+    archive_depth: u16,
+
+    /// Most an archive may expand to, the archives in it included (e.g. 512M, 4G); larger
+    /// archives, such as zip bombs, are reported as errors and left out
+    #[arg(long, value_name = "SIZE", default_value = "4G", value_parser = memory::parse_size)]
+    max_archive_size: u64,
+
+    /// Keep running after extracting --target and extract the documents that arrive or change
+    /// under it, such as new downloads
+    #[arg(long, requires = "target")]
+    watch: bool,
+
+    /// Seconds a file must be unchanged before --watch extracts it, so files still being
+    /// written are not read half-done
+    #[arg(long, value_name = "SECS", default_value_t = 5)]
+    settle_secs: u64,
+
+    /// Run as a daemon: queue the files under --target and those that arrive later, as
+    /// --watch does, in a queue kept on disk across restarts; `ctl` pauses, resumes and
+    /// reports on it
+    #[arg(long, requires = "target", conflicts_with = "scan_archives")]
+    daemon: bool,
+
+    /// Folder holding a daemon's queue and status (default: <output>/.to_texts-daemon)
+    #[arg(long, value_name = "DIR", requires = "daemon")]
+    state_dir: Option<PathBuf>,
+
+    /// Also send each extracted document to this search engine index, e.g.
+    /// http://localhost:7700/indexes/books (Meilisearch) or http://localhost:9200/books
+    /// (Elasticsearch)
+    #[arg(long, value_name = "URL")]
+    index_url: Option<String>,
+
+    /// API key for --index-url
+    #[arg(long, value_name = "KEY", requires = "index_url")]
+    index_api_key: Option<String>,
+
+    /// Search engine behind --index-url (default: meilisearch for URLs with /indexes/ in
+    /// their path, else elasticsearch)
+    #[arg(long, value_enum, requires = "index_url")]
+    index_engine: Option<Engine>,
+
+    /// Documents sent to --index-url in one request
+    #[arg(long, value_name = "N", default_value_t = 100,
+          value_parser = clap::value_parser!(u16).range(1..))]
+    index_batch: u16,
+
+    /// Times a batch is sent again when --index-url is unreachable, busy or failing
+    #[arg(long, value_name = "N", default_value_t = 3)]
+    index_retries: u32,
+
+    /// Number of files to extract at once
+    #[arg(short, long, value_name = "N", default_value_t = 1,
+          value_parser = clap::value_parser!(u16).range(1..))]
+    jobs: u16,
+
+    #[command(flatten)]
+    filter: FilterArgs,
+
+    #[command(flatten)]
+    processing: ProcessingArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct IngestArgs {
+    /// Downloads folder to pick up finished PDF and EPUB files from
+    #[arg(long, value_name = "DIR")]
+    downloads: PathBuf,
 
     /// Output path to save extracted texts
     #[arg(short, long)]
     output: PathBuf,
+
+    /// Folder that originals are moved into after extraction (default: <downloads>/archive)
+    #[arg(long, value_name = "DIR")]
+    archive: Option<PathBuf>,
+
+    /// Ledger file recording source, archive and output paths (default: <output>/ledger.jsonl)
+    #[arg(long, value_name = "FILE")]
+    ledger: Option<PathBuf>,
+
+    /// Seconds a file must be unmodified before it counts as a completed download
+    #[arg(long, value_name = "SECS", default_value_t = 10)]
+    settle_secs: u64,
+
+    #[command(flatten)]
+    processing: ProcessingArgs,
+}
+
+#[cfg(feature = "gui")]
+#[derive(clap::Args, Debug)]
+struct GuiArgs {
+    /// Input folder to start with
+    #[arg(short, long)]
+    target: Option<PathBuf>,
+
+    /// Output folder to start with
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    #[command(flatten)]
+    processing: ProcessingArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct ExtractArgs {
+    /// Read the document from standard input
+    #[arg(long, required = true)]
+    stdin: bool,
+
+    /// Type of the document read, by its usual extension (pdf, epub, docx, ...)
+    #[arg(long = "type", value_name = "EXTENSION", value_parser = source::parse_extension)]
+    file_type: String,
+
+    #[command(flatten)]
+    processing: ProcessingArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct RpcArgs {
+    /// Which files under a `list` target are listed
+    #[command(flatten)]
+    filter: FilterArgs,
+
+    #[command(flatten)]
+    processing: ProcessingArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct McpArgs {
+    #[command(flatten)]
+    processing: ProcessingArgs,
+}
+
+#[derive(clap::Args, Debug)]
+#[command(group(ArgGroup::new("state").required(true).args(["output", "state_dir"])))]
+struct CtlArgs {
+    #[arg(value_enum)]
+    action: CtlAction,
+
+    /// Output folder of the daemon, whose state is in the default folder
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// State folder of the daemon, when it was given one with --state-dir
+    #[arg(long, value_name = "DIR")]
+    state_dir: Option<PathBuf>,
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+enum CtlAction {
+    /// Show what the daemon is doing
+    Status,
+    /// Let the files being extracted finish and start no others
+    Pause,
+    /// Start extracting queued files again
+    Resume,
+}
+
+#[cfg(feature = "server")]
+#[derive(clap::Args, Debug)]
+struct ServeArgs {
+    /// Address and port to listen on; 0.0.0.0:8080 accepts requests from other machines
+    #[arg(long, value_name = "ADDR", default_value = "127.0.0.1:8080")]
+    listen: String,
+
+    /// Number of requests to handle at once
+    #[arg(short, long, value_name = "N", default_value_t = 4,
+          value_parser = clap::value_parser!(u16).range(1..))]
+    jobs: u16,
+
+    /// Largest upload accepted (e.g. 64M); larger ones are refused (413 over HTTP,
+    /// RESOURCE_EXHAUSTED over gRPC)
+    #[arg(long, value_name = "SIZE", default_value = "256M", value_parser = memory::parse_size)]
+    max_upload: u64,
+
+    #[command(flatten)]
+    processing: ProcessingArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct InfoArgs {
+    /// Document to describe
+    file: PathBuf,
+
+    /// Type of the document, by its usual extension, when its name has none
+    #[arg(long = "type", value_name = "EXTENSION", value_parser = source::parse_extension)]
+    file_type: Option<String>,
+
+    /// Print a JSON object instead of a table
+    #[arg(long)]
+    json: bool,
+
+    #[command(flatten)]
+    processing: ProcessingArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct InspectArgs {
+    /// PDF file to inspect
+    file: PathBuf,
+}
+
+#[derive(clap::Args, Debug)]
+struct SearchArgs {
+    /// Words to search for; documents with more of them, and rarer ones, rank higher
+    #[arg(required = true, value_name = "TERMS")]
+    query: Vec<String>,
+
+    /// Output folder of an earlier run
+    #[arg(short, long)]
+    #[cfg_attr(feature = "index", arg(required_unless_present = "index"))]
+    #[cfg_attr(not(feature = "index"), arg(required = true))]
+    output: Option<PathBuf>,
+
+    /// Search the index built by `index` in this folder instead of an output folder
+    #[cfg(feature = "index")]
+    #[arg(long, value_name = "DIR", conflicts_with = "output")]
+    index: Option<PathBuf>,
+
+    /// Most matches to show
+    #[arg(short = 'n', long, value_name = "N", default_value_t = 10)]
+    limit: usize,
+
+    /// Print a JSON array of {output, source, title, page, score, snippet} objects ({path,
+    /// title, author, score, snippet} with --index)
+    #[arg(long)]
+    json: bool,
+}
+
+#[cfg(feature = "index")]
+#[derive(clap::Args, Debug)]
+struct IndexArgs {
+    /// Path to search recursively for files to index
+    #[arg(short, long, required = true)]
+    target: PathBuf,
+
+    /// Folder of the index, created when it does not exist
+    #[arg(long, value_name = "DIR", required = true)]
+    index: PathBuf,
+
+    /// Extract and index files again even if they have not changed since they were indexed
+    #[arg(long)]
+    reindex: bool,
+
+    /// Number of files to extract at once
+    #[arg(short, long, value_name = "N", default_value_t = 1,
+          value_parser = clap::value_parser!(u16).range(1..))]
+    jobs: u16,
+
+    #[command(flatten)]
+    filter: FilterArgs,
+
+    #[command(flatten)]
+    processing: ProcessingArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct ValidateArgs {
+    /// Path to search recursively for files to check
+    #[arg(short, long, required = true)]
+    target: PathBuf,
+
+    /// Write every file checked, with its status, to this JSON file
+    #[arg(long, value_name = "FILE")]
+    report: Option<PathBuf>,
+
+    /// Count files with fewer than N letters and digits of text as image-only
+    #[arg(long, value_name = "N", default_value_t = 1)]
+    min_chars: usize,
+
+    /// Number of files to check at once
+    #[arg(short, long, value_name = "N", default_value_t = 1,
+          value_parser = clap::value_parser!(u16).range(1..))]
+    jobs: u16,
+
+    #[command(flatten)]
+    filter: FilterArgs,
+
+    #[command(flatten)]
+    processing: ProcessingArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct ListArgs {
+    /// Path to search recursively for supported files
+    #[arg(short, long, required = true)]
+    target: PathBuf,
+
+    #[command(flatten)]
+    filter: FilterArgs,
+
+    /// Print a JSON array of {path, type, extension, size} objects instead of a table
+    #[arg(long)]
+    json: bool,
+}
+
+/// Which files under --target are extracted.
+#[derive(clap::Args, Debug)]
+struct FilterArgs {
+    /// Extract only documents whose path under --target matches one of these glob patterns,
+    /// e.g. "**/*.pdf" (case-insensitive; may be repeated)
+    #[arg(long, value_name = "GLOB", value_parser = filter::parse_pattern)]
+    include: Vec<Pattern>,
+
+    /// Leave out files, archives and whole folders whose path under --target matches one of
+    /// these glob patterns, e.g. "**/drafts/**" (case-insensitive; may be repeated)
+    #[arg(long, value_name = "GLOB", value_parser = filter::parse_pattern)]
+    exclude: Vec<Pattern>,
+
+    /// Extract only documents whose full path matches one of these regular expressions (or an
+    /// --include pattern); may be repeated
+    #[arg(long, value_name = "REGEX", value_parser = filter::parse_regex)]
+    include_regex: Vec<Regex>,
+
+    /// Leave out files, archives and folders whose full path matches one of these regular
+    /// expressions, e.g. "_(draft|old)\.pdf$"; may be repeated
+    #[arg(long, value_name = "REGEX", value_parser = filter::parse_regex)]
+    exclude_regex: Vec<Regex>,
+
+    /// Search files and folders that .gitignore and .toignore files in --target ignore
+    #[arg(long)]
+    no_ignore: bool,
+
+    /// Search only this many levels of folders under --target (1 for only the files directly
+    /// in it)
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    max_depth: Option<u32>,
+
+    /// Follow symbolic links to files and folders under --target, which are otherwise left
+    /// out; a folder linked back into itself is searched once
+    #[arg(long)]
+    follow_symlinks: bool,
+
+    /// Leave out documents smaller than this, e.g. "10K" for near-empty stubs
+    #[arg(long, value_name = "SIZE", value_parser = memory::parse_size)]
+    min_size: Option<u64>,
+
+    /// Leave out documents larger than this, e.g. "200M" for giant scans
+    #[arg(long, value_name = "SIZE", value_parser = memory::parse_size)]
+    max_size: Option<u64>,
+
+    /// Extract only documents modified at or after this time: a date such as 2024-01-01, a
+    /// date and time such as 2024-01-01T18:30 (UTC), or an age such as 7d
+    #[arg(long, value_name = "TIME", value_parser = filter::parse_time)]
+    newer_than: Option<SystemTime>,
+
+    /// Extract only documents modified before this time, given as for --newer-than
+    #[arg(long, value_name = "TIME", value_parser = filter::parse_time)]
+    older_than: Option<SystemTime>,
+}
+
+impl FilterArgs {
+    fn to_filter(&self) -> PathFilter {
+        PathFilter {
+            include: self.include.clone(),
+            exclude: self.exclude.clone(),
+            include_regex: self.include_regex.clone(),
+            exclude_regex: self.exclude_regex.clone(),
+            ignore_files: !self.no_ignore,
+            max_depth: self.max_depth.map(|depth| depth as usize),
+            follow_symlinks: self.follow_symlinks,
+            min_size: self.min_size,
+            max_size: self.max_size,
+            modified_after: self.newer_than,
+            modified_before: self.older_than,
+        }
+    }
+}
+
+/// Options controlling how extracted documents are cleaned up and rendered, shared by all
+/// extraction modes.
+#[derive(clap::Args, Debug)]
+struct ProcessingArgs {
+    /// Output format
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+
+    /// Character encoding of the output files; characters the encoding cannot represent are
+    /// written as ? and reported (JSON and JSON Lines are always UTF-8)
+    #[arg(long, value_enum, value_name = "ENCODING", default_value_t = OutputEncoding::Utf8)]
+    output_encoding: OutputEncoding,
+
+    /// Line endings of the output files, whatever the source used
+    #[arg(long, value_enum, default_value_t = Newline::Lf)]
+    newline: Newline,
+
+    /// Reflow text output so lines are at most N columns wide (wide CJK characters count as two)
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u16).range(1..))]
+    wrap: Option<u16>,
+
+    /// Put each paragraph of text output on a single line
+    #[arg(long, conflicts_with = "wrap")]
+    no_wrap: bool,
+
+    /// Line written before each PDF or DjVu page of text output; `{page}` is the page number
+    /// and `\f` a form feed (e.g. "--- Page {page} ---")
+    #[arg(
+        long,
+        visible_alias = "page-marker",
+        value_name = "TEMPLATE",
+        allow_hyphen_values = true
+    )]
+    page_separator: Option<String>,
+
+    /// Remove printed page numbers ("Page 12", "- 12 -") and form feeds from PDF and DjVu pages
+    #[arg(long, conflicts_with = "page_separator")]
+    no_page_markers: bool,
+
+    /// Remove running headers and footers: lines, numbers aside, repeated at the top or bottom
+    /// of page after page of PDFs and DjVu books
+    #[arg(long)]
+    strip_headers_footers: bool,
+
+    /// Rejoin words broken across line ends ("exam-" / "ple"), keeping the hyphens of
+    /// compounds, and remove soft hyphens
+    #[arg(long)]
+    dehyphenate: bool,
+
+    /// Spell out common abbreviations (Dr., e.g., etc.) in SSML output
+    #[arg(long)]
+    expand_abbreviations: bool,
+
+    /// Replace curly quotes, dashes, ellipses and non-breaking spaces with ASCII equivalents
+    #[arg(long)]
+    ascii_punct: bool,
+
+    /// How superscript footnote markers in PDFs are rendered ("result3." vs "result[3].")
+    #[arg(long, value_enum, default_value_t = FootnoteMarkers::Keep)]
+    footnote_markers: FootnoteMarkers,
+
+    /// How superscripts and subscripts are rendered ("x2" vs "x²" vs "x^2")
+    #[arg(long, value_enum, default_value_t = ScriptMode::Keep)]
+    scripts: ScriptMode,
+
+    /// Wrap display equations in PDFs in [equation] ... [/equation] blocks
+    #[arg(long)]
+    mark_equations: bool,
+
+    /// Fence code samples (monospaced PDF lines, EPUB <pre> blocks) with ``` and keep their indentation
+    #[arg(long)]
+    fence_code: bool,
+
+    /// Put a [Figure 3.1: caption] placeholder where each image was (PDF images, EPUB figures)
+    #[arg(long)]
+    image_placeholders: bool,
+
+    /// Lay out PDF tables (runs of lines split into short columns) as tab-separated rows, or
+    /// as Markdown tables with --format markdown
+    #[arg(long, value_enum, default_value_t = TableMode::Detect)]
+    tables: TableMode,
+
+    /// Blank lines between the blocks of EPUB, HTML and CHM documents: keep them all, collapse
+    /// runs of them into one, or drop them for one block per line
+    #[arg(long, value_enum, default_value_t = WhitespaceMode::Aggressive)]
+    whitespace: WhitespaceMode,
+
+    /// Reading order of PDF pages without a structure tree: content-stream order, or column by
+    /// column on multi-column pages
+    #[arg(long, value_enum, default_value_t = PageLayout::Raw)]
+    layout: PageLayout,
+
+    /// Ignore the structure tree of tagged PDFs and read text in content-stream order
+    #[arg(long)]
+    no_structure: bool,
+
+    /// Leave letter-spaced PDF titles as extracted ("I N T R O") instead of joining the letters
+    #[arg(long)]
+    keep_letter_spacing: bool,
+
+    /// Leave out copyright and imprint pages (ISBN, "All rights reserved", cataloging data)
+    #[arg(long)]
+    drop_copyright_page: bool,
+
+    /// Skip everything before the first real chapter (cover, title page, contents, dedication)
+    #[arg(long)]
+    trim_front_matter: bool,
+
+    /// Skip everything after the last chapter (index, about the author, ads for other books)
+    #[arg(long)]
+    trim_back_matter: bool,
+
+    /// Extract only these PDF layers (optional content groups), by name; see `inspect`
+    #[arg(long, value_name = "NAMES", value_delimiter = ',')]
+    layers: Vec<String>,
+
+    /// Leave out these PDF layers, by name
+    #[arg(long, value_name = "NAMES", value_delimiter = ',')]
+    exclude_layers: Vec<String>,
+
+    /// Extract only these PDF pages, e.g. "1-20,35,40-" (numbered from 1, as viewers count
+    /// them); page separators and bookmarks keep the original numbers
+    #[arg(long, value_name = "PAGES", value_parser = pages::parse_pages)]
+    pages: Option<PageSelection>,
+
+    /// Extract only these EPUB chapters, by position in the reading order, e.g. "3-20"
+    #[arg(long, value_name = "CHAPTERS", value_parser = pages::parse_pages)]
+    chapters: Option<PageSelection>,
+
+    /// Leave out EPUB chapters whose table-of-contents title matches this regular expression
+    /// (case-insensitive), e.g. "copyright|acknowledg|dedication"
+    #[arg(long, value_name = "REGEX", value_parser = chapters::parse_title_pattern)]
+    exclude_chapters: Option<Regex>,
+
+    /// OCR scanned PDF and DjVu pages with Tesseract: pages with (almost) no text, every page, or none
+    #[arg(long, value_enum, default_value_t = OcrMode::Never)]
+    ocr: OcrMode,
+
+    /// Tesseract language(s) for --ocr, e.g. "eng" or "deu+eng"
+    #[arg(long, value_name = "LANGS", default_value = "eng")]
+    ocr_lang: String,
+
+    /// Password to try on encrypted PDFs; may be repeated
+    #[arg(long, value_name = "PASSWORD")]
+    pdf_password: Vec<String>,
+
+    /// File of passwords to try on encrypted PDFs, one per line
+    #[arg(long, value_name = "FILE")]
+    pdf_password_file: Option<PathBuf>,
+
+    /// Write one output file per bookmark (PDF outline, EPUB table of contents) or per work of
+    /// an omnibus into a folder named after the input
+    #[arg(long, value_enum)]
+    split: Option<SplitMode>,
+
+    /// Deepest bookmark level that starts a new file with --split bookmarks
+    #[arg(long, value_name = "N", default_value_t = 1, requires = "split",
+          value_parser = clap::value_parser!(u16).range(1..))]
+    split_level: u16,
+
+    /// What to do about an output already there from an earlier run: replace it, skip the file,
+    /// report the file as failed, or write the new output next to it numbered "book (2).txt"
+    #[arg(long, value_enum, default_value_t = OnConflict::Overwrite)]
+    on_conflict: OnConflict,
+
+    /// Name outputs from the document's metadata instead of the input's name, e.g.
+    /// "{author} - {title} [{stem}]"; also {language}, {collection} and {ext}, and `/` makes
+    /// folders
+    #[arg(long, value_name = "TEMPLATE", value_parser = naming::parse_template)]
+    name_template: Option<String>,
+
+    /// Append every document to this one file, under a separator line, instead of writing one
+    /// output per input; a relative path is taken from the output directory
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["split", "provenance", "stamps", "sidecar_metadata", "name_template"])]
+    combine: Option<PathBuf>,
+
+    /// Line written before each document of a --combine file; `{name}` is the input's file
+    /// name, `{source}` its path, `{title}` the document title and `\f` a form feed
+    #[arg(
+        long,
+        value_name = "TEMPLATE",
+        default_value = output::DEFAULT_DOCUMENT_SEPARATOR,
+        requires = "combine",
+        allow_hyphen_values = true
+    )]
+    document_separator: String,
+
+    /// Cap on the estimated memory of files extracted at once (e.g. 512M, 4G)
+    #[arg(long, value_name = "SIZE", value_parser = memory::parse_size)]
+    max_memory: Option<u64>,
+
+    /// Write a <output>.provenance.json sidecar recording tool, backend, options and source hash
+    #[arg(long)]
+    provenance: bool,
+
+    /// Start text outputs with # comment lines carrying the provenance record
+    #[arg(long)]
+    provenance_header: bool,
+
+    /// Write a <output>.stamps.json sidecar with the Bates number and exhibit stamp found on
+    /// each PDF page
+    #[arg(long)]
+    stamps: bool,
+
+    /// Write a <output>.meta.json sidecar with the title, author, language, page and chapter
+    /// counts, source hash and extraction time, keeping text outputs free of them
+    #[arg(long)]
+    sidecar_metadata: bool,
+
+    /// whisper.cpp model (ggml-*.bin) for transcribing .mp3/.m4b audiobooks
+    #[cfg(feature = "audio")]
+    #[arg(long, value_name = "FILE")]
+    whisper_model: Option<PathBuf>,
+
+    /// Spoken language of audiobooks as an ISO 639-1 code (detected when unset)
+    #[cfg(feature = "audio")]
+    #[arg(long, value_name = "CODE", requires = "whisper_model")]
+    audio_language: Option<String>,
+}
+
+impl ProcessingArgs {
+    fn to_options(&self) -> Result<ExtractOptions> {
+        let wrap = match (self.wrap, self.no_wrap) {
+            (Some(width), _) => Wrap::Width(width.into()),
+            (None, true) => Wrap::Paragraph,
+            (None, false) => Wrap::Preserve,
+        };
+
+        let json = matches!(self.format, OutputFormat::Json | OutputFormat::Jsonl);
+        let collection = self.format.is_collection();
+        if (json || collection) && self.output_encoding != OutputEncoding::Utf8 {
+            anyhow::bail!("--output-encoding cannot be used with --format json, jsonl, sqlite or parquet, which are always UTF-8");
+        }
+
+        if self.combine.is_some() && (json || collection || self.format == OutputFormat::Ssml) {
+            anyhow::bail!(
+                "--combine writes text or Markdown; use --format jsonl for a JSON Lines corpus"
+            );
+        }
+
+        let markdown = self.format == OutputFormat::Markdown;
+        let tables = match self.tables {
+            TableMode::Detect if markdown => Some(TableLayout::Markdown),
+            TableMode::Detect => Some(TableLayout::Tsv),
+            TableMode::Ignore => None,
+        };
+
+        let mut pdf_passwords = self.pdf_password.clone();
+        if let Some(path) = &self.pdf_password_file {
+            let list = fs::read_to_string(path)
+                .context(format!("Failed to read password file: {}", path.display()))?;
+            // Only line breaks are stripped; spaces may be part of a password
+            pdf_passwords.extend(
+                list.lines()
+                    .filter(|line| !line.is_empty())
+                    .map(str::to_string),
+            );
+        }
+
+        Ok(ExtractOptions {
+            memory: MemoryBudget::new(self.max_memory),
+            pdf: AssembleOptions {
+                footnotes: self.footnote_markers,
+                scripts: self.scripts,
+                mark_equations: self.mark_equations,
+                fence_code: self.fence_code,
+                use_structure: !self.no_structure,
+                join_letter_spacing: !self.keep_letter_spacing,
+                image_placeholders: self.image_placeholders,
+                tables,
+                layout: self.layout,
+            },
+            layers: LayerSelection {
+                include: self.layers.clone(),
+                exclude: self.exclude_layers.clone(),
+            },
+            pages: self.pages.clone().unwrap_or_default(),
+            chapters: ChapterSelection {
+                numbers: self.chapters.clone().unwrap_or_default(),
+                exclude_titles: self.exclude_chapters.clone(),
+            },
+            pdf_passwords,
+            ocr: OcrOptions {
+                mode: self.ocr,
+                language: self.ocr_lang.clone(),
+            },
+            html: HtmlOptions {
+                scripts: self.scripts,
+                fence_code: self.fence_code,
+                image_placeholders: self.image_placeholders,
+                tables: tables == Some(TableLayout::Markdown),
+                markup: markdown,
+                whitespace: self.whitespace,
+            },
+            postprocess: PostProcessOptions {
+                ascii_punctuation: self.ascii_punct,
+                plain_scripts: self.scripts == ScriptMode::Plain,
+                drop_copyright_page: self.drop_copyright_page,
+                trim_front_matter: self.trim_front_matter,
+                trim_back_matter: self.trim_back_matter,
+                drop_page_markers: self.no_page_markers,
+                strip_headers_footers: self.strip_headers_footers,
+                dehyphenate: self.dehyphenate,
+            },
+            output: OutputOptions {
+                format: self.format,
+                wrap,
+                page_separator: self.page_separator.clone(),
+                ssml: SsmlOptions {
+                    expand_abbreviations: self.expand_abbreviations,
+                },
+                split: self.split,
+                split_level: self.split_level.into(),
+                provenance: ProvenanceOptions {
+                    sidecar: self.provenance,
+                    header: self.provenance_header,
+                },
+                stamps: self.stamps,
+                metadata_sidecar: self.sidecar_metadata,
+                encoding: self.output_encoding,
+                newline: self.newline,
+                combine: self.combine.clone(),
+                document_separator: self.document_separator.clone(),
+                // Known once the sources are found
+                mirror_tree: Vec::new(),
+                name_template: self.name_template.clone(),
+                on_conflict: self.on_conflict,
+            },
+            #[cfg(feature = "audio")]
+            audio: audio::AudioOptions {
+                model: self.whisper_model.clone(),
+                language: self.audio_language.clone(),
+            },
+        })
+    }
 }
 
 fn main() -> Result<()> {
-    let args = Args::parse();
+    let cli = Cli::parse();
+
+    match cli.command {
+        Some(Command::Ingest(args)) => run_ingest(&args),
+        Some(Command::Extract(args)) => run_stdin_extract(&args),
+        Some(Command::Rpc(args)) => run_rpc(&args),
+        Some(Command::Mcp(args)) => run_mcp(&args),
+        Some(Command::Ctl(args)) => run_ctl(&args),
+        #[cfg(feature = "server")]
+        Some(Command::Serve(args)) => run_serve(&args),
+        #[cfg(feature = "grpc")]
+        Some(Command::Grpc(args)) => run_grpc(&args),
+        Some(Command::Info(args)) => run_info(&args),
+        Some(Command::Inspect(args)) => run_inspect(&args),
+        Some(Command::Search(args)) => run_search(&args),
+        #[cfg(feature = "index")]
+        Some(Command::Index(args)) => run_index(&args),
+        Some(Command::Validate(args)) => run_validate(&args),
+        Some(Command::List(args)) => run_list(&args),
+        #[cfg(feature = "gui")]
+        Some(Command::Gui(args)) => run_gui(*args),
+        None => run_extract(&cli.args),
+    }
+}
+
+fn run_rpc(args: &RpcArgs) -> Result<()> {
+    let options = stream_options(&args.processing, "rpc")?;
+    let filter = args.filter.to_filter();
+    rpc::run(
+        io::stdin().lock(),
+        io::stdout().lock(),
+        &options,
+        |target| discover_directory(target, &filter),
+    )
+}
+
+fn run_mcp(args: &McpArgs) -> Result<()> {
+    let options = stream_options(&args.processing, "mcp")?;
+    mcp::run(io::stdin().lock(), io::stdout().lock(), &options)
+}
+
+fn run_ctl(args: &CtlArgs) -> Result<()> {
+    let state = match (&args.state_dir, &args.output) {
+        (Some(dir), _) => dir.clone(),
+        (None, Some(output)) => output.join(daemon::STATE_DIR),
+        (None, None) => anyhow::bail!("--output or --state-dir is required"),
+    };
+    match args.action {
+        CtlAction::Pause => {
+            daemon::set_paused(&state, true)?;
+            println!("Paused; files being extracted are finished first");
+        }
+        CtlAction::Resume => {
+            daemon::set_paused(&state, false)?;
+            println!("Resumed");
+        }
+        CtlAction::Status => {
+            let status = Status::read(&state)?;
+            let now = SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_secs());
+            let state = if status.paused { "paused" } else { "running" };
+            println!("Daemon (pid {}): {state}", status.pid);
+            println!(
+                "Running for: {}",
+                format_duration(now.saturating_sub(status.started_at))
+            );
+            println!("Queued: {}", status.pending);
+            println!("Extracted: {}", status.saved);
+            println!("Errors: {}", status.failed);
+            for path in &status.processing {
+                println!("Extracting: {}", path.display());
+            }
+            let silent = now.saturating_sub(status.updated_at);
+            if silent > DAEMON_SILENCE {
+                println!(
+                    "No news for {}; the daemon may have stopped",
+                    format_duration(silent)
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Seconds without a status update after which `ctl status` warns that the daemon may be
+/// gone; an idle daemon updates its status twice a second, a busy one between batches.
+const DAEMON_SILENCE: u64 = 600;
+
+/// A duration such as `2h 05m` or `40s`.
+fn format_duration(seconds: u64) -> String {
+    match seconds {
+        0..60 => format!("{seconds}s"),
+        60..3600 => format!("{}m {:02}s", seconds / 60, seconds % 60),
+        _ => format!("{}h {:02}m", seconds / 3600, seconds % 3600 / 60),
+    }
+}
+
+#[cfg(feature = "server")]
+fn run_serve(args: &ServeArgs) -> Result<()> {
+    let options = stream_options(&args.processing, "serve")?;
+    let serve_options = ServeOptions {
+        listen: args.listen.clone(),
+        workers: args.jobs.into(),
+        max_upload: args.max_upload,
+    };
+    server::serve(&serve_options, &options)
+}
+
+#[cfg(feature = "grpc")]
+fn run_grpc(args: &ServeArgs) -> Result<()> {
+    let options = stream_options(&args.processing, "grpc")?;
+    let serve_options = ServeOptions {
+        listen: args.listen.clone(),
+        workers: args.jobs.into(),
+        max_upload: args.max_upload,
+    };
+    grpc::serve(&serve_options, options)
+}
+
+fn run_info(args: &InfoArgs) -> Result<()> {
+    if !args.file.is_file() {
+        anyhow::bail!("File does not exist: {}", args.file.display());
+    }
+    let extension = match &args.file_type {
+        Some(extension) => extension.clone(),
+        None => source::extension(&args.file)
+            .filter(|extension| source::SUPPORTED_EXTENSIONS.contains(&extension.as_str()))
+            .context(format!(
+                "Unsupported file type: {} (use --type to name it)",
+                args.file.display()
+            ))?,
+    };
+    let options = args.processing.to_options()?;
+    let info = info::read(&SourceFile::new(args.file.clone(), &extension), &options)?;
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&info)?);
+    } else {
+        print_info(&info);
+    }
+    Ok(())
+}
+
+fn print_info(info: &DocumentInfo) {
+    let count = |count: Option<usize>| count.map_or("-".to_string(), |count| count.to_string());
+    let rows = [
+        ("File", info.path.display().to_string()),
+        ("Type", info.format.to_string()),
+        (
+            "Title",
+            info.title.clone().unwrap_or_else(|| "-".to_string()),
+        ),
+        (
+            "Author",
+            info.author.clone().unwrap_or_else(|| "-".to_string()),
+        ),
+        (
+            "Language",
+            info.language.clone().unwrap_or_else(|| "-".to_string()),
+        ),
+        (
+            "Publisher",
+            info.publisher.clone().unwrap_or_else(|| "-".to_string()),
+        ),
+        ("Pages", count(info.page_count)),
+        ("Chapters", count(info.chapter_count)),
+        (
+            "Encrypted",
+            if info.encrypted { "yes" } else { "no" }.to_string(),
+        ),
+        (
+            "Size",
+            format!("{} ({} bytes)", memory::format_size(info.size), info.size),
+        ),
+    ];
+    for (name, value) in rows {
+        println!("{:<10} {value}", format!("{name}:"));
+    }
+}
+
+fn run_inspect(args: &InspectArgs) -> Result<()> {
+    let info = pdf::inspect(&args.file)?;
+
+    println!("File: {}", args.file.display());
+    println!("Pages: {}", info.pages);
+    if info.layers.is_empty() {
+        println!("Layers: none");
+    } else {
+        println!("Layers:");
+        for layer in &info.layers {
+            let state = if layer.visible { "on" } else { "off" };
+            println!("  {} ({} by default)", layer.name, state);
+        }
+    }
+    Ok(())
+}
+
+fn run_search(args: &SearchArgs) -> Result<()> {
+    #[cfg(feature = "index")]
+    if let Some(index) = &args.index {
+        return search_index(index, args);
+    }
+    let output = args.output.as_deref().context("--output is required")?;
+    if !output.is_dir() {
+        anyhow::bail!("Output directory does not exist: {}", output.display());
+    }
+    let hits = search::search(output, &args.query.join(" "), args.limit)?;
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&hits)?);
+        return Ok(());
+    }
+    if hits.is_empty() {
+        println!("No matches");
+        return Ok(());
+    }
+    for (rank, hit) in hits.iter().enumerate() {
+        let name = hit.output.display().to_string();
+        println!("{}. {}", rank + 1, hit.title.as_deref().unwrap_or(&name));
+        let mut origin = match &hit.source {
+            Some(source) => format!("{source} (in {name})"),
+            None => name.clone(),
+        };
+        if let Some(page) = hit.page {
+            origin.push_str(&format!(", page {page}"));
+        }
+        println!("   {origin}");
+        println!("   {}", hit.snippet);
+        println!();
+    }
+    Ok(())
+}
+
+#[cfg(feature = "index")]
+fn search_index(index: &Path, args: &SearchArgs) -> Result<()> {
+    if !index.is_dir() {
+        anyhow::bail!("Index does not exist: {}", index.display());
+    }
+    let hits = index::search(index, &args.query.join(" "), args.limit)?;
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&hits)?);
+        return Ok(());
+    }
+    if hits.is_empty() {
+        println!("No matches");
+        return Ok(());
+    }
+    for (rank, hit) in hits.iter().enumerate() {
+        let path = hit.path.display().to_string();
+        println!("{}. {}", rank + 1, hit.title.as_deref().unwrap_or(&path));
+        match &hit.author {
+            Some(author) => println!("   {path} (by {author})"),
+            None => println!("   {path}"),
+        }
+        if !hit.snippet.is_empty() {
+            println!("   {}", hit.snippet);
+        }
+        println!();
+    }
+    Ok(())
+}
 
-    // Validate target path exists
+#[cfg(feature = "index")]
+fn run_index(args: &IndexArgs) -> Result<()> {
     if !args.target.exists() {
         anyhow::bail!("Target path does not exist: {}", args.target.display());
     }
+    let options = args.processing.to_options()?;
+    let mut library = LibraryIndex::open(&args.index)?;
+    let files = discover_directory(&args.target, &args.filter.to_filter());
+    let (current, changed): (Vec<&SourceFile>, Vec<&SourceFile>) = files
+        .iter()
+        .partition(|file| !args.reindex && library.is_current(&file.path));
 
-    // Create output directory if it doesn't exist
-    fs::create_dir_all(&args.output).context(format!(
-        "Failed to create output directory: {}",
-        args.output.display()
-    ))?;
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(args.jobs.into())
+        .build()
+        .context("Failed to start worker threads")?;
+    let progress = progress_bar(changed.len());
+    let failures: Vec<String> = pool.install(|| {
+        changed
+            .par_iter()
+            .filter_map(|file| {
+                progress.set_message(file.path.display().to_string());
+                let indexed = to_texts::extract_source(file, &options)
+                    .and_then(|extracted| library.add(&file.path, &extracted.document));
+                progress.inc(1);
+                indexed
+                    .err()
+                    .map(|e| format!("{}: {e:#}", file.path.display()))
+            })
+            .collect()
+    });
+    progress.finish_and_clear();
+    library.commit()?;
 
+    for failure in &failures {
+        eprintln!("Failed to index {failure}");
+    }
     println!(
-        "Searching for PDF and EPUB files in: {}",
-        args.target.display()
+        "Indexed {} of {} files into {} ({} unchanged, {} failed)",
+        changed.len() - failures.len(),
+        files.len(),
+        args.index.display(),
+        current.len(),
+        failures.len()
     );
-    println!("Output directory: {}", args.output.display());
-    println!();
+    Ok(())
+}
 
-    let (processed_count, error_count) = process_directory(&args.target, &args.output)?;
+fn run_validate(args: &ValidateArgs) -> Result<()> {
+    if !args.target.exists() {
+        anyhow::bail!("Target path does not exist: {}", args.target.display());
+    }
+    let options = args.processing.to_options()?;
+    let filter = args.filter.to_filter();
+    let found: Vec<(PathBuf, Option<SourceFile>)> = walk(&args.target, &filter)
+        .filter(|entry| filter.accepts(entry.path(), relative_path(entry.path(), &args.target)))
+        .map(|entry| {
+            (
+                entry.path().to_path_buf(),
+                SourceFile::from_path(entry.path()),
+            )
+        })
+        .collect();
 
-    print_summary(processed_count, error_count);
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(args.jobs.into())
+        .build()
+        .context("Failed to start worker threads")?;
+    let progress = progress_bar(found.len());
+    let checks: Vec<Check> = pool.install(|| {
+        found
+            .par_iter()
+            .map(|(path, file)| {
+                progress.set_message(path.display().to_string());
+                let check = match file {
+                    Some(file) => validate::check(file, &options, args.min_chars),
+                    None => Check::unsupported(path),
+                };
+                progress.inc(1);
+                check
+            })
+            .collect()
+    });
+    progress.finish_and_clear();
 
+    for status in FileStatus::ALL.into_iter().skip(1) {
+        let files: Vec<&Check> = checks
+            .iter()
+            .filter(|check| check.status == status)
+            .collect();
+        if files.is_empty() {
+            continue;
+        }
+        println!("{} ({}):", status.label(), files.len());
+        for check in files {
+            match &check.detail {
+                Some(detail) => println!("  {} ({detail})", check.path.display()),
+                None => println!("  {}", check.path.display()),
+            }
+        }
+        println!();
+    }
+    let counts: Vec<String> = FileStatus::ALL
+        .iter()
+        .map(|&status| {
+            let count = checks.iter().filter(|check| check.status == status).count();
+            format!("{count} {}", status.label())
+        })
+        .collect();
+    let checked = if checks.len() == 1 { "file" } else { "files" };
+    println!("Checked {} {checked}: {}", checks.len(), counts.join(", "));
+
+    if let Some(report) = &args.report {
+        let json = serde_json::to_string_pretty(&checks)?;
+        fs::write(report, json + "\n")
+            .context(format!("Failed to write report: {}", report.display()))?;
+        println!("Report written to: {}", report.display());
+    }
     Ok(())
 }
 
-fn process_directory(target: &Path, output: &Path) -> Result<(usize, usize)> {
-    let mut processed_count = 0;
-    let mut error_count = 0;
+fn run_list(args: &ListArgs) -> Result<()> {
+    if !args.target.exists() {
+        anyhow::bail!("Target path does not exist: {}", args.target.display());
+    }
+    let files = discover_directory(&args.target, &args.filter.to_filter());
+    let size = |file: &SourceFile| fs::metadata(&file.path).map_or(0, |m| m.len());
 
-    // Walk through the directory recursively
-    for entry in WalkDir::new(target)
-        .follow_links(true)
-        .into_iter()
-        .filter_map(|e| e.ok())
-    {
-        let path = entry.path();
+    if args.json {
+        let entries: Vec<serde_json::Value> = files
+            .iter()
+            .map(|file| {
+                serde_json::json!({
+                    "path": file.path,
+                    "type": source::format_name(&file.extension),
+                    "extension": file.extension,
+                    "size": size(file),
+                })
+            })
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&entries)?);
+        return Ok(());
+    }
 
-        if !path.is_file() {
-            continue;
+    let mut total = 0;
+    for file in &files {
+        total += size(file);
+        println!(
+            "{:>8}  {:<13} {}",
+            memory::format_size(size(file)),
+            source::format_name(&file.extension),
+            file.path.display()
+        );
+    }
+    let found = if files.len() == 1 { "file" } else { "files" };
+    println!();
+    println!(
+        "{} {found}, {} in all",
+        files.len(),
+        memory::format_size(total)
+    );
+    Ok(())
+}
+
+#[cfg(feature = "gui")]
+fn run_gui(args: GuiArgs) -> Result<()> {
+    let options = args.processing.to_options()?;
+    if options.output.format.is_finished_at_end() {
+        anyhow::bail!(
+            "--format parquet is written out when a run ends and cannot be used with gui"
+        );
+    }
+    let gui_options = gui::GuiOptions {
+        target: args.target,
+        output: args.output,
+    };
+    let discover = |target: &Path| discover_directory(target, &PathFilter::default());
+    gui::run(gui_options, discover, move |file, output, run| {
+        write_source(file, output, &options, run, None).map(|written| written.path)
+    })
+}
+
+fn run_extract(args: &Args) -> Result<()> {
+    let output = args.output.as_deref().context("--output is required")?;
+    if output == Path::new("-") {
+        return run_stdout(args);
+    }
+
+    // Validate source path exists
+    if let Some(source) = args.calibre_library.as_ref().or(args.target.as_ref()) {
+        if !source.exists() {
+            anyhow::bail!("Target path does not exist: {}", source.display());
         }
+    }
 
-        if let Some(extension) = path.extension().and_then(|s| s.to_str()) {
-            match extension.to_lowercase().as_str() {
-                "pdf" | "epub" => {
-                    if process_file(path, output, extension) {
-                        processed_count += 1;
-                    } else {
-                        error_count += 1;
-                    }
-                }
-                _ => continue,
-            }
+    // Create output directory if it doesn't exist; a JSON Lines corpus, a SQLite database or
+    // a Parquet file may be named as a file instead, in a directory that is created
+    let format = args.processing.format;
+    if format.is_collection() && args.quarantine {
+        anyhow::bail!("--quarantine cannot move records out of a corpus or database");
+    }
+    if format.is_finished_at_end() && (args.watch || args.daemon) {
+        anyhow::bail!(
+            "--format parquet is written out when the run ends and cannot be used with --watch or --daemon"
+        );
+    }
+    let corpus_file = match format {
+        OutputFormat::Jsonl => output.extension().is_some_and(|e| e == "jsonl"),
+        OutputFormat::Sqlite => output
+            .extension()
+            .is_some_and(|e| ["db", "sqlite", "sqlite3"].iter().any(|known| e == *known)),
+        #[cfg(feature = "parquet")]
+        OutputFormat::Parquet => output.extension().is_some_and(|e| e == "parquet"),
+        _ => false,
+    };
+    let directory = match output.parent() {
+        Some(parent) if corpus_file => parent,
+        _ => output,
+    };
+    // The folder of a --combine file named with a path of its own is created too
+    let combined = args
+        .processing
+        .combine
+        .as_ref()
+        .map(|file| output.join(file));
+    if combined.is_some() && args.quarantine {
+        anyhow::bail!("--quarantine cannot move documents out of a --combine file");
+    }
+    let combined_directory = combined.as_deref().and_then(Path::parent);
+    for directory in std::iter::once(directory).chain(combined_directory) {
+        fs::create_dir_all(directory).context(format!(
+            "Failed to create output directory: {}",
+            directory.display()
+        ))?;
+    }
+
+    let mut options = args.processing.to_options()?;
+    let discovered = discover_sources(args, output)?;
+    if args.mirror_tree {
+        let mut roots: Vec<PathBuf> = args
+            .calibre_library
+            .iter()
+            .chain(&args.target)
+            .cloned()
+            .collect();
+        // Files unpacked from archives sit in the folders their archives are in
+        roots.extend(
+            discovered
+                .unpacked
+                .as_ref()
+                .map(|dir| dir.path().to_path_buf()),
+        );
+        options.output.mirror_tree = roots;
+    }
+    let manifest = match args.no_manifest {
+        true => None,
+        false => Some(Manifest::create(&directory.join(manifest::MANIFEST_FILE))?),
+    };
+    let pusher = args.index_url.as_ref().map(|url| {
+        Pusher::new(PushOptions {
+            url: url.clone(),
+            api_key: args.index_api_key.clone(),
+            engine: args.index_engine.unwrap_or_else(|| Engine::guess(url)),
+            batch_size: args.index_batch.into(),
+            retries: args.index_retries,
+        })
+    });
+    let run = Run {
+        output,
+        options: &options,
+        skip_existing: args.skip_existing,
+        min_chars: args.min_chars,
+        quarantine: args.quarantine,
+        push: pusher.as_ref(),
+        manifest: manifest.as_ref(),
+    };
+    if args.daemon {
+        return run_daemon(args, &run, &discovered.files);
+    }
+    let started = Instant::now();
+    let counts = process_files(&discovered.files, &run, args.jobs.into())?;
+    output::finish_outputs()?;
+    let elapsed = started.elapsed();
+    let encoding = args.processing.output_encoding;
+
+    print_summary(counts.saved, counts.failed + discovered.failed);
+    if counts.skipped > 0 {
+        println!("  Skipped (already extracted): {}", counts.skipped);
+    }
+    if let Some(pusher) = &pusher {
+        println!("  Indexed in {}: {}", pusher.url(), pusher.indexed());
+        if pusher.failed() > 0 {
+            println!("  Failed to index: {}", pusher.failed());
         }
     }
+    if let Some(manifest) = &manifest {
+        println!("  Manifest: {}", manifest.path().display());
+    }
+    print_low_text(&counts.low_text, &run);
+    print_locked(&counts.locked);
+    print_unmappable(&counts.unmappable, encoding);
+    print_renamed(&counts.renamed);
+    if let Some(path) = &args.summary_json {
+        let summary = summary_json(&counts, discovered.failed, &run, elapsed);
+        fs::write(path, serde_json::to_string_pretty(&summary)? + "\n")
+            .context(format!("Failed to write summary: {}", path.display()))?;
+    }
 
-    Ok((processed_count, error_count))
+    if args.watch {
+        watch_target(args, run.output, |files| {
+            if files.is_empty() {
+                return;
+            }
+            if let Err(e) = process_files(&files, &run, args.jobs.into()) {
+                eprintln!("Error: {e:#}");
+            }
+        })?;
+    }
+    Ok(())
 }
 
-fn process_file(path: &Path, output: &Path, extension: &str) -> bool {
-    let file_type = extension.to_lowercase();
+/// Queues the files found under --target and those that arrive there later, extracting them
+/// --jobs at a time unless paused, and keeps the status that `ctl` reads up to date.
+fn run_daemon(args: &Args, run: &Run, found: &[SourceFile]) -> Result<()> {
+    let state = args
+        .state_dir
+        .clone()
+        .unwrap_or_else(|| run.output.join(daemon::STATE_DIR));
+    let mut queue = Queue::open(&state)?;
+    queue.push(found.iter().map(|file| file.path.clone()))?;
+    let mut status = Status::new();
+    let jobs = usize::from(args.jobs);
+    println!("Daemon state directory: {}", state.display());
+
+    let mut tick = |arrived: Vec<SourceFile>| -> Result<()> {
+        queue.push(arrived.into_iter().map(|file| file.path))?;
+        status.paused = daemon::is_paused(&state);
+        status.pending = queue.len();
+        if status.paused || queue.is_empty() {
+            // Written all the same, so ctl can tell the daemon is alive
+            return status.write(&state);
+        }
+
+        let batch = queue.next(jobs);
+        status.processing = batch.clone();
+        status.write(&state)?;
+        let files: Vec<SourceFile> = batch
+            .iter()
+            .filter_map(|path| SourceFile::from_path(path))
+            .collect();
+        let counts = process_files(&files, run, jobs)?;
+        status.saved += counts.saved;
+        status.failed += counts.failed;
+        queue.finish(&batch)?;
+        status.processing.clear();
+        status.pending = queue.len();
+        status.write(&state)
+    };
+    watch_target(args, run.output, |arrived| {
+        if let Err(e) = tick(arrived) {
+            eprintln!("Error: {e:#}");
+        }
+    })
+}
+
+/// Calls `handle` with the documents that arrive or change under --target, until
+/// interrupted, and with no documents in between (see [`to_texts::watch::watch`]).
+fn watch_target(args: &Args, output: &Path, mut handle: impl FnMut(Vec<SourceFile>)) -> Result<()> {
+    let given = args.target.as_deref().context("--watch needs --target")?;
+    if !given.is_dir() {
+        anyhow::bail!("--watch needs a folder as --target: {}", given.display());
+    }
+    // Events name files under the canonical folder watched; they are named as under --target
+    // again, for --mirror-tree and the progress lines
+    let target = &fs::canonicalize(given)?;
+    let output = fs::canonicalize(output)?;
+    let filter = args.filter.to_filter();
+    println!();
     println!(
-        "Processing {}: {}",
-        file_type.to_uppercase(),
-        path.display()
+        "Watching for new and changed files in: {} (Ctrl+C to stop)",
+        target.display()
+    );
+
+    let wanted = |path: &Path| {
+        !path.starts_with(&output)
+            && watch_accepts(path, target, &filter)
+            && SourceFile::from_path(path).is_some()
+    };
+    let settle = Duration::from_secs(args.settle_secs);
+    to_texts::watch::watch(target, settle, wanted, |paths| {
+        let files: Vec<SourceFile> = paths
+            .iter()
+            .filter_map(|path| SourceFile::from_path(&given.join(path.strip_prefix(target).ok()?)))
+            .collect();
+        handle(files);
+    })
+}
+
+/// Whether a file seen while watching `target` is one a search of it would find; ignore
+/// files are not read.
+fn watch_accepts(path: &Path, target: &Path, filter: &PathFilter) -> bool {
+    let Ok(relative) = path.strip_prefix(target) else {
+        return false;
+    };
+    if filter
+        .max_depth
+        .is_some_and(|depth| relative.components().count() > depth)
+    {
+        return false;
+    }
+    let folder_excluded = relative
+        .ancestors()
+        .skip(1)
+        .filter(|folder| !folder.as_os_str().is_empty())
+        .any(|folder| filter.excluded(&target.join(folder), folder));
+    !folder_excluded && filter.accepts(path, relative)
+}
+
+/// Extracts the single file --target names to standard output, leaving the progress lines
+/// out so the text can be piped on.
+fn run_stdout(args: &Args) -> Result<()> {
+    let target = args
+        .target
+        .as_deref()
+        .filter(|target| target.is_file())
+        .context("--output - needs a single file as --target")?;
+    let options = stream_options(&args.processing, "--output -")?;
+    let file = SourceFile::from_path(target)
+        .context(format!("Unsupported file type: {}", target.display()))?;
+
+    let streamed = to_texts::stream_source(&file, &mut io::stdout().lock(), &options);
+    finish_stream(streamed, args.processing.output_encoding)
+}
+
+/// Extracts a document piped to standard input to standard output.
+fn run_stdin_extract(args: &ExtractArgs) -> Result<()> {
+    let options = stream_options(&args.processing, "--stdin")?;
+    let streamed = to_texts::stream_reader(
+        &mut io::stdin().lock(),
+        &args.file_type,
+        &mut io::stdout().lock(),
+        &options,
     );
+    finish_stream(streamed, args.processing.output_encoding)
+}
+
+/// The options of a run whose output goes to standard output, refusing those that write
+/// files of their own.
+fn stream_options(processing: &ProcessingArgs, mode: &str) -> Result<ExtractOptions> {
+    let options = processing.to_options()?;
+    let output = &options.output;
+    if output.split.is_some() || output.combine.is_some() {
+        anyhow::bail!("--split and --combine write files and cannot be used with {mode}");
+    }
+    if output.provenance.sidecar || output.stamps || output.metadata_sidecar {
+        anyhow::bail!(
+            "--provenance, --stamps and --sidecar-metadata write sidecar files and cannot be used with {mode}"
+        );
+    }
+    if output.format.is_collection() && output.format != OutputFormat::Jsonl {
+        anyhow::bail!("--format sqlite and parquet write files and cannot be used with {mode}");
+    }
+    Ok(options)
+}
 
-    let result = match file_type.as_str() {
-        "pdf" => extract_pdf_text(path, output),
-        "epub" => extract_epub_text(path, output),
-        _ => return false,
+/// Reports how streaming to standard output went; a reader that stopped reading early, such
+/// as head or less, is not an error.
+fn finish_stream(streamed: Result<usize>, encoding: OutputEncoding) -> Result<()> {
+    let unmappable = match streamed {
+        Ok(unmappable) => unmappable,
+        Err(e) if is_broken_pipe(&e) => return Ok(()),
+        Err(e) => return Err(e),
     };
+    if unmappable > 0 {
+        eprintln!(
+            "{unmappable} characters that {} cannot represent were written as ?",
+            encoding.label()
+        );
+    }
+    Ok(())
+}
 
-    match result {
-        Ok(output_path) => {
-            println!("  -> Saved to: {}", output_path.display());
-            true
+fn is_broken_pipe(error: &anyhow::Error) -> bool {
+    error
+        .downcast_ref::<io::Error>()
+        .is_some_and(|e| e.kind() == io::ErrorKind::BrokenPipe)
+}
+
+/// The files to extract, found before any is extracted.
+struct Discovered {
+    files: Vec<SourceFile>,
+    /// Items that failed before extraction (e.g. downloads or unreadable archives)
+    failed: usize,
+    /// Folder holding the files unpacked from archives, removed when dropped
+    unpacked: Option<TempDir>,
+}
+
+/// Collects the files to extract from whichever source was selected.
+fn discover_sources(args: &Args, output: &Path) -> Result<Discovered> {
+    if let Some(library) = &args.calibre_library {
+        println!("Reading Calibre library: {}", library.display());
+        println!("Output directory: {}", output.display());
+        println!();
+
+        let library = calibre::load_library(library)?;
+        for title in &library.unsupported {
+            println!("Skipping (no supported format): {title}");
         }
-        Err(e) => {
-            eprintln!("  -> Error: {}", e);
-            false
+        return Ok(Discovered {
+            files: library.books,
+            failed: 0,
+            unpacked: None,
+        });
+    }
+
+    if let Some(url) = &args.opds {
+        println!("Crawling OPDS catalog: {url}");
+        println!("Output directory: {}", output.display());
+        println!();
+
+        let options = opds::OpdsOptions {
+            delay: Duration::from_millis(args.opds_delay),
+            cache_dir: args
+                .opds_cache
+                .clone()
+                .unwrap_or_else(|| output.join(".opds-cache")),
+            max_feeds: args.opds_max_feeds,
+        };
+        let crawl = opds::crawl(url, &options)?;
+        println!();
+        return Ok(Discovered {
+            files: crawl.books,
+            failed: crawl.failed,
+            unpacked: None,
+        });
+    }
+
+    if let Some(list) = &args.files_from {
+        return discover_listed(list, args.null, &args.filter.to_filter(), output);
+    }
+
+    let target = args.target.as_ref().context("No input source given")?;
+    println!("Searching for PDF and EPUB files in: {}", target.display());
+    println!("Output directory: {}", output.display());
+    println!();
+
+    let filter = args.filter.to_filter();
+    let mut files = discover_directory(target, &filter);
+    if !args.scan_archives {
+        return Ok(Discovered {
+            files,
+            failed: 0,
+            unpacked: None,
+        });
+    }
+
+    let unpacked = tempfile::tempdir().context("Failed to create a folder to unpack archives")?;
+    let options = archive::UnpackOptions {
+        depth: args.archive_depth.into(),
+        max_bytes: args.max_archive_size,
+    };
+    let mut failed = 0;
+    for archive in discover_archives(target, &filter) {
+        // Unpacked into the archive's own folder, so archives of the same name in different
+        // folders stay apart
+        let folder = match archive.parent().and_then(|p| p.strip_prefix(target).ok()) {
+            Some(relative) => unpacked.path().join(relative),
+            None => unpacked.path().to_path_buf(),
+        };
+        match archive::unpack(&archive, &folder, &options) {
+            Ok(inner) => {
+                let found = if inner.len() == 1 { "file" } else { "files" };
+                println!(
+                    "Found {} supported {found} in: {}",
+                    inner.len(),
+                    archive.display()
+                );
+                files.extend(inner);
+            }
+            Err(e) => {
+                eprintln!("Error reading archive: {:#}", e);
+                failed += 1;
+            }
         }
     }
+    println!();
+    Ok(Discovered {
+        files,
+        failed,
+        unpacked: Some(unpacked),
+    })
 }
 
-fn print_summary(processed_count: usize, error_count: usize) {
+/// The documents named in the file `list` (standard input for `-`) that `filter` accepts,
+/// in the order listed, one per line or, with `null`, ended by NUL characters. Paths are
+/// matched against filter patterns as they are written.
+fn discover_listed(
+    list: &Path,
+    null: bool,
+    filter: &PathFilter,
+    output: &Path,
+) -> Result<Discovered> {
+    let bytes = if list == Path::new("-") {
+        println!("Reading the files to extract from standard input");
+        let mut bytes = Vec::new();
+        io::stdin()
+            .read_to_end(&mut bytes)
+            .context("Failed to read the file list from standard input")?;
+        bytes
+    } else {
+        println!("Reading the files to extract from: {}", list.display());
+        fs::read(list).context(format!("Failed to read file list: {}", list.display()))?
+    };
+    println!("Output directory: {}", output.display());
     println!();
-    println!("Summary:");
-    println!("  Successfully processed: {}", processed_count);
-    println!("  Errors: {}", error_count);
-}
 
-fn extract_pdf_text(pdf_path: &Path, output_dir: &Path) -> Result<PathBuf> {
-    // Extract text using pdf-extract which properly handles encodings
-    // Catch panics from the pdf-extract library
-    let text = panic::catch_unwind(|| {
-        pdf_extract::extract_text(pdf_path)
+    let separator = if null { b'\0' } else { b'\n' };
+    let mut files = Vec::new();
+    let mut failed = 0;
+    for entry in bytes.split(|&byte| byte == separator) {
+        // Lists written on Windows end their lines in CRLF
+        let entry = match entry.strip_suffix(b"\r") {
+            Some(line) if !null => line,
+            _ => entry,
+        };
+        if entry.iter().all(u8::is_ascii_whitespace) {
+            continue;
+        }
+        let path = path_from_bytes(entry);
+        let path = path.as_path();
+        if !path.is_file() {
+            eprintln!("File not found: {}", path.display());
+            failed += 1;
+        } else if !filter.accepts(path, path) {
+            continue;
+        } else if let Some(file) = SourceFile::from_path(path) {
+            files.push(file);
+        } else {
+            println!("Skipping (unsupported format): {}", path.display());
+        }
+    }
+    println!();
+    Ok(Discovered {
+        files,
+        failed,
+        unpacked: None,
     })
-    .map_err(|_| anyhow::anyhow!("PDF extraction panicked (likely unsupported PDF feature)"))
-    .and_then(|r| r.context(format!(
-        "Failed to extract text from PDF: {}",
-        pdf_path.display()
-    )))?;
-
-    // Generate output file path
-    let output_path = generate_output_path(pdf_path, output_dir, "txt")?;
-
-    // Write extracted text to file
-    fs::write(&output_path, text).context(format!(
-        "Failed to write output file: {}",
-        output_path.display()
-    ))?;
+}
+
+/// A path as written in a file list: any bytes on Unix, UTF-8 elsewhere.
+#[cfg(unix)]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    use std::os::unix::ffi::OsStrExt;
+    PathBuf::from(std::ffi::OsStr::from_bytes(bytes))
+}
 
-    Ok(output_path)
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
 }
 
-fn extract_epub_text(epub_path: &Path, output_dir: &Path) -> Result<PathBuf> {
-    let mut doc = epub::doc::EpubDoc::new(epub_path)
-        .context(format!("Failed to open EPUB: {}", epub_path.display()))?;
+/// Archives under `target` that `filter` does not exclude, in name order.
+fn discover_archives(target: &Path, filter: &PathFilter) -> Vec<PathBuf> {
+    walk(target, filter)
+        .filter(|entry| archive::is_archive(entry.path()))
+        .map(|entry| entry.into_path())
+        .collect()
+}
 
-    let mut text = String::new();
+fn discover_directory(target: &Path, filter: &PathFilter) -> Vec<SourceFile> {
+    walk(target, filter)
+        .filter(|entry| filter.accepts(entry.path(), relative_path(entry.path(), target)))
+        .filter_map(|entry| SourceFile::from_path(entry.path()))
+        .collect()
+}
 
-    // Extract metadata
-    append_metadata(&doc, &mut text);
+/// The files under `target`, recursively and in name order so runs are reproducible, leaving
+/// out the folders `filter` excludes. A file or folder reached again, through another link or
+/// a symbolic link looping back, is only taken the first time.
+fn walk<'a>(target: &'a Path, filter: &'a PathFilter) -> impl Iterator<Item = DirEntry> + 'a {
+    let mut ignores = IgnoreStack::default();
+    let mut visited = HashSet::new();
+    WalkDir::new(target)
+        .follow_links(filter.follow_symlinks)
+        .max_depth(filter.max_depth.unwrap_or(usize::MAX))
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(move |entry| {
+            let path = entry.path();
+            if entry.depth() > 0 && filter.excluded(path, relative_path(path, target)) {
+                return false;
+            }
+            if filter.ignore_files
+                && ignores.ignored(path, entry.depth(), entry.file_type().is_dir())
+            {
+                return false;
+            }
+            file_identity(entry).is_none_or(|identity| visited.insert(identity))
+        })
+        .filter_map(|e| e.ok())
+        // Without --follow-symlinks, links are entries of their own and left out here
+        .filter(|entry| entry.file_type().is_file())
+}
 
-    // Extract text from all resources
-    extract_resources(&mut doc, &mut text);
+/// What stays the same across every link to a file: its device and inode.
+#[cfg(unix)]
+type FileIdentity = (u64, u64);
+/// What stays the same across every link to a file: its canonical path.
+#[cfg(not(unix))]
+type FileIdentity = PathBuf;
 
-    // Generate output file path and write
-    let output_path = generate_output_path(epub_path, output_dir, "txt")?;
-    fs::write(&output_path, text).context(format!(
-        "Failed to write output file: {}",
-        output_path.display()
-    ))?;
+#[cfg(unix)]
+fn file_identity(entry: &DirEntry) -> Option<FileIdentity> {
+    use std::os::unix::fs::MetadataExt;
+    let metadata = entry.metadata().ok()?;
+    Some((metadata.dev(), metadata.ino()))
+}
 
-    Ok(output_path)
+#[cfg(not(unix))]
+fn file_identity(entry: &DirEntry) -> Option<FileIdentity> {
+    fs::canonicalize(entry.path()).ok()
 }
 
-fn append_metadata(doc: &epub::doc::EpubDoc<std::io::BufReader<std::fs::File>>, text: &mut String) {
-    if let Some(title) = doc.mdata("title") {
-        text.push_str("Title: ");
-        text.push_str(&title);
-        text.push('\n');
+/// `path` relative to the `target` it was found under, or its file name when it is the target.
+fn relative_path<'a>(path: &'a Path, target: &Path) -> &'a Path {
+    match path.strip_prefix(target) {
+        Ok(relative) if !relative.as_os_str().is_empty() => relative,
+        _ => path.file_name().map_or(path, Path::new),
+    }
+}
+
+fn run_ingest(args: &IngestArgs) -> Result<()> {
+    if !args.downloads.is_dir() {
+        anyhow::bail!(
+            "Downloads directory does not exist: {}",
+            args.downloads.display()
+        );
+    }
+
+    fs::create_dir_all(&args.output).context(format!(
+        "Failed to create output directory: {}",
+        args.output.display()
+    ))?;
+
+    let options = ingest::IngestOptions {
+        downloads: args.downloads.clone(),
+        output: args.output.clone(),
+        archive: args
+            .archive
+            .clone()
+            .unwrap_or_else(|| args.downloads.join("archive")),
+        ledger: args
+            .ledger
+            .clone()
+            .unwrap_or_else(|| args.output.join("ledger.jsonl")),
+        settle: Duration::from_secs(args.settle_secs),
+    };
+
+    println!("Ingesting downloads from: {}", options.downloads.display());
+    println!("Output directory: {}", options.output.display());
+    println!();
+
+    let extract_options = args.processing.to_options()?;
+    let run = OutputRun::default();
+    let summary = ingest::ingest(&options, |file| {
+        write_source(file, &options.output, &extract_options, &run, None)
+            .map(|written| (written.path, written.provenance))
+    })?;
+    output::finish_outputs()?;
+
+    print_summary(summary.processed, summary.errors);
+    if summary.pending > 0 {
+        println!("  Still downloading: {}", summary.pending);
     }
-    if let Some(creator) = doc.mdata("creator") {
-        text.push_str("Author: ");
-        text.push_str(&creator);
-        text.push('\n');
+
+    Ok(())
+}
+
+/// Where and how the files of one run are extracted.
+struct Run<'a> {
+    output: &'a Path,
+    options: &'a ExtractOptions,
+    /// Leave files alone whose output is already up to date
+    skip_existing: bool,
+    /// Outputs with fewer letters and digits than this are flagged
+    min_chars: usize,
+    /// Move flagged outputs into the quarantine folder
+    quarantine: bool,
+    /// Search engine index each saved document is also sent to
+    push: Option<&'a Pusher>,
+    /// Manifest each file is recorded in
+    manifest: Option<&'a Manifest>,
+}
+
+/// What became of one file.
+enum Outcome {
+    Saved,
+    /// Saved, but with less text than `--min-chars`
+    LowText,
+    Skipped,
+    Failed,
+    /// Failed because no password given opens the PDF
+    Locked,
+}
+
+impl Outcome {
+    fn label(&self) -> &'static str {
+        match self {
+            Outcome::Saved => "saved",
+            Outcome::LowText => "low-text",
+            Outcome::Skipped => "skipped",
+            Outcome::Failed => "failed",
+            Outcome::Locked => "locked",
+        }
     }
-    text.push('\n');
-    text.push_str("=".repeat(80).as_str());
-    text.push_str("\n\n");
 }
 
-fn extract_resources(
-    doc: &mut epub::doc::EpubDoc<std::io::BufReader<std::fs::File>>,
-    text: &mut String,
-) {
-    let resources = doc.resources.clone();
+/// One file extracted, for the summary and the manifest.
+struct Processed {
+    outcome: Outcome,
+    /// Characters its output encoding could not represent
+    unmappable: usize,
+    /// Where its output went
+    output: Option<PathBuf>,
+    error: Option<String>,
+    /// Words in the extracted text
+    words: Option<usize>,
+    /// Digest of the source as it was extracted
+    source_sha256: Option<String>,
+    elapsed: Duration,
+}
 
-    for (resource_id, (file_path, mime_type)) in resources.iter() {
-        if is_html_content(mime_type, &file_path.to_string_lossy()) {
-            if let Some((content, _)) = doc.get_resource_str(resource_id) {
-                let cleaned = strip_html_tags(&content);
-                text.push_str(&cleaned);
-                text.push_str("\n\n");
-            }
+impl Processed {
+    fn skipped() -> Processed {
+        Processed {
+            outcome: Outcome::Skipped,
+            unmappable: 0,
+            output: None,
+            error: None,
+            words: None,
+            source_sha256: None,
+            elapsed: Duration::ZERO,
         }
     }
+
+    /// A file whose extraction panicked, with the panic's message.
+    fn panicked(payload: &(dyn std::any::Any + Send)) -> Processed {
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|message| message.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_default();
+        Processed {
+            outcome: Outcome::Failed,
+            unmappable: 0,
+            output: None,
+            error: Some(format!("Extraction panicked: {message}")),
+            words: None,
+            source_sha256: None,
+            elapsed: Duration::ZERO,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Counts {
+    /// Files saved, including those with little text
+    saved: usize,
+    skipped: usize,
+    failed: usize,
+    /// Sources whose output has less text than `--min-chars`
+    low_text: Vec<PathBuf>,
+    /// Encrypted PDFs that no password opened, counted as failed too
+    locked: Vec<PathBuf>,
+    /// Sources whose output lost characters the output encoding cannot represent, with how
+    /// many
+    unmappable: Vec<(PathBuf, usize)>,
+    /// What became of each file, in discovery order
+    files: Vec<manifest::Entry>,
+    /// Sources whose output was given a name of its own, with the output path each got
+    renamed: Vec<(PathBuf, PathBuf)>,
 }
 
-fn is_html_content(mime_type: &str, path_str: &str) -> bool {
-    mime_type.starts_with("application/xhtml")
-        || mime_type.starts_with("text/html")
-        || path_str.ends_with(".xhtml")
-        || path_str.ends_with(".html")
+/// Extracts `files` on `jobs` worker threads, counting what became of them.
+fn process_files(files: &[SourceFile], run: &Run, jobs: usize) -> Result<Counts> {
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(jobs)
+        .build()
+        .context("Failed to start worker threads")?;
+
+    // Sources of the same name are told apart in discovery order, whichever finishes first
+    let outputs = OutputRun::default();
+    outputs.reserve(
+        files.iter().map(|file| file.path.as_path()),
+        run.output,
+        &run.options.output,
+    );
+
+    let progress = progress_bar(files.len());
+    // Each worker takes the next file in discovery order, so a record waiting for its turn at
+    // a shared output only ever waits on files already being extracted
+    let next = AtomicUsize::new(0);
+    let slots: Vec<Mutex<Option<Processed>>> = files.iter().map(|_| Mutex::new(None)).collect();
+    pool.scope(|scope| {
+        for _ in 0..jobs {
+            scope.spawn(|_| loop {
+                let position = next.fetch_add(1, Ordering::Relaxed);
+                let Some(file) = files.get(position) else {
+                    break;
+                };
+                progress.set_message(file.path.display().to_string());
+                // A file that panics is failed on its own rather than ending the run
+                let outcome = panic::catch_unwind(AssertUnwindSafe(|| {
+                    process_file(file, position, run, &outputs, &progress)
+                }))
+                .unwrap_or_else(|payload| {
+                    let processed = Processed::panicked(&*payload);
+                    progress.suspend(|| {
+                        eprintln!(
+                            "Processing {}: {}\n  -> Error: {}",
+                            file.extension.to_uppercase(),
+                            file.path.display(),
+                            processed.error.as_deref().unwrap_or_default()
+                        )
+                    });
+                    processed
+                });
+                outputs.source_done(position);
+                progress.inc(1);
+                *slots[position].lock().unwrap_or_else(|e| e.into_inner()) = Some(outcome);
+            });
+        }
+    });
+    let outcomes: Vec<Processed> = slots
+        .into_iter()
+        .map(|slot| {
+            slot.into_inner()
+                .unwrap_or_else(|e| e.into_inner())
+                .unwrap_or_else(Processed::skipped)
+        })
+        .collect();
+    progress.finish_and_clear();
+    // Documents extracted since are searchable once the round is done, not when a batch fills
+    if let Some(push) = run.push {
+        if let Err(e) = push.flush() {
+            eprintln!("Error: {e:#}");
+        }
+    }
+
+    // Documents added to a shared file have no size of their own
+    let output_options = &run.options.output;
+    let shared = output_options.format.is_collection() || output_options.combine.is_some();
+    let entries: Vec<manifest::Entry> = files
+        .iter()
+        .zip(&outcomes)
+        .map(|(file, processed)| {
+            let mut entry = manifest::Entry::new(&file.path, processed.outcome.label());
+            if let Some(output) = &processed.output {
+                entry.output = output.to_string_lossy().into_owned();
+                if !shared {
+                    entry.output_bytes = manifest::output_size(output);
+                }
+            }
+            entry.error = processed.error.clone().unwrap_or_default();
+            entry.words = processed.words;
+            entry.source_sha256 = processed.source_sha256.clone().unwrap_or_default();
+            entry.duration_ms = processed.elapsed.as_millis() as u64;
+            entry
+        })
+        .collect();
+    if let Some(manifest) = run.manifest {
+        manifest.record(&entries)?;
+    }
+
+    let mut counts = Counts {
+        files: entries,
+        renamed: outputs.renamed(),
+        ..Counts::default()
+    };
+    for (file, processed) in files.iter().zip(outcomes) {
+        if processed.unmappable > 0 {
+            counts
+                .unmappable
+                .push((file.path.clone(), processed.unmappable));
+        }
+        match processed.outcome {
+            Outcome::Saved => counts.saved += 1,
+            Outcome::LowText => {
+                counts.saved += 1;
+                counts.low_text.push(file.path.clone());
+            }
+            Outcome::Skipped => counts.skipped += 1,
+            Outcome::Failed => counts.failed += 1,
+            Outcome::Locked => {
+                counts.failed += 1;
+                counts.locked.push(file.path.clone());
+            }
+        }
+    }
+    Ok(counts)
 }
 
-fn strip_html_tags(html: &str) -> String {
-    let raw_text = extract_text_from_html(html);
-    clean_whitespace(&raw_text)
+/// A bar showing files done out of those discovered, the file being extracted and the time
+/// left; hidden when stdout is not a terminal, so logs and pipes only get the file lines.
+fn progress_bar(total: usize) -> ProgressBar {
+    if !std::io::stdout().is_terminal() {
+        return ProgressBar::hidden();
+    }
+    let style = ProgressStyle::with_template(
+        "[{elapsed_precise}] {bar:30} {pos}/{len} files, ETA {eta} {wide_msg}",
+    )
+    .unwrap_or_else(|_| ProgressStyle::default_bar());
+    ProgressBar::with_draw_target(Some(total as u64), ProgressDrawTarget::stdout())
+        .with_style(style)
 }
 
-fn extract_text_from_html(html: &str) -> String {
-    let mut result = String::new();
-    let mut in_tag = false;
-    let mut in_script_style = false;
-    let mut tag_name = String::new();
+/// Extracts one file, returning what became of it.
+fn process_file(
+    file: &SourceFile,
+    position: usize,
+    run: &Run,
+    outputs: &OutputRun,
+    progress: &ProgressBar,
+) -> Processed {
+    if run.skip_existing && is_up_to_date(file, run.output, run.options, outputs) {
+        progress.suspend(|| println!("Skipping (up to date): {}", file.path.display()));
+        return Processed::skipped();
+    }
+    let output_options = &run.options.output;
+    if output_options.on_conflict == OnConflict::Skip {
+        // Outputs named by a template are only found once the file is extracted
+        if output::existing_output(&file.path, run.output, output_options, outputs).is_some() {
+            progress.suspend(|| println!("Skipping (output exists): {}", file.path.display()));
+            return Processed::skipped();
+        }
+    }
+    let started = Instant::now();
+    let result = write_source(file, run.output, run.options, outputs, Some(position));
+    let elapsed = started.elapsed();
+    let unmappable = result.as_ref().map_or(0, |written| written.unmappable);
+    let words = result
+        .as_ref()
+        .ok()
+        .map(|written| written.document.word_count());
+    let source_sha256 = result
+        .as_ref()
+        .ok()
+        .map(|written| written.provenance.source_sha256.clone());
+    if let (Some(push), Ok(written)) = (run.push, &result) {
+        // Quarantined documents are kept out of the index as they are out of the output
+        if !(run.quarantine && written.text_chars < run.min_chars) {
+            if let Err(e) = push.add(&file.path, &written.document, &written.provenance) {
+                progress.suspend(|| eprintln!("Error: {e:#}"));
+            }
+        }
+    }
 
-    for ch in html.chars() {
-        if ch == '<' {
-            in_tag = true;
-            tag_name.clear();
-        } else if ch == '>' {
-            in_tag = false;
-            in_script_style = update_script_style_state(&tag_name, in_script_style);
-            tag_name.clear();
-        } else if in_tag {
-            if tag_name.len() < 20 {
-                // Limit tag name length
-                tag_name.push(ch);
+    // Report once the file is done, holding stdout so lines of files finishing at the same
+    // time are not interleaved, and with the progress bar cleared while they are printed
+    let (outcome, output, error) = progress.suspend(|| {
+        let mut stdout = std::io::stdout().lock();
+        let _ = writeln!(
+            stdout,
+            "Processing {}: {}",
+            file.extension.to_uppercase(),
+            file.path.display()
+        );
+        match result {
+            Ok(written) if written.text_chars < run.min_chars => {
+                let moved = if run.quarantine {
+                    output::quarantine(&written.path, run.output)
+                } else {
+                    Ok(written.path.clone())
+                };
+                match moved {
+                    Ok(path) => {
+                        let _ = writeln!(
+                            stdout,
+                            "  -> Saved to: {} (only {} characters of text)",
+                            path.display(),
+                            written.text_chars
+                        );
+                        (Outcome::LowText, Some(path), None)
+                    }
+                    Err(e) => {
+                        let _ = stdout.flush();
+                        eprintln!("  -> Error: {:#}", e);
+                        (Outcome::Failed, Some(written.path), Some(format!("{e:#}")))
+                    }
+                }
+            }
+            Ok(written) => {
+                let _ = writeln!(stdout, "  -> Saved to: {}", written.path.display());
+                (Outcome::Saved, Some(written.path), None)
+            }
+            Err(e)
+                if output_options.on_conflict == OnConflict::Skip
+                    && e.chain().any(|cause| cause.is::<OutputExists>()) =>
+            {
+                let _ = writeln!(stdout, "  -> Skipped: {}", e);
+                (Outcome::Skipped, None, None)
+            }
+            Err(e) => {
+                let _ = stdout.flush();
+                eprintln!("  -> Error: {}", e);
+                let outcome = if e.chain().any(|cause| cause.is::<pdf::LockedPdf>()) {
+                    Outcome::Locked
+                } else {
+                    Outcome::Failed
+                };
+                (outcome, None, Some(format!("{e:#}")))
             }
-        } else if !in_script_style {
-            result.push(ch);
         }
+    });
+    Processed {
+        outcome,
+        unmappable,
+        output,
+        error,
+        words,
+        source_sha256,
+        elapsed,
     }
+}
+
+fn print_summary(processed_count: usize, error_count: usize) {
+    println!();
+    println!("Summary:");
+    println!("  Successfully processed: {}", processed_count);
+    println!("  Errors: {}", error_count);
+}
 
-    result
+/// The run summary `--summary-json` writes: the counts and lists the summary prints, the
+/// result of each file and how long the run took.
+fn summary_json(
+    counts: &Counts,
+    discovery_failed: usize,
+    run: &Run,
+    elapsed: Duration,
+) -> serde_json::Value {
+    let unmappable: Vec<serde_json::Value> = counts
+        .unmappable
+        .iter()
+        .map(|(source, characters)| serde_json::json!({"source": source, "characters": characters}))
+        .collect();
+    let renamed: Vec<serde_json::Value> = counts
+        .renamed
+        .iter()
+        .map(|(source, output)| serde_json::json!({"source": source, "output": output}))
+        .collect();
+    let indexed = run.push.map(|pusher| {
+        serde_json::json!({"url": pusher.url(), "indexed": pusher.indexed(), "failed": pusher.failed()})
+    });
+    serde_json::json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "output": run.output,
+        "processed": counts.saved,
+        "errors": counts.failed + discovery_failed,
+        "skipped": counts.skipped,
+        "low_text": counts.low_text,
+        "locked": counts.locked,
+        "unmappable": unmappable,
+        "renamed": renamed,
+        "indexed": indexed,
+        "duration_ms": elapsed.as_millis() as u64,
+        "files": counts.files,
+    })
 }
 
-fn update_script_style_state(tag_name: &str, current_state: bool) -> bool {
-    let tag_lower = tag_name.to_lowercase();
-    if tag_lower == "script" || tag_lower == "style" {
-        true
-    } else if tag_lower == "/script" || tag_lower == "/style" {
-        false
-    } else {
-        current_state
+/// Lists the encrypted PDFs that none of the passwords given opened.
+fn print_locked(sources: &[PathBuf]) {
+    if sources.is_empty() {
+        return;
+    }
+    let files = if sources.len() == 1 { "PDF" } else { "PDFs" };
+    println!();
+    println!(
+        "{} {files} remained locked (try --pdf-password or --pdf-password-file):",
+        sources.len()
+    );
+    for source in sources {
+        println!("  {}", source.display());
     }
 }
 
-fn clean_whitespace(text: &str) -> String {
-    text.split('\n')
-        .map(|line| line.trim())
-        .filter(|line| !line.is_empty())
-        .collect::<Vec<_>>()
-        .join("\n")
+/// Lists the files whose output lost characters to `--output-encoding`.
+fn print_unmappable(sources: &[(PathBuf, usize)], encoding: OutputEncoding) {
+    if sources.is_empty() {
+        return;
+    }
+    let files = if sources.len() == 1 { "file" } else { "files" };
+    let total: usize = sources.iter().map(|(_, count)| count).sum();
+    println!();
+    println!(
+        "{} {files} had {total} characters that {} cannot represent, written as ?:",
+        sources.len(),
+        encoding.label()
+    );
+    for (source, count) in sources {
+        println!("  {} ({count})", source.display());
+    }
 }
 
-fn generate_output_path(input_path: &Path, output_dir: &Path, extension: &str) -> Result<PathBuf> {
-    let file_stem = input_path
-        .file_stem()
-        .context("Failed to get file stem")?
-        .to_string_lossy();
+/// Lists the sources whose output was renamed because an earlier source of the same name has
+/// the plain one.
+fn print_renamed(renamed: &[(PathBuf, PathBuf)]) {
+    if renamed.is_empty() {
+        return;
+    }
+    let (files, were, their) = if renamed.len() == 1 {
+        ("file", "was", "its")
+    } else {
+        ("files", "were", "their")
+    };
+    println!();
+    println!(
+        "{} {files} shared a name with another and {were} given a name of {their} own:",
+        renamed.len()
+    );
+    for (source, output) in renamed {
+        println!("  {} -> {}", source.display(), output.display());
+    }
+}
 
-    let output_filename = format!("{}.{}", file_stem, extension);
-    Ok(output_dir.join(output_filename))
+/// Lists the files that came out with little or no text, which usually need OCR.
+fn print_low_text(sources: &[PathBuf], run: &Run) {
+    if sources.is_empty() {
+        return;
+    }
+    let files = if sources.len() == 1 { "file" } else { "files" };
+    println!();
+    if run.min_chars <= 1 {
+        println!(
+            "{} {files} produced no text — likely scanned (try --ocr auto):",
+            sources.len()
+        );
+    } else {
+        println!(
+            "{} {files} produced fewer than {} characters of text — likely scanned (try --ocr auto):",
+            sources.len(),
+            run.min_chars
+        );
+    }
+    for source in sources {
+        println!("  {}", source.display());
+    }
+    if run.quarantine {
+        println!(
+            "Their outputs were moved to: {}",
+            run.output.join(output::QUARANTINE_DIR).display()
+        );
+    }
 }
@@ -0,0 +1,115 @@
+//! The `manifest.csv` a run writes to its output folder: one row per file with where its
+//! output went, what became of it, its size and SHA-256, word count and how long it took, for
+//! scripts that would otherwise parse the log.
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// File the manifest is written to in the output folder.
+pub const MANIFEST_FILE: &str = "manifest.csv";
+
+/// Columns of the manifest, the fields of [`Entry`].
+const HEADER: [&str; 9] = [
+    "source",
+    "output",
+    "status",
+    "error",
+    "source_bytes",
+    "source_sha256",
+    "output_bytes",
+    "words",
+    "duration_ms",
+];
+
+/// One file of a run.
+#[derive(Debug, Clone, Serialize)]
+pub struct Entry {
+    pub source: String,
+    /// The output file or folder of parts; empty when nothing was written
+    pub output: String,
+    /// `saved`, `low-text`, `skipped`, `failed` or `locked`
+    pub status: &'static str,
+    pub error: String,
+    pub source_bytes: Option<u64>,
+    /// Digest of the source as it was extracted; empty when it was not
+    pub source_sha256: String,
+    /// Bytes written; empty for documents added to a shared corpus, database or combined file
+    pub output_bytes: Option<u64>,
+    pub words: Option<usize>,
+    pub duration_ms: u64,
+}
+
+impl Entry {
+    pub fn new(source: &Path, status: &'static str) -> Entry {
+        Entry {
+            source: source.to_string_lossy().into_owned(),
+            output: String::new(),
+            status,
+            error: String::new(),
+            source_bytes: source.metadata().ok().map(|m| m.len()),
+            source_sha256: String::new(),
+            output_bytes: None,
+            words: None,
+            duration_ms: 0,
+        }
+    }
+}
+
+/// A manifest open for adding rows. Rows of files extracted at once may be added from
+/// several threads.
+pub struct Manifest {
+    path: PathBuf,
+    writer: Mutex<csv::Writer<File>>,
+}
+
+impl Manifest {
+    /// Creates the manifest at `path`, replacing the one an earlier run left there.
+    pub fn create(path: &Path) -> Result<Manifest> {
+        let failed = || format!("Failed to create manifest: {}", path.display());
+        // The header is written even when no file is extracted
+        let mut writer = csv::WriterBuilder::new()
+            .has_headers(false)
+            .from_path(path)
+            .with_context(failed)?;
+        writer.write_record(HEADER).with_context(failed)?;
+        writer.flush().with_context(failed)?;
+        Ok(Manifest {
+            path: path.to_path_buf(),
+            writer: Mutex::new(writer),
+        })
+    }
+
+    /// Adds `entries` and writes them out, so the manifest is complete as far as it goes even
+    /// if the run is stopped.
+    pub fn record(&self, entries: &[Entry]) -> Result<()> {
+        let mut writer = self.writer.lock().unwrap_or_else(|e| e.into_inner());
+        let failed = || format!("Failed to write manifest: {}", self.path.display());
+        for entry in entries {
+            writer.serialize(entry).with_context(failed)?;
+        }
+        writer.flush().with_context(failed)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// Bytes in `output`: the file, or the files in a folder of parts.
+pub fn output_size(output: &Path) -> Option<u64> {
+    let metadata = output.metadata().ok()?;
+    if metadata.is_file() {
+        return Some(metadata.len());
+    }
+    let parts = std::fs::read_dir(output).ok()?;
+    Some(
+        parts
+            .filter_map(|part| part.ok()?.metadata().ok())
+            .filter(|metadata| metadata.is_file())
+            .map(|metadata| metadata.len())
+            .sum(),
+    )
+}
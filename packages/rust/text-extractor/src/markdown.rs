@@ -0,0 +1,87 @@
+//! GitHub-flavoured Markdown rendering, with metadata as YAML front matter.
+
+use crate::document::{Document, SectionKind, CODE_FENCE};
+use crate::tables;
+use crate::wrap::{self, Wrap};
+
+pub fn render(doc: &Document, wrap: Wrap) -> String {
+    let mut out = String::new();
+
+    if !doc.metadata.is_empty() {
+        out.push_str("---\n");
+        for (key, value) in [
+            ("title", &doc.metadata.title),
+            ("author", &doc.metadata.author),
+            ("language", &doc.metadata.language),
+            ("collection", &doc.metadata.collection),
+        ] {
+            if let Some(value) = value {
+                // A JSON string is a valid double-quoted YAML scalar
+                out.push_str(&format!(
+                    "{key}: {}\n",
+                    serde_json::Value::from(value.as_str())
+                ));
+            }
+        }
+        out.push_str("---\n\n");
+    }
+
+    let mut pages = 0;
+    for section in &doc.sections {
+        // A rule marks each PDF page break
+        if section.kind == SectionKind::Page {
+            if pages > 0 {
+                out.push_str("---\n\n");
+            }
+            pages += 1;
+        }
+
+        if let Some(title) = &section.title {
+            out.push_str("## ");
+            out.push_str(title);
+            out.push_str("\n\n");
+        }
+
+        let mut paragraphs = section.paragraphs().into_iter().peekable();
+        while let Some(paragraph) = paragraphs.next() {
+            let verbatim = paragraph.starts_with(CODE_FENCE)
+                || tables::is_markdown_table(&paragraph)
+                || is_heading(&paragraph);
+            if verbatim {
+                out.push_str(&paragraph);
+                out.push('\n');
+            } else if let Wrap::Width(width) = wrap {
+                for line in wrap::wrap_paragraph(&escape_block(&paragraph), width) {
+                    out.push_str(&line);
+                    out.push('\n');
+                }
+            } else {
+                out.push_str(&escape_block(&paragraph));
+                out.push('\n');
+            }
+            // Items of one list stay together
+            let item = |paragraph: &String| paragraph.starts_with("- ");
+            if !(item(&paragraph) && paragraphs.peek().is_some_and(item)) {
+                out.push('\n');
+            }
+        }
+    }
+
+    out
+}
+
+/// Escapes a paragraph that would otherwise open a heading or a block quote.
+fn escape_block(paragraph: &str) -> String {
+    if paragraph.starts_with(['#', '>']) {
+        format!("\\{paragraph}")
+    } else {
+        paragraph.to_string()
+    }
+}
+
+/// Whether a paragraph is an ATX heading, `#` to `######` and a space, as EPUB headings are
+/// written.
+fn is_heading(paragraph: &str) -> bool {
+    let marks = paragraph.len() - paragraph.trim_start_matches('#').len();
+    (1..=6).contains(&marks) && paragraph[marks..].starts_with(' ')
+}
@@ -0,0 +1,404 @@
+//! Converts MathML islands in EPUB content documents to inline `$...$` / display `$$...$$` LaTeX.
+
+use roxmltree::{Document, Node};
+
+const MATHML_NS: &str = "http://www.w3.org/1998/Math/MathML";
+
+/// Replaces every `<math>` element in `html` with its LaTeX form, escaped as markup and display
+/// math in a `<div>` of its own. Islands that fail to parse are left for the HTML parser.
+pub fn replace_math(html: &str) -> String {
+    let mut out = String::with_capacity(html.len());
+    let mut rest = html;
+
+    while let Some((start, name)) = find_math_start(rest) {
+        let close = format!("</{name}>");
+        let Some(close_at) = rest[start..].find(&close).map(|i| start + i + close.len()) else {
+            break;
+        };
+
+        out.push_str(&rest[..start]);
+        let island = &rest[start..close_at];
+        match island_to_latex(island, name) {
+            Some((latex, true)) => {
+                out.push_str("<div>$$");
+                out.push_str(&latex);
+                out.push_str("$$</div>");
+            }
+            Some((latex, false)) => {
+                out.push('$');
+                out.push_str(&latex);
+                out.push('$');
+            }
+            None => out.push_str(island),
+        }
+        rest = &rest[close_at..];
+    }
+
+    out.push_str(rest);
+    out
+}
+
+/// Finds the next `<math` or `<prefix:math` start tag, returning its offset and tag name.
+fn find_math_start(html: &str) -> Option<(usize, &str)> {
+    let mut offset = 0;
+    while let Some(i) = html[offset..].find('<') {
+        let start = offset + i;
+        let name_end = html[start + 1..]
+            .find(|c: char| c.is_whitespace() || c == '>' || c == '/')
+            .map(|j| start + 1 + j)?;
+        let name = &html[start + 1..name_end];
+        let local = name.rsplit(':').next().unwrap_or(name);
+        if local.eq_ignore_ascii_case("math") && !html[name_end..].starts_with('/') {
+            return Some((start, name));
+        }
+        offset = start + 1;
+    }
+    None
+}
+
+/// Returns the LaTeX for one island and whether it is display (block) math.
+fn island_to_latex(island: &str, name: &str) -> Option<(String, bool)> {
+    // The prefix is usually declared on the <html> root, which the island no longer has
+    let wrapped = match name.split_once(':') {
+        Some((prefix, _)) => format!("<wrap xmlns:{prefix}=\"{MATHML_NS}\">{island}</wrap>"),
+        None => format!("<wrap>{island}</wrap>"),
+    };
+    let doc = Document::parse(&wrapped).ok()?;
+    let math = doc.root_element().first_element_child()?;
+
+    let display =
+        math.attribute("display") == Some("block") || math.attribute("mode") == Some("display");
+    let latex = tex_annotation(math).unwrap_or_else(|| convert_children(math));
+    let latex = latex
+        .trim()
+        .replace('<', "\\lt ")
+        .replace('>', "\\gt ")
+        .replace('&', "&amp;");
+
+    (!latex.is_empty()).then_some((latex, display))
+}
+
+/// Publishers often embed the original TeX source, which beats anything we can reconstruct.
+fn tex_annotation(math: Node) -> Option<String> {
+    math.descendants()
+        .find(|n| {
+            n.tag_name().name() == "annotation"
+                && n.attribute("encoding")
+                    .is_some_and(|e| e.eq_ignore_ascii_case("application/x-tex"))
+        })
+        .and_then(|n| n.text())
+        .map(str::to_string)
+}
+
+fn convert(node: Node) -> String {
+    let children: Vec<Node> = node.children().filter(Node::is_element).collect();
+    let arg = |i: usize| children.get(i).map(|n| convert(*n)).unwrap_or_default();
+
+    match node.tag_name().name() {
+        "mi" => identifier(&text_of(node)),
+        "mn" => text_of(node),
+        "mo" => operator(&text_of(node)),
+        "mtext" => {
+            let text = text_of(node);
+            if text.trim().is_empty() {
+                " ".to_string()
+            } else {
+                format!("\\text{{{text}}}")
+            }
+        }
+        "ms" => format!("\\text{{\"{}\"}}", text_of(node)),
+        "mspace" => " ".to_string(),
+        "mfrac" => format!("\\frac{{{}}}{{{}}}", arg(0), arg(1)),
+        "msqrt" => format!("\\sqrt{{{}}}", convert_children(node)),
+        "mroot" => format!("\\sqrt[{}]{{{}}}", arg(1), arg(0)),
+        "msup" => format!("{}^{}", group(&arg(0)), group(&arg(1))),
+        "msub" => format!("{}_{}", group(&arg(0)), group(&arg(1))),
+        "msubsup" | "munderover" => {
+            format!("{}_{}^{}", group(&arg(0)), group(&arg(1)), group(&arg(2)))
+        }
+        "munder" => format!("{}_{}", group(&arg(0)), group(&arg(1))),
+        "mover" => over(&arg(0), &arg(1)),
+        "mfenced" => {
+            let open = node.attribute("open").unwrap_or("(");
+            let close = node.attribute("close").unwrap_or(")");
+            let separator = node.attribute("separators").unwrap_or(",");
+            let items: Vec<String> = children.iter().map(|n| convert(*n)).collect();
+            format!(
+                "\\left{}{}\\right{}",
+                fence(open),
+                items.join(separator.trim()),
+                fence(close)
+            )
+        }
+        "mtable" => {
+            let rows: Vec<String> = children
+                .iter()
+                .map(|row| {
+                    row.children()
+                        .filter(Node::is_element)
+                        .map(convert)
+                        .collect::<Vec<_>>()
+                        .join(" & ")
+                })
+                .collect();
+            format!("\\begin{{matrix}}{}\\end{{matrix}}", rows.join(" \\\\ "))
+        }
+        "mphantom" | "annotation" | "annotation-xml" | "none" => String::new(),
+        "semantics" => arg(0),
+        // math, mrow, mstyle, mpadded, menclose, mtd and anything unknown
+        _ => convert_children(node),
+    }
+}
+
+fn convert_children(node: Node) -> String {
+    let mut out = String::new();
+    for child in node.children().filter(Node::is_element) {
+        push_token(&mut out, &convert(child));
+    }
+    out
+}
+
+/// Appends `token`, separating it from a preceding control word (`\alpha x`, not `\alphax`).
+fn push_token(out: &mut String, token: &str) {
+    let ends_in_command = out
+        .rsplit_once('\\')
+        .is_some_and(|(_, tail)| !tail.is_empty() && tail.chars().all(|c| c.is_ascii_alphabetic()));
+    if ends_in_command && token.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        out.push(' ');
+    }
+    out.push_str(token);
+}
+
+fn text_of(node: Node) -> String {
+    node.descendants()
+        .filter(Node::is_text)
+        .filter_map(|n| n.text())
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+/// Wraps arguments of `^` and `_` in braces unless they are a single character or command.
+fn group(latex: &str) -> String {
+    let is_command = latex
+        .strip_prefix('\\')
+        .is_some_and(|name| !name.is_empty() && name.chars().all(|c| c.is_ascii_alphabetic()));
+    if latex.chars().count() == 1 || is_command {
+        latex.to_string()
+    } else {
+        format!("{{{latex}}}")
+    }
+}
+
+fn identifier(text: &str) -> String {
+    const FUNCTIONS: [&str; 14] = [
+        "sin", "cos", "tan", "cot", "sec", "csc", "log", "ln", "exp", "lim", "max", "min", "det",
+        "sup",
+    ];
+    if FUNCTIONS.contains(&text) {
+        return format!("\\{text}");
+    }
+    text.chars().map(symbol).collect()
+}
+
+fn operator(text: &str) -> String {
+    text.chars().map(symbol).collect()
+}
+
+/// LaTeX for a single character of identifier or operator text.
+fn symbol(ch: char) -> String {
+    let command = match ch {
+        'α' => "\\alpha",
+        'β' => "\\beta",
+        'γ' => "\\gamma",
+        'δ' => "\\delta",
+        'ε' | 'ϵ' => "\\epsilon",
+        'ζ' => "\\zeta",
+        'η' => "\\eta",
+        'θ' => "\\theta",
+        'ι' => "\\iota",
+        'κ' => "\\kappa",
+        'λ' => "\\lambda",
+        'μ' => "\\mu",
+        'ν' => "\\nu",
+        'ξ' => "\\xi",
+        'π' => "\\pi",
+        'ρ' => "\\rho",
+        'σ' => "\\sigma",
+        'τ' => "\\tau",
+        'φ' | 'ϕ' => "\\phi",
+        'χ' => "\\chi",
+        'ψ' => "\\psi",
+        'ω' => "\\omega",
+        'Γ' => "\\Gamma",
+        'Δ' => "\\Delta",
+        'Θ' => "\\Theta",
+        'Λ' => "\\Lambda",
+        'Π' => "\\Pi",
+        'Σ' => "\\Sigma",
+        'Φ' => "\\Phi",
+        'Ψ' => "\\Psi",
+        'Ω' => "\\Omega",
+        '∑' => "\\sum",
+        '∏' => "\\prod",
+        '∫' => "\\int",
+        '∮' => "\\oint",
+        '∂' => "\\partial",
+        '∇' => "\\nabla",
+        '∞' => "\\infty",
+        '±' => "\\pm",
+        '∓' => "\\mp",
+        '×' => "\\times",
+        '÷' => "\\div",
+        '⋅' | '·' => "\\cdot",
+        '∘' => "\\circ",
+        '≤' => "\\leq",
+        '≥' => "\\geq",
+        '≠' => "\\neq",
+        '≈' => "\\approx",
+        '≡' => "\\equiv",
+        '∼' => "\\sim",
+        '∝' => "\\propto",
+        '∈' => "\\in",
+        '∉' => "\\notin",
+        '⊂' => "\\subset",
+        '⊆' => "\\subseteq",
+        '∪' => "\\cup",
+        '∩' => "\\cap",
+        '∅' => "\\emptyset",
+        '∀' => "\\forall",
+        '∃' => "\\exists",
+        '¬' => "\\neg",
+        '∧' => "\\wedge",
+        '∨' => "\\vee",
+        '→' => "\\to",
+        '←' => "\\leftarrow",
+        '⇒' => "\\Rightarrow",
+        '⇔' => "\\Leftrightarrow",
+        '…' => "\\ldots",
+        '⋯' => "\\cdots",
+        '−' => "-",
+        '′' => "'",
+        '\u{2061}' | '\u{2062}' | '\u{2063}' => "",
+        '{' => "\\{",
+        '}' => "\\}",
+        '%' => "\\%",
+        '#' => "\\#",
+        _ => return ch.to_string(),
+    };
+    command.to_string()
+}
+
+fn fence(delimiter: &str) -> &str {
+    match delimiter {
+        "" => ".",
+        "{" => "\\{",
+        "}" => "\\}",
+        "‖" => "\\|",
+        "⟨" => "\\langle",
+        "⟩" => "\\rangle",
+        _ => delimiter,
+    }
+}
+
+fn over(base: &str, accent: &str) -> String {
+    match accent {
+        "¯" | "‾" | "_" => format!("\\overline{{{base}}}"),
+        "^" | "ˆ" => format!("\\hat{{{base}}}"),
+        "~" | "˜" => format!("\\tilde{{{base}}}"),
+        "\\to" | "⃗" => format!("\\vec{{{base}}}"),
+        "˙" | "." => format!("\\dot{{{base}}}"),
+        _ => format!("\\overset{{{accent}}}{{{base}}}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn latex(mathml: &str) -> String {
+        island_to_latex(mathml, "math").unwrap().0
+    }
+
+    #[test]
+    fn inline_and_display_islands() {
+        let html = r#"<p>Let <math><mi>x</mi><mo>=</mo><mn>2</mn></math> hold.</p><math display="block"><mi>y</mi></math>"#;
+        assert_eq!(replace_math(html), "<p>Let $x=2$ hold.</p><div>$$y$$</div>");
+        let html = r#"<m:math xmlns:m="http://www.w3.org/1998/Math/MathML" mode="display"><m:mi>π</m:mi></m:math>"#;
+        assert_eq!(replace_math(html), "<div>$$\\pi$$</div>");
+    }
+
+    #[test]
+    fn scripts_fractions_and_roots() {
+        assert_eq!(
+            latex("<math><msup><mi>x</mi><mn>2</mn></msup></math>"),
+            "x^2"
+        );
+        assert_eq!(
+            latex("<math><msubsup><mo>∑</mo><mrow><mi>i</mi><mo>=</mo><mn>1</mn></mrow><mi>n</mi></msubsup></math>"),
+            "\\sum_{i=1}^n"
+        );
+        assert_eq!(
+            latex(
+                "<math><mfrac><mn>1</mn><mrow><mi>a</mi><mo>+</mo><mi>b</mi></mrow></mfrac></math>"
+            ),
+            "\\frac{1}{a+b}"
+        );
+        assert_eq!(
+            latex("<math><mroot><mi>x</mi><mn>3</mn></mroot><msqrt><mi>y</mi></msqrt></math>"),
+            "\\sqrt[3]{x}\\sqrt{y}"
+        );
+        assert_eq!(
+            latex("<math><mover><mi>v</mi><mo>→</mo></mover><mover><mi>z</mi><mo>¯</mo></mover></math>"),
+            "\\vec{v}\\overline{z}"
+        );
+    }
+
+    #[test]
+    fn symbols_functions_and_fences() {
+        assert_eq!(
+            latex("<math><mi>sin</mi><mi>α</mi><mi>x</mi></math>"),
+            "\\sin\\alpha x"
+        );
+        assert_eq!(
+            latex(r#"<math><mfenced open="{" close=""><mi>a</mi><mi>b</mi></mfenced></math>"#),
+            "\\left\\{a,b\\right."
+        );
+        assert_eq!(
+            latex("<math><mtable><mtr><mtd><mn>1</mn></mtd><mtd><mn>0</mn></mtd></mtr><mtr><mtd><mn>0</mn></mtd><mtd><mn>1</mn></mtd></mtr></mtable></math>"),
+            // Escaped for the HTML the island goes back into
+            "\\begin{matrix}1 &amp; 0 \\\\ 0 &amp; 1\\end{matrix}"
+        );
+        assert_eq!(
+            latex("<math><mtext>if</mtext><mspace/><mphantom><mi>q</mi></mphantom></math>"),
+            "\\text{if}"
+        );
+    }
+
+    #[test]
+    fn tex_annotations_are_preferred() {
+        let mathml = r#"<math><semantics><mi>x</mi><annotation encoding="application/x-tex">x_{0} &lt; 1</annotation></semantics></math>"#;
+        assert_eq!(replace_math(mathml), "$x_{0} \\lt  1$");
+        let mathml = r#"<math><semantics><mi>x</mi><annotation encoding="text/plain">ignored</annotation></semantics></math>"#;
+        assert_eq!(replace_math(mathml), "$x$");
+    }
+
+    #[test]
+    fn malformed_islands_are_left_alone() {
+        // Not well-formed: the HTML parser gets the island back unchanged
+        let html = "<math><mi>x</mo></math> after";
+        assert_eq!(replace_math(html), html);
+        // No closing tag
+        let html = "before <math><mi>x</mi>";
+        assert_eq!(replace_math(html), html);
+        // Empty islands and self-closing tags
+        assert_eq!(replace_math("<math></math>"), "<math></math>");
+        assert_eq!(replace_math("a <math/> b"), "a <math/> b");
+        // Lookalike tags and stray angle brackets
+        assert_eq!(
+            replace_math("<mathx>1 < 2</mathx> <"),
+            "<mathx>1 < 2</mathx> <"
+        );
+        assert_eq!(replace_math("a<b<math><mn>1</mn></math>"), "a<b$1$");
+    }
+}
@@ -0,0 +1,167 @@
+//! Finding where a book's body starts and ends, so front and back matter can be trimmed.
+//!
+//! EPUB landmarks are trusted when present. Otherwise the outline's entry titles are
+//! classified, and documents without an outline fall back to the heading at the top of each
+//! section.
+
+use std::ops::Range;
+
+use crate::document::Document;
+
+/// Titles of sections that come before the body. Forewords, prefaces and introductions are
+/// part of what readers read, so they are not listed.
+const FRONT_MATTER: &[&str] = &[
+    "cover",
+    "title page",
+    "title",
+    "half title",
+    "copyright",
+    "contents",
+    "table of contents",
+    "dedication",
+    "epigraph",
+    "also by",
+    "books by",
+    "praise for",
+    "about this book",
+    "frontispiece",
+    "list of illustrations",
+    "list of figures",
+    "list of tables",
+    "acknowledgments",
+    "acknowledgements",
+];
+
+/// Titles of sections that follow the body.
+const BACK_MATTER: &[&str] = &[
+    "index",
+    "about the author",
+    "about the authors",
+    "about the publisher",
+    "also by",
+    "other books by",
+    "more from",
+    "books by",
+    "acknowledgments",
+    "acknowledgements",
+    "notes",
+    "endnotes",
+    "bibliography",
+    "further reading",
+    "glossary",
+    "colophon",
+    "copyright",
+    "credits",
+    "reading group guide",
+    "discussion questions",
+    "excerpt",
+    "preview",
+    "sneak peek",
+    "coming soon",
+    "newsletter",
+];
+
+/// Openings of the first heading of a body section, for documents without an outline.
+const BODY_HEADINGS: &[&str] = &[
+    "chapter ", "prologue", "part one", "part 1", "part i", "book one", "book 1",
+];
+
+/// The range of sections holding the body, trimmed at the front, the back or both.
+pub fn body(doc: &Document, trim_front: bool, trim_back: bool) -> Range<usize> {
+    let len = doc.sections.len();
+    let start = if trim_front {
+        body_start(doc).unwrap_or(0)
+    } else {
+        0
+    };
+    let end = if trim_back {
+        back_matter_start(doc, start).unwrap_or(len)
+    } else {
+        len
+    };
+    start..end.max(start)
+}
+
+fn body_start(doc: &Document) -> Option<usize> {
+    if let Some(start) = doc.landmarks.body_start {
+        return Some(start);
+    }
+    if !doc.outline.is_empty() {
+        return doc
+            .outline
+            .iter()
+            .find(|entry| !is_titled(&entry.title, FRONT_MATTER))
+            .map(|entry| entry.section);
+    }
+    doc.sections
+        .iter()
+        .position(|section| opens_body(&section.text))
+}
+
+/// The first section of the back matter after `start`: where the trailing run of back-matter
+/// entries begins, so notes or an index in the middle of the book are left alone.
+fn back_matter_start(doc: &Document, start: usize) -> Option<usize> {
+    if let Some(back) = doc.landmarks.back_matter.filter(|&back| back > start) {
+        return Some(back);
+    }
+    if !doc.outline.is_empty() {
+        let entries: Vec<_> = doc
+            .outline
+            .iter()
+            .filter(|entry| entry.section > start && entry.level == 1)
+            .collect();
+        let trailing = entries
+            .iter()
+            .rev()
+            .take_while(|entry| is_titled(&entry.title, BACK_MATTER))
+            .last()?;
+        return Some(trailing.section);
+    }
+
+    // Without an outline only the first page of an index carries its heading, so take the
+    // earliest back-matter heading that no body heading follows
+    let mut back = None;
+    for (i, section) in doc.sections.iter().enumerate().skip(start + 1) {
+        if opens_body(&section.text) {
+            back = None;
+        } else if back.is_none() && is_titled(first_line(&section.text), BACK_MATTER) {
+            back = Some(i);
+        }
+    }
+    back
+}
+
+/// Whether a bookmark or heading names front or back matter rather than part of the body.
+pub fn is_matter_title(title: &str) -> bool {
+    is_titled(title, FRONT_MATTER) || is_titled(title, BACK_MATTER)
+}
+
+/// Whether `title` is one of `titles`, or starts with one followed by more words
+/// ("Also by Jane Doe", "Index of Names").
+fn is_titled(title: &str, titles: &[&str]) -> bool {
+    let title = title
+        .trim()
+        .trim_end_matches(|c: char| c.is_ascii_punctuation())
+        .to_lowercase();
+    titles.iter().any(|known| {
+        title == *known
+            || title
+                .strip_prefix(known)
+                .is_some_and(|rest| rest.starts_with(' '))
+    })
+}
+
+fn opens_body(text: &str) -> bool {
+    let heading = first_line(text).to_lowercase();
+    BODY_HEADINGS
+        .iter()
+        .any(|opening| heading.starts_with(opening))
+}
+
+/// The first non-blank line of `text`, trimmed.
+pub fn first_line(text: &str) -> &str {
+    text.lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .unwrap_or_default()
+}
@@ -0,0 +1,155 @@
+//! A Model Context Protocol server on standard input and output, so LLM agents can extract
+//! documents by path with the `extract_document` and `get_metadata` tools.
+//!
+//! The protocol is JSON-RPC 2.0, one message per line, as [`crate::rpc`] speaks it. A call
+//! that carries a progress token is followed by progress notifications while the document is
+//! extracted; a document that fails to extract answers with a tool error the agent can read.
+
+use anyhow::Result;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::io::{BufRead, Write};
+
+use crate::extract::ExtractOptions;
+use crate::rpc::{self, DocumentParams, Failure};
+
+/// Protocol versions understood, oldest first; the newest is offered to clients that ask for
+/// another.
+const PROTOCOL_VERSIONS: [&str; 3] = ["2024-11-05", "2025-03-26", "2025-06-18"];
+
+#[derive(Deserialize)]
+struct InitializeParams {
+    #[serde(rename = "protocolVersion")]
+    protocol_version: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct CallParams {
+    name: String,
+    #[serde(default)]
+    arguments: Value,
+    #[serde(rename = "_meta", default)]
+    meta: Value,
+}
+
+/// Answers the messages read from `input` on `out` until `input` ends, extracting with
+/// `options`.
+pub fn run(input: impl BufRead, out: impl Write, options: &ExtractOptions) -> Result<()> {
+    rpc::serve(input, out, |method, params, out| match method {
+        "initialize" => rpc::parse(params).map(initialize),
+        "ping" => Ok(json!({})),
+        "tools/list" => Ok(json!({"tools": tools()})),
+        "tools/call" => rpc::parse(params).and_then(|params| call(params, options, out)),
+        // Notifications from the client, such as `notifications/initialized`
+        _ if method.starts_with("notifications/") => Ok(Value::Null),
+        _ => Err(Failure::new(
+            rpc::METHOD_NOT_FOUND,
+            format!("unknown method: {method}"),
+        )),
+    })
+}
+
+fn initialize(params: InitializeParams) -> Value {
+    let latest = PROTOCOL_VERSIONS[PROTOCOL_VERSIONS.len() - 1];
+    let version = params
+        .protocol_version
+        .as_deref()
+        .filter(|asked| PROTOCOL_VERSIONS.contains(asked))
+        .unwrap_or(latest);
+    json!({
+        "protocolVersion": version,
+        "capabilities": {"tools": {}},
+        "serverInfo": {"name": "to_texts", "version": env!("CARGO_PKG_VERSION")},
+        "instructions": "Extracts the text and metadata of PDF, EPUB, DOCX and other documents \
+            on this machine, by path.",
+    })
+}
+
+fn tools() -> Value {
+    let path = json!({
+        "type": "string",
+        "description": "Path of the document on this machine",
+    });
+    let file_type = json!({
+        "type": "string",
+        "description": "Type of the document by its usual extension (pdf, epub, docx, ...), \
+            when its path has none",
+    });
+    json!([
+        {
+            "name": "extract_document",
+            "description": "Extract the text of a document (PDF, EPUB, DOCX, HTML, RTF, ...)",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "path": path,
+                    "type": file_type,
+                    "format": {
+                        "type": "string",
+                        "enum": ["text", "markdown", "json", "ssml"],
+                        "description": "Format of the text; plain text unless the server was \
+                            started with another",
+                    },
+                },
+                "required": ["path"],
+            },
+        },
+        {
+            "name": "get_metadata",
+            "description": "Get the title, author, language, publisher, type, size, page or \
+                chapter count and encryption of a document",
+            "inputSchema": {
+                "type": "object",
+                "properties": {"path": path, "type": file_type},
+                "required": ["path"],
+            },
+        },
+    ])
+}
+
+fn call(
+    params: CallParams,
+    options: &ExtractOptions,
+    out: &mut dyn Write,
+) -> Result<Value, Failure> {
+    let tool = params.name.as_str();
+    if !matches!(tool, "extract_document" | "get_metadata") {
+        return Err(Failure::new(
+            rpc::INVALID_PARAMS,
+            format!("unknown tool: {tool} (use extract_document or get_metadata)"),
+        ));
+    }
+    let arguments: DocumentParams = rpc::parse(params.arguments)?;
+    let token = params.meta.get("progressToken").cloned();
+    let progress = |out: &mut dyn Write, progress: u32, message: String| {
+        if let Some(token) = &token {
+            let notification = json!({
+                "jsonrpc": "2.0",
+                "method": "notifications/progress",
+                "params": {
+                    "progressToken": token,
+                    "progress": progress,
+                    "total": 1,
+                    "message": message,
+                },
+            });
+            // A client that stopped reading cannot be told anything more
+            let _ = rpc::send(out, &notification);
+        }
+    };
+    progress(out, 0, format!("Extracting {}", arguments.path.display()));
+
+    let result = match tool {
+        "extract_document" => rpc::render(&arguments, options)
+            .map(|rendered| json!({"content": [{"type": "text", "text": rendered.text}]})),
+        _ => rpc::info(arguments, options).map(|info| {
+            let text = serde_json::to_string_pretty(&info).unwrap_or_default();
+            json!({"content": [{"type": "text", "text": text}], "structuredContent": info})
+        }),
+    };
+    progress(out, 1, "Done".to_string());
+    // Failures of the tool itself are results the agent can read, not protocol errors
+    Ok(result.unwrap_or_else(
+        |failure| json!({"content": [{"type": "text", "text": failure.message}], "isError": true}),
+    ))
+}
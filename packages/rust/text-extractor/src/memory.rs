@@ -0,0 +1,179 @@
+//! A global memory budget for files being extracted.
+//!
+//! Peak memory while extracting grows with the size of the file, so each file reserves an
+//! estimate of what it will need before it is opened, and waits while the files already in
+//! flight would push the total past the budget.
+
+use std::sync::{Condvar, Mutex, MutexGuard};
+
+use crate::source::SourceFile;
+
+/// Rough peak memory per byte of input: PDFs are decoded page by page with their object
+/// table held in memory, EPUBs are compressed and inflate several times over.
+const PDF_FACTOR: u64 = 8;
+const EPUB_FACTOR: u64 = 6;
+/// Audiobooks are decoded a window at a time and DjVu books by djvulibre's own tools; the file
+/// itself is read once.
+const AUDIO_FACTOR: u64 = 1;
+/// LaTeX sources, FB2 books, RTF documents and HTML files are read whole, with the files they include,
+/// and copied a few times.
+const LATEX_FACTOR: u64 = 4;
+
+/// Estimated peak memory for extracting `file`, in bytes.
+pub fn estimate(file: &SourceFile) -> u64 {
+    let size = std::fs::metadata(&file.path).map_or(0, |m| m.len());
+    let factor = match file.extension.as_str() {
+        "epub" | "ibooks" | "chm" | "fb2.zip" | "docx" | "odt" => EPUB_FACTOR,
+        "mp3" | "m4b" | "djvu" | "djv" => AUDIO_FACTOR,
+        "tex" | "fb2" | "rtf" | "html" | "xhtml" | "htm" => LATEX_FACTOR,
+        _ => PDF_FACTOR,
+    };
+    size.saturating_mul(factor)
+}
+
+/// Parses sizes such as `4G`, `512M` or `1500000` (binary units, bytes without a suffix).
+pub fn parse_size(text: &str) -> Result<u64, String> {
+    let text = text.trim();
+    let split = text
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("invalid size: {text}"))?;
+    let multiplier: u64 = match unit
+        .trim()
+        .to_ascii_uppercase()
+        .trim_end_matches("IB")
+        .trim_end_matches('B')
+    {
+        "" => 1,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        "T" => 1 << 40,
+        _ => return Err(format!("unknown size unit in {text} (use K, M, G or T)")),
+    };
+
+    let bytes = number * multiplier as f64;
+    if bytes < 1. {
+        return Err(format!("size must be at least one byte: {text}"));
+    }
+    Ok(bytes as u64)
+}
+
+/// Writes a size the way [`parse_size`] reads it, e.g. `1.5M`, for listings.
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["K", "M", "G", "T"];
+    if bytes < 1 << 10 {
+        return bytes.to_string();
+    }
+    let mut size = bytes as f64;
+    let mut unit = "";
+    for next in UNITS {
+        if size < 1024. {
+            break;
+        }
+        size /= 1024.;
+        unit = next;
+    }
+    format!("{size:.1}{unit}")
+}
+
+/// Bytes reserved by files in flight, limited to a budget.
+pub struct MemoryBudget {
+    limit: Option<u64>,
+    in_use: Mutex<u64>,
+    released: Condvar,
+}
+
+impl MemoryBudget {
+    /// A budget of `limit` bytes; `None` never waits.
+    pub fn new(limit: Option<u64>) -> Self {
+        MemoryBudget {
+            limit,
+            in_use: Mutex::new(0),
+            released: Condvar::new(),
+        }
+    }
+
+    /// Blocks until `bytes` fit in the budget and reserves them until the guard is dropped.
+    ///
+    /// A file larger than the whole budget waits until nothing else is in flight and then
+    /// runs alone.
+    pub fn reserve(&self, bytes: u64) -> Reservation<'_> {
+        let Some(limit) = self.limit else {
+            return Reservation {
+                budget: self,
+                bytes: 0,
+            };
+        };
+        let bytes = bytes.min(limit);
+
+        let mut in_use = self.lock();
+        while *in_use > 0 && *in_use + bytes > limit {
+            in_use = self
+                .released
+                .wait(in_use)
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+        }
+        *in_use += bytes;
+        Reservation {
+            budget: self,
+            bytes,
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, u64> {
+        // A panic while extracting one file must not stall the others
+        self.in_use
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Memory reserved for one file, returned to the budget on drop.
+pub struct Reservation<'a> {
+    budget: &'a MemoryBudget,
+    bytes: u64,
+}
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        if self.bytes > 0 {
+            *self.budget.lock() -= self.bytes;
+            self.budget.released.notify_all();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sizes() {
+        assert_eq!(parse_size("1500000"), Ok(1_500_000));
+        assert_eq!(parse_size("4G"), Ok(4 << 30));
+        assert_eq!(parse_size("512m"), Ok(512 << 20));
+        assert_eq!(parse_size("1.5 MiB"), Ok(3 << 19));
+        assert_eq!(parse_size("2KB"), Ok(2048));
+        assert_eq!(parse_size(" 1T "), Ok(1 << 40));
+    }
+
+    #[test]
+    fn invalid_sizes() {
+        for text in ["", "M", "4X", "0", "0.5", "1.2.3G", "-1M", "4 GB extra"] {
+            assert!(parse_size(text).is_err(), "{text}");
+        }
+    }
+
+    #[test]
+    fn formatted_sizes_parse_back() {
+        for bytes in [1, 1023, 1 << 10, 3 << 19, 64 << 20, 4 << 30] {
+            let formatted = format_size(bytes);
+            assert_eq!(parse_size(&formatted), Ok(bytes), "{formatted}");
+        }
+    }
+}
@@ -0,0 +1,122 @@
+//! Bibliographic metadata written as a header above extracted text, or to a sidecar next to
+//! it.
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::document::{Document, SectionKind};
+use crate::provenance::Provenance;
+
+#[derive(Debug, Clone, Default)]
+pub struct Metadata {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    /// BCP 47 language tag, e.g. "en-US"
+    pub language: Option<String>,
+    /// Title of the omnibus or anthology a single work was split out of
+    pub collection: Option<String>,
+}
+
+impl Metadata {
+    /// Fills any missing fields from `fallback`, keeping values already set.
+    pub fn or(self, fallback: Metadata) -> Metadata {
+        Metadata {
+            title: self.title.or(fallback.title),
+            author: self.author.or(fallback.author),
+            language: self.language.or(fallback.language),
+            collection: self.collection.or(fallback.collection),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.title.is_none()
+            && self.author.is_none()
+            && self.language.is_none()
+            && self.collection.is_none()
+    }
+}
+
+/// The `<output>.meta.json` record of a document: its metadata and the source it was
+/// extracted from, so text outputs can go without a header.
+#[derive(Debug, Serialize)]
+pub struct MetadataSidecar {
+    pub source: PathBuf,
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub language: Option<String>,
+    pub collection: Option<String>,
+    pub page_count: Option<usize>,
+    pub chapter_count: Option<usize>,
+    pub source_sha256: String,
+    /// When the document was extracted, in seconds since the epoch
+    pub extracted_at: u64,
+}
+
+impl MetadataSidecar {
+    pub fn of(doc: &Document, source: &Path, provenance: &Provenance) -> MetadataSidecar {
+        let count = |kind: SectionKind| {
+            let count = doc
+                .sections
+                .iter()
+                .filter(|section| section.kind == kind)
+                .count();
+            (count > 0).then_some(count)
+        };
+        let metadata = doc.metadata.clone();
+        MetadataSidecar {
+            source: source.to_path_buf(),
+            title: metadata.title,
+            author: metadata.author,
+            language: metadata.language,
+            collection: metadata.collection,
+            page_count: count(SectionKind::Page),
+            chapter_count: count(SectionKind::Chapter),
+            source_sha256: provenance.source_sha256.clone(),
+            extracted_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_secs()),
+        }
+    }
+
+    /// Writes `<output>.meta.json` next to `output_path`.
+    pub fn write_sidecar(&self, output_path: &Path) -> Result<PathBuf> {
+        let mut name = output_path.as_os_str().to_owned();
+        name.push(".meta.json");
+        let sidecar = PathBuf::from(name);
+
+        fs::write(&sidecar, serde_json::to_string_pretty(self)? + "\n").context(format!(
+            "Failed to write metadata sidecar: {}",
+            sidecar.display()
+        ))?;
+        Ok(sidecar)
+    }
+}
+
+pub fn append_metadata(metadata: &Metadata, text: &mut String) {
+    if let Some(title) = &metadata.title {
+        text.push_str("Title: ");
+        text.push_str(title);
+        text.push('\n');
+    }
+    if let Some(author) = &metadata.author {
+        text.push_str("Author: ");
+        text.push_str(author);
+        text.push('\n');
+    }
+    if let Some(language) = &metadata.language {
+        text.push_str("Language: ");
+        text.push_str(language);
+        text.push('\n');
+    }
+    if let Some(collection) = &metadata.collection {
+        text.push_str("Collection: ");
+        text.push_str(collection);
+        text.push('\n');
+    }
+    text.push('\n');
+    text.push_str("=".repeat(80).as_str());
+    text.push_str("\n\n");
+}
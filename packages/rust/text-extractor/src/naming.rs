@@ -0,0 +1,188 @@
+//! Output file names: the input's name by default, or one built by `--name-template` from
+//! the document's metadata, e.g. `{author} - {title}`, made valid on Windows as well as Unix
+//! so a run never fails on a name another platform allowed.
+
+use crate::metadata::Metadata;
+use crate::provenance;
+
+/// Placeholders a name template may hold.
+const PLACEHOLDERS: &[&str] = &[
+    "{title}",
+    "{author}",
+    "{language}",
+    "{collection}",
+    "{stem}",
+    "{ext}",
+];
+
+/// Names Windows reserves for devices, with or without an extension.
+const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Longest output name in bytes, leaving room under the usual 255 for the extension, a
+/// collision suffix and sidecar suffixes such as `.provenance.json`.
+const MAX_NAME_BYTES: usize = 200;
+
+/// Written for `{author}` when the document names none.
+const UNKNOWN_AUTHOR: &str = "Unknown Author";
+
+/// Checks a `--name-template`: it must use only known placeholders and hold one at all.
+pub fn parse_template(template: &str) -> Result<String, String> {
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let end = rest[start..]
+            .find('}')
+            .map_or(rest.len(), |end| start + end + 1);
+        let placeholder = &rest[start..end];
+        if !PLACEHOLDERS.contains(&placeholder) {
+            return Err(format!(
+                "unknown placeholder {placeholder} (use {})",
+                PLACEHOLDERS.join(", ")
+            ));
+        }
+        rest = &rest[end..];
+    }
+    if !PLACEHOLDERS.iter().any(|p| template.contains(p)) {
+        return Err(
+            "the template names no placeholder, so every output would get the same name"
+                .to_string(),
+        );
+    }
+    Ok(template.to_string())
+}
+
+/// The output name, without extension, that `template` gives a source named `stem` with
+/// extension `extension` holding a document with `metadata`. Each `/`-separated part of the
+/// template is a folder. A missing title falls back to the stem and a missing author to
+/// "Unknown Author"; a missing language or collection is left out together with the brackets
+/// and dashes around it.
+pub(crate) fn render_template(
+    template: &str,
+    stem: &str,
+    extension: &str,
+    metadata: &Metadata,
+) -> String {
+    let values = [
+        ("{title}", metadata.title.as_deref().unwrap_or(stem)),
+        (
+            "{author}",
+            metadata.author.as_deref().unwrap_or(UNKNOWN_AUTHOR),
+        ),
+        (
+            "{language}",
+            metadata.language.as_deref().unwrap_or_default(),
+        ),
+        (
+            "{collection}",
+            metadata.collection.as_deref().unwrap_or_default(),
+        ),
+        ("{stem}", stem),
+        ("{ext}", extension),
+    ];
+    template
+        .split('/')
+        .filter(|part| !part.trim().is_empty())
+        .map(|part| {
+            let mut name = part.to_string();
+            for (placeholder, value) in values {
+                // A value holding `/` would otherwise make folders of its own
+                name = name.replace(placeholder, &value.replace(['/', '\\'], " "));
+            }
+            file_name_safe(&tidy(&name))
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Removes what empty values leave behind: empty brackets, doubled dashes and separators at
+/// either end.
+fn tidy(name: &str) -> String {
+    let mut name = name.split_whitespace().collect::<Vec<_>>().join(" ");
+    loop {
+        let tidied = name
+            .replace("()", "")
+            .replace("[]", "")
+            .replace("( )", "")
+            .replace("[ ]", "")
+            .replace(" - - ", " - ")
+            .replace("  ", " ");
+        if tidied == name {
+            break;
+        }
+        name = tidied;
+    }
+    name.trim_matches(|c: char| c.is_whitespace() || "-_,.".contains(c))
+        .to_string()
+}
+
+/// Bookmark titles as file names: path separators and characters Windows rejects are
+/// dropped, and very long titles are cut short.
+pub(crate) fn file_name_safe(title: &str) -> String {
+    const MAX_CHARS: usize = 80;
+
+    let cleaned: String = title
+        .chars()
+        .map(|c| {
+            if c.is_control() || "/\\:*?\"<>|".contains(c) {
+                ' '
+            } else {
+                c
+            }
+        })
+        .collect();
+    let name: String = cleaned
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .chars()
+        .take(MAX_CHARS)
+        .collect();
+    let name = name.trim_end_matches(['.', ' ']);
+    if name.is_empty() {
+        "Untitled".to_string()
+    } else {
+        name.to_string()
+    }
+}
+
+/// `name` as a file name every platform accepts: characters Windows rejects become `_`,
+/// trailing dots and spaces go, device names such as `CON` get a `_`, and names too long for
+/// the file system are cut short and end in a digest of the whole name, so cut names stay
+/// apart.
+pub(crate) fn portable(name: &str) -> String {
+    let mut name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_control() || "<>:\"/\\|?*".contains(c) {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect();
+    name.truncate(name.trim_end_matches(['.', ' ']).len());
+    if name.is_empty() {
+        name.push('_');
+    }
+
+    let device = name.split('.').next().unwrap_or_default().trim_end();
+    if RESERVED_NAMES
+        .iter()
+        .any(|r| r.eq_ignore_ascii_case(device))
+    {
+        name.insert(device.len(), '_');
+    }
+
+    if name.len() > MAX_NAME_BYTES {
+        let hash = provenance::short_hash(&name);
+        let mut end = MAX_NAME_BYTES - hash.len() - 1;
+        while !name.is_char_boundary(end) {
+            end -= 1;
+        }
+        let kept = name[..end].trim_end_matches(['.', ' ']);
+        name = format!("{kept}-{hash}");
+    }
+    name
+}
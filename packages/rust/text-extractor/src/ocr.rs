@@ -0,0 +1,158 @@
+//! OCR for scanned PDFs and DjVu books, whose pages are images with little or no text layer.
+//!
+//! Pages are rendered with `pdftoppm` (poppler-utils) or `ddjvu` (djvulibre) and read with the
+//! `tesseract` command; these must be on the `PATH`. Shelling out keeps the build free of native libraries and
+//! lets the installed Tesseract language packs be used as they are.
+
+use anyhow::{Context, Result};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+use crate::document::{Document, SectionKind};
+use crate::source;
+
+/// Resolution pages are rendered at; Tesseract reads body text best at around 300 dpi.
+const RENDER_DPI: &str = "300";
+
+/// Pages with fewer letters and digits than this count as having no text layer.
+const MIN_TEXT_CHARS: usize = 16;
+
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OcrMode {
+    /// OCR pages that have (almost) no extractable text
+    Auto,
+    /// OCR every page, replacing the text layer
+    Always,
+    /// Never OCR
+    #[default]
+    Never,
+}
+
+#[derive(Debug, Clone)]
+pub struct OcrOptions {
+    pub mode: OcrMode,
+    /// Tesseract language codes, e.g. `eng` or `deu+eng`
+    pub language: String,
+}
+
+impl Default for OcrOptions {
+    fn default() -> Self {
+        OcrOptions {
+            mode: OcrMode::default(),
+            language: "eng".to_string(),
+        }
+    }
+}
+
+/// Replaces the text of the PDF or DjVu pages that need it with their OCR'd text.
+pub fn apply(path: &Path, doc: &mut Document, options: &OcrOptions) -> Result<()> {
+    let pages: Vec<usize> = doc
+        .sections
+        .iter()
+        .enumerate()
+        .filter(|(_, section)| section.kind == SectionKind::Page && section.page.is_some())
+        .filter(|(_, section)| match options.mode {
+            OcrMode::Always => true,
+            OcrMode::Auto => text_chars(&section.text) < MIN_TEXT_CHARS,
+            OcrMode::Never => false,
+        })
+        .map(|(index, _)| index)
+        .collect();
+    if pages.is_empty() {
+        return Ok(());
+    }
+
+    let images = tempfile::tempdir().context("Failed to create a folder for OCR images")?;
+    for index in pages {
+        let section = &mut doc.sections[index];
+        let Some(page) = section.page else {
+            continue;
+        };
+        let text = recognise_page(path, page, &options.language, images.path())
+            .map_err(|e| anyhow::anyhow!("OCR failed on page {page} of {}: {e}", path.display()))?;
+        // In auto mode, keep a text layer that OCR found nothing better than
+        if options.mode == OcrMode::Always || text_chars(&text) >= text_chars(&section.text) {
+            section.text = text;
+        }
+    }
+    Ok(())
+}
+
+/// Renders one page to an image and reads it with Tesseract.
+fn recognise_page(path: &Path, page: usize, language: &str, images: &Path) -> Result<String> {
+    let image = render_page(path, page, images)?;
+    let output = run(
+        Command::new("tesseract")
+            .arg(&image)
+            .arg("stdout")
+            .args(["-l", language]),
+        "tesseract",
+        "OCR",
+    )?;
+    // The image is no longer needed; the folder goes when the extraction ends anyway
+    let _ = std::fs::remove_file(&image);
+
+    let text = String::from_utf8_lossy(&output.stdout);
+    let lines: Vec<&str> = text
+        .trim_matches(|c: char| c.is_whitespace() || c == '\u{c}')
+        .lines()
+        .map(str::trim_end)
+        .collect();
+    // Page text ends with a line break, as pdf-extract leaves it
+    Ok(lines.join("\n") + "\n")
+}
+
+/// Renders one page of a PDF to a grayscale PNG at [`RENDER_DPI`], or of a DjVu book to a
+/// TIFF at the resolution it was scanned at.
+fn render_page(path: &Path, page: usize, images: &Path) -> Result<PathBuf> {
+    let prefix = images.join(format!("page-{page}"));
+    let page = page.to_string();
+    if matches!(source::extension(path).as_deref(), Some("djvu" | "djv")) {
+        let image = prefix.with_extension("tif");
+        run(
+            Command::new("ddjvu")
+                .args(["-format=tiff", &format!("-page={page}")])
+                .arg(path)
+                .arg(&image),
+            "ddjvu (from djvulibre)",
+            "OCR",
+        )?;
+        return Ok(image);
+    }
+
+    run(
+        Command::new("pdftoppm")
+            .args(["-r", RENDER_DPI, "-gray", "-png", "-singlefile"])
+            .args(["-f", &page, "-l", &page])
+            .arg(path)
+            .arg(&prefix),
+        "pdftoppm (from poppler-utils)",
+        "OCR",
+    )?;
+    Ok(prefix.with_extension("png"))
+}
+
+/// Runs a helper program, turning a missing program or a failed run into an error that
+/// names it and what it was needed for.
+pub(crate) fn run(command: &mut Command, program: &str, purpose: &str) -> Result<Output> {
+    let output = command.output().map_err(|e| {
+        if e.kind() == ErrorKind::NotFound {
+            anyhow::anyhow!("{purpose} needs {program} installed and on the PATH")
+        } else {
+            anyhow::anyhow!("Failed to run {program}: {e}")
+        }
+    })?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "{program} failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(output)
+}
+
+fn text_chars(text: &str) -> usize {
+    text.chars().filter(|c| c.is_alphanumeric()).count()
+}
@@ -0,0 +1,603 @@
+//! OpenDocument text files (`.odt`), as written by LibreOffice and others.
+//!
+//! The text is read from `content.xml`, one line per paragraph. `<text:h>` headings start
+//! sections and bookmarks at their outline level, list items get `- ` or `1. ` markers from
+//! their list style, and tables are laid out like HTML tables. Generated indexes (tables of
+//! contents and the like), notes, comments and tracked deletions are left out. Title, author
+//! and language come from `meta.xml`.
+
+use anyhow::{Context, Result};
+use roxmltree::Node;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use zip::ZipArchive;
+
+use crate::document::{Document, Landmarks, OutlineEntry, Section, SectionKind};
+use crate::extract::HtmlOptions;
+use crate::figures::{self, IMAGE};
+use crate::metadata::Metadata;
+use crate::scripts::{self, Script, ScriptMode};
+use crate::tables;
+use crate::xml::{attribute, child, elements, text};
+
+const CONTENT_XML: &str = "content.xml";
+const STYLES_XML: &str = "styles.xml";
+const META_XML: &str = "meta.xml";
+
+/// Inline elements whose content is not part of the running text.
+const HIDDEN_INLINE: &[&str] = &["note", "annotation", "frame", "bookmark-ref"];
+
+pub fn extract(path: &Path, metadata: Option<&Metadata>, options: HtmlOptions) -> Result<Document> {
+    let file = File::open(path).context(format!("Failed to open: {}", path.display()))?;
+    let mut archive =
+        ZipArchive::new(file).context(format!("Not an OpenDocument file: {}", path.display()))?;
+
+    let content = entry(&mut archive, CONTENT_XML)?
+        .context(format!("No {CONTENT_XML} in {}", path.display()))?;
+    let content = roxmltree::Document::parse(&content)
+        .context(format!("Failed to parse {CONTENT_XML}: {}", path.display()))?;
+    // Named styles and properties only refine the text; a broken part is ignored
+    let styles = entry(&mut archive, STYLES_XML)?;
+    let styles = styles
+        .as_deref()
+        .and_then(|xml| roxmltree::Document::parse(xml).ok());
+    let embedded = entry(&mut archive, META_XML)?
+        .map(|xml| meta(&xml))
+        .unwrap_or_default();
+
+    let mut converter = Converter {
+        options,
+        styles: Styles::default(),
+        lists: Vec::new(),
+        sections: vec![Part::default()],
+    };
+    for root in styles.iter().chain([&content]) {
+        converter.styles.read(root.root_element());
+    }
+    let text = child(content.root_element(), "body").and_then(|body| child(body, "text"));
+    if let Some(text) = text {
+        converter.blocks(text);
+    }
+
+    // The outermost heading level used becomes level 1
+    let top = converter
+        .sections
+        .iter()
+        .filter_map(|part| part.heading.as_ref().map(|(level, _)| *level))
+        .min()
+        .unwrap_or(1);
+    let mut outline = Vec::new();
+    let mut sections = Vec::new();
+    for part in converter.sections {
+        let mut lines = part.lines;
+        if let Some((level, title)) = part.heading {
+            outline.push(OutlineEntry {
+                title: title.clone(),
+                level: level - top + 1,
+                section: sections.len(),
+            });
+            lines.insert(0, title);
+        }
+        if lines.is_empty() {
+            continue;
+        }
+        let text = lines.join("\n");
+        sections.push(Section {
+            kind: SectionKind::Chapter,
+            title: None,
+            text: if options.image_placeholders {
+                figures::label(&text, false)
+            } else {
+                text
+            },
+            language: None,
+            page: None,
+        });
+    }
+
+    Ok(Document {
+        metadata: metadata.cloned().unwrap_or_default().or(embedded),
+        sections,
+        outline,
+        landmarks: Landmarks::default(),
+    })
+}
+
+/// The text of a package part, or `None` when the package does not have it.
+fn entry(archive: &mut ZipArchive<File>, name: &str) -> Result<Option<String>> {
+    let Ok(mut entry) = archive.by_name(name) else {
+        return Ok(None);
+    };
+    let mut xml = String::new();
+    entry
+        .read_to_string(&mut xml)
+        .context(format!("Failed to read {name}"))?;
+    Ok(Some(xml))
+}
+
+/// Title, author and language from `meta.xml`.
+fn meta(xml: &str) -> Metadata {
+    let Ok(doc) = roxmltree::Document::parse(xml) else {
+        return Metadata::default();
+    };
+    let Some(meta) = child(doc.root_element(), "meta") else {
+        return Metadata::default();
+    };
+    let field = |name: &str| {
+        child(meta, name)
+            .map(text)
+            .filter(|value| !value.is_empty())
+    };
+    Metadata {
+        title: field("title"),
+        author: field("creator").or_else(|| field("initial-creator")),
+        language: field("language"),
+        collection: None,
+    }
+}
+
+/// What the converter needs from the styles: which text styles raise or lower text and
+/// which list levels are numbered.
+#[derive(Default)]
+struct Styles {
+    scripts: HashMap<String, Script>,
+    /// Numbered levels of each list style, counting from 1
+    numbered: HashMap<String, HashSet<usize>>,
+}
+
+impl Styles {
+    /// Reads the named and automatic styles of `styles.xml` or `content.xml`.
+    fn read(&mut self, root: Node) {
+        for container in ["styles", "automatic-styles"] {
+            let Some(container) = child(root, container) else {
+                continue;
+            };
+            for style in container.children().filter(Node::is_element) {
+                let Some(name) = attribute(style, "name") else {
+                    continue;
+                };
+                match style.tag_name().name() {
+                    "style" => {
+                        let position = child(style, "text-properties")
+                            .and_then(|properties| attribute(properties, "text-position"));
+                        if let Some(script) = position.and_then(text_position) {
+                            self.scripts.insert(name.to_string(), script);
+                        }
+                    }
+                    "list-style" => {
+                        let numbered = elements(style, "list-level-style-number")
+                            .filter_map(|level| attribute(level, "level")?.parse().ok())
+                            .collect();
+                        self.numbered.insert(name.to_string(), numbered);
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+/// Raised or lowered text from a `style:text-position` such as `super 58%` or `-33% 58%`.
+fn text_position(position: &str) -> Option<Script> {
+    let offset = position.split_whitespace().next()?;
+    match offset {
+        "super" => Some(Script::Super),
+        "sub" => Some(Script::Sub),
+        _ => {
+            let percent: f64 = offset.trim_end_matches('%').parse().ok()?;
+            if percent > 0.0 {
+                Some(Script::Super)
+            } else if percent < 0.0 {
+                Some(Script::Sub)
+            } else {
+                None
+            }
+        }
+    }
+}
+
+/// A heading and the lines under it, up to the next heading.
+#[derive(Default)]
+struct Part {
+    heading: Option<(usize, String)>,
+    lines: Vec<String>,
+}
+
+/// A list being converted: its style and the last number given at its level.
+struct List {
+    style: Option<String>,
+    counter: usize,
+}
+
+struct Converter {
+    options: HtmlOptions,
+    styles: Styles,
+    /// Open lists, innermost last
+    lists: Vec<List>,
+    sections: Vec<Part>,
+}
+
+impl Converter {
+    fn lines(&mut self) -> &mut Vec<String> {
+        &mut self
+            .sections
+            .last_mut()
+            .expect("there is always a section")
+            .lines
+    }
+
+    /// Converts the block elements of the body or a container within it.
+    fn blocks(&mut self, parent: Node) {
+        for node in parent.children().filter(Node::is_element) {
+            match node.tag_name().name() {
+                "h" => self.heading(node),
+                "p" => self.paragraph(node, None),
+                "list" => self.list(node),
+                "table" => self.table(node),
+                "section" => self.blocks(node),
+                // Generated indexes (tables of contents and the like) would repeat the text;
+                // they, tracked changes and declarations print nothing
+                _ => {}
+            }
+        }
+    }
+
+    fn heading(&mut self, heading: Node) {
+        let text = self.inline(heading);
+        if text.is_empty() {
+            return;
+        }
+        let level = attribute(heading, "outline-level")
+            .and_then(|level| level.parse().ok())
+            .unwrap_or(1);
+        self.sections.push(Part {
+            heading: Some((level, text)),
+            lines: Vec::new(),
+        });
+    }
+
+    fn paragraph(&mut self, paragraph: Node, marker: Option<String>) {
+        let content = self.inline(paragraph);
+        if !content.is_empty() {
+            let line = match marker {
+                Some(marker) => marker + &content,
+                None => content,
+            };
+            self.lines().push(line);
+        }
+        if self.options.image_placeholders {
+            for frame in paragraph
+                .descendants()
+                .filter(|node| node.has_tag_name("frame"))
+            {
+                let description = child(frame, "title")
+                    .or_else(|| child(frame, "desc"))
+                    .map(text)
+                    .unwrap_or_default();
+                let line = format!("{IMAGE}{description}{IMAGE}");
+                self.lines().push(line);
+            }
+        }
+    }
+
+    /// Converts a list, nested lists one level down. Only the first paragraph of an item
+    /// gets its marker.
+    fn list(&mut self, list: Node) {
+        let style = attribute(list, "style-name")
+            .map(str::to_string)
+            .or_else(|| self.lists.last().and_then(|outer| outer.style.clone()));
+        self.lists.push(List { style, counter: 0 });
+
+        for item in list.children().filter(Node::is_element) {
+            let header = item.has_tag_name("list-header");
+            if !header && !item.has_tag_name("list-item") {
+                continue;
+            }
+            let mut marker = (!header).then(|| self.list_marker());
+            for node in item.children().filter(Node::is_element) {
+                match node.tag_name().name() {
+                    "p" | "h" => self.paragraph(node, marker.take()),
+                    "list" => self.list(node),
+                    _ => {}
+                }
+            }
+        }
+        self.lists.pop();
+    }
+
+    fn list_marker(&mut self) -> String {
+        let depth = self.lists.len();
+        let Some(list) = self.lists.last_mut() else {
+            return "- ".to_string();
+        };
+        let numbered = list
+            .style
+            .as_ref()
+            .and_then(|style| self.styles.numbered.get(style))
+            .is_some_and(|levels| levels.contains(&depth));
+        if numbered {
+            list.counter += 1;
+            format!("{}. ", list.counter)
+        } else {
+            "- ".to_string()
+        }
+    }
+
+    fn table(&mut self, table: Node) {
+        let mut rows = Vec::new();
+        table_rows(table, &mut rows);
+        let rows: Vec<Vec<String>> = rows
+            .into_iter()
+            .map(|row| {
+                row.children()
+                    .filter(|cell| cell.has_tag_name("table-cell"))
+                    .map(|cell| {
+                        elements(cell, "p")
+                            .map(|p| self.inline(p))
+                            .filter(|text| !text.is_empty())
+                            .collect::<Vec<_>>()
+                            .join(" ")
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .filter(|row| row.iter().any(|cell| !cell.is_empty()))
+            .collect();
+        if rows.is_empty() {
+            return;
+        }
+
+        if self.options.tables {
+            let table = tables::markdown(&rows);
+            self.lines().push(table);
+        } else {
+            let lines: Vec<String> = rows.into_iter().map(|row| row.join(" | ")).collect();
+            self.lines().extend(lines);
+        }
+    }
+
+    /// The text of a paragraph on one line, with whitespace collapsed.
+    fn inline(&self, paragraph: Node) -> String {
+        let mut text = String::new();
+        self.append_inline(paragraph, &mut text);
+        text.split_whitespace().collect::<Vec<_>>().join(" ")
+    }
+
+    fn append_inline(&self, parent: Node, out: &mut String) {
+        for node in parent.children() {
+            if node.is_text() {
+                out.push_str(node.text().unwrap_or_default());
+                continue;
+            }
+            if !node.is_element() {
+                continue;
+            }
+            let name = node.tag_name().name();
+            match name {
+                "s" | "tab" | "line-break" => out.push(' '),
+                _ if HIDDEN_INLINE.contains(&name) => {}
+                _ => {
+                    let script = attribute(node, "style-name")
+                        .and_then(|style| self.styles.scripts.get(style).copied());
+                    match script {
+                        Some(script) if self.options.scripts != ScriptMode::Keep => {
+                            let mut inner = String::new();
+                            self.append_inline(node, &mut inner);
+                            let follows_digit =
+                                out.chars().last().is_some_and(|c| c.is_ascii_digit());
+                            out.push_str(&scripts::render(
+                                inner.trim(),
+                                script,
+                                self.options.scripts,
+                                follows_digit,
+                            ));
+                        }
+                        // Spans, links and fields show their content
+                        _ => self.append_inline(node, out),
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The rows of a table, including those in header and row groups.
+fn table_rows<'a, 'input>(parent: Node<'a, 'input>, rows: &mut Vec<Node<'a, 'input>>) {
+    for node in parent.children().filter(Node::is_element) {
+        match node.tag_name().name() {
+            "table-row" => rows.push(node),
+            "table-header-rows" | "table-rows" | "table-row-group" => table_rows(node, rows),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use zip::write::SimpleFileOptions;
+    use zip::ZipWriter;
+
+    const NAMESPACES: &str = r#"xmlns:office="urn:oasis:names:tc:opendocument:xmlns:office:1.0" xmlns:text="urn:oasis:names:tc:opendocument:xmlns:text:1.0" xmlns:table="urn:oasis:names:tc:opendocument:xmlns:table:1.0" xmlns:style="urn:oasis:names:tc:opendocument:xmlns:style:1.0" xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:meta="urn:oasis:names:tc:opendocument:xmlns:meta:1.0""#;
+
+    /// Writes a package of the given parts and extracts it.
+    fn convert(parts: &[(&str, &str)], options: HtmlOptions) -> Result<Document> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.odt");
+        let mut zip = ZipWriter::new(File::create(&path).unwrap());
+        for (name, data) in parts {
+            zip.start_file(*name, SimpleFileOptions::default()).unwrap();
+            zip.write_all(data.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap();
+        extract(&path, None, options)
+    }
+
+    fn content(styles: &str, body: &str) -> String {
+        format!(
+            r#"<office:document-content {NAMESPACES}><office:automatic-styles>{styles}</office:automatic-styles><office:body><office:text>{body}</office:text></office:body></office:document-content>"#
+        )
+    }
+
+    fn text_of(doc: &Document) -> String {
+        doc.sections
+            .iter()
+            .map(|section| section.text.as_str())
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+
+    #[test]
+    fn headings_start_sections() {
+        let body = r#"<text:p>Preface.</text:p>
+            <text:h text:outline-level="2">First</text:h><text:p>One.</text:p>
+            <text:h text:outline-level="3">Inner</text:h><text:p>Two.</text:p>
+            <text:h text:outline-level="2"> </text:h>"#;
+        let doc = convert(&[(CONTENT_XML, &content("", body))], HtmlOptions::default()).unwrap();
+        assert_eq!(text_of(&doc), "Preface.\n\nFirst\nOne.\n\nInner\nTwo.");
+        let outline: Vec<_> = doc
+            .outline
+            .iter()
+            .map(|entry| (entry.title.as_str(), entry.level, entry.section))
+            .collect();
+        assert_eq!(outline, [("First", 1, 1), ("Inner", 2, 2)]);
+    }
+
+    #[test]
+    fn lists_are_numbered_by_their_style() {
+        let styles = r#"<text:list-style style:name="L1"><text:list-level-style-number text:level="1"/><text:list-level-style-bullet text:level="2"/></text:list-style>"#;
+        let body = r#"<text:list text:style-name="L1">
+            <text:list-item><text:p>One</text:p><text:p>more</text:p></text:list-item>
+            <text:list-item><text:p>Two</text:p>
+              <text:list><text:list-item><text:p>Inner</text:p></text:list-item></text:list>
+            </text:list-item>
+            <text:list-header><text:p>Header</text:p></text:list-header>
+          </text:list>
+          <text:list><text:list-item><text:p>Plain</text:p></text:list-item></text:list>"#;
+        let doc = convert(
+            &[(CONTENT_XML, &content(styles, body))],
+            HtmlOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            text_of(&doc),
+            "1. One\nmore\n2. Two\n- Inner\nHeader\n- Plain"
+        );
+    }
+
+    #[test]
+    fn tables_and_sections() {
+        let body = r#"<text:section><table:table>
+            <table:table-header-rows><table:table-row>
+              <table:table-cell><text:p>A</text:p></table:table-cell>
+              <table:table-cell><text:p>B</text:p></table:table-cell>
+            </table:table-row></table:table-header-rows>
+            <table:table-row>
+              <table:table-cell><text:p>1</text:p><text:p>more</text:p></table:table-cell>
+              <table:table-cell><text:p>2</text:p></table:table-cell>
+            </table:table-row>
+            <table:table-row><table:table-cell/><table:table-cell/></table:table-row>
+          </table:table></text:section>"#;
+        let doc = convert(&[(CONTENT_XML, &content("", body))], HtmlOptions::default()).unwrap();
+        assert_eq!(text_of(&doc), "A | B\n1 more | 2");
+    }
+
+    #[test]
+    fn notes_comments_and_indexes_are_left_out() {
+        let body = r#"<text:table-of-content><text:index-body><text:p>Contents 1</text:p></text:index-body></text:table-of-content>
+            <text:p>Word<text:note><text:note-citation>1</text:note-citation><text:note-body><text:p>Footnote</text:p></text:note-body></text:note>
+              <office:annotation><text:p>Comment</text:p></office:annotation>and<text:s/>more<text:tab/>text<text:line-break/><text:span>end</text:span>.</text:p>
+            <text:tracked-changes><text:changed-region><text:deletion><text:p>Deleted</text:p></text:deletion></text:changed-region></text:tracked-changes>"#;
+        let doc = convert(&[(CONTENT_XML, &content("", body))], HtmlOptions::default()).unwrap();
+        assert_eq!(text_of(&doc), "Word and more text end.");
+    }
+
+    #[test]
+    fn raised_text_follows_the_script_mode() {
+        let styles = r#"<style:style style:name="T1" style:family="text"><style:text-properties style:text-position="super 58%"/></style:style>
+            <style:style style:name="T2" style:family="text"><style:text-properties style:text-position="-33% 58%"/></style:style>"#;
+        let body = r#"<text:p>x<text:span text:style-name="T1">2</text:span> H<text:span text:style-name="T2">2</text:span>O</text:p>"#;
+        let parts = [(CONTENT_XML, content(styles, body))];
+        let parts: Vec<_> = parts
+            .iter()
+            .map(|(name, xml)| (*name, xml.as_str()))
+            .collect();
+
+        let doc = convert(&parts, HtmlOptions::default()).unwrap();
+        assert_eq!(text_of(&doc), "x2 H2O");
+        let options = HtmlOptions {
+            scripts: ScriptMode::Unicode,
+            ..Default::default()
+        };
+        assert_eq!(text_of(&convert(&parts, options).unwrap()), "x² H₂O");
+    }
+
+    #[test]
+    fn text_positions() {
+        assert_eq!(text_position("super 58%"), Some(Script::Super));
+        assert_eq!(text_position("sub"), Some(Script::Sub));
+        assert_eq!(text_position("33% 58%"), Some(Script::Super));
+        assert_eq!(text_position("-33% 58%"), Some(Script::Sub));
+        assert_eq!(text_position("0% 100%"), None);
+        assert_eq!(text_position("high"), None);
+        assert_eq!(text_position(""), None);
+    }
+
+    #[test]
+    fn metadata_from_meta_xml() {
+        let meta_xml = format!(
+            r#"<office:document-meta {NAMESPACES}><office:meta><dc:title>Emma</dc:title><meta:initial-creator>Jane Austen</meta:initial-creator><dc:language>en-GB</dc:language><dc:creator></dc:creator></office:meta></office:document-meta>"#
+        );
+        let body = content("", "<text:p>Text</text:p>");
+        let doc = convert(
+            &[(CONTENT_XML, &body), (META_XML, &meta_xml)],
+            HtmlOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(doc.metadata.title.as_deref(), Some("Emma"));
+        assert_eq!(doc.metadata.author.as_deref(), Some("Jane Austen"));
+        assert_eq!(doc.metadata.language.as_deref(), Some("en-GB"));
+    }
+
+    #[test]
+    fn malformed_packages() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("plain.odt");
+        std::fs::write(&path, "not a zip").unwrap();
+        let error = extract(&path, None, HtmlOptions::default()).unwrap_err();
+        assert!(error.to_string().starts_with("Not an OpenDocument file"));
+
+        let error = convert(&[(META_XML, "<meta/>")], HtmlOptions::default()).unwrap_err();
+        assert!(error.to_string().starts_with("No content.xml"));
+        let error =
+            convert(&[(CONTENT_XML, "<office:document")], HtmlOptions::default()).unwrap_err();
+        assert!(error.to_string().starts_with("Failed to parse content.xml"));
+
+        // Broken styles and metadata only lose what they would have added
+        let body = content("", "<text:p>Kept</text:p>");
+        let doc = convert(
+            &[
+                (CONTENT_XML, &body),
+                (STYLES_XML, "<office:"),
+                (META_XML, "<<"),
+            ],
+            HtmlOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(text_of(&doc), "Kept");
+        assert_eq!(doc.metadata.title, None);
+
+        // A document without a body is empty
+        let doc = convert(
+            &[(
+                CONTENT_XML,
+                &format!("<office:document-content {NAMESPACES}/>"),
+            )],
+            HtmlOptions::default(),
+        )
+        .unwrap();
+        assert!(doc.sections.is_empty());
+    }
+}
@@ -0,0 +1,325 @@
+//! Crawls OPDS catalogs (Atom feeds) and downloads acquisition links for supported formats.
+
+use anyhow::{Context, Result};
+use std::collections::{HashSet, VecDeque};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+use url::Url;
+
+use crate::metadata::Metadata;
+use crate::provenance;
+use crate::source::SourceFile;
+
+/// Acquisition media types we can extract, in order of preference.
+const ACQUISITION_TYPES: [(&str, &str); 2] =
+    [("application/epub+zip", "epub"), ("application/pdf", "pdf")];
+
+const USER_AGENT: &str = concat!("to_texts/", env!("CARGO_PKG_VERSION"));
+
+pub struct OpdsOptions {
+    /// Pause between consecutive network requests
+    pub delay: Duration,
+    /// Where downloaded books are kept; existing files are not fetched again
+    pub cache_dir: PathBuf,
+    /// Upper bound on feed pages visited, guarding against endless pagination
+    pub max_feeds: usize,
+}
+
+pub struct OpdsCrawl {
+    pub books: Vec<SourceFile>,
+    pub failed: usize,
+}
+
+/// An acquisition link found on a feed entry.
+struct Acquisition {
+    url: Url,
+    extension: &'static str,
+    metadata: Metadata,
+}
+
+pub fn crawl(start: &str, options: &OpdsOptions) -> Result<OpdsCrawl> {
+    let start = Url::parse(start).context(format!("Invalid OPDS URL: {start}"))?;
+    fs::create_dir_all(&options.cache_dir).context(format!(
+        "Failed to create OPDS cache directory: {}",
+        options.cache_dir.display()
+    ))?;
+
+    let mut crawler = Crawler {
+        options,
+        made_request: false,
+    };
+    let mut crawl = OpdsCrawl {
+        books: Vec::new(),
+        failed: 0,
+    };
+    let mut queue = VecDeque::from([start]);
+    let mut visited = HashSet::new();
+
+    while let Some(feed_url) = queue.pop_front() {
+        if visited.len() >= options.max_feeds {
+            println!(
+                "Reached OPDS feed limit ({}), stopping crawl",
+                options.max_feeds
+            );
+            break;
+        }
+        if !visited.insert(feed_url.clone()) {
+            continue;
+        }
+
+        println!("Reading OPDS feed: {feed_url}");
+        let body = match crawler.fetch_text(&feed_url) {
+            Ok(body) => body,
+            Err(e) => {
+                eprintln!("  -> Error: {e:#}");
+                crawl.failed += 1;
+                continue;
+            }
+        };
+
+        let (feeds, acquisitions) = match parse_feed(&body, &feed_url) {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                eprintln!("  -> Error: {e:#}");
+                crawl.failed += 1;
+                continue;
+            }
+        };
+        queue.extend(feeds.into_iter().filter(|url| !visited.contains(url)));
+
+        for acquisition in acquisitions {
+            match crawler.download(&acquisition) {
+                Ok(path) => crawl.books.push(SourceFile {
+                    path,
+                    extension: acquisition.extension.to_string(),
+                    metadata: Some(acquisition.metadata),
+                }),
+                Err(e) => {
+                    eprintln!("  -> Error: {e:#}");
+                    crawl.failed += 1;
+                }
+            }
+        }
+    }
+
+    Ok(crawl)
+}
+
+/// Issues requests with a politeness delay between them.
+struct Crawler<'a> {
+    options: &'a OpdsOptions,
+    made_request: bool,
+}
+
+impl Crawler<'_> {
+    fn throttle(&mut self) {
+        if self.made_request {
+            thread::sleep(self.options.delay);
+        }
+        self.made_request = true;
+    }
+
+    fn fetch_text(&mut self, url: &Url) -> Result<String> {
+        self.throttle();
+        let mut response = ureq::get(url.as_str())
+            .header("User-Agent", USER_AGENT)
+            .call()
+            .context(format!("Failed to fetch OPDS feed: {url}"))?;
+        Ok(response.body_mut().read_to_string()?)
+    }
+
+    fn download(&mut self, acquisition: &Acquisition) -> Result<PathBuf> {
+        let title = acquisition.metadata.title.as_deref().unwrap_or("untitled");
+        let path = self
+            .options
+            .cache_dir
+            .join(cache_file_name(title, acquisition));
+
+        if path.exists() {
+            println!("  Cached: {}", path.display());
+            return Ok(path);
+        }
+
+        println!("  Downloading: {title}");
+        self.throttle();
+        let response = ureq::get(acquisition.url.as_str())
+            .header("User-Agent", USER_AGENT)
+            .call()
+            .context(format!("Failed to download: {}", acquisition.url))?;
+
+        // Write to a temporary name first so an interrupted download is never mistaken for a cached one
+        let partial = path.with_extension("part");
+        let mut file = fs::File::create(&partial)
+            .context(format!("Failed to create file: {}", partial.display()))?;
+        io::copy(&mut response.into_body().into_reader(), &mut file)
+            .context(format!("Failed to save download: {}", partial.display()))?;
+        fs::rename(&partial, &path).context(format!(
+            "Failed to move download into place: {}",
+            path.display()
+        ))?;
+
+        Ok(path)
+    }
+}
+
+/// Returns the catalog feeds linked from this page and the books it offers.
+fn parse_feed(body: &str, base: &Url) -> Result<(Vec<Url>, Vec<Acquisition>)> {
+    let xml = roxmltree::Document::parse(body).context("Failed to parse OPDS feed XML")?;
+    let root = xml.root_element();
+    let mut feeds = Vec::new();
+    let mut acquisitions = Vec::new();
+
+    // Feed-level links: only pagination, never "up"/"start" which would re-walk the catalog
+    for link in children(root, "link") {
+        if link.attribute("rel") == Some("next") {
+            feeds.extend(resolve(base, link.attribute("href")));
+        }
+    }
+
+    for entry in children(root, "entry") {
+        let links: Vec<_> = children(entry, "link").collect();
+
+        match pick_acquisition(&links) {
+            Some((href, extension)) => {
+                if let Some(url) = resolve(base, Some(href)) {
+                    acquisitions.push(Acquisition {
+                        url,
+                        extension,
+                        metadata: entry_metadata(entry),
+                    });
+                }
+            }
+            None => feeds.extend(
+                links
+                    .iter()
+                    .filter(|link| is_navigation_link(link))
+                    .filter_map(|link| resolve(base, link.attribute("href"))),
+            ),
+        }
+    }
+
+    Ok((feeds, acquisitions))
+}
+
+fn children<'a, 'input>(
+    node: roxmltree::Node<'a, 'input>,
+    name: &'static str,
+) -> impl Iterator<Item = roxmltree::Node<'a, 'input>> {
+    node.children()
+        .filter(move |child| child.is_element() && child.tag_name().name() == name)
+}
+
+fn child_text(node: roxmltree::Node, name: &'static str) -> Option<String> {
+    children(node, name)
+        .next()
+        .and_then(|child| child.text())
+        .map(|text| text.trim().to_string())
+        .filter(|text| !text.is_empty())
+}
+
+fn entry_metadata(entry: roxmltree::Node) -> Metadata {
+    let authors: Vec<_> = children(entry, "author")
+        .filter_map(|author| child_text(author, "name"))
+        .collect();
+
+    Metadata {
+        title: child_text(entry, "title"),
+        author: (!authors.is_empty()).then(|| authors.join(" & ")),
+        language: child_text(entry, "language"),
+        collection: None,
+    }
+}
+
+fn pick_acquisition<'a>(links: &[roxmltree::Node<'a, '_>]) -> Option<(&'a str, &'static str)> {
+    ACQUISITION_TYPES.iter().find_map(|(mime, extension)| {
+        links.iter().find_map(|link| {
+            let rel = link.attribute("rel")?;
+            let link_type = link.attribute("type")?;
+            let open = rel == "http://opds-spec.org/acquisition"
+                || rel == "http://opds-spec.org/acquisition/open-access";
+            (open && link_type.starts_with(mime))
+                .then(|| link.attribute("href"))
+                .flatten()
+                .map(|href| (href, *extension))
+        })
+    })
+}
+
+fn is_navigation_link(link: &roxmltree::Node) -> bool {
+    let link_type = link.attribute("type").unwrap_or_default();
+    let rel = link.attribute("rel").unwrap_or("subsection");
+    link_type.starts_with("application/atom+xml")
+        && !link_type.contains("type=entry")
+        && !matches!(rel, "self" | "start" | "up" | "search" | "alternate")
+}
+
+fn resolve(base: &Url, href: Option<&str>) -> Option<Url> {
+    href.and_then(|href| base.join(href).ok())
+}
+
+/// The cache file of an acquisition: its title, and a digest of its URL so books of the same
+/// title are not mistaken for each other, as in `Emma (1f0c9a3e).epub`.
+fn cache_file_name(title: &str, acquisition: &Acquisition) -> String {
+    format!(
+        "{} ({}).{}",
+        cache_stem(title),
+        provenance::short_hash(acquisition.url.as_str()),
+        acquisition.extension
+    )
+}
+
+/// Turns a book title into a filesystem-friendly cache file stem.
+fn cache_stem(title: &str) -> String {
+    let stem: String = title
+        .chars()
+        .map(|ch| {
+            if ch.is_alphanumeric() || matches!(ch, ' ' | '-' | '_' | '.') {
+                ch
+            } else {
+                '_'
+            }
+        })
+        .take(120)
+        .collect();
+
+    let stem = stem.trim().trim_end_matches('.');
+    if stem.is_empty() {
+        "untitled".to_string()
+    } else {
+        stem.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn acquisition(url: &str) -> Acquisition {
+        Acquisition {
+            url: Url::parse(url).unwrap(),
+            extension: "epub",
+            metadata: Metadata::default(),
+        }
+    }
+
+    #[test]
+    fn books_of_the_same_title_are_cached_apart() {
+        let first = cache_file_name("Poems", &acquisition("https://example.org/books/1.epub"));
+        let second = cache_file_name("Poems", &acquisition("https://example.org/books/2.epub"));
+        assert_ne!(first, second);
+        assert!(first.starts_with("Poems (") && first.ends_with(").epub"));
+        assert_eq!(
+            first,
+            cache_file_name("Poems", &acquisition("https://example.org/books/1.epub"))
+        );
+    }
+
+    #[test]
+    fn cache_stems() {
+        assert_eq!(cache_stem("War/Peace: Vol. 1."), "War_Peace_ Vol. 1");
+        assert_eq!(cache_stem("  "), "untitled");
+    }
+}
@@ -0,0 +1,161 @@
+//! PDF outlines (bookmarks).
+
+use pdf_extract::{Dictionary, Document, Object, ObjectId};
+use std::collections::{HashMap, HashSet};
+
+use crate::document::OutlineEntry;
+use crate::structure::text_string;
+
+/// The document's bookmarks in outline order, with the index of the page each one opens.
+///
+/// Bookmarks whose destination cannot be resolved to a page are skipped.
+pub fn read(doc: &Document) -> Vec<OutlineEntry> {
+    let Some(first) = doc
+        .catalog()
+        .ok()
+        .and_then(|catalog| catalog.get_deref(b"Outlines", doc).ok())
+        .and_then(|outlines| outlines.as_dict().ok())
+        .and_then(|outlines| outlines.get(b"First").ok())
+    else {
+        return Vec::new();
+    };
+
+    let pages: HashMap<ObjectId, usize> = doc
+        .get_pages()
+        .into_values()
+        .enumerate()
+        .map(|(index, id)| (id, index))
+        .collect();
+    let mut reader = OutlineReader {
+        doc,
+        pages,
+        visited: HashSet::new(),
+        entries: Vec::new(),
+    };
+    reader.read_siblings(first, 1);
+    reader.entries
+}
+
+struct OutlineReader<'a> {
+    doc: &'a Document,
+    pages: HashMap<ObjectId, usize>,
+    /// Guards against outlines whose /Next or /First links loop
+    visited: HashSet<ObjectId>,
+    entries: Vec<OutlineEntry>,
+}
+
+impl<'a> OutlineReader<'a> {
+    fn read_siblings(&mut self, first: &Object, level: usize) {
+        let mut next = first.as_reference().ok();
+        while let Some(id) = next {
+            if !self.visited.insert(id) {
+                return;
+            }
+            let Ok(item) = self.doc.get_dictionary(id) else {
+                return;
+            };
+
+            let page = self.destination_page(item);
+            let title = item.get(b"Title").ok().and_then(text_string);
+            if let (Some(title), Some(page)) = (title, page) {
+                self.entries.push(OutlineEntry {
+                    title,
+                    level,
+                    section: page,
+                });
+            }
+            if let Ok(child) = item.get(b"First") {
+                self.read_siblings(child, level + 1);
+            }
+            next = item.get(b"Next").and_then(Object::as_reference).ok();
+        }
+    }
+
+    /// Page index of the item's `/Dest`, or of its `/A` GoTo action.
+    fn destination_page(&self, item: &Dictionary) -> Option<usize> {
+        let dest = match item.get(b"Dest") {
+            Ok(dest) => dest,
+            Err(_) => {
+                let action = item.get_deref(b"A", self.doc).ok()?.as_dict().ok()?;
+                if action.get(b"S").and_then(Object::as_name).ok()? != b"GoTo" {
+                    return None;
+                }
+                action.get(b"D").ok()?
+            }
+        };
+        self.resolve(dest, 0)
+    }
+
+    fn resolve(&self, dest: &Object, depth: usize) -> Option<usize> {
+        // Named destinations may point at other names, but never very deeply
+        if depth > 8 {
+            return None;
+        }
+        match self.doc.dereference(dest).ok()?.1 {
+            Object::Array(explicit) => {
+                let page = explicit.first()?;
+                match page {
+                    Object::Reference(id) => self.pages.get(id).copied(),
+                    // Some writers give a page index instead of a page reference
+                    Object::Integer(index) => usize::try_from(*index).ok(),
+                    _ => None,
+                }
+            }
+            // A destination dictionary wraps the array in /D
+            Object::Dictionary(dict) => self.resolve(dict.get(b"D").ok()?, depth + 1),
+            Object::Name(name) => self.resolve(self.named(name)?, depth + 1),
+            Object::String(name, _) => self.resolve(self.named(name)?, depth + 1),
+            _ => None,
+        }
+    }
+
+    /// Looks a named destination up in the catalog's `/Dests` or the `/Names` tree.
+    fn named(&self, name: &[u8]) -> Option<&'a Object> {
+        let catalog = self.doc.catalog().ok()?;
+        if let Ok(dest) = catalog
+            .get_deref(b"Dests", self.doc)
+            .and_then(Object::as_dict)
+            .and_then(|dests| dests.get(name))
+        {
+            return Some(dest);
+        }
+
+        let tree = catalog
+            .get_deref(b"Names", self.doc)
+            .and_then(Object::as_dict)
+            .and_then(|names| names.get_deref(b"Dests", self.doc))
+            .and_then(Object::as_dict)
+            .ok()?;
+        self.name_tree_lookup(tree, name, 0)
+    }
+
+    fn name_tree_lookup(
+        &self,
+        node: &'a Dictionary,
+        name: &[u8],
+        depth: usize,
+    ) -> Option<&'a Object> {
+        if depth > 32 {
+            return None;
+        }
+        if let Ok(names) = node
+            .get_deref(b"Names", self.doc)
+            .and_then(Object::as_array)
+        {
+            let found = names
+                .chunks_exact(2)
+                .find(|pair| pair[0].as_str().ok() == Some(name))
+                .map(|pair| &pair[1]);
+            if found.is_some() {
+                return found;
+            }
+        }
+        let kids = node
+            .get_deref(b"Kids", self.doc)
+            .and_then(Object::as_array)
+            .ok()?;
+        kids.iter()
+            .filter_map(|kid| self.doc.dereference(kid).ok()?.1.as_dict().ok())
+            .find_map(|kid| self.name_tree_lookup(kid, name, depth + 1))
+    }
+}
@@ -0,0 +1,964 @@
+//! Rendering extracted documents and writing them to the output directory.
+
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Condvar, Mutex};
+
+use crate::document::{Document, SectionKind, CODE_FENCE};
+use crate::encoding::{self, Encoded, OutputEncoding};
+use crate::json;
+use crate::markdown;
+use crate::metadata::{append_metadata, Metadata, MetadataSidecar};
+use crate::naming;
+#[cfg(feature = "parquet")]
+use crate::parquet_file;
+use crate::provenance::{self, Provenance, ProvenanceOptions};
+use crate::source;
+use crate::split::{self, Part, SplitMode};
+use crate::sqlite;
+use crate::ssml::{self, SsmlOptions};
+use crate::stamps::Stamps;
+use crate::tables;
+use crate::wrap::{self, Wrap};
+
+/// Folder of the output directory that outputs with little or no text are moved into.
+pub const QUARANTINE_DIR: &str = "quarantine";
+
+/// File that `--format jsonl` appends to when the output is a folder.
+pub const CORPUS_FILE: &str = "corpus.jsonl";
+
+/// Database that `--format sqlite` writes to when the output is a folder.
+pub const DATABASE_FILE: &str = "library.db";
+
+/// File that `--format parquet` writes when the output is a folder.
+#[cfg(feature = "parquet")]
+pub const PARQUET_FILE: &str = "corpus.parquet";
+
+/// Header written before each document of a combined file when no other is given.
+pub const DEFAULT_DOCUMENT_SEPARATOR: &str = "===== {name} =====";
+
+/// Held while a record is appended to a corpus or a combined file, so records of files
+/// extracted at once do not interleave.
+static CORPUS_LOCK: Mutex<()> = Mutex::new(());
+
+/// What the outputs of one run share: the output paths handed out so far, so two sources of
+/// the same name (`a/book.pdf` and `b/book.epub`) do not overwrite each other's output, and
+/// the turns of the sources at a corpus, database or combined file, so their records are
+/// written in the order the sources were found whichever finishes first. Each run makes its
+/// own, so nothing carries over into the next.
+#[derive(Default)]
+pub struct OutputRun {
+    claims: Mutex<Claims>,
+    turns: Mutex<Turns>,
+    turn_taken: Condvar,
+}
+
+#[derive(Default)]
+struct Turns {
+    /// Sources done with, by position in discovery order
+    done: Vec<bool>,
+    /// Position of the first source not done with; its record is the next written
+    next: usize,
+}
+
+#[derive(Default)]
+struct Claims {
+    /// Source of each output path, keyed in lowercase for case-insensitive file systems
+    owners: HashMap<String, PathBuf>,
+    /// Sources whose output was renamed, with the first name it got
+    renamed: Vec<(PathBuf, PathBuf)>,
+}
+
+/// Files written next to an output file, named after it.
+const SIDECAR_SUFFIXES: [&str; 3] = [".provenance.json", ".stamps.json", ".meta.json"];
+
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    /// Plain text with a metadata header
+    #[default]
+    Text,
+    /// Speech Synthesis Markup Language for TTS engines
+    Ssml,
+    /// GitHub-flavoured Markdown, with headings, emphasis, lists and tables kept as Markdown
+    #[cfg_attr(feature = "cli", value(alias = "md"))]
+    Markdown,
+    /// JSON with the metadata, page and chapter counts and one text block per page or chapter
+    Json,
+    /// One JSON object per document (source, metadata and text) appended to a single corpus file
+    Jsonl,
+    /// One SQLite database of documents, their pages or chapters and a full-text (FTS5) index
+    Sqlite,
+    /// One Parquet file with a row of source path, title, author, language, page count and
+    /// text per document
+    #[cfg(feature = "parquet")]
+    Parquet,
+}
+
+impl OutputFormat {
+    /// The format `name` names on the command line, in any case.
+    pub fn from_name(name: &str) -> Option<OutputFormat> {
+        match name.to_ascii_lowercase().as_str() {
+            "text" => Some(OutputFormat::Text),
+            "ssml" => Some(OutputFormat::Ssml),
+            "markdown" | "md" => Some(OutputFormat::Markdown),
+            "json" => Some(OutputFormat::Json),
+            "jsonl" => Some(OutputFormat::Jsonl),
+            "sqlite" => Some(OutputFormat::Sqlite),
+            #[cfg(feature = "parquet")]
+            "parquet" => Some(OutputFormat::Parquet),
+            _ => None,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Text => "txt",
+            OutputFormat::Ssml => "ssml",
+            OutputFormat::Markdown => "md",
+            OutputFormat::Json => "json",
+            OutputFormat::Jsonl => "jsonl",
+            OutputFormat::Sqlite => "db",
+            #[cfg(feature = "parquet")]
+            OutputFormat::Parquet => "parquet",
+        }
+    }
+
+    /// Whether every document goes into one file, a corpus or a database, rather than a file
+    /// of its own.
+    pub fn is_collection(self) -> bool {
+        #[cfg(feature = "parquet")]
+        if self == OutputFormat::Parquet {
+            return true;
+        }
+        matches!(self, OutputFormat::Jsonl | OutputFormat::Sqlite)
+    }
+
+    /// Whether the output is only complete once [`finish_outputs`] is called at the end of a
+    /// run, so runs that never end cannot write it.
+    pub fn is_finished_at_end(self) -> bool {
+        #[cfg(feature = "parquet")]
+        if self == OutputFormat::Parquet {
+            return true;
+        }
+        false
+    }
+}
+
+/// Line endings of the written files.
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Newline {
+    /// Unix line feeds
+    #[default]
+    Lf,
+    /// Windows carriage return and line feed pairs
+    Crlf,
+    /// Whichever the platform running the extraction uses
+    Platform,
+}
+
+impl Newline {
+    fn as_str(self) -> &'static str {
+        match self {
+            Newline::Lf => "\n",
+            Newline::Crlf => "\r\n",
+            Newline::Platform if cfg!(windows) => "\r\n",
+            Newline::Platform => "\n",
+        }
+    }
+
+    /// `text` with every line ending, whatever the source used, made this one.
+    pub fn apply(self, text: &str) -> String {
+        let text = text.replace("\r\n", "\n").replace('\r', "\n");
+        match self.as_str() {
+            "\n" => text,
+            newline => text.replace('\n', newline),
+        }
+    }
+}
+
+/// What to do when an output is already there from an earlier run.
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OnConflict {
+    /// Replace it
+    #[default]
+    Overwrite,
+    /// Leave it, and the file it came from, alone
+    Skip,
+    /// Leave it alone and report the file as failed
+    Error,
+    /// Write the new output next to it, numbered: book (2).txt
+    Rename,
+}
+
+/// An output that was not written because one is already there; see [`OnConflict`].
+#[derive(Debug)]
+pub struct OutputExists {
+    pub path: PathBuf,
+}
+
+impl fmt::Display for OutputExists {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "output already exists: {}", self.path.display())
+    }
+}
+
+impl std::error::Error for OutputExists {}
+
+#[derive(Clone)]
+pub struct OutputOptions {
+    pub format: OutputFormat,
+    pub wrap: Wrap,
+    /// Template of the line written before each PDF page of text output
+    pub page_separator: Option<String>,
+    pub ssml: SsmlOptions,
+    /// Split into one file per bookmark or per contained work
+    pub split: Option<SplitMode>,
+    /// Deepest outline level that starts a new file with [`SplitMode::Bookmarks`]
+    pub split_level: usize,
+    pub provenance: ProvenanceOptions,
+    /// Write a sidecar with the Bates number and exhibit stamp of each PDF page
+    pub stamps: bool,
+    /// Write a sidecar with the document's metadata, source hash and extraction time
+    pub metadata_sidecar: bool,
+    /// Character encoding of the written files; JSON and JSON Lines are always UTF-8
+    pub encoding: OutputEncoding,
+    pub newline: Newline,
+    /// Append every document to this one file, relative to the output directory, instead of
+    /// writing one output per input
+    pub combine: Option<PathBuf>,
+    /// Template of the line written before each document of a combined file
+    pub document_separator: String,
+    /// Folders whose layout is reproduced in the output directory: the output of an input
+    /// under one of them goes into the subfolder it is in; other outputs go to the top
+    pub mirror_tree: Vec<PathBuf>,
+    /// Template of output names built from the document's metadata, e.g. `{author} - {title}`,
+    /// instead of the input's name
+    pub name_template: Option<String>,
+    /// What to do about outputs already there; corpora and combined files are appended to
+    pub on_conflict: OnConflict,
+}
+
+impl Default for OutputOptions {
+    /// Plain text, one file per input, as extracted.
+    fn default() -> Self {
+        OutputOptions {
+            format: OutputFormat::default(),
+            wrap: Wrap::default(),
+            page_separator: None,
+            ssml: SsmlOptions::default(),
+            split: None,
+            split_level: 1,
+            provenance: ProvenanceOptions::default(),
+            stamps: false,
+            metadata_sidecar: false,
+            encoding: OutputEncoding::default(),
+            newline: Newline::default(),
+            combine: None,
+            document_separator: DEFAULT_DOCUMENT_SEPARATOR.to_string(),
+            mirror_tree: Vec::new(),
+            name_template: None,
+            on_conflict: OnConflict::default(),
+        }
+    }
+}
+
+/// Renders `doc`, extracted from `source`, in the output format.
+pub fn render(doc: &Document, source: &Path, options: &OutputOptions) -> String {
+    match options.format {
+        OutputFormat::Text => render_text(doc, options, true),
+        OutputFormat::Ssml => ssml::render(doc, &options.ssml),
+        OutputFormat::Markdown => markdown::render(doc, options.wrap),
+        OutputFormat::Json => json::render(doc, source),
+        OutputFormat::Jsonl => json::record(doc, source, &render_text(doc, options, false)),
+        // The text a database or Parquet row holds
+        OutputFormat::Sqlite => render_text(doc, options, false),
+        #[cfg(feature = "parquet")]
+        OutputFormat::Parquet => render_text(doc, options, false),
+    }
+}
+
+/// The text of `doc`, after its metadata header when `header` is set.
+fn render_text(doc: &Document, options: &OutputOptions, header: bool) -> String {
+    let wrap = options.wrap;
+    let page_separator = options.page_separator.as_deref();
+    let mut text = String::new();
+
+    if header && !doc.metadata.is_empty() {
+        append_metadata(&doc.metadata, &mut text);
+    }
+
+    for section in &doc.sections {
+        if let (Some(template), Some(page)) = (page_separator, section.page) {
+            if !text.is_empty() && !text.ends_with('\n') {
+                text.push('\n');
+            }
+            text.push_str(&page_separator_line(template, page));
+            text.push('\n');
+        }
+
+        match wrap {
+            Wrap::Preserve => {
+                text.push_str(&section.text);
+                if section.kind == SectionKind::Chapter {
+                    text.push_str("\n\n");
+                }
+            }
+            Wrap::Paragraph => {
+                for paragraph in section.paragraphs() {
+                    text.push_str(&paragraph);
+                    text.push('\n');
+                }
+            }
+            Wrap::Width(width) => {
+                // Blank lines keep paragraphs apart once their lines have been reflowed
+                for paragraph in section.paragraphs() {
+                    if paragraph.starts_with(CODE_FENCE) || tables::is_tsv_table(&paragraph) {
+                        text.push_str(&paragraph);
+                        text.push_str("\n\n");
+                        continue;
+                    }
+                    for line in wrap::wrap_paragraph(&paragraph, width) {
+                        text.push_str(&line);
+                        text.push('\n');
+                    }
+                    text.push('\n');
+                }
+            }
+        }
+    }
+
+    text
+}
+
+/// Fills in a `--page-separator` template: `{page}` becomes the page number and `\f` a form
+/// feed.
+fn page_separator_line(template: &str, page: usize) -> String {
+    template
+        .replace("{page}", &page.to_string())
+        .replace("\\f", "\u{c}")
+}
+
+/// What [`write_document`] wrote.
+pub struct Written {
+    /// The output file, or the folder of parts when splitting
+    pub path: PathBuf,
+    /// Characters the output encoding could not represent, written as `?`
+    pub unmappable: usize,
+}
+
+/// Renders `doc` and writes it next to the other outputs, returning the written path.
+///
+/// When splitting, the parts go into a folder named after the input, which is returned
+/// instead; documents that cannot be split are written whole. JSON Lines records are
+/// appended to the corpus file instead, SQLite and Parquet rows written to their file, and with
+/// `combine` documents to the combined file, whole documents only. Those wait for the turn of
+/// the source at `position` in [`OutputRun::reserve`], if it has one.
+pub fn write_document(
+    doc: &Document,
+    input_path: &Path,
+    output_dir: &Path,
+    options: &OutputOptions,
+    provenance: &Provenance,
+    run: &OutputRun,
+    position: Option<usize>,
+) -> Result<Written> {
+    if options.format == OutputFormat::Jsonl {
+        let corpus = corpus_path(output_dir, options.format);
+        let record = options.newline.apply(&render(doc, input_path, options));
+        run.wait_for_turn(position);
+        append_record(&corpus, record.as_bytes(), &[])?;
+        return Ok(Written {
+            path: corpus,
+            unmappable: 0,
+        });
+    }
+    if options.format == OutputFormat::Sqlite {
+        let database = corpus_path(output_dir, options.format);
+        let text = render(doc, input_path, options);
+        run.wait_for_turn(position);
+        sqlite::write_document(&database, doc, input_path, &text, provenance)?;
+        return Ok(Written {
+            path: database,
+            unmappable: 0,
+        });
+    }
+    #[cfg(feature = "parquet")]
+    if options.format == OutputFormat::Parquet {
+        let file = corpus_path(output_dir, options.format);
+        let text = render(doc, input_path, options);
+        run.wait_for_turn(position);
+        parquet_file::write_document(&file, doc, input_path, &text)?;
+        return Ok(Written {
+            path: file,
+            unmappable: 0,
+        });
+    }
+    if let Some(combined) = &options.combine {
+        let path = output_dir.join(combined);
+        run.wait_for_turn(position);
+        let unmappable = append_document(doc, input_path, &path, options, provenance)?;
+        return Ok(Written { path, unmappable });
+    }
+
+    let output_dir = &mirrored_dir(input_path, output_dir, options);
+    let name = output_name(input_path, Some(&doc.metadata), options)?;
+    if let Some(mode) = options.split {
+        let parts = split::parts(doc, mode, options.split_level);
+        if !parts.is_empty() {
+            let folder = run.parts_folder(input_path, output_dir, &name);
+            let folder = run.settle_conflict(input_path, folder, false, options.on_conflict)?;
+            return write_parts(&parts, input_path, &folder, options, provenance);
+        }
+    }
+
+    let output_path = run.output_path(input_path, output_dir, &name, options.format.extension());
+    let output_path = run.settle_conflict(input_path, output_path, true, options.on_conflict)?;
+    // Templates may put the output in folders of its own
+    let folder = output_path.parent().unwrap_or(output_dir);
+    fs::create_dir_all(folder).context(format!(
+        "Failed to create output directory: {}",
+        folder.display()
+    ))?;
+    let unmappable = write_output(doc, input_path, &output_path, options, provenance)?;
+    Ok(Written {
+        path: output_path,
+        unmappable,
+    })
+}
+
+/// Completes the outputs that are only written out at the end of a run (`--format
+/// parquet`); call it once every document is written.
+pub fn finish_outputs() -> Result<()> {
+    #[cfg(feature = "parquet")]
+    parquet_file::finish()?;
+    Ok(())
+}
+
+/// The folder of `output_dir` that the output of `input_path` goes into: the one matching
+/// the input's folder under the first [`OutputOptions::mirror_tree`] root that holds it, else
+/// `output_dir` itself.
+fn mirrored_dir(input_path: &Path, output_dir: &Path, options: &OutputOptions) -> PathBuf {
+    let relative = options
+        .mirror_tree
+        .iter()
+        .find_map(|root| input_path.parent()?.strip_prefix(root).ok());
+    match relative {
+        Some(relative) => output_dir.join(relative),
+        None => output_dir.to_path_buf(),
+    }
+}
+
+/// The corpus file `--format jsonl` appends to, or the database or Parquet file `--format
+/// sqlite` or `parquet` write to: `output` itself, or [`CORPUS_FILE`], [`DATABASE_FILE`] or
+/// `PARQUET_FILE` in it when it is a folder.
+pub fn corpus_path(output: &Path, format: OutputFormat) -> PathBuf {
+    if output.is_dir() {
+        match format {
+            OutputFormat::Sqlite => output.join(DATABASE_FILE),
+            #[cfg(feature = "parquet")]
+            OutputFormat::Parquet => output.join(PARQUET_FILE),
+            _ => output.join(CORPUS_FILE),
+        }
+    } else {
+        output.to_path_buf()
+    }
+}
+
+/// Appends `record` to `corpus`, leaving off the byte order mark `bom` it starts with unless
+/// the file is new.
+fn append_record(corpus: &Path, record: &[u8], bom: &[u8]) -> Result<()> {
+    let _lock = CORPUS_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(corpus)
+        .context(format!("Failed to open corpus file: {}", corpus.display()))?;
+    let started = file.metadata().is_ok_and(|m| m.len() > 0);
+    let record = match record.strip_prefix(bom) {
+        Some(rest) if started => rest,
+        _ => record,
+    };
+    file.write_all(record).context(format!(
+        "Failed to append to corpus file: {}",
+        corpus.display()
+    ))
+}
+
+/// Appends `doc` to the combined file `path` after a separator line naming it, returning the
+/// number of characters the output encoding could not represent.
+fn append_document(
+    doc: &Document,
+    input_path: &Path,
+    path: &Path,
+    options: &OutputOptions,
+    provenance: &Provenance,
+) -> Result<usize> {
+    let mut rendered = document_separator_line(&options.document_separator, doc, input_path);
+    rendered.push_str("\n\n");
+    if options.provenance.header && options.format == OutputFormat::Text {
+        rendered.push_str(&provenance.header());
+    }
+    rendered.push_str(render(doc, input_path, options).trim_end());
+    // A blank line before the next document's separator
+    rendered.push_str("\n\n");
+
+    let encoded = encoding::encode(&options.newline.apply(&rendered), options.encoding);
+    append_record(path, &encoded.bytes, options.encoding.bom())?;
+    Ok(encoded.unmappable)
+}
+
+/// Fills in a `--document-separator` template: `{source}` becomes the input path, `{name}`
+/// its file name, `{title}` the document's title (else the file name) and `\f` a form feed.
+fn document_separator_line(template: &str, doc: &Document, input_path: &Path) -> String {
+    let name = input_path
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
+    let title = doc.metadata.title.as_deref().unwrap_or(&name);
+    template
+        .replace("\\f", "\u{c}")
+        .replace("{source}", &input_path.display().to_string())
+        .replace("{name}", &name)
+        .replace("{title}", title)
+}
+
+/// Writes one rendered output, with the provenance header and sidecars if requested,
+/// returning the number of characters the output encoding could not represent.
+fn write_output(
+    doc: &Document,
+    input_path: &Path,
+    output_path: &Path,
+    options: &OutputOptions,
+    provenance: &Provenance,
+) -> Result<usize> {
+    let encoded = encode_document(doc, input_path, options, provenance);
+    fs::write(output_path, encoded.bytes).context(format!(
+        "Failed to write output file: {}",
+        output_path.display()
+    ))?;
+
+    if options.provenance.sidecar {
+        provenance.write_sidecar(output_path)?;
+    }
+    if options.stamps {
+        if let Some(stamps) = Stamps::of(doc) {
+            stamps.write_sidecar(output_path)?;
+        }
+    }
+    if options.metadata_sidecar {
+        MetadataSidecar::of(doc, input_path, provenance).write_sidecar(output_path)?;
+    }
+    Ok(encoded.unmappable)
+}
+
+/// `doc` rendered and encoded as its output file holds it, for writing anywhere.
+pub(crate) fn encode_document(
+    doc: &Document,
+    input_path: &Path,
+    options: &OutputOptions,
+    provenance: &Provenance,
+) -> Encoded {
+    let mut rendered = render(doc, input_path, options);
+    if options.provenance.header && options.format == OutputFormat::Text {
+        rendered.insert_str(0, &provenance.header());
+    }
+
+    let encoding = match options.format {
+        OutputFormat::Json | OutputFormat::Jsonl => OutputEncoding::Utf8,
+        _ => options.encoding,
+    };
+    if options.format == OutputFormat::Ssml && encoding != OutputEncoding::Utf8 {
+        // The XML declaration has to name the encoding the file is actually in
+        rendered = rendered.replacen(
+            "encoding=\"UTF-8\"",
+            &format!("encoding=\"{}\"", encoding.label()),
+            1,
+        );
+    }
+    encoding::encode(&options.newline.apply(&rendered), encoding)
+}
+
+/// Writes the parts of a split document into `folder`.
+fn write_parts(
+    parts: &[Part],
+    input_path: &Path,
+    folder: &Path,
+    options: &OutputOptions,
+    provenance: &Provenance,
+) -> Result<Written> {
+    fs::create_dir_all(folder).context(format!(
+        "Failed to create output directory: {}",
+        folder.display()
+    ))?;
+
+    // Numbered so the files sort in reading order and repeated titles stay apart
+    let digits = parts.len().to_string().len().max(2);
+    let mut unmappable = 0;
+    for (i, part) in parts.iter().enumerate() {
+        let file_name = format!(
+            "{:0digits$} - {}.{}",
+            i + 1,
+            naming::file_name_safe(&part.title),
+            options.format.extension()
+        );
+        unmappable += write_output(
+            &part.doc,
+            input_path,
+            &folder.join(file_name),
+            options,
+            provenance,
+        )?;
+    }
+
+    Ok(Written {
+        path: folder.to_path_buf(),
+        unmappable,
+    })
+}
+
+/// The output [`write_document`] would write for `input_path`, if it is already there: the
+/// folder of parts when splitting, else the single file. A corpus or database holds many documents, so it
+/// never counts as the output of one, and names from a template are only known once the
+/// document is extracted. The name is claimed in `run` as [`write_document`] would claim it.
+pub fn existing_output(
+    input_path: &Path,
+    output_dir: &Path,
+    options: &OutputOptions,
+    run: &OutputRun,
+) -> Option<PathBuf> {
+    if options.format.is_collection()
+        || options.combine.is_some()
+        || options.name_template.is_some()
+    {
+        return None;
+    }
+    let output_dir = &mirrored_dir(input_path, output_dir, options);
+    let name = output_name(input_path, None, options).ok()?;
+    if options.split.is_some() {
+        let folder = run.parts_folder(input_path, output_dir, &name);
+        if folder.is_dir() {
+            return Some(folder);
+        }
+    }
+    let output_path = run.output_path(input_path, output_dir, &name, options.format.extension());
+    output_path.is_file().then_some(output_path)
+}
+
+/// Moves an output written by [`write_document`], with its sidecars, into a `quarantine`
+/// folder of `output_dir`, returning its new path.
+pub fn quarantine(output_path: &Path, output_dir: &Path) -> Result<PathBuf> {
+    let folder = output_dir.join(QUARANTINE_DIR);
+    fs::create_dir_all(&folder).context(format!(
+        "Failed to create quarantine directory: {}",
+        folder.display()
+    ))?;
+    let name = output_path
+        .file_name()
+        .context("Failed to get output file name")?;
+    let target = folder.join(name);
+    // An earlier run's quarantined copy of the same output is replaced
+    if target.is_dir() {
+        fs::remove_dir_all(&target)?;
+    }
+    fs::rename(output_path, &target).context(format!(
+        "Failed to move {} into quarantine",
+        output_path.display()
+    ))?;
+
+    // Sidecars sit inside a folder of parts, but next to a single output file
+    for suffix in SIDECAR_SUFFIXES {
+        let mut sidecar = output_path.as_os_str().to_owned();
+        sidecar.push(suffix);
+        let sidecar = PathBuf::from(sidecar);
+        if sidecar.is_file() {
+            let mut moved = name.to_owned();
+            moved.push(suffix);
+            fs::rename(&sidecar, folder.join(moved)).context(format!(
+                "Failed to move {} into quarantine",
+                sidecar.display()
+            ))?;
+        }
+    }
+    Ok(target)
+}
+
+/// The name, without extension, of the output of `input_path`: the input's own, or the one
+/// the name template gives the document's `metadata`, made valid on every platform.
+fn output_name(
+    input_path: &Path,
+    metadata: Option<&Metadata>,
+    options: &OutputOptions,
+) -> Result<String> {
+    let file_stem = source::file_stem(input_path)
+        .context("Failed to get file stem")?
+        .to_string_lossy();
+    let name = match (&options.name_template, metadata) {
+        (Some(template), Some(metadata)) => {
+            let extension = source::extension(input_path).unwrap_or_default();
+            naming::render_template(template, &file_stem, &extension, metadata)
+        }
+        _ => file_stem.into_owned(),
+    };
+    Ok(name
+        .split('/')
+        .map(naming::portable)
+        .collect::<Vec<_>>()
+        .join("/"))
+}
+
+impl OutputRun {
+    /// Claims the outputs [`write_document`] will write for each of `input_paths`, in order,
+    /// so when sources share a name the first keeps it however many files are extracted at
+    /// once. Names from a template are claimed as documents are written instead.
+    ///
+    /// Records of a corpus, database or combined file are written in the same order: the
+    /// source at each position waits until every one before it is marked done with
+    /// [`OutputRun::source_done`], so every position must be, and sources should be started in
+    /// this order. A path listed twice has a turn at each of its positions.
+    pub fn reserve<'a>(
+        &self,
+        input_paths: impl IntoIterator<Item = &'a Path>,
+        output_dir: &Path,
+        options: &OutputOptions,
+    ) {
+        let input_paths: Vec<&Path> = input_paths.into_iter().collect();
+        {
+            let mut turns = self.turns.lock().unwrap_or_else(|e| e.into_inner());
+            *turns = Turns {
+                done: vec![false; input_paths.len()],
+                next: 0,
+            };
+        }
+        if options.format.is_collection()
+            || options.combine.is_some()
+            || options.name_template.is_some()
+        {
+            return;
+        }
+        for input_path in input_paths {
+            let output_dir = &mirrored_dir(input_path, output_dir, options);
+            let Ok(name) = output_name(input_path, None, options) else {
+                continue;
+            };
+            // Documents that cannot be split claim their file name when they are written
+            match options.split {
+                Some(_) => self.parts_folder(input_path, output_dir, &name),
+                None => self.output_path(input_path, output_dir, &name, options.format.extension()),
+            };
+        }
+    }
+
+    /// Marks the source at `position` in [`OutputRun::reserve`] as done with, written or not, so
+    /// the records of the sources after it may be written.
+    pub fn source_done(&self, position: usize) {
+        let mut turns = self.turns.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(done) = turns.done.get_mut(position) else {
+            return;
+        };
+        *done = true;
+        while turns.done.get(turns.next) == Some(&true) {
+            turns.next += 1;
+        }
+        self.turn_taken.notify_all();
+    }
+
+    /// Waits until every source reserved before `position` is done with; sources without a
+    /// reserved position go straight ahead.
+    fn wait_for_turn(&self, position: Option<usize>) {
+        let mut turns = self.turns.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(position) = position.filter(|&position| position < turns.done.len()) else {
+            return;
+        };
+        while turns.next < position {
+            turns = self
+                .turn_taken
+                .wait(turns)
+                .unwrap_or_else(|e| e.into_inner());
+        }
+    }
+
+    /// Sources whose output was given a name of its own because another source of the same name
+    /// already had the plain one, with the output path each got.
+    pub fn renamed(&self) -> Vec<(PathBuf, PathBuf)> {
+        let claims = self.claims.lock().unwrap_or_else(|e| e.into_inner());
+        claims.renamed.clone()
+    }
+
+    /// The path in `output_dir` of the single output file of `input_path`, claimed for it.
+    fn output_path(
+        &self,
+        input_path: &Path,
+        output_dir: &Path,
+        name: &str,
+        extension: &str,
+    ) -> PathBuf {
+        self.claim(input_path, output_dir, |suffix| {
+            format!("{name}{suffix}.{extension}")
+        })
+    }
+
+    /// The folder of `output_dir` that the parts of a split `input_path` go into.
+    fn parts_folder(&self, input_path: &Path, output_dir: &Path, name: &str) -> PathBuf {
+        self.claim(input_path, output_dir, |suffix| format!("{name}{suffix}"))
+    }
+
+    /// Where to write an output bound for `path`, a file or else a folder of parts, given what
+    /// `policy` says about one already there. Fails with [`OutputExists`] when it is to be left
+    /// alone.
+    fn settle_conflict(
+        &self,
+        input_path: &Path,
+        path: PathBuf,
+        is_file: bool,
+        policy: OnConflict,
+    ) -> Result<PathBuf> {
+        if !path.exists() || policy == OnConflict::Overwrite {
+            return Ok(path);
+        }
+        if policy != OnConflict::Rename {
+            return Err(OutputExists { path }.into());
+        }
+
+        let name = path
+            .file_name()
+            .context("Failed to get output file name")?
+            .to_string_lossy();
+        let (stem, extension) = match name.rsplit_once('.') {
+            Some((stem, extension)) if is_file => (stem, format!(".{extension}")),
+            _ => (&*name, String::new()),
+        };
+        let mut claims = self.claims.lock().unwrap_or_else(|e| e.into_inner());
+        let key = |path: &Path| path.to_string_lossy().to_lowercase();
+        // Numbers taken by outputs of this run are passed over too
+        let numbered = (2..)
+            .map(|number| path.with_file_name(format!("{stem} ({number}){extension}")))
+            .find(|numbered| !numbered.exists() && !claims.owners.contains_key(&key(numbered)))
+            .context("Failed to find a free output name")?;
+        claims
+            .owners
+            .insert(key(&numbered), input_path.to_path_buf());
+        Ok(numbered)
+    }
+
+    /// The path in `output_dir` named `name("")` for the output of `input_path`, unless another
+    /// source has it; then `name` is given a suffix from a digest of the source's path, as in
+    /// `book (1f0c9a3e).txt`.
+    fn claim(
+        &self,
+        input_path: &Path,
+        output_dir: &Path,
+        name: impl Fn(&str) -> String,
+    ) -> PathBuf {
+        let mut claims = self.claims.lock().unwrap_or_else(|e| e.into_inner());
+        let path = output_dir.join(name(""));
+        let key = path.to_string_lossy().to_lowercase();
+        match claims.owners.get(&key) {
+            Some(owner) if owner != input_path => {}
+            Some(_) => return path,
+            None => {
+                claims.owners.insert(key, input_path.to_path_buf());
+                return path;
+            }
+        }
+
+        let hash = provenance::short_hash(&input_path.to_string_lossy());
+        let renamed = output_dir.join(name(&format!(" ({hash})")));
+        let key = renamed.to_string_lossy().to_lowercase();
+        claims.owners.insert(key, input_path.to_path_buf());
+        if !claims
+            .renamed
+            .iter()
+            .any(|(source, _)| source == input_path)
+        {
+            claims
+                .renamed
+                .push((input_path.to_path_buf(), renamed.clone()));
+        }
+        renamed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{mpsc, Arc};
+    use std::thread;
+    use std::time::Duration;
+
+    /// Whether `run.wait_for_turn(position)` returns within a few seconds.
+    fn turn_comes(run: &Arc<OutputRun>, position: usize) -> bool {
+        let run = Arc::clone(run);
+        let (sent, received) = mpsc::channel();
+        thread::spawn(move || {
+            run.wait_for_turn(Some(position));
+            let _ = sent.send(());
+        });
+        received.recv_timeout(Duration::from_secs(5)).is_ok()
+    }
+
+    #[test]
+    fn a_path_listed_twice_has_a_turn_at_each_position() {
+        let output = tempfile::tempdir().unwrap();
+        let options = OutputOptions {
+            format: OutputFormat::Jsonl,
+            ..Default::default()
+        };
+        let book = Path::new("books/book.pdf");
+        let run = Arc::new(OutputRun::default());
+        run.reserve(
+            [book, Path::new("other.pdf"), book],
+            output.path(),
+            &options,
+        );
+
+        assert!(turn_comes(&run, 0));
+        assert!(!turn_comes(&run, 2));
+        run.source_done(0);
+        assert!(turn_comes(&run, 1));
+        run.source_done(1);
+        assert!(turn_comes(&run, 2));
+        run.source_done(2);
+    }
+
+    #[test]
+    fn names_are_claimed_within_one_run() {
+        let output = Path::new("out");
+        let options = OutputOptions::default();
+        let first = Path::new("a/book.pdf");
+        let second = Path::new("b/book.epub");
+
+        let run = OutputRun::default();
+        run.reserve([first, second], output, &options);
+        assert_eq!(
+            run.output_path(first, output, "book", "txt"),
+            output.join("book.txt")
+        );
+        let renamed = run.output_path(second, output, "book", "txt");
+        assert_ne!(renamed, output.join("book.txt"));
+        assert_eq!(run.renamed(), [(second.to_path_buf(), renamed)]);
+
+        let next = OutputRun::default();
+        next.reserve([second], output, &options);
+        assert_eq!(
+            next.output_path(second, output, "book", "txt"),
+            output.join("book.txt")
+        );
+        assert!(next.renamed().is_empty());
+    }
+
+    #[test]
+    fn format_names() {
+        assert_eq!(OutputFormat::from_name("text"), Some(OutputFormat::Text));
+        assert_eq!(OutputFormat::from_name("MD"), Some(OutputFormat::Markdown));
+        assert_eq!(OutputFormat::from_name("Jsonl"), Some(OutputFormat::Jsonl));
+        assert_eq!(OutputFormat::from_name("txt"), None);
+        assert_eq!(OutputFormat::from_name(""), None);
+    }
+}
@@ -0,0 +1,79 @@
+//! Page ranges to extract from PDFs, such as `1-20,35,40-`, for leaving covers, indexes and
+//! appendices out of a corpus.
+
+/// Which pages to extract, by number counting from 1.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PageSelection {
+    /// Inclusive ranges, open-ended when the end is `None`; empty means every page
+    ranges: Vec<(usize, Option<usize>)>,
+}
+
+impl PageSelection {
+    pub fn is_all(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// Whether page `page`, counting from 1, is selected.
+    pub fn contains(&self, page: usize) -> bool {
+        self.is_all()
+            || self
+                .ranges
+                .iter()
+                .any(|&(first, last)| page >= first && last.is_none_or(|last| page <= last))
+    }
+}
+
+/// Parses page lists such as `1-20,35,40-`: single pages, ranges, and ranges open at either
+/// end (`-5` for the first five pages). Chapter lists are written the same way.
+pub fn parse_pages(text: &str) -> Result<PageSelection, String> {
+    let number = |part: &str| match part.trim().parse::<usize>() {
+        Ok(0) => Err(format!("numbering starts at 1: {text}")),
+        Ok(page) => Ok(page),
+        Err(_) => Err(format!("invalid range: {text} (use e.g. 1-20,35,40-)")),
+    };
+
+    let mut ranges = Vec::new();
+    for part in text
+        .split(',')
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+    {
+        let range = match part.split_once('-') {
+            Some((first, "")) => (number(first)?, None),
+            Some(("", last)) => (1, Some(number(last)?)),
+            Some((first, last)) => (number(first)?, Some(number(last)?)),
+            None => (number(part)?, Some(number(part)?)),
+        };
+        if range.1.is_some_and(|last| last < range.0) {
+            return Err(format!("range runs backwards: {part}"));
+        }
+        ranges.push(range);
+    }
+    if ranges.is_empty() {
+        return Err("no numbers given".to_string());
+    }
+    Ok(PageSelection { ranges })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn page_lists() {
+        let pages = parse_pages("1-3, 7,10-").unwrap();
+        let selected: Vec<usize> = (1..=12).filter(|&page| pages.contains(page)).collect();
+        assert_eq!(selected, [1, 2, 3, 7, 10, 11, 12]);
+
+        let first_two = parse_pages("-2").unwrap();
+        assert!(first_two.contains(2) && !first_two.contains(3));
+        assert!(PageSelection::default().contains(1_000));
+    }
+
+    #[test]
+    fn invalid_page_lists() {
+        for text in ["", ",", "0", "0-3", "5-2", "a", "1-b", "1--3"] {
+            assert!(parse_pages(text).is_err(), "{text}");
+        }
+    }
+}
@@ -0,0 +1,91 @@
+//! The Parquet corpus `--format parquet` writes: one row per document with its source path,
+//! title, author, language, page count and text, in columns that Spark, Polars or pandas load
+//! directly.
+//!
+//! A Parquet file is only readable once its footer is written, so rows are buffered in an
+//! open writer per file and [`finish`] closes them at the end of the run.
+
+use anyhow::{Context, Result};
+use arrow_array::{ArrayRef, Int64Array, RecordBatch, StringArray};
+use arrow_schema::{DataType, Field, Schema};
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+use std::collections::hash_map::{Entry, HashMap};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock, Mutex};
+
+use crate::document::{Document, SectionKind};
+
+/// Bytes of rows buffered before they are written out as a row group.
+const ROW_GROUP_BYTES: usize = 64 * 1024 * 1024;
+
+/// Writers of the files written to in this run, by path.
+static WRITERS: LazyLock<Mutex<HashMap<PathBuf, ArrowWriter<File>>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+fn schema() -> Arc<Schema> {
+    Arc::new(Schema::new(vec![
+        Field::new("source_path", DataType::Utf8, false),
+        Field::new("title", DataType::Utf8, true),
+        Field::new("author", DataType::Utf8, true),
+        Field::new("language", DataType::Utf8, true),
+        Field::new("page_count", DataType::Int64, true),
+        Field::new("text", DataType::Utf8, false),
+    ]))
+}
+
+/// Adds a row for `doc`, extracted from `source` as `text`, to the file at `path`. The file
+/// is created, replacing any file there, by the first row written to it in a run.
+pub fn write_document(path: &Path, doc: &Document, source: &Path, text: &str) -> Result<()> {
+    let pages = doc
+        .sections
+        .iter()
+        .filter(|section| section.kind == SectionKind::Page)
+        .count();
+    let metadata = &doc.metadata;
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(StringArray::from(vec![source.to_string_lossy().as_ref()])),
+        Arc::new(StringArray::from(vec![metadata.title.as_deref()])),
+        Arc::new(StringArray::from(vec![metadata.author.as_deref()])),
+        Arc::new(StringArray::from(vec![metadata.language.as_deref()])),
+        Arc::new(Int64Array::from(vec![(pages > 0).then_some(pages as i64)])),
+        Arc::new(StringArray::from(vec![text])),
+    ];
+    let batch = RecordBatch::try_new(schema(), columns)?;
+
+    let mut writers = WRITERS.lock().unwrap_or_else(|e| e.into_inner());
+    let writer = match writers.entry(path.to_path_buf()) {
+        Entry::Occupied(entry) => entry.into_mut(),
+        Entry::Vacant(entry) => {
+            let file = File::create(path)
+                .context(format!("Failed to create Parquet file: {}", path.display()))?;
+            let properties = WriterProperties::builder()
+                .set_compression(Compression::SNAPPY)
+                .build();
+            entry.insert(ArrowWriter::try_new(file, schema(), Some(properties))?)
+        }
+    };
+    let failed = || format!("Failed to write to Parquet file: {}", path.display());
+    writer.write(&batch).with_context(failed)?;
+    if writer.in_progress_size() > ROW_GROUP_BYTES {
+        writer.flush().with_context(failed)?;
+    }
+    Ok(())
+}
+
+/// Writes out the rows still buffered and closes every file written to in this run.
+pub fn finish() -> Result<()> {
+    let writers: Vec<(PathBuf, ArrowWriter<File>)> = WRITERS
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .drain()
+        .collect();
+    for (path, writer) in writers {
+        writer
+            .close()
+            .context(format!("Failed to finish Parquet file: {}", path.display()))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,371 @@
+//! PDF loading and per-page text extraction on top of pdf-extract.
+
+use anyhow::{Context, Result};
+use pdf_extract::encryption::DecryptionError;
+use pdf_extract::{output_doc_page, Object, ObjectId};
+use std::collections::HashSet;
+use std::fmt;
+use std::panic;
+use std::path::Path;
+
+use crate::columns::{self, PageLayout};
+use crate::document::OutlineEntry;
+use crate::figures;
+use crate::layers::{self, Layer, LayerSelection};
+use crate::layout::{self, AssembleOptions, GlyphCollector};
+use crate::metadata::Metadata;
+use crate::outline;
+use crate::pages::PageSelection;
+use crate::structure::{self, StructTree};
+use crate::xfa::XfaForm;
+
+/// Text of a PDF, page by page.
+pub struct PdfText {
+    /// Document language from the catalog's `/Lang`
+    pub language: Option<String>,
+    pub pages: Vec<PdfPage>,
+    /// Content of an XFA form that the pages do not show
+    pub form: Option<String>,
+    /// Bookmarks, by page index
+    pub outline: Vec<OutlineEntry>,
+}
+
+pub struct PdfPage {
+    pub text: String,
+    /// Set when most of the page is tagged with a language other than the document's
+    pub language: Option<String>,
+}
+
+/// A password-protected PDF that neither the empty password nor any of those given opens.
+#[derive(Debug)]
+pub struct LockedPdf {
+    /// Number of passwords tried besides the empty one
+    pub tried: usize,
+}
+
+impl fmt::Display for LockedPdf {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.tried {
+            0 => write!(f, "the PDF is password-protected and no password was given"),
+            1 => write!(
+                f,
+                "the PDF is password-protected and the password given does not open it"
+            ),
+            n => write!(
+                f,
+                "the PDF is password-protected and none of the {n} passwords given opens it"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LockedPdf {}
+
+/// Extracts the text of every page, in page order; pages outside `pages` are left empty.
+/// Encrypted files are opened with the first of the empty password and `passwords` that
+/// fits.
+pub fn extract_pages(
+    pdf_path: &Path,
+    options: &AssembleOptions,
+    layers: &LayerSelection,
+    pages: &PageSelection,
+    passwords: &[String],
+) -> Result<PdfText> {
+    guarded(pdf_path, || {
+        extract_pages_unguarded(load(pdf_path, passwords)?, options, layers, pages)
+    })
+}
+
+/// Extracts a PDF embedded in another file, such as the preview saved inside an iWork
+/// package; `container` names that file in errors.
+pub fn extract_embedded_pages(
+    bytes: &[u8],
+    container: &Path,
+    options: &AssembleOptions,
+    layers: &LayerSelection,
+    pages: &PageSelection,
+    passwords: &[String],
+) -> Result<PdfText> {
+    guarded(container, || {
+        extract_pages_unguarded(
+            decrypted(pdf_extract::Document::load_mem(bytes)?, passwords)?,
+            options,
+            layers,
+            pages,
+        )
+    })
+}
+
+fn guarded(
+    pdf_path: &Path,
+    extract: impl FnOnce() -> Result<PdfText> + panic::UnwindSafe,
+) -> Result<PdfText> {
+    // Catch panics from the pdf-extract library
+    panic::catch_unwind(extract)
+        .map_err(|_| anyhow::anyhow!("PDF extraction panicked (likely unsupported PDF feature)"))
+        .and_then(|r| {
+            r.context(format!(
+                "Failed to extract text from PDF: {}",
+                pdf_path.display()
+            ))
+        })
+}
+
+/// What `inspect` reports about a PDF.
+pub struct PdfInfo {
+    pub pages: usize,
+    pub layers: Vec<Layer>,
+}
+
+pub fn inspect(pdf_path: &Path) -> Result<PdfInfo> {
+    let doc = load(pdf_path, &[]).context(format!("Failed to open PDF: {}", pdf_path.display()))?;
+    Ok(PdfInfo {
+        pages: doc.get_pages().len(),
+        layers: layers::list(&doc),
+    })
+}
+
+/// What a PDF says about itself, read without extracting any page.
+pub struct PdfMetadata {
+    pub pages: usize,
+    pub encrypted: bool,
+    /// Set when no password opens the file, whose title and author then stay unread
+    pub locked: bool,
+    /// Title and author from the document information, language from the catalog
+    pub metadata: Metadata,
+}
+
+/// Reads the page count and metadata of a PDF, opening encrypted files with the first of the
+/// empty password and `passwords` that fits.
+pub fn metadata(pdf_path: &Path, passwords: &[String]) -> Result<PdfMetadata> {
+    let doc = pdf_extract::Document::load(pdf_path)
+        .context(format!("Failed to open PDF: {}", pdf_path.display()))?;
+    let encrypted = doc.is_encrypted();
+    // The page tree is never encrypted, only strings and streams
+    let pages = doc.get_pages().len();
+    let doc = match decrypted(doc, passwords) {
+        Ok(doc) => doc,
+        Err(e) if e.is::<LockedPdf>() => {
+            return Ok(PdfMetadata {
+                pages,
+                encrypted,
+                locked: true,
+                metadata: Metadata::default(),
+            })
+        }
+        Err(e) => return Err(e),
+    };
+
+    let info = doc
+        .trailer
+        .get(b"Info")
+        .and_then(|info| doc.dereference(info))
+        .and_then(|(_, info)| info.as_dict())
+        .ok();
+    let field = |key: &[u8]| {
+        let value = info?
+            .get(key)
+            .and_then(|value| doc.dereference(value))
+            .ok()?;
+        structure::text_string(value.1)
+    };
+    Ok(PdfMetadata {
+        pages,
+        encrypted,
+        locked: false,
+        metadata: Metadata {
+            title: field(b"Title"),
+            author: field(b"Author"),
+            language: doc
+                .catalog()
+                .ok()
+                .and_then(|catalog| catalog.get(b"Lang").ok())
+                .and_then(structure::text_string),
+            collection: None,
+        },
+    })
+}
+
+fn load(pdf_path: &Path, passwords: &[String]) -> Result<pdf_extract::Document> {
+    decrypted(pdf_extract::Document::load(pdf_path)?, passwords)
+}
+
+fn decrypted(
+    mut doc: pdf_extract::Document,
+    passwords: &[String],
+) -> Result<pdf_extract::Document> {
+    if !doc.is_encrypted() {
+        return Ok(doc);
+    }
+    // Owner-locked files open with the empty user password; the key is checked before
+    // anything is decrypted, so a wrong password leaves the document as it was
+    for password in std::iter::once("").chain(passwords.iter().map(String::as_str)) {
+        match doc.decrypt(password) {
+            Ok(()) => return Ok(doc),
+            Err(pdf_extract::Error::Decryption(DecryptionError::IncorrectPassword)) => {}
+            Err(e) => return Err(anyhow::anyhow!("Failed to decrypt the PDF: {e}")),
+        }
+    }
+    Err(LockedPdf {
+        tried: passwords.len(),
+    }
+    .into())
+}
+
+fn extract_pages_unguarded(
+    mut doc: pdf_extract::Document,
+    options: &AssembleOptions,
+    layers: &LayerSelection,
+    selection: &PageSelection,
+) -> Result<PdfText> {
+    if !layers.is_all() {
+        let hidden = layers.hidden(&layers::list(&doc));
+        if !hidden.is_empty() {
+            for page_id in doc.get_pages().into_values() {
+                // A page whose content cannot be rewritten keeps all its layers
+                let _ = layers::hide_content(&mut doc, page_id, &hidden);
+            }
+        }
+    }
+
+    if options.image_placeholders {
+        for page_id in doc.get_pages().into_values() {
+            // A page whose content cannot be rewritten goes without placeholders
+            let _ = figures::mark_images(&mut doc, page_id);
+        }
+    }
+
+    let tree = if options.use_structure {
+        StructTree::read(&doc)
+    } else {
+        None
+    };
+    let language = doc
+        .catalog()
+        .ok()
+        .and_then(|catalog| catalog.get(b"Lang").ok())
+        .and_then(structure::text_string);
+
+    let mut span_languages = Vec::new();
+    if tree.is_some() {
+        for page_id in doc.get_pages().into_values() {
+            // A page whose content cannot be rewritten keeps content-stream order
+            let _ = structure::tag_marked_content(&mut doc, page_id, &mut span_languages);
+        }
+    }
+
+    let mut pages = Vec::new();
+    for (page_num, page_id) in doc.get_pages() {
+        if !selection.contains(page_num as usize) {
+            pages.push(PdfPage {
+                text: String::new(),
+                language: None,
+            });
+            continue;
+        }
+        let mut collector = GlyphCollector::new();
+        output_doc_page(&doc, &mut collector, page_num)?;
+        layout::orient(&mut collector.page, page_rotation(&doc, page_id));
+
+        let structured = tree
+            .as_ref()
+            .and_then(|tree| structure::assemble(&collector.page, tree.blocks(page_id), options));
+        let page_language = tree.as_ref().and_then(|tree| {
+            structure::page_language(&collector.page, page_id, tree, &span_languages)
+        });
+
+        let mut text = structured
+            .or_else(|| match options.layout {
+                PageLayout::Columns => columns::assemble(&collector.page, options),
+                PageLayout::Raw => None,
+            })
+            .unwrap_or_else(|| layout::assemble(&collector.page, options));
+        if options.image_placeholders {
+            text = figures::label(&text, true);
+        }
+        pages.push(PdfPage {
+            text,
+            language: page_language.filter(|lang| Some(lang) != language.as_ref()),
+        });
+    }
+
+    // Static XFA forms draw their fields on the pages as well; dynamic ones only carry a
+    // placeholder, shorter than the form itself
+    let form = XfaForm::read(&doc).and_then(|xfa| {
+        let text = xfa.text()?;
+        let page_chars: usize = pages.iter().map(|page| page.text.trim().len()).sum();
+        (xfa.needs_rendering || page_chars < text.len()).then_some(text)
+    });
+
+    Ok(PdfText {
+        language,
+        pages,
+        form,
+        outline: outline::read(&doc),
+    })
+}
+
+/// The page's `/Rotate`, which may be inherited from the page tree. A tree whose `/Parent`
+/// links loop back on themselves counts as unrotated.
+fn page_rotation(doc: &pdf_extract::Document, page_id: ObjectId) -> i64 {
+    let mut visited = HashSet::new();
+    let mut node_id = Some(page_id);
+    while let Some(id) = node_id.filter(|&id| visited.insert(id)) {
+        let Ok(dict) = doc.get_dictionary(id) else {
+            break;
+        };
+        if let Ok(rotate) = dict.get(b"Rotate").and_then(|r| doc.dereference(r)) {
+            return rotate.1.as_i64().unwrap_or(0);
+        }
+        node_id = dict.get(b"Parent").and_then(Object::as_reference).ok();
+    }
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pdf_extract::{Dictionary, Document};
+
+    /// A document with a page under two page tree nodes, the first rotated by `rotate`, whose
+    /// `/Parent` links close in a loop when `looped`.
+    fn page_tree(rotate: Option<i64>, looped: bool) -> (Document, ObjectId) {
+        let mut doc = Document::with_version("1.7");
+        let root = doc.new_object_id();
+        let pages = doc.new_object_id();
+        let page = doc.new_object_id();
+
+        let mut root_dict = Dictionary::new();
+        if looped {
+            root_dict.set("Parent", Object::Reference(pages));
+        }
+        if let Some(rotate) = rotate {
+            root_dict.set("Rotate", Object::Integer(rotate));
+        }
+        let mut pages_dict = Dictionary::new();
+        pages_dict.set("Parent", Object::Reference(root));
+        let mut page_dict = Dictionary::new();
+        page_dict.set("Parent", Object::Reference(pages));
+
+        doc.objects.insert(root, Object::Dictionary(root_dict));
+        doc.objects.insert(pages, Object::Dictionary(pages_dict));
+        doc.objects.insert(page, Object::Dictionary(page_dict));
+        (doc, page)
+    }
+
+    #[test]
+    fn rotation_is_inherited() {
+        let (doc, page) = page_tree(Some(90), false);
+        assert_eq!(page_rotation(&doc, page), 90);
+        let (doc, page) = page_tree(None, false);
+        assert_eq!(page_rotation(&doc, page), 0);
+    }
+
+    #[test]
+    fn looped_page_tree_ends() {
+        let (doc, page) = page_tree(None, true);
+        assert_eq!(page_rotation(&doc, page), 0);
+        let (doc, page) = page_tree(Some(270), true);
+        assert_eq!(page_rotation(&doc, page), 270);
+    }
+}
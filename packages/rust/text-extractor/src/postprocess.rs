@@ -0,0 +1,80 @@
+//! Text clean-up passes applied to an extracted document before it is rendered.
+
+use crate::copyright;
+use crate::document::Document;
+use crate::hyphenation;
+use crate::matter;
+use crate::punctuation;
+use crate::running_heads;
+use crate::scripts;
+
+#[derive(Debug, Clone, Default)]
+pub struct PostProcessOptions {
+    /// Fold typographic punctuation and spaces to ASCII
+    pub ascii_punctuation: bool,
+    /// Rewrite Unicode super/subscript characters as `^2` / `_2`
+    pub plain_scripts: bool,
+    /// Leave out copyright and imprint pages
+    pub drop_copyright_page: bool,
+    /// Leave out sections before the first chapter
+    pub trim_front_matter: bool,
+    /// Leave out sections after the last chapter
+    pub trim_back_matter: bool,
+    /// Remove printed page numbers and form feeds from PDF pages
+    pub drop_page_markers: bool,
+    /// Remove lines repeated at the top or bottom of page after page
+    pub strip_headers_footers: bool,
+    /// Rejoin words broken across line ends
+    pub dehyphenate: bool,
+}
+
+pub fn apply(doc: &mut Document, options: &PostProcessOptions) {
+    if options.trim_front_matter || options.trim_back_matter {
+        let body = matter::body(doc, options.trim_front_matter, options.trim_back_matter);
+        let mut index = 0;
+        doc.retain_sections(|_| {
+            index += 1;
+            body.contains(&(index - 1))
+        });
+    }
+    if options.drop_copyright_page {
+        doc.retain_sections(|section| !copyright::is_copyright_page(&section.text));
+    }
+    if options.strip_headers_footers {
+        running_heads::strip(doc);
+    }
+    if options.drop_page_markers {
+        for section in &mut doc.sections {
+            *section = section.without_page_furniture();
+        }
+    }
+    if options.dehyphenate {
+        for section in &mut doc.sections {
+            let language = section.language.as_ref().or(doc.metadata.language.as_ref());
+            section.text = hyphenation::dehyphenate(&section.text, language.map(String::as_str));
+        }
+    }
+    if options.ascii_punctuation {
+        map_text(doc, punctuation::to_ascii);
+    }
+    if options.plain_scripts {
+        map_text(doc, scripts::unicode_to_plain);
+    }
+}
+
+/// Runs `f` over every piece of text in the document, including metadata and titles.
+fn map_text(doc: &mut Document, f: impl Fn(&str) -> String) {
+    for value in [&mut doc.metadata.title, &mut doc.metadata.author]
+        .into_iter()
+        .flatten()
+    {
+        *value = f(value);
+    }
+
+    for section in &mut doc.sections {
+        if let Some(title) = &mut section.title {
+            *title = f(title);
+        }
+        section.text = f(&section.text);
+    }
+}
@@ -0,0 +1,176 @@
+//! Provenance records tying each output to the source, tool build and options that produced it.
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::source::SourceFile;
+
+const TOOL: &str = concat!("text-extractor ", env!("CARGO_PKG_VERSION"));
+const PDF_BACKEND: &str = concat!(
+    "pdf-extract ",
+    env!("PDF_EXTRACT_VERSION"),
+    ", lopdf ",
+    env!("LOPDF_VERSION")
+);
+const EPUB_BACKEND: &str = concat!("epub ", env!("EPUB_VERSION"), ", zip ", env!("ZIP_VERSION"));
+const OFFICE_BACKEND: &str = concat!(
+    "roxmltree ",
+    env!("ROXMLTREE_VERSION"),
+    ", zip ",
+    env!("ZIP_VERSION")
+);
+/// djvulibre and 7-Zip are run as installed, so their versions are not known when building
+const DJVU_BACKEND: &str = "djvulibre";
+const CHM_BACKEND: &str = "7-Zip";
+/// RTF and HTML files are parsed here; only their text encodings come from a library
+const TEXT_BACKEND: &str = concat!("encoding_rs ", env!("ENCODING_RS_VERSION"));
+const FB2_BACKEND: &str = concat!(
+    "roxmltree ",
+    env!("ROXMLTREE_VERSION"),
+    ", encoding_rs ",
+    env!("ENCODING_RS_VERSION")
+);
+/// Pages IWA archives are decoded here; only the package is read with a library
+const PAGES_BACKEND: &str = concat!("zip ", env!("ZIP_VERSION"));
+/// LaTeX sources are read here without a library
+const LATEX_BACKEND: &str = "built-in";
+/// Recorded for a source no extractor is known for
+const UNKNOWN_BACKEND: &str = "unknown";
+#[cfg(feature = "audio")]
+const AUDIO_BACKEND: &str = concat!(
+    "whisper-rs ",
+    env!("WHISPER_RS_VERSION"),
+    ", symphonia ",
+    env!("SYMPHONIA_VERSION")
+);
+
+/// Number of hex digits kept from the options digest; enough to tell configurations apart.
+const OPTIONS_HASH_LEN: usize = 16;
+/// Number of hex digits kept from the digests that tell output names apart.
+const SHORT_HASH_LEN: usize = 8;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Provenance {
+    pub tool: &'static str,
+    pub backend: &'static str,
+    /// Digest of every option that affects the extracted text
+    pub options: String,
+    pub source_sha256: String,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ProvenanceOptions {
+    /// Write `<output>.provenance.json` next to each output file
+    pub sidecar: bool,
+    /// Start text outputs with `#` comment lines carrying the record
+    pub header: bool,
+}
+
+impl Provenance {
+    pub fn of(file: &SourceFile, options_hash: &str) -> Result<Self> {
+        Ok(Provenance {
+            tool: TOOL,
+            backend: backend(&file.extension),
+            options: options_hash.to_string(),
+            source_sha256: sha256_file(&file.path)?,
+        })
+    }
+
+    /// The record as `#` comment lines, ending with a blank line.
+    pub fn header(&self) -> String {
+        format!(
+            "# Extracted by {} ({})\n# Options: {}\n# Source SHA-256: {}\n\n",
+            self.tool, self.backend, self.options, self.source_sha256
+        )
+    }
+
+    pub fn write_sidecar(&self, output_path: &Path) -> Result<PathBuf> {
+        let mut name = output_path.as_os_str().to_owned();
+        name.push(".provenance.json");
+        let sidecar = PathBuf::from(name);
+
+        fs::write(&sidecar, serde_json::to_string_pretty(self)? + "\n").context(format!(
+            "Failed to write provenance sidecar: {}",
+            sidecar.display()
+        ))?;
+        Ok(sidecar)
+    }
+}
+
+/// Whether the sidecar next to `output_path` records extracting `file` as it is now with the
+/// same options, or `None` when there is no readable sidecar.
+pub fn sidecar_matches(output_path: &Path, file: &SourceFile, options_hash: &str) -> Option<bool> {
+    let mut name = output_path.as_os_str().to_owned();
+    name.push(".provenance.json");
+    let sidecar: serde_json::Value = serde_json::from_str(&fs::read_to_string(name).ok()?).ok()?;
+
+    if sidecar["options"].as_str()? != options_hash {
+        return Some(false);
+    }
+    let recorded = sidecar["source_sha256"].as_str()?;
+    Some(sha256_file(&file.path).is_ok_and(|sha256| sha256 == recorded))
+}
+
+/// The libraries that extract sources with `extension`.
+fn backend(extension: &str) -> &'static str {
+    match extension {
+        "pdf" => PDF_BACKEND,
+        "epub" | "ibooks" => EPUB_BACKEND,
+        "pages" => PAGES_BACKEND,
+        "tex" => LATEX_BACKEND,
+        "fb2" | "fb2.zip" => FB2_BACKEND,
+        "docx" | "odt" => OFFICE_BACKEND,
+        "rtf" | "html" | "xhtml" | "htm" => TEXT_BACKEND,
+        "djvu" | "djv" => DJVU_BACKEND,
+        "chm" => CHM_BACKEND,
+        #[cfg(feature = "audio")]
+        "mp3" | "m4b" => AUDIO_BACKEND,
+        _ => UNKNOWN_BACKEND,
+    }
+}
+
+/// Digest of a canonical description of the options.
+pub fn options_hash(description: &str) -> String {
+    let digest = Sha256::digest(description.as_bytes());
+    hex(&digest)[..OPTIONS_HASH_LEN].to_string()
+}
+
+/// Short digest of `text`, such as a source's path, for telling apart output names that
+/// would otherwise be the same.
+pub(crate) fn short_hash(text: &str) -> String {
+    let digest = Sha256::digest(text.as_bytes());
+    hex(&digest)[..SHORT_HASH_LEN].to_string()
+}
+
+pub(crate) fn sha256_file(path: &Path) -> Result<String> {
+    let mut file =
+        File::open(path).context(format!("Failed to open source: {}", path.display()))?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)
+        .context(format!("Failed to hash source: {}", path.display()))?;
+    Ok(hex(&hasher.finalize()))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::SUPPORTED_EXTENSIONS;
+
+    #[test]
+    fn every_supported_extension_has_a_backend() {
+        for extension in SUPPORTED_EXTENSIONS {
+            assert_ne!(backend(extension), UNKNOWN_BACKEND, "{extension}");
+        }
+        assert_eq!(backend("tex"), LATEX_BACKEND);
+        assert_eq!(backend("pages"), PAGES_BACKEND);
+        assert_eq!(backend("txt"), UNKNOWN_BACKEND);
+    }
+}
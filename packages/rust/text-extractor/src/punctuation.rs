@@ -0,0 +1,29 @@
+//! Folding typographic punctuation and spaces to ASCII for legacy consumers.
+
+/// Replaces curly quotes, dashes, ellipses and exotic spaces with ASCII equivalents.
+pub fn to_ascii(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+
+    for ch in text.chars() {
+        match ch {
+            '\u{2018}' | '\u{2019}' | '\u{201A}' | '\u{201B}' | '\u{2032}' | '\u{2039}'
+            | '\u{203A}' => out.push('\''),
+            '\u{201C}' | '\u{201D}' | '\u{201E}' | '\u{201F}' | '\u{2033}' | '\u{00AB}'
+            | '\u{00BB}' => out.push('"'),
+            // Hyphens, figure dash, en dash and minus sign
+            '\u{2010}' | '\u{2011}' | '\u{2012}' | '\u{2013}' | '\u{2212}' => out.push('-'),
+            // Em dash and horizontal bar
+            '\u{2014}' | '\u{2015}' => out.push_str("--"),
+            '\u{2026}' => out.push_str("..."),
+            // No-break, figure, thin, hair and other fixed-width spaces
+            '\u{00A0}' | '\u{2000}'..='\u{200A}' | '\u{202F}' | '\u{205F}' | '\u{3000}' => {
+                out.push(' ');
+            }
+            // Invisible characters that only affect line breaking
+            '\u{00AD}' | '\u{200B}' | '\u{2060}' | '\u{FEFF}' => {}
+            _ => out.push(ch),
+        }
+    }
+
+    out
+}
@@ -0,0 +1,237 @@
+//! Sending extracted documents to a search engine as they are produced, for `--index-url`:
+//! Meilisearch's documents API or Elasticsearch's bulk API, a batch at a time.
+//!
+//! Each document is sent as `{id, source, title, author, language, collection, page_count,
+//! chapter_count, source_sha256, text}`, its `id` a digest of its source path, so extracting
+//! a file again replaces what was sent for it before.
+
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::mem;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use crate::document::{Document, SectionKind};
+use crate::provenance::Provenance;
+
+/// Wait before the first retry of a failed batch; it doubles with each retry after.
+const FIRST_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Engine {
+    /// Meilisearch, with an index URL such as http://localhost:7700/indexes/books
+    Meilisearch,
+    /// Elasticsearch or OpenSearch, with an index URL such as http://localhost:9200/books
+    Elasticsearch,
+}
+
+impl Engine {
+    /// The engine `url` names an index of: Meilisearch index URLs have an `/indexes/` path.
+    pub fn guess(url: &str) -> Engine {
+        if url.contains("/indexes/") {
+            Engine::Meilisearch
+        } else {
+            Engine::Elasticsearch
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PushOptions {
+    /// The index documents are sent to
+    pub url: String,
+    /// Sent as a bearer token to Meilisearch and as an `ApiKey` to Elasticsearch
+    pub api_key: Option<String>,
+    pub engine: Engine,
+    /// Documents sent in one request
+    pub batch_size: usize,
+    /// Times a batch is sent again after a network error, a 429 or a 5xx answer
+    pub retries: u32,
+}
+
+/// Collects documents into batches and sends each once it is full. Documents extracted at
+/// once may be added from several threads.
+pub struct Pusher {
+    options: PushOptions,
+    pending: Mutex<Vec<Value>>,
+    indexed: AtomicUsize,
+    failed: AtomicUsize,
+}
+
+impl Pusher {
+    pub fn new(options: PushOptions) -> Pusher {
+        Pusher {
+            options,
+            pending: Mutex::new(Vec::new()),
+            indexed: AtomicUsize::new(0),
+            failed: AtomicUsize::new(0),
+        }
+    }
+
+    /// Queues `doc`, extracted from `source`, and sends the batch if it is full. Fails when
+    /// the batch could not be sent.
+    pub fn add(&self, source: &Path, doc: &Document, provenance: &Provenance) -> Result<()> {
+        let record = record(source, doc, provenance);
+        let batch = {
+            let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+            pending.push(record);
+            if pending.len() < self.options.batch_size {
+                return Ok(());
+            }
+            mem::take(&mut *pending)
+        };
+        self.send(batch)
+    }
+
+    /// Sends the documents still queued.
+    pub fn flush(&self) -> Result<()> {
+        let batch = mem::take(&mut *self.pending.lock().unwrap_or_else(|e| e.into_inner()));
+        if batch.is_empty() {
+            return Ok(());
+        }
+        self.send(batch)
+    }
+
+    /// Documents the engine accepted so far.
+    pub fn indexed(&self) -> usize {
+        self.indexed.load(Ordering::Relaxed)
+    }
+
+    /// Documents that could not be sent, or that the engine rejected.
+    pub fn failed(&self) -> usize {
+        self.failed.load(Ordering::Relaxed)
+    }
+
+    pub fn url(&self) -> &str {
+        &self.options.url
+    }
+
+    fn send(&self, batch: Vec<Value>) -> Result<()> {
+        let count = batch.len();
+        let (endpoint, content_type, body) = match self.options.engine {
+            Engine::Meilisearch => (
+                format!(
+                    "{}/documents?primaryKey=id",
+                    self.options.url.trim_end_matches('/')
+                ),
+                "application/json",
+                serde_json::to_string(&batch)?,
+            ),
+            Engine::Elasticsearch => {
+                let mut body = String::new();
+                for record in &batch {
+                    body.push_str(&json!({"index": {"_id": record["id"]}}).to_string());
+                    body.push('\n');
+                    body.push_str(&record.to_string());
+                    body.push('\n');
+                }
+                let endpoint = format!("{}/_bulk", self.options.url.trim_end_matches('/'));
+                (endpoint, "application/x-ndjson", body)
+            }
+        };
+
+        let answer = match self.post(&endpoint, content_type, &body) {
+            Ok(answer) => answer,
+            Err(e) => {
+                self.failed.fetch_add(count, Ordering::Relaxed);
+                return Err(e.context(format!("Failed to send {count} documents to {endpoint}")));
+            }
+        };
+        // Elasticsearch answers 200 even when some documents were rejected
+        let rejected = match self.options.engine {
+            Engine::Meilisearch => 0,
+            Engine::Elasticsearch => rejected_items(&answer),
+        };
+        self.indexed.fetch_add(count - rejected, Ordering::Relaxed);
+        self.failed.fetch_add(rejected, Ordering::Relaxed);
+        if rejected > 0 {
+            anyhow::bail!("{endpoint} rejected {rejected} of {count} documents");
+        }
+        Ok(())
+    }
+
+    /// Posts `body`, retrying with a growing delay, and returns the answer.
+    fn post(&self, endpoint: &str, content_type: &str, body: &str) -> Result<Value> {
+        let mut delay = FIRST_RETRY_DELAY;
+        let mut attempt = 0;
+        loop {
+            let mut request = ureq::post(endpoint).header("Content-Type", content_type);
+            if let Some(key) = &self.options.api_key {
+                let authorization = match self.options.engine {
+                    Engine::Meilisearch => format!("Bearer {key}"),
+                    Engine::Elasticsearch => format!("ApiKey {key}"),
+                };
+                request = request.header("Authorization", authorization);
+            }
+            let error = match request.send(body) {
+                Ok(mut response) => {
+                    let answer = response.body_mut().read_to_string()?;
+                    return Ok(serde_json::from_str(&answer).unwrap_or(Value::Null));
+                }
+                Err(e) => e,
+            };
+            let transient = match &error {
+                ureq::Error::StatusCode(status) => *status == 429 || *status >= 500,
+                ureq::Error::Io(_) | ureq::Error::Timeout(_) | ureq::Error::ConnectionFailed => {
+                    true
+                }
+                _ => false,
+            };
+            if !transient || attempt == self.options.retries {
+                return Err(error).context("Request failed");
+            }
+            thread::sleep(delay);
+            delay *= 2;
+            attempt += 1;
+        }
+    }
+}
+
+/// The document sent for `doc`.
+fn record(source: &Path, doc: &Document, provenance: &Provenance) -> Value {
+    let source = source.to_string_lossy();
+    let count = |kind: SectionKind| {
+        let count = doc
+            .sections
+            .iter()
+            .filter(|section| section.kind == kind)
+            .count();
+        (count > 0).then_some(count)
+    };
+    let text: Vec<&str> = doc
+        .sections
+        .iter()
+        .map(|section| section.text.as_str())
+        .collect();
+    let metadata = &doc.metadata;
+    json!({
+        "id": format!("{:x}", Sha256::digest(source.as_bytes())),
+        "source": source,
+        "title": metadata.title,
+        "author": metadata.author,
+        "language": metadata.language,
+        "collection": metadata.collection,
+        "page_count": count(SectionKind::Page),
+        "chapter_count": count(SectionKind::Chapter),
+        "source_sha256": provenance.source_sha256,
+        "text": text.join("\n\n"),
+    })
+}
+
+/// Items of an Elasticsearch bulk answer that carry an error.
+fn rejected_items(answer: &Value) -> usize {
+    if answer["errors"].as_bool() != Some(true) {
+        return 0;
+    }
+    answer["items"].as_array().map_or(0, |items| {
+        items
+            .iter()
+            .filter(|item| item["index"].get("error").is_some())
+            .count()
+    })
+}
@@ -0,0 +1,278 @@
+//! JSON-RPC 2.0 over standard input and output, for editors and automation agents that drive
+//! the extractor as a subprocess: one request per line in, one response per line out.
+//!
+//! - `extract` `{"path", "type"?, "format"?}` answers with the document's metadata and its
+//!   text in `format` (any `--format`), or with the document itself for `json`
+//! - `info` `{"path", "type"?}` answers with the document's type, size, metadata, page or
+//!   chapter count and whether it is encrypted
+//! - `list` `{"target"}` answers with the files a run over `target` would extract
+//!
+//! `type` names the document's type when its path has no usable extension. Requests without
+//! an `id` are notifications and get no response.
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::fs;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use crate::extract::{self, ExtractOptions};
+use crate::output::{self, OutputFormat};
+use crate::source::{self, SourceFile};
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+pub(crate) const METHOD_NOT_FOUND: i64 = -32601;
+pub(crate) const INVALID_PARAMS: i64 = -32602;
+/// A document that could not be extracted
+const EXTRACTION_FAILED: i64 = -32000;
+
+/// A request that failed, with its JSON-RPC error code.
+pub(crate) struct Failure {
+    pub code: i64,
+    pub message: String,
+}
+
+impl Failure {
+    pub fn new(code: i64, message: impl Into<String>) -> Failure {
+        Failure {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub(crate) struct DocumentParams {
+    pub path: PathBuf,
+    #[serde(rename = "type")]
+    file_type: Option<String>,
+    format: Option<String>,
+}
+
+/// A document extracted and rendered in the format a request asked for.
+pub(crate) struct Rendered {
+    pub file: SourceFile,
+    pub extracted: extract::ExtractedDocument,
+    pub format: OutputFormat,
+    pub text: String,
+}
+
+#[derive(Deserialize)]
+struct ListParams {
+    target: PathBuf,
+}
+
+/// Answers the requests read from `input` on `out` until `input` ends, extracting with
+/// `options`. `discover` finds the files under a `list` target.
+pub fn run<D>(
+    input: impl BufRead,
+    out: impl Write,
+    options: &ExtractOptions,
+    discover: D,
+) -> Result<()>
+where
+    D: Fn(&Path) -> Vec<SourceFile>,
+{
+    serve(input, out, |method, params, _| match method {
+        "extract" => parse(params).and_then(|params| extract(params, options)),
+        "info" => parse(params).and_then(|params| info(params, options)),
+        "list" => parse(params).and_then(|params| list(params, &discover)),
+        _ => Err(Failure::new(
+            METHOD_NOT_FOUND,
+            format!("unknown method: {method} (use extract, info or list)"),
+        )),
+    })
+}
+
+/// Answers the JSON-RPC requests read from `input`, one per line, on `out` until `input`
+/// ends. `handle` answers a call to a method with its params, and may send notifications of
+/// its own on the writer it is given.
+pub(crate) fn serve<H>(input: impl BufRead, mut out: impl Write, mut handle: H) -> Result<()>
+where
+    H: FnMut(&str, Value, &mut dyn Write) -> Result<Value, Failure>,
+{
+    for line in input.lines() {
+        let line = line.context("Failed to read a request")?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = match serde_json::from_str::<Value>(&line) {
+            Err(e) => Some(reply(
+                Value::Null,
+                Err(Failure::new(PARSE_ERROR, format!("parse error: {e}"))),
+            )),
+            Ok(Value::Array(batch)) if batch.is_empty() => Some(reply(
+                Value::Null,
+                Err(Failure::new(INVALID_REQUEST, "empty batch")),
+            )),
+            Ok(Value::Array(batch)) => {
+                let answers: Vec<Value> = batch
+                    .iter()
+                    .filter_map(|request| answer(request, &mut out, &mut handle))
+                    .collect();
+                (!answers.is_empty()).then_some(Value::Array(answers))
+            }
+            Ok(request) => answer(&request, &mut out, &mut handle),
+        };
+        if let Some(response) = response {
+            send(&mut out, &response)?;
+        }
+    }
+    Ok(())
+}
+
+/// Writes one message, on a line of its own.
+pub(crate) fn send(out: &mut dyn Write, message: &Value) -> Result<()> {
+    serde_json::to_writer(&mut *out, message)?;
+    out.write_all(b"\n")?;
+    out.flush()?;
+    Ok(())
+}
+
+/// The response to one request, or `None` for a notification.
+fn answer<H>(request: &Value, out: &mut dyn Write, handle: &mut H) -> Option<Value>
+where
+    H: FnMut(&str, Value, &mut dyn Write) -> Result<Value, Failure>,
+{
+    let id = request.get("id").cloned();
+    let Some(method) = request.get("method").and_then(Value::as_str) else {
+        return Some(reply(
+            id.unwrap_or(Value::Null),
+            Err(Failure::new(INVALID_REQUEST, "invalid request")),
+        ));
+    };
+    let params = request.get("params").cloned().unwrap_or(Value::Null);
+    let result = handle(method, params, out);
+    Some(reply(id?, result))
+}
+
+fn reply(id: Value, result: Result<Value, Failure>) -> Value {
+    match result {
+        Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+        Err(failure) => json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": {"code": failure.code, "message": failure.message},
+        }),
+    }
+}
+
+pub(crate) fn parse<T: for<'de> Deserialize<'de>>(params: Value) -> Result<T, Failure> {
+    serde_json::from_value(params)
+        .map_err(|e| Failure::new(INVALID_PARAMS, format!("invalid params: {e}")))
+}
+
+/// The file `params` names, with the type it was given or the one its extension names.
+fn source_file(params: &DocumentParams) -> Result<SourceFile, Failure> {
+    let path = &params.path;
+    let extension = match &params.file_type {
+        Some(given) => {
+            source::parse_extension(given).map_err(|e| Failure::new(INVALID_PARAMS, e))?
+        }
+        None => source::extension(path)
+            .filter(|extension| source::SUPPORTED_EXTENSIONS.contains(&extension.as_str()))
+            .ok_or_else(|| {
+                Failure::new(
+                    INVALID_PARAMS,
+                    format!(
+                        "unsupported type: {} (use a supported extension or add type)",
+                        path.display()
+                    ),
+                )
+            })?,
+    };
+    if !path.is_file() {
+        return Err(Failure::new(
+            INVALID_PARAMS,
+            format!("file does not exist: {}", path.display()),
+        ));
+    }
+    Ok(SourceFile::new(path.clone(), &extension))
+}
+
+fn extract_document(
+    file: &SourceFile,
+    options: &ExtractOptions,
+) -> Result<extract::ExtractedDocument, Failure> {
+    extract::extract_source(file, options)
+        .map_err(|e| Failure::new(EXTRACTION_FAILED, format!("{e:#}")))
+}
+
+/// Extracts the document `params` names and renders it in the format they ask for.
+pub(crate) fn render(
+    params: &DocumentParams,
+    options: &ExtractOptions,
+) -> Result<Rendered, Failure> {
+    let file = source_file(params)?;
+    let mut output_options = options.output.clone();
+    if let Some(format) = &params.format {
+        output_options.format = OutputFormat::from_name(format)
+            .ok_or_else(|| Failure::new(INVALID_PARAMS, format!("unknown format: {format}")))?;
+        if output_options.format.is_collection() && output_options.format != OutputFormat::Jsonl {
+            return Err(Failure::new(
+                INVALID_PARAMS,
+                "sqlite and parquet write files; ask for text, markdown or json",
+            ));
+        }
+    }
+    let extracted = extract_document(&file, options)?;
+    let text = output::render(&extracted.document, &file.path, &output_options);
+    Ok(Rendered {
+        text: output_options.newline.apply(&text),
+        format: output_options.format,
+        file,
+        extracted,
+    })
+}
+
+fn extract(params: DocumentParams, options: &ExtractOptions) -> Result<Value, Failure> {
+    let rendered = render(&params, options)?;
+    let metadata = &rendered.extracted.document.metadata;
+    let mut result = json!({
+        "path": rendered.file.path,
+        "title": metadata.title,
+        "author": metadata.author,
+        "language": metadata.language,
+        "collection": metadata.collection,
+    });
+    match rendered.format {
+        OutputFormat::Json | OutputFormat::Jsonl => {
+            let document: Value = serde_json::from_str(&rendered.text)
+                .map_err(|e| Failure::new(EXTRACTION_FAILED, e.to_string()))?;
+            result["document"] = document;
+        }
+        _ => result["text"] = Value::String(rendered.text),
+    }
+    Ok(result)
+}
+
+/// What the document `params` names is, as [`crate::info::read`] finds it.
+pub(crate) fn info(params: DocumentParams, options: &ExtractOptions) -> Result<Value, Failure> {
+    let file = source_file(&params)?;
+    let info = crate::info::read(&file, options)
+        .map_err(|e| Failure::new(EXTRACTION_FAILED, format!("{e:#}")))?;
+    serde_json::to_value(info).map_err(|e| Failure::new(EXTRACTION_FAILED, e.to_string()))
+}
+
+fn list(params: ListParams, discover: &dyn Fn(&Path) -> Vec<SourceFile>) -> Result<Value, Failure> {
+    if !params.target.exists() {
+        return Err(Failure::new(
+            INVALID_PARAMS,
+            format!("target path does not exist: {}", params.target.display()),
+        ));
+    }
+    let files: Vec<Value> = discover(&params.target)
+        .iter()
+        .map(|file| {
+            json!({
+                "path": file.path,
+                "type": source::format_name(&file.extension),
+                "extension": file.extension,
+                "size": fs::metadata(&file.path).map_or(0, |m| m.len()),
+            })
+        })
+        .collect();
+    Ok(Value::Array(files))
+}
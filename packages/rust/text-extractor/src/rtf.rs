@@ -0,0 +1,656 @@
+//! Rich Text Format documents (`.rtf`), read by stripping control words.
+//!
+//! Text outside the groups that hold fonts, styles, pictures, headers and the like is kept,
+//! one line per paragraph, decoded in the document's ANSI code page with `\u` escapes for
+//! the rest. Paragraphs with an outline level (Word's headings) start sections and bookmarks,
+//! list numbers and bullets come from the `\listtext` Word writes before each item, and table
+//! rows are laid out like HTML tables. Title and author come from the `\info` group.
+
+use anyhow::{Context, Result};
+use encoding_rs::Encoding;
+use std::fs;
+use std::path::Path;
+
+use crate::document::{Document, Landmarks, OutlineEntry, Section, SectionKind};
+use crate::metadata::Metadata;
+use crate::tables;
+
+/// Groups whose content is never printed.
+const HIDDEN_DESTINATIONS: &[&str] = &[
+    "fonttbl",
+    "colortbl",
+    "stylesheet",
+    "listtable",
+    "listoverridetable",
+    "revtbl",
+    "rsidtbl",
+    "filetbl",
+    "pict",
+    "object",
+    "objdata",
+    "nonshppict",
+    "shp",
+    "header",
+    "headerl",
+    "headerr",
+    "headerf",
+    "footer",
+    "footerl",
+    "footerr",
+    "footerf",
+    "footnote",
+    "annotation",
+    "ftnsep",
+    "ftnsepc",
+    "aftnsep",
+    "fldinst",
+    "xe",
+    "tc",
+    "template",
+    "userprops",
+    "docvar",
+    "xmlnstbl",
+    "themedata",
+    "colorschememapping",
+    "datastore",
+    "latentstyles",
+    "generator",
+];
+
+/// Control words that stand for a character.
+const SYMBOLS: &[(&str, char)] = &[
+    ("emdash", '—'),
+    ("endash", '–'),
+    ("bullet", '•'),
+    ("lquote", '‘'),
+    ("rquote", '’'),
+    ("ldblquote", '“'),
+    ("rdblquote", '”'),
+    ("emspace", ' '),
+    ("enspace", ' '),
+    ("qmspace", ' '),
+    ("tab", ' '),
+];
+
+pub fn extract(path: &Path, metadata: Option<&Metadata>, tables: bool) -> Result<Document> {
+    let source = fs::read(path).context(format!("Failed to read RTF file: {}", path.display()))?;
+    if !source.starts_with(b"{\\rtf") {
+        anyhow::bail!("Not an RTF file: {}", path.display());
+    }
+
+    let mut parser = Parser {
+        source: &source,
+        at: 0,
+        encoding: encoding_rs::WINDOWS_1252,
+        groups: vec![Group::default()],
+        pending: Vec::new(),
+        fallback: 0,
+        high_surrogate: None,
+        paragraph: String::new(),
+        list_text: String::new(),
+        title: String::new(),
+        author: String::new(),
+        outline_level: None,
+        in_table: false,
+        cells: Vec::new(),
+        output: Output {
+            tables,
+            ..Output::default()
+        },
+    };
+    parser.parse();
+    parser.end_paragraph();
+    parser.output.flush_table();
+
+    let embedded = Metadata {
+        title: non_empty(&parser.title),
+        author: non_empty(&parser.author),
+        language: None,
+        collection: None,
+    };
+
+    let parts = parser.output.parts;
+    // The outermost outline level used becomes level 1
+    let top = parts
+        .iter()
+        .filter_map(|part| part.heading.as_ref().map(|(level, _)| *level))
+        .min()
+        .unwrap_or(1);
+    let mut outline = Vec::new();
+    let mut sections = Vec::new();
+    for part in parts {
+        let mut lines = part.lines;
+        if let Some((level, title)) = part.heading {
+            outline.push(OutlineEntry {
+                title: title.clone(),
+                level: level - top + 1,
+                section: sections.len(),
+            });
+            lines.insert(0, title);
+        }
+        if lines.is_empty() {
+            continue;
+        }
+        sections.push(Section {
+            kind: SectionKind::Chapter,
+            title: None,
+            text: lines.join("\n"),
+            language: None,
+            page: None,
+        });
+    }
+
+    Ok(Document {
+        metadata: metadata.cloned().unwrap_or_default().or(embedded),
+        sections,
+        outline,
+        landmarks: Landmarks::default(),
+    })
+}
+
+fn non_empty(text: &str) -> Option<String> {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    (!text.is_empty()).then_some(text)
+}
+
+/// Where the text of a group goes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum Destination {
+    #[default]
+    Text,
+    Hidden,
+    /// The `\info` group, of which only the title and author are read
+    Info,
+    Title,
+    Author,
+    /// The number or bullet of a list item
+    ListText,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Group {
+    destination: Destination,
+    /// Fallback characters that follow each `\u` escape
+    unicode_skip: usize,
+    /// Set by `\*`: an unknown destination starting this group is hidden
+    ignorable: bool,
+}
+
+impl Default for Group {
+    fn default() -> Self {
+        Group {
+            destination: Destination::Text,
+            unicode_skip: 1,
+            ignorable: false,
+        }
+    }
+}
+
+/// A heading and the lines under it, up to the next heading.
+#[derive(Default)]
+struct Part {
+    heading: Option<(usize, String)>,
+    lines: Vec<String>,
+}
+
+#[derive(Default)]
+struct Output {
+    tables: bool,
+    parts: Vec<Part>,
+    /// Rows of the table being read
+    rows: Vec<Vec<String>>,
+}
+
+impl Output {
+    fn line(&mut self, line: String) {
+        self.flush_table();
+        if self.parts.is_empty() {
+            self.parts.push(Part::default());
+        }
+        if let Some(part) = self.parts.last_mut() {
+            part.lines.push(line);
+        }
+    }
+
+    fn heading(&mut self, level: usize, title: String) {
+        self.flush_table();
+        self.parts.push(Part {
+            heading: Some((level, title)),
+            lines: Vec::new(),
+        });
+    }
+
+    fn flush_table(&mut self) {
+        let rows: Vec<Vec<String>> = self
+            .rows
+            .drain(..)
+            .filter(|row| row.iter().any(|cell| !cell.is_empty()))
+            .collect();
+        if rows.is_empty() {
+            return;
+        }
+        let lines = if self.tables {
+            vec![tables::markdown(&rows)]
+        } else {
+            rows.into_iter().map(|row| row.join(" | ")).collect()
+        };
+        if self.parts.is_empty() {
+            self.parts.push(Part::default());
+        }
+        if let Some(part) = self.parts.last_mut() {
+            part.lines.extend(lines);
+        }
+    }
+}
+
+struct Parser<'a> {
+    source: &'a [u8],
+    at: usize,
+    /// The document's ANSI code page, for text and `\'hh` escapes
+    encoding: &'static Encoding,
+    /// Open groups, innermost last
+    groups: Vec<Group>,
+    /// Bytes of text not yet decoded
+    pending: Vec<u8>,
+    /// Fallback characters of the last `\u` escape still to skip
+    fallback: usize,
+    /// The first half of a surrogate pair written as two `\u` escapes
+    high_surrogate: Option<u32>,
+    paragraph: String,
+    list_text: String,
+    title: String,
+    author: String,
+    /// Outline level of the paragraph being read, making it a heading
+    outline_level: Option<usize>,
+    /// Whether the paragraph being read is in a table cell
+    in_table: bool,
+    /// Cells of the table row being read
+    cells: Vec<String>,
+    output: Output,
+}
+
+impl Parser<'_> {
+    fn group(&self) -> Group {
+        self.groups.last().copied().unwrap_or_default()
+    }
+
+    fn group_mut(&mut self) -> &mut Group {
+        if self.groups.is_empty() {
+            self.groups.push(Group::default());
+        }
+        self.groups.last_mut().expect("a group was just pushed")
+    }
+
+    fn parse(&mut self) {
+        while let Some(&byte) = self.source.get(self.at) {
+            self.at += 1;
+            match byte {
+                b'{' => {
+                    self.flush();
+                    let group = Group {
+                        ignorable: false,
+                        ..self.group()
+                    };
+                    self.groups.push(group);
+                }
+                b'}' => {
+                    self.flush();
+                    let closed = self.groups.pop().unwrap_or_default();
+                    if closed.destination == Destination::ListText
+                        && self.group().destination != Destination::ListText
+                    {
+                        self.end_list_text();
+                    }
+                }
+                b'\\' => self.control(),
+                // Line breaks in the file are only there to keep lines short
+                b'\r' | b'\n' => {}
+                _ => self.text_byte(byte),
+            }
+        }
+        self.flush();
+    }
+
+    fn control(&mut self) {
+        let Some(&next) = self.source.get(self.at) else {
+            return;
+        };
+        if !next.is_ascii_alphabetic() {
+            self.at += 1;
+            match next {
+                b'\'' => {
+                    let hex = self.source.get(self.at..self.at + 2).unwrap_or_default();
+                    self.at += hex.len();
+                    if let Some(byte) = std::str::from_utf8(hex)
+                        .ok()
+                        .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                    {
+                        self.text_byte(byte);
+                    }
+                }
+                b'*' => self.group_mut().ignorable = true,
+                b'\\' | b'{' | b'}' => self.text_byte(next),
+                b'~' => self.text_char('\u{a0}'),
+                b'_' => self.text_char('-'),
+                b'\r' | b'\n' if self.group().destination == Destination::Text => {
+                    self.end_paragraph()
+                }
+                // Optional hyphens and the rest print nothing
+                _ => {}
+            }
+            return;
+        }
+
+        let start = self.at;
+        while self
+            .source
+            .get(self.at)
+            .is_some_and(|byte| byte.is_ascii_alphabetic())
+        {
+            self.at += 1;
+        }
+        let word = String::from_utf8_lossy(&self.source[start..self.at]).into_owned();
+        let number_start = self.at;
+        if self.source.get(self.at) == Some(&b'-') {
+            self.at += 1;
+        }
+        while self
+            .source
+            .get(self.at)
+            .is_some_and(|byte| byte.is_ascii_digit())
+        {
+            self.at += 1;
+        }
+        let parameter: Option<i64> = std::str::from_utf8(&self.source[number_start..self.at])
+            .ok()
+            .and_then(|number| number.parse().ok());
+        // A space ends the control word and is part of it
+        if self.source.get(self.at) == Some(&b' ') {
+            self.at += 1;
+        }
+        self.control_word(&word, parameter);
+    }
+
+    fn control_word(&mut self, word: &str, parameter: Option<i64>) {
+        let destination = match word {
+            "info" => Some(Destination::Info),
+            "title" if self.group().destination == Destination::Info => Some(Destination::Title),
+            "author" if self.group().destination == Destination::Info => Some(Destination::Author),
+            "listtext" | "pntext" => Some(Destination::ListText),
+            _ if HIDDEN_DESTINATIONS.contains(&word) => Some(Destination::Hidden),
+            _ if self.group().ignorable => Some(Destination::Hidden),
+            _ => None,
+        };
+        if let Some(destination) = destination {
+            self.flush();
+            if self.group().destination != Destination::Hidden {
+                self.group_mut().destination = destination;
+            }
+            self.group_mut().ignorable = false;
+            return;
+        }
+
+        if let Some((_, symbol)) = SYMBOLS.iter().find(|(name, _)| *name == word) {
+            self.text_char(*symbol);
+            return;
+        }
+        // Paragraph and table structure only counts in the body text; a footnote's or list
+        // number's own paragraph marks must not end the paragraph they sit in
+        let body = self.group().destination == Destination::Text;
+        match word {
+            "par" | "sect" | "page" if body => self.end_paragraph(),
+            "line" => self.text_char('\n'),
+            "pard" if body => {
+                self.outline_level = None;
+                self.in_table = false;
+            }
+            "intbl" if body => self.in_table = true,
+            "outlinelevel" if body => {
+                self.outline_level = parameter
+                    .and_then(|level| usize::try_from(level).ok())
+                    .filter(|level| *level < 9)
+                    .map(|level| level + 1);
+            }
+            "cell" | "nestcell" if body => {
+                self.flush();
+                let cell = collapse(&std::mem::take(&mut self.paragraph));
+                self.cells.push(cell);
+            }
+            "row" | "nestrow" if body => {
+                self.flush();
+                let row = std::mem::take(&mut self.cells);
+                if !row.is_empty() {
+                    self.output.rows.push(row);
+                }
+            }
+            "u" => {
+                if let Some(code) = parameter {
+                    // Code points above 32767 are written as negative numbers, and those
+                    // past U+FFFF as a pair of UTF-16 surrogates
+                    let code = if code < 0 { code + 65536 } else { code };
+                    let code = u32::try_from(code).ok();
+                    match (self.high_surrogate.take(), code) {
+                        (_, Some(high @ 0xd800..=0xdbff)) => self.high_surrogate = Some(high),
+                        (Some(high), Some(low @ 0xdc00..=0xdfff)) => {
+                            let code = 0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00);
+                            self.text_char(char::from_u32(code).unwrap_or('\u{fffd}'));
+                        }
+                        (_, code) => {
+                            if let Some(c) = code.and_then(char::from_u32) {
+                                self.text_char(c);
+                            }
+                        }
+                    }
+                    self.fallback = self.group().unicode_skip;
+                }
+            }
+            "uc" => {
+                if let Some(skip) = parameter.and_then(|n| usize::try_from(n).ok()) {
+                    self.group_mut().unicode_skip = skip;
+                }
+            }
+            "ansicpg" => {
+                if let Some(encoding) = parameter.and_then(code_page) {
+                    self.encoding = encoding;
+                }
+            }
+            "mac" => self.encoding = encoding_rs::MACINTOSH,
+            "bin" => {
+                // Binary data, always part of a picture or object
+                let length = parameter.and_then(|n| usize::try_from(n).ok()).unwrap_or(0);
+                self.at = (self.at + length).min(self.source.len());
+            }
+            _ => {}
+        }
+    }
+
+    fn text_byte(&mut self, byte: u8) {
+        if self.fallback > 0 {
+            self.fallback -= 1;
+            return;
+        }
+        self.high_surrogate = None;
+        if self.group().destination != Destination::Hidden {
+            self.pending.push(byte);
+        }
+    }
+
+    fn text_char(&mut self, c: char) {
+        self.flush();
+        self.fallback = 0;
+        self.high_surrogate = None;
+        self.push_str(&c.to_string());
+    }
+
+    /// Decodes the pending bytes into the current destination.
+    fn flush(&mut self) {
+        if self.pending.is_empty() {
+            return;
+        }
+        let bytes = std::mem::take(&mut self.pending);
+        let (text, _, _) = self.encoding.decode(&bytes);
+        self.push_str(&text);
+    }
+
+    fn push_str(&mut self, text: &str) {
+        match self.group().destination {
+            Destination::Text => self.paragraph.push_str(text),
+            Destination::ListText => self.list_text.push_str(text),
+            Destination::Title => self.title.push_str(text),
+            Destination::Author => self.author.push_str(text),
+            Destination::Hidden | Destination::Info => {}
+        }
+    }
+
+    /// Turns the number or bullet before a list item into a `1. ` or `- ` marker.
+    fn end_list_text(&mut self) {
+        let marker = collapse(&std::mem::take(&mut self.list_text));
+        if marker.is_empty() {
+            return;
+        }
+        let marker = if marker.chars().any(char::is_alphanumeric) {
+            format!("{marker} ")
+        } else {
+            "- ".to_string()
+        };
+        self.paragraph.insert_str(0, &marker);
+    }
+
+    fn end_paragraph(&mut self) {
+        self.flush();
+        // Paragraphs within a cell run together; the cell ends them
+        if self.in_table {
+            self.paragraph.push(' ');
+            return;
+        }
+        let paragraph = std::mem::take(&mut self.paragraph);
+        let lines: Vec<String> = paragraph
+            .split('\n')
+            .map(collapse)
+            .filter(|line| !line.is_empty())
+            .collect();
+        if lines.is_empty() {
+            return;
+        }
+        match self.outline_level {
+            Some(level) => self.output.heading(level, lines.join(" ")),
+            None => {
+                for line in lines {
+                    self.output.line(line);
+                }
+            }
+        }
+    }
+}
+
+/// The encoding of a Windows code page number.
+fn code_page(number: i64) -> Option<&'static Encoding> {
+    let label = match number {
+        932 => "shift_jis".to_string(),
+        936 => "gbk".to_string(),
+        949 => "euc-kr".to_string(),
+        950 => "big5".to_string(),
+        10000 => "macintosh".to_string(),
+        _ => format!("windows-{number}"),
+    };
+    Encoding::for_label(label.as_bytes())
+}
+
+fn collapse(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn convert(source: &[u8]) -> Result<Document> {
+        let folder = tempfile::tempdir().unwrap();
+        let path = folder.path().join("document.rtf");
+        fs::write(&path, source).unwrap();
+        extract(&path, None, false)
+    }
+
+    fn text_of(source: &str) -> String {
+        convert(source.as_bytes())
+            .unwrap()
+            .sections
+            .iter()
+            .map(|section| section.text.as_str())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    #[test]
+    fn paragraphs_without_hidden_groups() {
+        let source = r"{\rtf1\ansi{\fonttbl{\f0 Times;}}{\colortbl;\red0\green0\blue0;}
+{\*\generator Writer;}{\*\unknownthing hidden}{\header Running head\par}
+\pard\f0 First \b bold\b0  paragraph.\par
+Second\line line.\par}";
+        assert_eq!(text_of(source), "First bold paragraph.\nSecond\nline.");
+    }
+
+    #[test]
+    fn title_and_author_from_info() {
+        let document =
+            convert(br"{\rtf1{\info{\title The Title}{\author A. Writer}}Body\par}").unwrap();
+        assert_eq!(document.metadata.title.as_deref(), Some("The Title"));
+        assert_eq!(document.metadata.author.as_deref(), Some("A. Writer"));
+        assert_eq!(document.sections[0].text, "Body");
+    }
+
+    #[test]
+    fn escapes_and_code_pages() {
+        assert_eq!(
+            text_of(r"{\rtf1\ansi caf\'e9 \{braces\} back\\slash\emdash end\par}"),
+            "café {braces} back\\slash—end"
+        );
+        assert_eq!(text_of(r"{\rtf1\ansi\ansicpg1251 \'e4\'e0\par}"), "да");
+        // The `?` after each \u is the fallback for readers without Unicode
+        assert_eq!(text_of(r"{\rtf1 \u8364?5\par}"), "€5");
+        assert_eq!(
+            text_of(r"{\rtf1 {\uc2\u-10179??\u-8704??x}\par}"),
+            "\u{1f600}x"
+        );
+    }
+
+    #[test]
+    fn headings_lists_and_tables() {
+        let document = convert(
+            br"{\rtf1\pard\outlinelevel0 Chapter\par\pard Text\par
+\pard\outlinelevel1 Part\par\pard
+{\listtext 1.\tab}First\par{\listtext \'b7\tab}Second\par
+\pard\intbl A\cell B\cell\row\pard\intbl 1\cell 2\cell\row\pard After\par}",
+        )
+        .unwrap();
+        let outline: Vec<(&str, usize)> = document
+            .outline
+            .iter()
+            .map(|entry| (entry.title.as_str(), entry.level))
+            .collect();
+        assert_eq!(outline, [("Chapter", 1), ("Part", 2)]);
+        assert_eq!(document.sections[0].text, "Chapter\nText");
+        assert_eq!(
+            document.sections[1].text,
+            "Part\n1. First\n- Second\nA | B\n1 | 2\nAfter"
+        );
+    }
+
+    #[test]
+    fn malformed_sources_do_not_panic() {
+        for source in [
+            r"{\rtf1 unclosed {group",
+            r"{\rtf1 too many}}} closes}",
+            r"{\rtf1 ends in a backslash\",
+            r"{\rtf1 cut escape\'",
+            r"{\rtf1 half escape\'e",
+            r"{\rtf1 \u9999999999999999999 \u-99999 \u-10179 x \u-8704 \uc-1 x}",
+            r"{\rtf1 \bin99999999 data}",
+            r"{\rtf1 \outlinelevel-3 \ansicpg99999 text\par}",
+            r"{\rtf1 \cell\row\cell\intbl}",
+        ] {
+            convert(source.as_bytes()).unwrap();
+        }
+        assert!(convert(b"plain text").is_err());
+    }
+}
@@ -0,0 +1,121 @@
+//! Running headers and footers: the book or chapter title, page numbers and the like repeated
+//! at the top or bottom of page after page.
+//!
+//! A line counts as running when the same text, numbers aside, sits among the first or last
+//! few lines of enough pages over the stretch where it appears. Books alternate heads between
+//! left and right pages, and chapter titles run only through their chapter, so neither
+//! needs to be on most pages of the whole document.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::document::{Document, SectionKind};
+
+/// Lines at each end of a page where running heads are looked for.
+const EDGE_LINES: usize = 3;
+/// Fewest pages a line must repeat on.
+const MIN_REPEATS: usize = 3;
+/// Share of the pages from its first to its last appearance that a running line is on.
+const MIN_SHARE: f64 = 0.4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Edge {
+    Top,
+    Bottom,
+}
+
+/// Removes running headers and footers from the document's pages.
+pub fn strip(doc: &mut Document) {
+    let pages: Vec<usize> = (0..doc.sections.len())
+        .filter(|&i| doc.sections[i].kind == SectionKind::Page)
+        .collect();
+    if pages.len() < MIN_REPEATS {
+        return;
+    }
+
+    // Pages (in page order) each edge line appears on
+    let mut seen: HashMap<(Edge, String), Vec<usize>> = HashMap::new();
+    for (number, &section) in pages.iter().enumerate() {
+        let mut keys: HashSet<(Edge, String)> = HashSet::new();
+        for (edge, _, line) in edge_lines(&doc.sections[section].text) {
+            keys.insert((edge, key(line)));
+        }
+        for key in keys {
+            seen.entry(key).or_default().push(number);
+        }
+    }
+
+    let running: HashSet<(Edge, String)> = seen
+        .into_iter()
+        .filter(|(_, on)| {
+            let span = on[on.len() - 1] - on[0] + 1;
+            on.len() >= MIN_REPEATS && on.len() as f64 >= span as f64 * MIN_SHARE
+        })
+        .map(|(key, _)| key)
+        .collect();
+    if running.is_empty() {
+        return;
+    }
+
+    for &section in &pages {
+        let text = &doc.sections[section].text;
+        let drop: HashSet<usize> = edge_lines(text)
+            .filter(|&(edge, _, line)| running.contains(&(edge, key(line))))
+            .map(|(_, i, _)| i)
+            .collect();
+        if drop.is_empty() {
+            continue;
+        }
+        let kept: Vec<&str> = text
+            .split('\n')
+            .enumerate()
+            .filter(|(i, _)| !drop.contains(i))
+            .map(|(_, line)| line)
+            .collect();
+        doc.sections[section].text = kept.join("\n");
+    }
+}
+
+/// The first and last few non-blank lines of a page, with their line numbers.
+fn edge_lines(text: &str) -> impl Iterator<Item = (Edge, usize, &str)> {
+    let lines: Vec<(usize, &str)> = text
+        .split('\n')
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .collect();
+    let top: Vec<(Edge, usize, &str)> = lines
+        .iter()
+        .take(EDGE_LINES)
+        .map(|&(i, line)| (Edge::Top, i, line))
+        .collect();
+    let bottom: Vec<(Edge, usize, &str)> = lines
+        .iter()
+        .rev()
+        .take(EDGE_LINES)
+        .map(|&(i, line)| (Edge::Bottom, i, line))
+        .collect();
+    top.into_iter().chain(bottom)
+}
+
+/// The line with its numbers blanked out, so "Chapter 2 · 41" and "Chapter 2 · 43" match,
+/// in lowercase with its spaces collapsed.
+fn key(line: &str) -> String {
+    let mut key = String::with_capacity(line.len());
+    for word in line.split_whitespace() {
+        if !key.is_empty() {
+            key.push(' ');
+        }
+        let mut in_number = false;
+        for c in word.chars() {
+            if c.is_ascii_digit() {
+                if !in_number {
+                    key.push('#');
+                }
+                in_number = true;
+            } else {
+                in_number = false;
+                key.extend(c.to_lowercase());
+            }
+        }
+    }
+    key
+}
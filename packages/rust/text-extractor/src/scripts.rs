@@ -0,0 +1,163 @@
+//! Rendering superscript and subscript runs (x², H₂O, 1st) in plain text.
+
+/// How raised and lowered text is written out.
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ScriptMode {
+    /// Flatten into inline characters as extracted ("x2", "H2O")
+    #[default]
+    Keep,
+    /// Use Unicode superscript/subscript characters where they exist ("x²", "H₂O")
+    Unicode,
+    /// Use ASCII caret/underscore notation ("x^2", "H_2O")
+    Plain,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Script {
+    Super,
+    Sub,
+}
+
+const SUPERSCRIPTS: [(char, char); 17] = [
+    ('0', '⁰'),
+    ('1', '¹'),
+    ('2', '²'),
+    ('3', '³'),
+    ('4', '⁴'),
+    ('5', '⁵'),
+    ('6', '⁶'),
+    ('7', '⁷'),
+    ('8', '⁸'),
+    ('9', '⁹'),
+    ('+', '⁺'),
+    ('-', '⁻'),
+    ('=', '⁼'),
+    ('(', '⁽'),
+    (')', '⁾'),
+    ('n', 'ⁿ'),
+    ('i', 'ⁱ'),
+];
+
+const SUBSCRIPTS: [(char, char); 28] = [
+    ('0', '₀'),
+    ('1', '₁'),
+    ('2', '₂'),
+    ('3', '₃'),
+    ('4', '₄'),
+    ('5', '₅'),
+    ('6', '₆'),
+    ('7', '₇'),
+    ('8', '₈'),
+    ('9', '₉'),
+    ('+', '₊'),
+    ('-', '₋'),
+    ('=', '₌'),
+    ('(', '₍'),
+    (')', '₎'),
+    ('a', 'ₐ'),
+    ('e', 'ₑ'),
+    ('o', 'ₒ'),
+    ('x', 'ₓ'),
+    ('h', 'ₕ'),
+    ('k', 'ₖ'),
+    ('l', 'ₗ'),
+    ('m', 'ₘ'),
+    ('n', 'ₙ'),
+    ('p', 'ₚ'),
+    ('s', 'ₛ'),
+    ('t', 'ₜ'),
+    ('j', 'ⱼ'),
+];
+
+/// Ordinal suffixes that are conventionally raised but read fine inline ("1st", "2e", "3º").
+const ORDINAL_SUFFIXES: [&str; 10] = ["st", "nd", "rd", "th", "er", "e", "re", "o", "a", "º"];
+
+/// Renders a raised or lowered run. `follows_digit` tells whether it comes straight after a
+/// digit, which is how ordinal suffixes are recognised.
+pub fn render(text: &str, script: Script, mode: ScriptMode, follows_digit: bool) -> String {
+    if mode == ScriptMode::Keep
+        || (script == Script::Super && follows_digit && ORDINAL_SUFFIXES.contains(&text))
+    {
+        return text.to_string();
+    }
+
+    if mode == ScriptMode::Unicode {
+        let table: &[(char, char)] = match script {
+            Script::Super => &SUPERSCRIPTS,
+            Script::Sub => &SUBSCRIPTS,
+        };
+        let mapped: Option<String> = text
+            .chars()
+            .map(|ch| {
+                table
+                    .iter()
+                    .find(|(plain, _)| *plain == ch)
+                    .map(|(_, s)| *s)
+            })
+            .collect();
+        if let Some(mapped) = mapped {
+            return mapped;
+        }
+    }
+
+    plain(text, script)
+}
+
+fn plain(text: &str, script: Script) -> String {
+    let marker = match script {
+        Script::Super => '^',
+        Script::Sub => '_',
+    };
+    if text.chars().count() == 1 || text.chars().all(|ch| ch.is_ascii_digit()) {
+        format!("{marker}{text}")
+    } else {
+        format!("{marker}({text})")
+    }
+}
+
+/// Rewrites Unicode superscript/subscript characters already present in the text
+/// using caret/underscore notation ("x²" → "x^2").
+pub fn unicode_to_plain(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut run = String::new();
+    let mut run_script = Script::Super;
+
+    for ch in text.chars() {
+        let found = SUPERSCRIPTS
+            .iter()
+            .find(|(_, s)| *s == ch)
+            .map(|(plain, _)| (Script::Super, *plain))
+            .or_else(|| {
+                SUBSCRIPTS
+                    .iter()
+                    .find(|(_, s)| *s == ch)
+                    .map(|(plain, _)| (Script::Sub, *plain))
+            });
+
+        match found {
+            Some((script, plain_char)) if run.is_empty() || script == run_script => {
+                run_script = script;
+                run.push(plain_char);
+            }
+            Some((script, plain_char)) => {
+                out.push_str(&plain(&run, run_script));
+                run.clear();
+                run_script = script;
+                run.push(plain_char);
+            }
+            None => {
+                if !run.is_empty() {
+                    out.push_str(&plain(&run, run_script));
+                    run.clear();
+                }
+                out.push(ch);
+            }
+        }
+    }
+
+    if !run.is_empty() {
+        out.push_str(&plain(&run, run_script));
+    }
+    out
+}
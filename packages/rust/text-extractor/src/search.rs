@@ -0,0 +1,289 @@
+//! Searching the outputs of earlier runs, for `search`: text, Markdown, JSON and JSON Lines
+//! files under an output folder are read back, ranked against the query and shown with a
+//! snippet and the source they were extracted from.
+//!
+//! Matching is case-insensitive and by substring, so it works for scripts written without
+//! spaces too. Documents are ranked by tf-idf: terms rare across the outputs weigh more,
+//! repeats count for less and less, and a match in the title counts double.
+
+use anyhow::Result;
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+use crate::daemon;
+
+/// Extensions of the outputs searched.
+const SEARCHED: [&str; 4] = ["txt", "md", "json", "jsonl"];
+/// Characters of context shown around the best match.
+const SNIPPET_CHARS: usize = 160;
+
+/// An output that matched, with where its text came from.
+#[derive(Debug, Clone, Serialize)]
+pub struct Hit {
+    /// The output file
+    pub output: PathBuf,
+    /// The file it was extracted from, when the output names it (JSON and JSON Lines)
+    pub source: Option<String>,
+    pub title: Option<String>,
+    /// The PDF page the snippet is from, when known
+    pub page: Option<usize>,
+    pub score: f64,
+    pub snippet: String,
+}
+
+/// A document read back from an output, in parts (pages or chapters) when the output keeps
+/// them apart.
+struct Entry {
+    output: PathBuf,
+    source: Option<String>,
+    title: Option<String>,
+    parts: Vec<Part>,
+}
+
+struct Part {
+    page: Option<usize>,
+    text: String,
+}
+
+/// Searches the outputs under `output_dir` for the terms of `query`, best first, returning at
+/// most `limit` hits.
+pub fn search(output_dir: &Path, query: &str, limit: usize) -> Result<Vec<Hit>> {
+    let terms: Vec<String> = query
+        .split_whitespace()
+        .map(str::to_lowercase)
+        .filter(|term| !term.is_empty())
+        .collect();
+    if terms.is_empty() {
+        anyhow::bail!("Nothing to search for");
+    }
+    let entries = read_outputs(output_dir);
+
+    // Documents each term occurs in, for weighing rare terms above common ones
+    let lowered: Vec<Vec<Folded>> = entries
+        .iter()
+        .map(|entry| entry.parts.iter().map(|part| fold(&part.text)).collect())
+        .collect();
+    let document_count = |term: &str| {
+        lowered
+            .iter()
+            .filter(|parts| parts.iter().any(|part| part.lower.contains(term)))
+            .count()
+    };
+    let weights: Vec<f64> = terms
+        .iter()
+        .map(|term| (1.0 + entries.len() as f64 / (1 + document_count(term)) as f64).ln())
+        .collect();
+
+    let mut hits = Vec::new();
+    for (entry, parts) in entries.iter().zip(&lowered) {
+        let title = entry.title.as_deref().map(str::to_lowercase);
+        let mut score = 0.0;
+        for (term, weight) in terms.iter().zip(&weights) {
+            let count: usize = parts
+                .iter()
+                .map(|part| part.lower.matches(term.as_str()).count())
+                .sum();
+            if count > 0 {
+                score += weight * (1.0 + (count as f64).ln());
+            }
+            if title
+                .as_deref()
+                .is_some_and(|title| title.contains(term.as_str()))
+            {
+                score += weight;
+            }
+        }
+        if score == 0.0 {
+            continue;
+        }
+
+        // The snippet comes from the part that matches the most terms
+        let best = (0..parts.len())
+            .max_by_key(|&index| {
+                let part = &parts[index].lower;
+                let distinct = terms
+                    .iter()
+                    .filter(|term| part.contains(term.as_str()))
+                    .count();
+                // Earlier parts win ties
+                (distinct, std::cmp::Reverse(index))
+            })
+            .unwrap_or(0);
+        let (page, snippet) = match entry.parts.get(best) {
+            Some(part) => (part.page, snippet(&part.text, &parts[best], &terms)),
+            None => (None, String::new()),
+        };
+        hits.push(Hit {
+            output: entry.output.clone(),
+            source: entry.source.clone(),
+            title: entry.title.clone(),
+            page,
+            score,
+            snippet,
+        });
+    }
+    hits.sort_by(|a, b| b.score.total_cmp(&a.score).then(a.output.cmp(&b.output)));
+    hits.truncate(limit);
+    Ok(hits)
+}
+
+/// The documents in the outputs under `output_dir`, leaving out sidecars and daemon state.
+fn read_outputs(output_dir: &Path) -> Vec<Entry> {
+    let mut entries = Vec::new();
+    let walk = WalkDir::new(output_dir)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|entry| entry.file_name() != daemon::STATE_DIR)
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_file());
+    for entry in walk {
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy();
+        if name.ends_with(".provenance.json")
+            || name.ends_with(".stamps.json")
+            || name.ends_with(".meta.json")
+        {
+            continue;
+        }
+        let Some(extension) = path.extension().and_then(|e| e.to_str()) else {
+            continue;
+        };
+        if !SEARCHED.contains(&extension) {
+            continue;
+        }
+        // Outputs in legacy encodings are searched as far as they decode
+        let Ok(bytes) = fs::read(path) else {
+            continue;
+        };
+        let text = String::from_utf8_lossy(&bytes);
+        match extension {
+            "json" => entries.extend(json_entry(path, &text)),
+            "jsonl" => entries.extend(text.lines().filter_map(|line| record_entry(path, line))),
+            _ => entries.push(text_entry(path, &text)),
+        }
+    }
+    entries
+}
+
+/// A text or Markdown output, titled by its metadata header or front matter.
+fn text_entry(path: &Path, text: &str) -> Entry {
+    let title = text.lines().take(20).find_map(|line| {
+        let title = line
+            .strip_prefix("Title: ")
+            .or_else(|| line.strip_prefix("title: "))?;
+        Some(title.trim().trim_matches('"').to_string())
+    });
+    Entry {
+        output: path.to_path_buf(),
+        source: None,
+        title,
+        parts: vec![Part {
+            page: None,
+            text: text.to_string(),
+        }],
+    }
+}
+
+/// A JSON output, one part per block; other JSON files under the folder are skipped.
+fn json_entry(path: &Path, text: &str) -> Option<Entry> {
+    let json: serde_json::Value = serde_json::from_str(text).ok()?;
+    let blocks = json.get("blocks")?.as_array()?;
+    let parts = blocks
+        .iter()
+        .map(|block| Part {
+            page: block["page"].as_u64().map(|page| page as usize),
+            text: block["text"].as_str().unwrap_or_default().to_string(),
+        })
+        .collect();
+    Some(Entry {
+        output: path.to_path_buf(),
+        source: json["source"].as_str().map(str::to_string),
+        title: json["title"].as_str().map(str::to_string),
+        parts,
+    })
+}
+
+/// One record of a JSON Lines corpus.
+fn record_entry(path: &Path, line: &str) -> Option<Entry> {
+    let json: serde_json::Value = serde_json::from_str(line).ok()?;
+    Some(Entry {
+        output: path.to_path_buf(),
+        source: json["source"].as_str().map(str::to_string),
+        title: json["title"].as_str().map(str::to_string),
+        parts: vec![Part {
+            page: None,
+            text: json["text"].as_str()?.to_string(),
+        }],
+    })
+}
+
+/// Text lowercased for matching, with the offset in the original of each byte, since
+/// lowercasing can change a character's length.
+struct Folded {
+    lower: String,
+    offsets: Vec<usize>,
+}
+
+fn fold(text: &str) -> Folded {
+    let mut lower = String::with_capacity(text.len());
+    let mut offsets = Vec::with_capacity(text.len());
+    for (offset, c) in text.char_indices() {
+        for lowered in c.to_lowercase() {
+            lower.push(lowered);
+            offsets.resize(lower.len(), offset);
+        }
+    }
+    Folded { lower, offsets }
+}
+
+/// About [`SNIPPET_CHARS`] characters of `text` around the place where the most terms occur
+/// close together, on one line.
+fn snippet(text: &str, folded: &Folded, terms: &[String]) -> String {
+    let mut best: Option<(usize, usize)> = None;
+    for term in terms {
+        for (at, _) in folded.lower.match_indices(term.as_str()).take(100) {
+            let nearby = folded.lower[at..]
+                .char_indices()
+                .nth(SNIPPET_CHARS)
+                .map_or(folded.lower.len(), |(end, _)| at + end);
+            let around = &folded.lower[at..nearby];
+            let distinct = terms
+                .iter()
+                .filter(|term| around.contains(term.as_str()))
+                .count();
+            if best.is_none_or(|(count, _)| distinct > count) {
+                best = Some((distinct, at));
+            }
+        }
+    }
+    let Some((_, at)) = best else {
+        return String::new();
+    };
+    let at = folded.offsets[at];
+
+    // Start a little before the match, but not before the start of its line
+    let line_start = text[..at].rfind('\n').map_or(0, |newline| newline + 1);
+    let start = text[line_start..at]
+        .char_indices()
+        .rev()
+        .take(SNIPPET_CHARS / 4)
+        .last()
+        .map_or(at, |(offset, _)| line_start + offset);
+    let end = text[start..]
+        .char_indices()
+        .nth(SNIPPET_CHARS)
+        .map_or(text.len(), |(offset, _)| start + offset);
+    let mut snippet = text[start..end]
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    if start > 0 {
+        snippet.insert_str(0, "...");
+    }
+    if end < text.len() {
+        snippet.push_str("...");
+    }
+    snippet
+}
@@ -0,0 +1,545 @@
+//! Extraction over HTTP, for services that cannot shell out to the extractor or share a file
+//! system with it.
+//!
+//! `POST /extract` takes a `multipart/form-data` upload and answers with the text of its
+//! first file, in the format `?format=` names (`text`, `markdown` or `json`; JSON too when
+//! the request only accepts `application/json`). The file's type comes from its name, or from
+//! `?type=` when the name has no usable extension. `GET /healthz` answers `ok` while the
+//! server runs.
+
+use anyhow::Result;
+use percent_encoding::percent_decode_str;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::encoding::OutputEncoding;
+use crate::extract::{self, ExtractOptions};
+use crate::output::{self, OutputFormat};
+use crate::source;
+
+/// Longest header line accepted in a multipart part, in bytes
+const MAX_HEADER_LINE: usize = 8 * 1024;
+
+pub struct ServeOptions {
+    /// Address and port to listen on, e.g. `127.0.0.1:8080`
+    pub listen: String,
+    /// Requests handled at once
+    pub workers: usize,
+    /// Largest upload accepted, in bytes
+    pub max_upload: u64,
+}
+
+/// A request turned away, with its status code and the reason given.
+struct Rejection {
+    status: u16,
+    message: String,
+}
+
+impl Rejection {
+    fn new(status: u16, message: impl Into<String>) -> Rejection {
+        Rejection {
+            status,
+            message: message.into(),
+        }
+    }
+}
+
+/// What a successful request is answered with.
+struct Answer {
+    content_type: String,
+    body: Vec<u8>,
+}
+
+/// Serves extraction with `extract` until the listener fails.
+pub fn serve(options: &ServeOptions, extract: &ExtractOptions) -> Result<()> {
+    let server = Server::http(&options.listen)
+        .map_err(|e| anyhow::anyhow!("Failed to listen on {}: {e}", options.listen))?;
+    println!("Listening on http://{}", options.listen);
+
+    std::thread::scope(|scope| {
+        let workers: Vec<_> = (0..options.workers.max(1))
+            .map(|_| {
+                scope.spawn(|| -> Result<()> {
+                    loop {
+                        let request = server.recv()?;
+                        let (method, url) = (request.method().clone(), request.url().to_string());
+                        if let Err(e) = handle(request, options, extract) {
+                            eprintln!("Error answering {method} {url}: {e}");
+                        }
+                    }
+                })
+            })
+            .collect();
+        for worker in workers {
+            match worker.join() {
+                Ok(result) => result?,
+                Err(panic) => std::panic::resume_unwind(panic),
+            }
+        }
+        Ok(())
+    })
+}
+
+fn handle(mut request: Request, options: &ServeOptions, extract: &ExtractOptions) -> Result<()> {
+    let url = request.url().to_string();
+    let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+    let result = match (request.method(), path) {
+        (Method::Get, "/healthz") => Ok(Answer {
+            content_type: "text/plain; charset=utf-8".to_string(),
+            body: b"ok\n".to_vec(),
+        }),
+        (Method::Post, "/extract") => extract_upload(&mut request, query, options, extract),
+        (_, "/healthz" | "/extract") => Err(Rejection::new(405, "method not allowed")),
+        _ => Err(Rejection::new(404, "not found")),
+    };
+
+    let response = match result {
+        Ok(answer) => {
+            Response::from_data(answer.body).with_header(content_type(&answer.content_type))
+        }
+        Err(rejection) => Response::from_string(rejection.message + "\n")
+            .with_status_code(rejection.status)
+            .with_header(content_type("text/plain; charset=utf-8")),
+    };
+    request.respond(response)?;
+    Ok(())
+}
+
+fn content_type(value: &str) -> Header {
+    Header::from_bytes("Content-Type", value).expect("content types are valid header values")
+}
+
+fn extract_upload(
+    request: &mut Request,
+    query: &str,
+    options: &ServeOptions,
+    extract: &ExtractOptions,
+) -> Result<Answer, Rejection> {
+    let too_large = || {
+        let limit = crate::memory::format_size(options.max_upload);
+        Rejection::new(413, format!("upload larger than {limit}"))
+    };
+    if request
+        .body_length()
+        .is_some_and(|length| length as u64 > options.max_upload)
+    {
+        return Err(too_large());
+    }
+    let upload_type = header(request, "Content-Type").unwrap_or_default();
+    let accept = header(request, "Accept").unwrap_or_default();
+
+    let boundary = multipart_boundary(&upload_type)
+        .ok_or_else(|| Rejection::new(400, "expected a multipart/form-data upload"))?;
+    // The upload is read as it arrives and only its file is kept, on disk
+    let mut body = Multipart::new(request.as_reader().take(options.max_upload + 1), &boundary);
+    let unreadable = |body: &Multipart<_>, e: io::Error| {
+        if body.read > options.max_upload {
+            too_large()
+        } else {
+            Rejection::new(400, format!("failed to read the upload: {e}"))
+        }
+    };
+    let name = match body.next_file() {
+        Ok(Some(name)) => name,
+        Ok(None) => return Err(Rejection::new(400, "no file in the upload")),
+        Err(e) => return Err(unreadable(&body, e)),
+    };
+
+    let extension = match query_value(query, "type") {
+        Some(given) => source::parse_extension(&given).map_err(|e| Rejection::new(415, e))?,
+        None => source::extension(Path::new(&name))
+            .filter(|extension| source::SUPPORTED_EXTENSIONS.contains(&extension.as_str()))
+            .ok_or_else(|| {
+                Rejection::new(
+                    415,
+                    format!("unsupported type: {name} (use a supported extension or add ?type=)"),
+                )
+            })?,
+    };
+    let format = match query_value(query, "format").as_deref() {
+        Some("text" | "txt") => OutputFormat::Text,
+        Some("markdown" | "md") => OutputFormat::Markdown,
+        Some("json") => OutputFormat::Json,
+        Some(other) => {
+            return Err(Rejection::new(
+                400,
+                format!("unknown format: {other} (use text, markdown or json)"),
+            ))
+        }
+        None if accept.trim() == "application/json" => OutputFormat::Json,
+        None => extract.output.format,
+    };
+
+    let mut spooled =
+        extract::spool_file(&extension).map_err(|e| Rejection::new(500, format!("{e:#}")))?;
+    if let Err(e) = io::copy(&mut body, &mut spooled) {
+        return Err(unreadable(&body, e));
+    }
+    if body.read > options.max_upload {
+        return Err(too_large());
+    }
+    spooled
+        .flush()
+        .map_err(|e| Rejection::new(500, format!("failed to spool the upload: {e}")))?;
+    let (extracted, provenance) = extract::extract_spooled(&spooled, &extension, extract)
+        .map_err(|e| Rejection::new(422, format!("{e:#}")))?;
+    let mut output_options = extract.output.clone();
+    output_options.format = format;
+    let encoded = output::encode_document(
+        &extracted.document,
+        Path::new(&name),
+        &output_options,
+        &provenance,
+    );
+    let charset = match format {
+        OutputFormat::Json | OutputFormat::Jsonl => OutputEncoding::Utf8,
+        _ => output_options.encoding,
+    }
+    .label();
+    let content_type = match format {
+        OutputFormat::Json | OutputFormat::Jsonl => "application/json".to_string(),
+        OutputFormat::Markdown => format!("text/markdown; charset={charset}"),
+        OutputFormat::Ssml => format!("application/ssml+xml; charset={charset}"),
+        // Text, and the formats serving refuses
+        _ => format!("text/plain; charset={charset}"),
+    };
+    Ok(Answer {
+        content_type,
+        body: encoded.bytes,
+    })
+}
+
+fn header(request: &Request, name: &'static str) -> Option<String> {
+    request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv(name))
+        .map(|header| header.value.to_string())
+}
+
+/// The decoded value of `name` in a query string.
+fn query_value(query: &str, name: &str) -> Option<String> {
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| percent_decode_str(value).decode_utf8_lossy().into_owned())
+}
+
+/// The boundary of a `multipart/form-data` content type.
+fn multipart_boundary(content_type: &str) -> Option<String> {
+    let kind = content_type.split(';').next()?;
+    if !kind.trim().eq_ignore_ascii_case("multipart/form-data") {
+        return None;
+    }
+    header_parameters(content_type)
+        .into_iter()
+        .find_map(|(key, value)| (key == "boundary" && !value.is_empty()).then_some(value))
+}
+
+/// The file name of a `Content-Disposition` header line, when it has one: its RFC 6266
+/// `filename*`, else its `filename`.
+fn disposition_file_name(line: &str) -> Option<String> {
+    let (field, value) = line.split_once(':')?;
+    if !field.trim().eq_ignore_ascii_case("content-disposition") {
+        return None;
+    }
+    let parameters = header_parameters(value);
+    let extended = parameters
+        .iter()
+        .find_map(|(key, value)| (key == "filename*").then(|| extended_value(value))?);
+    extended.or_else(|| {
+        parameters
+            .into_iter()
+            .find_map(|(key, value)| (key == "filename").then_some(value))
+    })
+}
+
+/// The `key=value` parameters that follow the first `;` of a header value, keys lowercased
+/// and quoted values unquoted, so a `;` inside quotes stays part of its value.
+fn header_parameters(value: &str) -> Vec<(String, String)> {
+    let Some((_, mut rest)) = value.split_once(';') else {
+        return Vec::new();
+    };
+    let mut parameters = Vec::new();
+    loop {
+        rest = rest.trim_start_matches(|c: char| c == ';' || c.is_whitespace());
+        if rest.is_empty() {
+            return parameters;
+        }
+        let key_end = rest.find(['=', ';']).unwrap_or(rest.len());
+        let key = rest[..key_end].trim().to_ascii_lowercase();
+        rest = &rest[key_end..];
+        let Some(after) = rest.strip_prefix('=') else {
+            continue;
+        };
+        let after = after.trim_start();
+        let value = match after.strip_prefix('"') {
+            Some(quoted) => {
+                let mut value = String::new();
+                let mut chars = quoted.char_indices();
+                let mut end = quoted.len();
+                while let Some((at, c)) = chars.next() {
+                    match c {
+                        '\\' => value.extend(chars.next().map(|(_, escaped)| escaped)),
+                        '"' => {
+                            end = at + 1;
+                            break;
+                        }
+                        c => value.push(c),
+                    }
+                }
+                rest = &quoted[end..];
+                value
+            }
+            None => {
+                let end = after.find(';').unwrap_or(after.len());
+                rest = &after[end..];
+                after[..end].trim().to_string()
+            }
+        };
+        parameters.push((key, value));
+    }
+}
+
+/// The text of an RFC 5987 extended value such as `UTF-8''na%C3%AFve.pdf`.
+fn extended_value(value: &str) -> Option<String> {
+    let mut fields = value.splitn(3, '\'');
+    let charset = fields.next()?;
+    let _language = fields.next()?;
+    let bytes: Vec<u8> = percent_decode_str(fields.next()?).collect();
+    if charset.eq_ignore_ascii_case("iso-8859-1") {
+        Some(bytes.iter().map(|&byte| char::from(byte)).collect())
+    } else {
+        Some(String::from_utf8_lossy(&bytes).into_owned())
+    }
+}
+
+/// A `multipart/form-data` body read as it arrives: [`Multipart::next_file`] moves to the
+/// next part that carries a file, and reading then yields that file's content and ends with
+/// the part.
+struct Multipart<R> {
+    reader: R,
+    /// `CRLF--boundary`, which ends every part
+    delimiter: Vec<u8>,
+    /// Bytes read but not used yet
+    buffer: Vec<u8>,
+    /// Bytes read from `reader` so far
+    read: u64,
+    /// Whether the content of the part being read has ended
+    part_done: bool,
+}
+
+impl<R: Read> Multipart<R> {
+    fn new(reader: R, boundary: &str) -> Multipart<R> {
+        Multipart {
+            reader,
+            delimiter: format!("\r\n--{boundary}").into_bytes(),
+            // The first delimiter opens the body without a CRLF before it
+            buffer: b"\r\n".to_vec(),
+            read: 0,
+            part_done: true,
+        }
+    }
+
+    /// Skips to the next part that carries a file and returns its file name, or `None` when
+    /// the body ends without one.
+    fn next_file(&mut self) -> io::Result<Option<String>> {
+        loop {
+            // The rest of the part being read, or the preamble before the first part
+            self.part_done = false;
+            match io::copy(self, &mut io::sink()) {
+                Ok(_) => {}
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+                Err(e) => return Err(e),
+            }
+            // `--` after a delimiter closes the body; a CRLF opens the next part
+            while self.buffer.len() < 2 && self.fill()? {}
+            if self.buffer.starts_with(b"--") {
+                return Ok(None);
+            }
+            self.read_line()?;
+
+            let mut headers = Vec::new();
+            loop {
+                let line = self.read_line()?;
+                if line.is_empty() {
+                    break;
+                }
+                headers.push(String::from_utf8_lossy(&line).into_owned());
+            }
+            if let Some(name) = headers.iter().find_map(|line| disposition_file_name(line)) {
+                self.part_done = false;
+                return Ok(Some(name));
+            }
+        }
+    }
+
+    /// A header line, without its CRLF.
+    fn read_line(&mut self) -> io::Result<Vec<u8>> {
+        loop {
+            if let Some(at) = self.buffer.windows(2).position(|pair| pair == b"\r\n") {
+                let line = self.buffer[..at].to_vec();
+                self.buffer.drain(..at + 2);
+                return Ok(line);
+            }
+            if self.buffer.len() > MAX_HEADER_LINE {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "part header line too long",
+                ));
+            }
+            if !self.fill()? {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+        }
+    }
+
+    /// Reads more of the body into the buffer, returning `false` at its end.
+    fn fill(&mut self) -> io::Result<bool> {
+        let mut chunk = [0; 64 * 1024];
+        let count = self.reader.read(&mut chunk)?;
+        self.buffer.extend_from_slice(&chunk[..count]);
+        self.read += count as u64;
+        Ok(count > 0)
+    }
+}
+
+impl<R: Read> Read for Multipart<R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        if self.part_done {
+            return Ok(0);
+        }
+        loop {
+            let delimiter = &self.delimiter;
+            let found = self
+                .buffer
+                .windows(delimiter.len())
+                .position(|window| window == delimiter);
+            // Bytes certain to be content: up to the delimiter, else all but those that may
+            // start one
+            let content = match found {
+                Some(0) => {
+                    self.buffer.drain(..self.delimiter.len());
+                    self.part_done = true;
+                    return Ok(0);
+                }
+                Some(at) => at,
+                None => self.buffer.len().saturating_sub(delimiter.len() - 1),
+            };
+            if content > 0 {
+                let count = content.min(out.len());
+                out[..count].copy_from_slice(&self.buffer[..count]);
+                self.buffer.drain(..count);
+                return Ok(count);
+            }
+            if !self.fill()? {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "the upload ends inside a part",
+                ));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A reader that hands out one byte at a time, so delimiters arrive split across reads.
+    struct Trickle<'a>(&'a [u8]);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+            let Some((&first, rest)) = self.0.split_first() else {
+                return Ok(0);
+            };
+            out[0] = first;
+            self.0 = rest;
+            Ok(1)
+        }
+    }
+
+    fn multipart_file(body: &[u8], boundary: &str) -> io::Result<Option<(String, Vec<u8>)>> {
+        let mut multipart = Multipart::new(Trickle(body), boundary);
+        let Some(name) = multipart.next_file()? else {
+            return Ok(None);
+        };
+        let mut content = Vec::new();
+        multipart.read_to_end(&mut content)?;
+        Ok(Some((name, content)))
+    }
+
+    #[test]
+    fn multipart_boundaries() {
+        assert_eq!(
+            multipart_boundary("multipart/form-data; boundary=abc123").as_deref(),
+            Some("abc123")
+        );
+        assert_eq!(
+            multipart_boundary("Multipart/Form-Data; charset=utf-8; Boundary=\"a;b c\"").as_deref(),
+            Some("a;b c")
+        );
+        assert_eq!(multipart_boundary("multipart/form-data"), None);
+        assert_eq!(multipart_boundary("multipart/form-data; boundary="), None);
+        assert_eq!(multipart_boundary("text/plain; boundary=abc"), None);
+    }
+
+    #[test]
+    fn disposition_file_names() {
+        let name = |line| disposition_file_name(line);
+        assert_eq!(
+            name("Content-Disposition: form-data; name=\"file\"; filename=\"a.pdf\"").as_deref(),
+            Some("a.pdf")
+        );
+        assert_eq!(
+            name("content-disposition: form-data; filename=\"a; b.pdf\"; name=file").as_deref(),
+            Some("a; b.pdf")
+        );
+        assert_eq!(
+            name(r#"Content-Disposition: form-data; filename="say \"hi\".pdf""#).as_deref(),
+            Some("say \"hi\".pdf")
+        );
+        assert_eq!(
+            name("Content-Disposition: form-data; filename=\"naive.pdf\"; filename*=UTF-8''na%C3%AFve%3B1.pdf")
+                .as_deref(),
+            Some("naïve;1.pdf")
+        );
+        assert_eq!(
+            name("Content-Disposition: form-data; filename*=iso-8859-1'fr'%E9t%E9.docx").as_deref(),
+            Some("été.docx")
+        );
+        assert_eq!(name("Content-Disposition: form-data; name=\"title\""), None);
+        assert_eq!(name("Content-Type: text/plain; filename=a.pdf"), None);
+    }
+
+    #[test]
+    fn multipart_file_after_other_fields() {
+        let body = b"preamble\r\n--XyZ\r\n\
+            Content-Disposition: form-data; name=\"title\"\r\n\r\n\
+            a title\r\n\
+            --XyZ\r\n\
+            Content-Disposition: form-data; name=\"file\"; filename=\"a; b.txt\"\r\n\
+            Content-Type: text/plain\r\n\r\n\
+            line one --XyZ\r\n--Xy\r\n-\r\n\r\n\
+            --XyZ--\r\n";
+        let (name, content) = multipart_file(body, "XyZ").unwrap().unwrap();
+        assert_eq!(name, "a; b.txt");
+        assert_eq!(content, b"line one --XyZ\r\n--Xy\r\n-\r\n");
+    }
+
+    #[test]
+    fn multipart_without_a_file() {
+        let body = b"--b\r\nContent-Disposition: form-data; name=\"x\"\r\n\r\n1\r\n--b--";
+        assert!(multipart_file(body, "b").unwrap().is_none());
+        assert!(multipart_file(b"no parts at all", "b").unwrap().is_none());
+    }
+
+    #[test]
+    fn multipart_cut_short_is_an_error() {
+        let body = b"--b\r\nContent-Disposition: form-data; filename=\"a.txt\"\r\n\r\nhalf a fi";
+        assert!(multipart_file(body, "b").is_err());
+    }
+}
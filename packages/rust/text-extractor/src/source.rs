@@ -0,0 +1,110 @@
+//! Input files discovered for extraction, independent of where they came from.
+
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+
+use crate::metadata::Metadata;
+
+/// Extensions (lowercase) that have an extractor.
+#[cfg(not(feature = "audio"))]
+pub const SUPPORTED_EXTENSIONS: &[&str] = &[
+    "pdf", "epub", "pages", "ibooks", "tex", "djvu", "djv", "html", "xhtml", "htm", "chm", "fb2",
+    "fb2.zip", "docx", "odt", "rtf",
+];
+/// Extensions (lowercase) that have an extractor, audiobooks included.
+#[cfg(feature = "audio")]
+pub const SUPPORTED_EXTENSIONS: &[&str] = &[
+    "pdf", "epub", "pages", "ibooks", "tex", "djvu", "djv", "html", "xhtml", "htm", "chm", "fb2",
+    "fb2.zip", "docx", "odt", "rtf", "mp3", "m4b",
+];
+
+/// Extensions of two parts, of which [`Path::extension`] only sees the last.
+const COMPOUND_EXTENSIONS: &[&str] = &["fb2.zip"];
+
+/// A file queued for extraction.
+pub struct SourceFile {
+    pub path: PathBuf,
+    /// Lowercase extension used to pick the extractor
+    pub extension: String,
+    /// Canonical metadata from the catalog the file came from, overriding embedded values
+    pub metadata: Option<Metadata>,
+}
+
+impl SourceFile {
+    pub fn new(path: PathBuf, extension: &str) -> Self {
+        SourceFile {
+            path,
+            extension: extension.to_lowercase(),
+            metadata: None,
+        }
+    }
+
+    /// Builds a source for `path` if its extension is one we can extract. LaTeX files
+    /// that are only chapters included by another file are left to that file.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = extension(path)?;
+        if extension == "tex" && !crate::latex::is_document(path) {
+            return None;
+        }
+        SUPPORTED_EXTENSIONS
+            .contains(&extension.as_str())
+            .then(|| SourceFile::new(path.to_path_buf(), &extension))
+    }
+}
+
+/// Parses a document type given as its extension, such as `pdf` or `.EPUB`.
+pub fn parse_extension(text: &str) -> Result<String, String> {
+    let extension = text.trim().trim_start_matches('.').to_lowercase();
+    if SUPPORTED_EXTENSIONS.contains(&extension.as_str()) {
+        Ok(extension)
+    } else {
+        Err(format!(
+            "unsupported type: {text} (use one of {})",
+            SUPPORTED_EXTENSIONS.join(", ")
+        ))
+    }
+}
+
+/// The lowercase extension of `path`, both parts of a compound one like `fb2.zip`.
+pub fn extension(path: &Path) -> Option<String> {
+    let name = path.file_name()?.to_str()?.to_lowercase();
+    if let Some(compound) = COMPOUND_EXTENSIONS
+        .iter()
+        .find(|compound| name.ends_with(&format!(".{compound}")))
+    {
+        return Some(compound.to_string());
+    }
+    Some(path.extension()?.to_str()?.to_lowercase())
+}
+
+/// The kind of document a supported extension holds, as listings name it.
+pub fn format_name(extension: &str) -> &'static str {
+    match extension {
+        "pdf" => "PDF",
+        "epub" => "EPUB",
+        "pages" => "Pages",
+        "ibooks" => "iBooks Author",
+        "tex" => "LaTeX",
+        "djvu" | "djv" => "DjVu",
+        "html" | "xhtml" | "htm" => "HTML",
+        "chm" => "CHM",
+        "fb2" | "fb2.zip" => "FictionBook",
+        "docx" => "Word",
+        "odt" => "OpenDocument",
+        "rtf" => "RTF",
+        "mp3" | "m4b" => "Audiobook",
+        _ => "Unknown",
+    }
+}
+
+/// The file name of `path` without its extension, compound or not, e.g. `book` for
+/// `book.fb2.zip`.
+pub fn file_stem(path: &Path) -> Option<&OsStr> {
+    let stem = path.file_stem()?;
+    let extension = extension(path)?;
+    if extension.contains('.') {
+        Path::new(stem).file_stem()
+    } else {
+        Some(stem)
+    }
+}
@@ -0,0 +1,99 @@
+//! Splitting a document into one output per bookmarked chapter or contained work.
+
+use std::ops::Range;
+
+use crate::anthology;
+use crate::document::{Document, Landmarks, OutlineEntry};
+
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SplitMode {
+    /// One file per bookmark (PDF outline or EPUB table of contents)
+    Bookmarks,
+    /// One file per complete work bound into an omnibus or anthology
+    Works,
+}
+
+/// A run of sections opened by one outline entry.
+pub struct Part {
+    pub title: String,
+    pub doc: Document,
+}
+
+/// Title of the part holding the pages before the first bookmark.
+pub const FRONT_MATTER: &str = "Front matter";
+
+/// Splits `doc` the way `mode` asks; `max_level` limits the bookmarks used by
+/// [`SplitMode::Bookmarks`]. Returns nothing when the document cannot be split.
+pub fn parts(doc: &Document, mode: SplitMode, max_level: usize) -> Vec<Part> {
+    match mode {
+        SplitMode::Bookmarks => by_outline(doc, max_level),
+        SplitMode::Works => anthology::works(doc),
+    }
+}
+
+/// Splits `doc` at its outline entries up to `max_level` deep.
+///
+/// Sections before the first entry become a front matter part, and entries opening on the
+/// same section as the next one are dropped rather than written empty. Returns nothing when
+/// the document has no usable outline.
+pub fn by_outline(doc: &Document, max_level: usize) -> Vec<Part> {
+    let mut starts: Vec<&OutlineEntry> = doc
+        .outline
+        .iter()
+        .filter(|entry| entry.level <= max_level && entry.section < doc.sections.len())
+        .collect();
+    starts.sort_by_key(|entry| entry.section);
+    if starts.is_empty() {
+        return Vec::new();
+    }
+
+    let mut bounds: Vec<(&str, usize)> = Vec::new();
+    if starts[0].section > 0 {
+        bounds.push((FRONT_MATTER, 0));
+    }
+    bounds.extend(
+        starts
+            .iter()
+            .map(|entry| (entry.title.as_str(), entry.section)),
+    );
+
+    let mut parts = Vec::new();
+    for (i, &(title, start)) in bounds.iter().enumerate() {
+        let end = bounds.get(i + 1).map_or(doc.sections.len(), |next| next.1);
+        if start >= end {
+            continue;
+        }
+
+        let mut part = slice(doc, start..end);
+        part.sections[0]
+            .title
+            .get_or_insert_with(|| title.to_string());
+        parts.push(Part {
+            title: title.to_string(),
+            doc: part,
+        });
+    }
+    parts
+}
+
+/// The sections in `range` as a document of their own, with the bookmarks that fall inside
+/// it. `range` must not be empty.
+pub fn slice(doc: &Document, range: Range<usize>) -> Document {
+    let outline = doc
+        .outline
+        .iter()
+        .filter(|entry| range.contains(&entry.section))
+        .map(|entry| OutlineEntry {
+            section: entry.section - range.start,
+            ..entry.clone()
+        })
+        .collect();
+
+    Document {
+        metadata: doc.metadata.clone(),
+        sections: doc.sections[range].to_vec(),
+        outline,
+        landmarks: Landmarks::default(),
+    }
+}
@@ -0,0 +1,139 @@
+//! The SQLite database `--format sqlite` writes: one row per document with its metadata and
+//! text, one per page or chapter, and an FTS5 table for full-text queries in plain SQL.
+//!
+//! ```sql
+//! SELECT source, title, snippet(documents_fts, 2, '[', ']', '...', 12)
+//! FROM documents_fts JOIN documents ON documents.id = documents_fts.rowid
+//! WHERE documents_fts MATCH 'dynamic programming' ORDER BY rank;
+//! ```
+//!
+//! Documents are keyed by their source path, so extracting a file again replaces its rows.
+
+use anyhow::{Context, Result};
+use rusqlite::{params, Connection};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::document::{Document, SectionKind};
+use crate::provenance::Provenance;
+
+/// Held while a document is written, so documents extracted at once do not find the database
+/// busy.
+static DATABASE_LOCK: Mutex<()> = Mutex::new(());
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS documents (
+    id INTEGER PRIMARY KEY,
+    source TEXT NOT NULL UNIQUE,
+    title TEXT,
+    author TEXT,
+    language TEXT,
+    collection TEXT,
+    page_count INTEGER,
+    chapter_count INTEGER,
+    source_sha256 TEXT,
+    backend TEXT,
+    extracted_at INTEGER NOT NULL,
+    text TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS sections (
+    document_id INTEGER NOT NULL REFERENCES documents (id) ON DELETE CASCADE,
+    position INTEGER NOT NULL,
+    kind TEXT NOT NULL,
+    title TEXT,
+    page INTEGER,
+    language TEXT,
+    text TEXT NOT NULL,
+    PRIMARY KEY (document_id, position)
+);
+CREATE VIRTUAL TABLE IF NOT EXISTS documents_fts USING fts5 (
+    title, author, text, content = 'documents', content_rowid = 'id'
+);
+CREATE TRIGGER IF NOT EXISTS documents_fts_insert AFTER INSERT ON documents BEGIN
+    INSERT INTO documents_fts (rowid, title, author, text)
+    VALUES (new.id, new.title, new.author, new.text);
+END;
+CREATE TRIGGER IF NOT EXISTS documents_fts_delete AFTER DELETE ON documents BEGIN
+    INSERT INTO documents_fts (documents_fts, rowid, title, author, text)
+    VALUES ('delete', old.id, old.title, old.author, old.text);
+END;
+";
+
+/// Writes `doc`, extracted from `source` as `text`, to the database at `path`, creating it
+/// when it does not exist and replacing the rows of an earlier extraction of `source`.
+pub fn write_document(
+    path: &Path,
+    doc: &Document,
+    source: &Path,
+    text: &str,
+    provenance: &Provenance,
+) -> Result<()> {
+    let _lock = DATABASE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut connection =
+        Connection::open(path).context(format!("Failed to open database: {}", path.display()))?;
+    connection.execute_batch("PRAGMA foreign_keys = ON;")?;
+    connection
+        .execute_batch(SCHEMA)
+        .context(format!("Failed to create tables in: {}", path.display()))?;
+
+    let count = |kind: SectionKind| {
+        let count = doc
+            .sections
+            .iter()
+            .filter(|section| section.kind == kind)
+            .count();
+        (count > 0).then_some(count as i64)
+    };
+    let extracted_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs() as i64);
+    let source = source.to_string_lossy();
+    let metadata = &doc.metadata;
+
+    let transaction = connection.transaction()?;
+    transaction.execute("DELETE FROM documents WHERE source = ?1", [&source])?;
+    transaction.execute(
+        "INSERT INTO documents (source, title, author, language, collection, page_count,
+             chapter_count, source_sha256, backend, extracted_at, text)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+        params![
+            source,
+            metadata.title,
+            metadata.author,
+            metadata.language,
+            metadata.collection,
+            count(SectionKind::Page),
+            count(SectionKind::Chapter),
+            provenance.source_sha256,
+            provenance.backend,
+            extracted_at,
+            text,
+        ],
+    )?;
+    let document_id = transaction.last_insert_rowid();
+    {
+        let mut insert = transaction.prepare(
+            "INSERT INTO sections (document_id, position, kind, title, page, language, text)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        )?;
+        for (position, section) in doc.sections.iter().enumerate() {
+            let kind = match section.kind {
+                SectionKind::Page => "page",
+                SectionKind::Chapter => "chapter",
+            };
+            insert.execute(params![
+                document_id,
+                position as i64 + 1,
+                kind,
+                section.title,
+                section.page.map(|page| page as i64),
+                section.language,
+                section.text,
+            ])?;
+        }
+    }
+    transaction
+        .commit()
+        .context(format!("Failed to write to database: {}", path.display()))
+}
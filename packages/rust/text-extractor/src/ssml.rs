@@ -0,0 +1,185 @@
+//! Speech-ready SSML rendering for feeding books to a TTS engine.
+
+use crate::document::{Document, SectionKind};
+
+/// Pause inserted between chapters and after the title announcement.
+const CHAPTER_BREAK: &str = r#"<break strength="x-strong" time="2s"/>"#;
+
+/// Abbreviations that end in a period without ending the sentence, with their spoken forms.
+const ABBREVIATIONS: [(&str, &str); 16] = [
+    ("Mr.", "Mister"),
+    ("Mrs.", "Missus"),
+    ("Ms.", "Miz"),
+    ("Dr.", "Doctor"),
+    ("Prof.", "Professor"),
+    ("St.", "Saint"),
+    ("Jr.", "Junior"),
+    ("Sr.", "Senior"),
+    ("vs.", "versus"),
+    ("etc.", "et cetera"),
+    ("e.g.", "for example"),
+    ("i.e.", "that is"),
+    ("approx.", "approximately"),
+    ("Fig.", "Figure"),
+    ("Vol.", "Volume"),
+    ("Ch.", "Chapter"),
+];
+
+#[derive(Debug, Clone, Default)]
+pub struct SsmlOptions {
+    /// Replace common abbreviations with their spoken form
+    pub expand_abbreviations: bool,
+}
+
+pub fn render(doc: &Document, options: &SsmlOptions) -> String {
+    let mut out = String::new();
+    out.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    out.push_str("<speak version=\"1.1\" xmlns=\"http://www.w3.org/2001/10/synthesis\"");
+    if let Some(language) = &doc.metadata.language {
+        out.push_str(&format!(" xml:lang=\"{}\"", escape_xml(language)));
+    }
+    out.push_str(">\n");
+
+    if let Some(title) = &doc.metadata.title {
+        out.push_str("<p>");
+        push_sentence(&mut out, title, options);
+        if let Some(author) = &doc.metadata.author {
+            push_sentence(&mut out, &format!("by {author}"), options);
+        }
+        out.push_str("</p>\n");
+        out.push_str(CHAPTER_BREAK);
+        out.push('\n');
+    }
+
+    let mut first_chapter = true;
+    for section in &doc.sections {
+        let section = section.without_page_furniture();
+        let paragraphs = section.paragraphs();
+        if paragraphs.is_empty() {
+            continue;
+        }
+
+        // Pages flow into each other; only chapter boundaries get an audible pause
+        if section.kind == SectionKind::Chapter {
+            if !first_chapter {
+                out.push_str(CHAPTER_BREAK);
+                out.push('\n');
+            }
+            first_chapter = false;
+        }
+
+        // Lets the engine switch voices for passages in another language
+        if let Some(language) = &section.language {
+            out.push_str(&format!("<lang xml:lang=\"{}\">\n", escape_xml(language)));
+        }
+
+        if let Some(title) = &section.title {
+            out.push_str("<p>");
+            push_sentence(&mut out, title, options);
+            out.push_str("</p>\n");
+        }
+
+        for paragraph in paragraphs {
+            out.push_str("<p>");
+            for sentence in split_sentences(&paragraph) {
+                push_sentence(&mut out, sentence, options);
+            }
+            out.push_str("</p>\n");
+        }
+
+        if section.language.is_some() {
+            out.push_str("</lang>\n");
+        }
+    }
+
+    out.push_str("</speak>\n");
+    out
+}
+
+fn push_sentence(out: &mut String, sentence: &str, options: &SsmlOptions) {
+    let spoken = if options.expand_abbreviations {
+        expand_abbreviations(sentence)
+    } else {
+        sentence.to_string()
+    };
+    out.push_str("<s>");
+    out.push_str(&escape_xml(&spoken));
+    out.push_str("</s>");
+}
+
+fn split_sentences(paragraph: &str) -> Vec<&str> {
+    let mut sentences = Vec::new();
+    let mut start = 0;
+    let mut chars = paragraph.char_indices().peekable();
+
+    while let Some((i, ch)) = chars.next() {
+        if !matches!(ch, '.' | '!' | '?') {
+            continue;
+        }
+
+        // Keep closing quotes and brackets with the sentence they end
+        let mut end = i + ch.len_utf8();
+        while let Some(&(j, next)) = chars.peek() {
+            if matches!(next, '"' | '\'' | '”' | '’' | ')' | ']' | '.' | '!' | '?') {
+                end = j + next.len_utf8();
+                chars.next();
+            } else {
+                break;
+            }
+        }
+
+        let at_boundary = chars.peek().is_none_or(|&(_, next)| next.is_whitespace());
+        let last_word = paragraph[start..end]
+            .split_whitespace()
+            .last()
+            .unwrap_or("");
+        if at_boundary && !is_abbreviation(last_word) {
+            let sentence = paragraph[start..end].trim();
+            if !sentence.is_empty() {
+                sentences.push(sentence);
+            }
+            start = end;
+        }
+    }
+
+    let rest = paragraph[start..].trim();
+    if !rest.is_empty() {
+        sentences.push(rest);
+    }
+    sentences
+}
+
+fn is_abbreviation(word: &str) -> bool {
+    let word = word.trim_start_matches(|c: char| !c.is_alphanumeric());
+    ABBREVIATIONS.iter().any(|(abbr, _)| *abbr == word)
+        // Single initials such as the "J." in "J. R. R. Tolkien"
+        || (word.len() == 2 && word.starts_with(|c: char| c.is_uppercase()))
+}
+
+fn expand_abbreviations(sentence: &str) -> String {
+    sentence
+        .split(' ')
+        .map(|word| {
+            ABBREVIATIONS
+                .iter()
+                .find(|(abbr, _)| *abbr == word)
+                .map_or(word, |(_, spoken)| spoken)
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(ch),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,204 @@
+//! Bates numbers and exhibit stamps on the pages of legal document sets, recorded so
+//! extracted text can be cited by the identifiers stamped on each page.
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::document::{Document, SectionKind};
+
+/// Lines at the top and bottom of a page searched for a Bates number.
+const MARGIN_LINES: usize = 3;
+/// Digits in the number part of a Bates number.
+const BATES_DIGITS: std::ops::RangeInclusive<usize> = 4..=10;
+/// Longest prefix ("ACME", "SMITH-PROD") before a Bates number's digits.
+const MAX_PREFIX_LEN: usize = 16;
+/// Words that may come before "Exhibit" on a stamp.
+const EXHIBIT_PARTIES: &[&str] = &[
+    "plaintiff's",
+    "plaintiffs'",
+    "plaintiffs",
+    "defendant's",
+    "defendants'",
+    "defendants",
+    "government's",
+    "petitioner's",
+    "respondent's",
+    "joint",
+    "deposition",
+];
+/// Longest exhibit designation ("12", "A", "PX-104").
+const MAX_EXHIBIT_LEN: usize = 8;
+
+#[derive(Debug, Serialize)]
+pub struct PageStamps {
+    /// Page number in the source PDF, counting from 1
+    pub page: usize,
+    pub bates: Option<String>,
+    pub exhibit: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Stamps {
+    /// Bates range covered by the output, for citing it as a whole
+    pub first_bates: Option<String>,
+    pub last_bates: Option<String>,
+    pub pages: Vec<PageStamps>,
+}
+
+impl Stamps {
+    /// The stamps on each PDF page of `doc`, or `None` when it has no pages.
+    pub fn of(doc: &Document) -> Option<Stamps> {
+        let pages: Vec<PageStamps> = doc
+            .sections
+            .iter()
+            .filter(|section| section.kind == SectionKind::Page)
+            .filter_map(|section| {
+                Some(PageStamps {
+                    page: section.page?,
+                    bates: bates_number(&section.text),
+                    exhibit: exhibit_stamp(&section.text),
+                })
+            })
+            .collect();
+        if pages.is_empty() {
+            return None;
+        }
+
+        let mut numbered = pages.iter().filter_map(|page| page.bates.clone());
+        let first_bates = numbered.next();
+        let last_bates = numbered.next_back().or_else(|| first_bates.clone());
+        Some(Stamps {
+            first_bates,
+            last_bates,
+            pages,
+        })
+    }
+
+    /// Writes `<output>.stamps.json` next to `output_path`.
+    pub fn write_sidecar(&self, output_path: &Path) -> Result<PathBuf> {
+        let mut name = output_path.as_os_str().to_owned();
+        name.push(".stamps.json");
+        let sidecar = PathBuf::from(name);
+
+        fs::write(&sidecar, serde_json::to_string_pretty(self)? + "\n").context(format!(
+            "Failed to write stamps sidecar: {}",
+            sidecar.display()
+        ))?;
+        Ok(sidecar)
+    }
+}
+
+/// A Bates number on a line of its own, or at the end of a line, near the bottom or top of
+/// the page; the bottom is checked first, where stamps usually go.
+fn bates_number(text: &str) -> Option<String> {
+    let lines: Vec<&str> = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect();
+    let bottom = lines.iter().rev().take(MARGIN_LINES);
+    let top = lines.iter().take(MARGIN_LINES);
+
+    bottom.chain(top).find_map(|line| {
+        // Stamps share the footer line with page numbers and confidentiality legends
+        let last = line.split_whitespace().last()?;
+        is_bates(last).then(|| last.to_string()).or_else(|| {
+            // Prefixes are sometimes separated from the digits by a space ("ACME 000123")
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let joined = words.get(words.len().checked_sub(2)?..)?.join(" ");
+            is_bates(&joined).then_some(joined)
+        })
+    })
+}
+
+/// Whether `token` is an alphabetic prefix followed by a zero-padded number, such as
+/// "ACME0001234", "SMITH_000123" or "DEF-00042"; a bare number needs its padding zeros.
+fn is_bates(token: &str) -> bool {
+    let prefix = token.trim_end_matches(|c: char| c.is_ascii_digit());
+    let digits = &token[prefix.len()..];
+    if !BATES_DIGITS.contains(&digits.len()) {
+        return false;
+    }
+
+    let prefix = prefix.trim_end_matches(['_', '-', ' ', '.']);
+    if prefix.is_empty() {
+        return digits.starts_with("00");
+    }
+    prefix.len() <= MAX_PREFIX_LEN
+        && prefix
+            .chars()
+            .next()
+            .is_some_and(|c| c.is_ascii_uppercase())
+        && prefix
+            .chars()
+            .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || matches!(c, '_' | '-' | '.'))
+}
+
+/// An exhibit stamp such as "EXHIBIT 12" or "Plaintiff's Exhibit A" on a line of its own;
+/// mentions of exhibits in running text are longer than that.
+fn exhibit_stamp(text: &str) -> Option<String> {
+    text.lines().map(str::trim).find_map(|line| {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let at = words.iter().position(|word| {
+            word.eq_ignore_ascii_case("exhibit") || word.eq_ignore_ascii_case("ex.")
+        })?;
+        let parties = &words[..at];
+        let designation = words.get(at + 1..)?;
+
+        let stamped = parties.len() <= 1
+            && parties
+                .iter()
+                .all(|party| EXHIBIT_PARTIES.contains(&party.to_lowercase().as_str()))
+            && designation.len() == 1
+            && designation[0].len() <= MAX_EXHIBIT_LEN
+            && designation[0]
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-');
+        stamped.then(|| words.join(" "))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bates_numbers() {
+        assert!(is_bates("ACME0001234"));
+        assert!(is_bates("SMITH_000123"));
+        assert!(is_bates("DEF-00042"));
+        assert!(is_bates("ACME 000123"));
+        assert!(is_bates("0001234"));
+        assert!(!is_bates("1234"));
+        assert!(!is_bates("acme0001234"));
+        assert!(!is_bates("ACME12"));
+    }
+
+    #[test]
+    fn non_ascii_tokens_are_not_bates_numbers() {
+        assert!(!is_bates("🔨"));
+        assert!(!is_bates("Straße"));
+        assert!(!is_bates("ÉTÉ0001234"));
+        assert_eq!(bates_number("Text\n\nSeite 3 — Überblick 🔨"), None);
+    }
+
+    #[test]
+    fn bates_number_in_footer() {
+        let page = "Body text\nmore text\nConfidential   Page 3   ACME0001234";
+        assert_eq!(bates_number(page).as_deref(), Some("ACME0001234"));
+        let page = "Body text\nACME 123456";
+        assert_eq!(bates_number(page).as_deref(), Some("ACME 123456"));
+    }
+
+    #[test]
+    fn exhibit_stamps() {
+        assert_eq!(
+            exhibit_stamp("Plaintiff's Exhibit 12").as_deref(),
+            Some("Plaintiff's Exhibit 12")
+        );
+        assert_eq!(exhibit_stamp("EXHIBIT A").as_deref(), Some("EXHIBIT A"));
+        assert_eq!(exhibit_stamp("as shown in Exhibit 12 of the filing"), None);
+    }
+}
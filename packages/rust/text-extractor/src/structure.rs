@@ -0,0 +1,447 @@
+//! Tagged PDF support: reading order and block boundaries from the structure tree.
+//!
+//! pdf-extract does not report marked content, so before a page is rendered its content stream
+//! is rewritten to announce every change of marked-content ID with an empty fill at a marker
+//! position. The glyph collector picks those up and tags each glyph with the MCID it belongs
+//! to, which the structure tree then orders into blocks.
+
+use anyhow::Result;
+use pdf_extract::content::{Content, Operation};
+use pdf_extract::{Dictionary, Document, Object, ObjectId, Path, PathOp};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::layout::{self, AssembleOptions, Glyph, PageGlyphs};
+
+/// Which marked-content sequence a glyph was shown in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ContentTag {
+    #[default]
+    Untagged,
+    Mcid(i64),
+    /// Pagination and decoration (running heads, page numbers) that is not part of the content
+    Artifact,
+}
+
+/// x coordinates of the injected marker paths; no real drawing moves this far off the page.
+const MARKER_X: f64 = -987_654.0;
+const LANGUAGE_MARKER_X: f64 = -987_000.0;
+const UNTAGGED_CODE: i64 = -1;
+const ARTIFACT_CODE: i64 = -2;
+
+/// Structure elements that start a new block of text.
+const BLOCK_ROLES: [&str; 23] = [
+    "P",
+    "H",
+    "H1",
+    "H2",
+    "H3",
+    "H4",
+    "H5",
+    "H6",
+    "LI",
+    "TOCI",
+    "Caption",
+    "BlockQuote",
+    "BibEntry",
+    "Code",
+    "Note",
+    "TD",
+    "TH",
+    "Title",
+    "Figure",
+    "Formula",
+    "Index",
+    "Div",
+    "Lbl",
+];
+
+/// A run of content the structure tree groups together, in reading order.
+#[derive(Debug)]
+pub struct Block {
+    id: usize,
+    mcids: Vec<i64>,
+}
+
+/// Blocks of a document's structure tree, per page.
+#[derive(Debug, Default)]
+pub struct StructTree {
+    pages: HashMap<ObjectId, Vec<Block>>,
+    /// `/Lang` of the elements owning each marked-content sequence, where one is set
+    languages: HashMap<(ObjectId, i64), String>,
+}
+
+impl StructTree {
+    /// Reads the structure tree, or returns `None` for untagged documents.
+    pub fn read(doc: &Document) -> Option<Self> {
+        let root = doc
+            .catalog()
+            .ok()?
+            .get_deref(b"StructTreeRoot", doc)
+            .ok()?
+            .as_dict()
+            .ok()?;
+
+        let mut walker = Walker {
+            doc,
+            role_map: root
+                .get_deref(b"RoleMap", doc)
+                .and_then(Object::as_dict)
+                .ok(),
+            tree: StructTree::default(),
+            next_block: 0,
+            visited: HashSet::new(),
+        };
+        walker.visit(root.get(b"K").ok()?, None, None, None);
+
+        (!walker.tree.pages.is_empty()).then_some(walker.tree)
+    }
+
+    pub fn blocks(&self, page: ObjectId) -> &[Block] {
+        self.pages.get(&page).map_or(&[], Vec::as_slice)
+    }
+}
+
+struct Walker<'a> {
+    doc: &'a Document,
+    role_map: Option<&'a Dictionary>,
+    tree: StructTree,
+    next_block: usize,
+    visited: HashSet<ObjectId>,
+}
+
+/// The block content is currently collected into, and whether it is a list item (whose label
+/// and body stay together).
+type CurrentBlock = Option<(usize, bool)>;
+
+impl<'a> Walker<'a> {
+    fn visit(
+        &mut self,
+        object: &'a Object,
+        page: Option<ObjectId>,
+        block: CurrentBlock,
+        lang: Option<&str>,
+    ) {
+        let Ok((id, object)) = self.doc.dereference(object) else {
+            return;
+        };
+        // Malformed trees can contain cycles
+        if let Some(id) = id {
+            if !self.visited.insert(id) {
+                return;
+            }
+        }
+
+        match object {
+            Object::Integer(mcid) => self.add(page, block, *mcid, lang),
+            Object::Array(kids) => {
+                for kid in kids {
+                    self.visit(kid, page, block, lang);
+                }
+            }
+            Object::Dictionary(dict) => self.visit_dict(dict, page, block, lang),
+            _ => {}
+        }
+    }
+
+    fn visit_dict(
+        &mut self,
+        dict: &'a Dictionary,
+        page: Option<ObjectId>,
+        block: CurrentBlock,
+        lang: Option<&str>,
+    ) {
+        let page = dict.get(b"Pg").and_then(Object::as_reference).ok().or(page);
+        let own_lang = dict.get(b"Lang").ok().and_then(text_string);
+        let lang = own_lang.as_deref().or(lang);
+
+        match dict.get(b"Type").and_then(Object::as_name) {
+            Ok(b"MCR") => {
+                if let Ok(mcid) = dict.get(b"MCID").and_then(Object::as_i64) {
+                    self.add(page, block, mcid, lang);
+                }
+                return;
+            }
+            // Annotations and other objects carry no page content
+            Ok(b"OBJR") => return,
+            _ => {}
+        }
+
+        let role = self.role(dict);
+        if role == "Artifact" {
+            return;
+        }
+
+        let in_list_item = block.is_some_and(|(_, list_item)| list_item);
+        let block = if block.is_none() || (BLOCK_ROLES.contains(&role.as_str()) && !in_list_item) {
+            self.next_block += 1;
+            Some((self.next_block, role == "LI" || role == "TOCI"))
+        } else {
+            block
+        };
+
+        if let Ok(kids) = dict.get(b"K") {
+            self.visit(kids, page, block, lang);
+        }
+    }
+
+    /// The standard structure type of an element, following the role map for custom types.
+    fn role(&self, dict: &Dictionary) -> String {
+        let mut role = dict
+            .get(b"S")
+            .and_then(Object::as_name)
+            .unwrap_or_default()
+            .to_vec();
+
+        for _ in 0..8 {
+            match self
+                .role_map
+                .and_then(|map| map.get(&role).and_then(Object::as_name).ok())
+            {
+                Some(mapped) if mapped != role.as_slice() => role = mapped.to_vec(),
+                _ => break,
+            }
+        }
+
+        String::from_utf8_lossy(&role).into_owned()
+    }
+
+    fn add(&mut self, page: Option<ObjectId>, block: CurrentBlock, mcid: i64, lang: Option<&str>) {
+        let (Some(page), Some((id, _))) = (page, block) else {
+            return;
+        };
+        if let Some(lang) = lang {
+            self.tree.languages.insert((page, mcid), lang.to_string());
+        }
+        let blocks = self.tree.pages.entry(page).or_default();
+        match blocks.last_mut() {
+            Some(last) if last.id == id => last.mcids.push(mcid),
+            _ => blocks.push(Block {
+                id,
+                mcids: vec![mcid],
+            }),
+        }
+    }
+}
+
+/// Rewrites a page's content stream so marked-content changes reach the glyph collector.
+///
+/// Languages set on marked content are announced by their index in `languages`, which collects
+/// them across the document.
+pub fn tag_marked_content(
+    doc: &mut Document,
+    page_id: ObjectId,
+    languages: &mut Vec<String>,
+) -> Result<()> {
+    let content = Content::decode(&doc.get_page_content(page_id)?)?;
+    let mut operations = Vec::with_capacity(content.operations.len());
+    let mut stack: Vec<(ContentTag, Option<usize>)> = Vec::new();
+
+    for operation in content.operations {
+        let is_begin = matches!(operation.operator.as_str(), "BMC" | "BDC");
+        let is_end = operation.operator == "EMC";
+
+        if is_begin {
+            let (parent_tag, parent_lang) = stack.last().copied().unwrap_or_default();
+            let lang = properties(&operation)
+                .and_then(|props| props.get(b"Lang").ok())
+                .and_then(text_string)
+                .map(|lang| intern(languages, lang))
+                .or(parent_lang);
+            stack.push((begin_tag(&operation, parent_tag), lang));
+        } else if is_end {
+            stack.pop();
+        }
+        operations.push(operation);
+
+        if is_begin || is_end {
+            let (tag, lang) = stack.last().copied().unwrap_or_default();
+            let code = match tag {
+                ContentTag::Untagged => UNTAGGED_CODE,
+                ContentTag::Mcid(mcid) => mcid,
+                ContentTag::Artifact => ARTIFACT_CODE,
+            };
+            let lang_code = lang.map_or(UNTAGGED_CODE, |index| index as i64);
+            push_marker(&mut operations, MARKER_X, code);
+            push_marker(&mut operations, LANGUAGE_MARKER_X, lang_code);
+        }
+    }
+
+    doc.change_page_content(page_id, Content { operations }.encode()?)?;
+    Ok(())
+}
+
+fn push_marker(operations: &mut Vec<Operation>, x: f64, code: i64) {
+    operations.push(Operation::new(
+        "m",
+        vec![Object::Real(x as f32), Object::Integer(code)],
+    ));
+    operations.push(Operation::new("f", Vec::new()));
+}
+
+fn intern(languages: &mut Vec<String>, lang: String) -> usize {
+    match languages.iter().position(|known| *known == lang) {
+        Some(index) => index,
+        None => {
+            languages.push(lang);
+            languages.len() - 1
+        }
+    }
+}
+
+/// The inline property list of a `BDC` operation.
+fn properties(operation: &Operation) -> Option<&Dictionary> {
+    operation.operands.get(1).and_then(|o| o.as_dict().ok())
+}
+
+fn begin_tag(operation: &Operation, parent: ContentTag) -> ContentTag {
+    // Artifacts stay artifacts, whatever is nested inside them
+    if parent == ContentTag::Artifact {
+        return parent;
+    }
+    if operation.operands.first().and_then(|o| o.as_name().ok()) == Some(b"Artifact") {
+        return ContentTag::Artifact;
+    }
+    properties(operation)
+        .and_then(|props| props.get(b"MCID").and_then(Object::as_i64).ok())
+        .map_or(parent, ContentTag::Mcid)
+}
+
+/// What an injected marker path announces.
+pub enum Marker {
+    Tag(ContentTag),
+    /// Index of the marked-content language, if any
+    Language(Option<usize>),
+}
+
+/// Recognises the marker paths injected by [`tag_marked_content`].
+pub fn marker(path: &Path) -> Option<Marker> {
+    let [PathOp::MoveTo(x, code)] = path.ops.as_slice() else {
+        return None;
+    };
+    let code = *code as i64;
+
+    if (*x - MARKER_X).abs() < 1.0 {
+        Some(Marker::Tag(match code {
+            UNTAGGED_CODE => ContentTag::Untagged,
+            ARTIFACT_CODE => ContentTag::Artifact,
+            mcid => ContentTag::Mcid(mcid),
+        }))
+    } else if (*x - LANGUAGE_MARKER_X).abs() < 1.0 {
+        Some(Marker::Language(usize::try_from(code).ok()))
+    } else {
+        None
+    }
+}
+
+/// Decodes a PDF text string, which is either UTF-16BE with a byte order mark or
+/// PDFDocEncoding (close enough to Latin-1 for language tags).
+pub fn text_string(object: &Object) -> Option<String> {
+    let bytes = object.as_str().ok()?;
+    let text = match bytes {
+        [0xFE, 0xFF, rest @ ..] => {
+            let units: Vec<u16> = rest
+                .chunks_exact(2)
+                .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+                .collect();
+            String::from_utf16_lossy(&units)
+        }
+        _ => bytes.iter().map(|&b| b as char).collect(),
+    };
+    let text = text.trim().to_string();
+    (!text.is_empty()).then_some(text)
+}
+
+/// The language most of the page's content is in, from marked content or the structure tree.
+/// `None` means the document's own language.
+pub fn page_language(
+    page: &PageGlyphs,
+    page_id: ObjectId,
+    tree: &StructTree,
+    languages: &[String],
+) -> Option<String> {
+    // Ordered so ties between languages resolve the same way on every run
+    let mut counts: BTreeMap<Option<&str>, usize> = BTreeMap::new();
+    for glyph in page.glyphs.iter().filter(|g| g.tag != ContentTag::Artifact) {
+        let lang = glyph
+            .lang
+            .and_then(|index| languages.get(index))
+            .or_else(|| match glyph.tag {
+                ContentTag::Mcid(mcid) => tree.languages.get(&(page_id, mcid)),
+                _ => None,
+            })
+            .map(String::as_str);
+        *counts.entry(lang).or_default() += 1;
+    }
+
+    counts
+        .into_iter()
+        .max_by_key(|(_, count)| *count)
+        .and_then(|(lang, _)| lang.map(str::to_string))
+}
+
+/// Share of a page's non-artifact glyphs the structure tree must account for before its
+/// reading order is trusted over the content stream's.
+const MIN_COVERAGE: f64 = 0.5;
+
+/// Assembles a page in structure-tree order, one paragraph per block, leaving out artifacts.
+/// Returns `None` when the tree covers too little of the page to be trusted.
+pub fn assemble(page: &PageGlyphs, blocks: &[Block], options: &AssembleOptions) -> Option<String> {
+    let content: Vec<&Glyph> = page
+        .glyphs
+        .iter()
+        .filter(|g| g.tag != ContentTag::Artifact)
+        .collect();
+
+    let mut by_mcid: HashMap<i64, Vec<&Glyph>> = HashMap::new();
+    for glyph in &content {
+        if let ContentTag::Mcid(mcid) = glyph.tag {
+            by_mcid.entry(mcid).or_default().push(glyph);
+        }
+    }
+
+    let mut covered = HashSet::new();
+    let mut paragraphs = Vec::new();
+    for block in blocks {
+        let glyphs: Vec<&Glyph> = block
+            .mcids
+            .iter()
+            .filter(|mcid| covered.insert(**mcid))
+            .filter_map(|mcid| by_mcid.get(mcid))
+            .flatten()
+            .copied()
+            .collect();
+        paragraphs.push(assemble_glyphs(page, &glyphs, options));
+    }
+
+    // Content the tree does not mention still belongs on the page, after the tagged blocks
+    let rest: Vec<&Glyph> = content
+        .iter()
+        .filter(|g| match g.tag {
+            ContentTag::Mcid(mcid) => !covered.contains(&mcid),
+            _ => true,
+        })
+        .copied()
+        .collect();
+    let tagged = content.len() - rest.len();
+    if (tagged as f64) < content.len() as f64 * MIN_COVERAGE {
+        return None;
+    }
+    paragraphs.push(assemble_glyphs(page, &rest, options));
+
+    let paragraphs: Vec<String> = paragraphs.into_iter().filter(|p| !p.is_empty()).collect();
+    Some(format!("\n{}\n", paragraphs.join("\n\n")))
+}
+
+/// Assembles a subset of the page's glyphs, trimmed.
+pub(crate) fn assemble_glyphs(
+    page: &PageGlyphs,
+    glyphs: &[&Glyph],
+    options: &AssembleOptions,
+) -> String {
+    let subset = PageGlyphs {
+        width: page.width,
+        height: page.height,
+        glyphs: glyphs.iter().map(|g| (*g).clone()).collect(),
+    };
+    layout::assemble(&subset, options).trim().to_string()
+}
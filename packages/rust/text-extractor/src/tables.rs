@@ -0,0 +1,311 @@
+//! Tables rendered inline as GitHub-flavoured Markdown for Markdown output, and as
+//! tab-separated rows for the other formats.
+//!
+//! EPUB `<table>` elements are converted as the document tree is read. In PDFs a table is a
+//! run of short lines that wide gaps split into columns.
+
+use scraper::ElementRef;
+
+use crate::dom::collapsed_text;
+use crate::layout::{Glyph, Line, WIDE_GAP};
+
+/// Fewest lines in a PDF table; a lone line with wide gaps is more often a heading with its
+/// page number, or an equation with its number.
+const MIN_ROWS: usize = 3;
+/// Longest average cell, in characters. Lines of a two-column page also have a wide gap in
+/// the middle, but their "cells" are runs of prose.
+const MAX_MEAN_CELL_CHARS: usize = 25;
+
+/// Whether tables are looked for in PDF pages.
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TableMode {
+    /// Lay out runs of lines that split into columns as tables
+    #[default]
+    Detect,
+    /// Leave table lines as extracted
+    Ignore,
+}
+
+/// How a detected table is written out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TableLayout {
+    /// A GitHub-flavoured Markdown table
+    Markdown,
+    /// One row per line, its cells separated by tabs
+    Tsv,
+}
+
+/// Whether a PDF line splits into two or more short columns.
+pub fn is_table_line(line: &Line, glyphs: &[Glyph]) -> bool {
+    let cells = pdf_cells(line, glyphs);
+    let chars: usize = cells.iter().map(|cell| cell.chars().count()).sum();
+    cells.len() >= 2 && chars <= cells.len() * MAX_MEAN_CELL_CHARS
+}
+
+/// Whether a run of table lines is long enough to be a table.
+pub fn is_table(rows: usize) -> bool {
+    rows >= MIN_ROWS
+}
+
+/// Renders a run of PDF table lines, taking the first as the header row.
+pub fn render_pdf(block: &[Line], glyphs: &[Glyph], layout: TableLayout) -> String {
+    let rows: Vec<Vec<String>> = block.iter().map(|line| pdf_cells(line, glyphs)).collect();
+    match layout {
+        TableLayout::Markdown => markdown(&rows),
+        TableLayout::Tsv => tsv(&rows),
+    }
+}
+
+/// The line's text split at gaps wide enough to separate columns.
+fn pdf_cells(line: &Line, glyphs: &[Glyph]) -> Vec<String> {
+    let mut cells = Vec::new();
+    let mut cell = String::new();
+    let mut last_end: Option<f64> = None;
+
+    for glyph in &glyphs[line.glyphs.clone()] {
+        if let Some(end) = last_end {
+            if glyph.x > end + glyph.size * WIDE_GAP {
+                cells.push(std::mem::take(&mut cell));
+            } else if glyph.word_start && glyph.x > end + glyph.size * 0.1 {
+                cell.push(' ');
+            }
+        }
+        cell.push_str(&glyph.text);
+        last_end = Some(glyph.x + glyph.advance);
+    }
+    cells.push(cell);
+
+    cells
+        .into_iter()
+        .map(|cell| cell.trim().to_string())
+        .filter(|cell| !cell.is_empty())
+        .collect()
+}
+
+/// A `<table>` as its caption followed by its rows as Markdown, one row per line. `None`
+/// for tables without cells.
+pub fn html_table(table: ElementRef) -> Option<String> {
+    let mut caption = String::new();
+    let mut rows: Vec<Vec<String>> = Vec::new();
+    // Rows of tables nested in a cell belong to that cell
+    let own = |element: &ElementRef| {
+        element
+            .ancestors()
+            .filter_map(ElementRef::wrap)
+            .find(|ancestor| ancestor.value().name() == "table")
+            .is_some_and(|ancestor| ancestor == table)
+    };
+
+    for element in table.descendants().filter_map(ElementRef::wrap) {
+        match element.value().name() {
+            "caption" if own(&element) => caption = collapsed_text(element),
+            "tr" if own(&element) => {
+                let cells: Vec<String> = element
+                    .children()
+                    .filter_map(ElementRef::wrap)
+                    .filter(|cell| matches!(cell.value().name(), "td" | "th"))
+                    .map(collapsed_text)
+                    .collect();
+                if !cells.is_empty() {
+                    rows.push(cells);
+                }
+            }
+            _ => {}
+        }
+    }
+    if rows.is_empty() {
+        return None;
+    }
+
+    let mut text = caption;
+    if !text.is_empty() {
+        text.push('\n');
+    }
+    text.push_str(&markdown(&rows));
+    Some(text)
+}
+
+/// A GitHub-flavoured Markdown table, the first row being the header, without a trailing
+/// newline. Short rows are padded with empty cells.
+pub fn markdown(rows: &[Vec<String>]) -> String {
+    let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+    let line = |cells: Vec<String>| format!("| {} |", cells.join(" | "));
+    let row = |row: &Vec<String>| {
+        line(
+            (0..columns)
+                .map(|i| {
+                    row.get(i)
+                        .map_or(String::new(), |cell| cell.replace('|', "\\|"))
+                })
+                .collect(),
+        )
+    };
+
+    let mut lines = Vec::with_capacity(rows.len() + 1);
+    if let Some((header, body)) = rows.split_first() {
+        lines.push(row(header));
+        lines.push(line(vec!["---".to_string(); columns]));
+        lines.extend(body.iter().map(row));
+    }
+    lines.join("\n")
+}
+
+/// Tab-separated rows without a trailing newline, every row padded with empty cells to the
+/// same number of columns.
+pub fn tsv(rows: &[Vec<String>]) -> String {
+    let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+    rows.iter()
+        .map(|row| {
+            (0..columns)
+                .map(|i| {
+                    row.get(i)
+                        .map_or(String::new(), |cell| cell.replace('\t', " "))
+                })
+                .collect::<Vec<_>>()
+                .join("\t")
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Whether a line is a row of a tab-separated table.
+pub fn is_tsv_row(line: &str) -> bool {
+    line.contains('\t')
+}
+
+/// Whether a paragraph is a whole tab-separated table.
+pub fn is_tsv_table(paragraph: &str) -> bool {
+    paragraph.lines().all(is_tsv_row)
+}
+
+/// Whether `line` is the `| --- | --- |` row under a Markdown table's header.
+pub fn is_separator_row(line: &str) -> bool {
+    let line = line.trim();
+    line.starts_with('|') && line.contains("---") && line.chars().all(|c| "|-: ".contains(c))
+}
+
+/// Whether a paragraph is a whole Markdown table.
+pub fn is_markdown_table(paragraph: &str) -> bool {
+    paragraph.starts_with('|') && paragraph.lines().nth(1).is_some_and(is_separator_row)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structure::ContentTag;
+    use scraper::{Html, Selector};
+
+    fn cells(rows: &[&[&str]]) -> Vec<Vec<String>> {
+        rows.iter()
+            .map(|row| row.iter().map(|cell| cell.to_string()).collect())
+            .collect()
+    }
+
+    /// A PDF line of words at the given x positions, in a 10-point font six units a character.
+    fn pdf_line(words: &[(f64, &str)], glyphs: &mut Vec<Glyph>) -> Line {
+        let start = glyphs.len();
+        for (x, word) in words {
+            glyphs.push(Glyph {
+                x: *x,
+                y: 0.0,
+                size: 10.0,
+                advance: 6.0 * word.chars().count() as f64,
+                word_start: true,
+                text: word.to_string(),
+                tag: ContentTag::Untagged,
+                lang: None,
+                turns: 0,
+            });
+        }
+        Line {
+            start: 0,
+            end: 0,
+            glyphs: start..glyphs.len(),
+            x0: 0.0,
+            x1: 0.0,
+            last_gap: None,
+        }
+    }
+
+    fn html_tables(html: &str) -> Vec<Option<String>> {
+        let html = Html::parse_fragment(html);
+        let selector = Selector::parse("table").unwrap();
+        html.select(&selector).map(html_table).collect()
+    }
+
+    #[test]
+    fn markdown_tables() {
+        let rows = cells(&[&["Name", "Count"], &["a|b", "1"], &["short"]]);
+        assert_eq!(
+            markdown(&rows),
+            "| Name | Count |\n| --- | --- |\n| a\\|b | 1 |\n| short |  |"
+        );
+        assert_eq!(markdown(&[]), "");
+        assert!(is_markdown_table(&markdown(&rows)));
+        assert!(!is_markdown_table("| a |\nnot a separator"));
+        assert!(is_separator_row("  |:---|---:|"));
+        assert!(!is_separator_row("| - |"));
+        assert!(!is_separator_row("--- | ---"));
+    }
+
+    #[test]
+    fn tsv_tables() {
+        let rows = cells(&[&["a\tb", "c"], &["d"]]);
+        assert_eq!(tsv(&rows), "a b\tc\nd\t");
+        assert_eq!(tsv(&[]), "");
+        assert!(is_tsv_table(&tsv(&rows)));
+        assert!(!is_tsv_table("a\tb\nprose"));
+    }
+
+    #[test]
+    fn html_tables_with_captions_and_nested_tables() {
+        let tables = html_tables(
+            "<table><caption>Totals</caption>
+               <thead><tr><th>A</th><th>B</th></tr></thead>
+               <tr><td>1</td><td><table><tr><td>x</td></tr></table></td></tr>
+               <tr></tr>
+             </table>
+             <table><caption>Empty</caption></table>",
+        );
+        assert_eq!(
+            tables[0].as_deref(),
+            Some("Totals\n| A | B |\n| --- | --- |\n| 1 | x |")
+        );
+        // The nested table, on its own
+        assert_eq!(tables[1].as_deref(), Some("| x |\n| --- |"));
+        assert_eq!(tables[2], None);
+    }
+
+    #[test]
+    fn pdf_tables_are_short_cells_split_by_wide_gaps() {
+        let mut glyphs = Vec::new();
+        let header = pdf_line(&[(0.0, "Item"), (100.0, "Price")], &mut glyphs);
+        let row = pdf_line(&[(0.0, "Tea"), (26.0, "cup"), (100.0, "2")], &mut glyphs);
+        let prose = pdf_line(
+            &[
+                (0.0, "a line of prose that runs on and on"),
+                (300.0, "beside another column of prose text"),
+            ],
+            &mut glyphs,
+        );
+        let single = pdf_line(&[(0.0, "One"), (20.0, "cell")], &mut glyphs);
+
+        assert!(is_table_line(&header, &glyphs));
+        assert!(is_table_line(&row, &glyphs));
+        assert!(!is_table_line(&prose, &glyphs));
+        assert!(!is_table_line(&single, &glyphs));
+        assert!(!is_table(2));
+        assert!(is_table(3));
+
+        let block = [header, row];
+        assert_eq!(
+            render_pdf(&block, &glyphs, TableLayout::Markdown),
+            "| Item | Price |\n| --- | --- |\n| Tea cup | 2 |"
+        );
+        assert_eq!(
+            render_pdf(&block, &glyphs, TableLayout::Tsv),
+            "Item\tPrice\nTea cup\t2"
+        );
+    }
+}
@@ -0,0 +1,73 @@
+//! Letter-spaced (tracked) PDF text.
+//!
+//! Titles set with wide tracking are often drawn one letter at a time, so the assembler puts
+//! a space after every letter: "I N T R O D U C T I O N". Runs of single letters spaced
+//! evenly on one line are joined back into words, and only gaps clearly wider than the
+//! run's letter spacing are kept as spaces between words.
+
+use crate::layout::Glyph;
+
+/// Fewest letters in a run before it is taken for tracked text rather than separate
+/// one-letter words ("a I", "x y z").
+const MIN_LETTERS: usize = 4;
+/// Widest gap between tracked letters, as a fraction of the font size.
+const MAX_LETTER_GAP: f64 = 1.5;
+/// A gap this many times the run's narrowest one separates words.
+const WORD_GAP_RATIO: f64 = 1.6;
+/// ... and is at least this much wider, as a fraction of the font size.
+const MIN_WORD_GAP: f64 = 0.15;
+
+/// For each glyph, whether it belongs to the same word as the letter before it although the
+/// assembler would put a space between them.
+pub fn joined(glyphs: &[Glyph]) -> Vec<bool> {
+    let mut joined = vec![false; glyphs.len()];
+    let mut start = 0;
+
+    while start < glyphs.len() {
+        let mut end = start + 1;
+        if is_letter(&glyphs[start]) {
+            while end < glyphs.len()
+                && is_letter(&glyphs[end])
+                && letter_gap(&glyphs[end - 1], &glyphs[end]).is_some()
+            {
+                end += 1;
+            }
+        }
+        if end - start >= MIN_LETTERS {
+            join_run(&glyphs[start..end], &mut joined[start..end]);
+        }
+        start = end;
+    }
+
+    joined
+}
+
+/// Marks every gap of a run of spaced letters except those wide enough to part words.
+fn join_run(run: &[Glyph], joined: &mut [bool]) {
+    let gaps: Vec<f64> = run
+        .windows(2)
+        .filter_map(|pair| letter_gap(&pair[0], &pair[1]))
+        .collect();
+    let narrowest = gaps.iter().copied().fold(f64::INFINITY, f64::min);
+
+    for (i, &gap) in gaps.iter().enumerate() {
+        let size = run[i + 1].size;
+        let word_break = gap > narrowest * WORD_GAP_RATIO && gap - narrowest > size * MIN_WORD_GAP;
+        joined[i + 1] = !word_break;
+    }
+}
+
+/// The gap between two letters on the same line that the assembler would turn into a
+/// space, if it is narrow enough to be tracking.
+fn letter_gap(previous: &Glyph, glyph: &Glyph) -> Option<f64> {
+    let gap = glyph.x - (previous.x + previous.advance);
+    let same_line = (glyph.y - previous.y).abs() < glyph.size * 0.5
+        && (glyph.size - previous.size).abs() < glyph.size * 0.1;
+    (glyph.word_start && same_line && gap > glyph.size * 0.1 && gap < glyph.size * MAX_LETTER_GAP)
+        .then_some(gap)
+}
+
+fn is_letter(glyph: &Glyph) -> bool {
+    let mut chars = glyph.text.chars();
+    chars.next().is_some_and(char::is_alphabetic) && chars.next().is_none()
+}
@@ -0,0 +1,112 @@
+//! Pre-flight checks of a library, for `validate`: each file is opened and extracted, with
+//! nothing written, and classified, so files that would fail or come out empty are found
+//! before a long run rather than at its end.
+
+use serde::Serialize;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+
+use crate::epub_archive::{self, ChapterReader};
+use crate::extract::{self, ExtractOptions};
+use crate::pdf::LockedPdf;
+use crate::source::SourceFile;
+
+/// What became of a file opened for validation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Status {
+    Ok,
+    /// A PDF no password given opens, or an EPUB under DRM
+    Encrypted,
+    /// Unreadable, truncated or not the type its extension names
+    Corrupt,
+    /// Opens but has (almost) no text, usually a scan without a text layer
+    ImageOnly,
+    /// No extractor reads its type
+    Unsupported,
+}
+
+impl Status {
+    pub const ALL: [Status; 5] = [
+        Status::Ok,
+        Status::Encrypted,
+        Status::Corrupt,
+        Status::ImageOnly,
+        Status::Unsupported,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Status::Ok => "ok",
+            Status::Encrypted => "encrypted",
+            Status::Corrupt => "corrupt",
+            Status::ImageOnly => "image-only",
+            Status::Unsupported => "unsupported",
+        }
+    }
+}
+
+/// A file and what validating it found.
+#[derive(Debug, Clone, Serialize)]
+pub struct Check {
+    pub path: PathBuf,
+    pub status: Status,
+    /// Why the file is not ok
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+impl Check {
+    fn new(path: &Path, status: Status, detail: Option<String>) -> Check {
+        Check {
+            path: path.to_path_buf(),
+            status,
+            detail,
+        }
+    }
+
+    /// A file of a type no extractor reads.
+    pub fn unsupported(path: &Path) -> Check {
+        Check::new(path, Status::Unsupported, None)
+    }
+}
+
+/// Extracts `file` with `options`, keeping nothing, and classifies it. Files with fewer than
+/// `min_chars` letters and digits of text are image-only.
+pub fn check(file: &SourceFile, options: &ExtractOptions, min_chars: usize) -> Check {
+    let path = file.path.as_path();
+    if file.extension == "epub" {
+        match ChapterReader::open(path) {
+            Ok(mut reader) => {
+                if epub_archive::is_encrypted(&mut reader) {
+                    let detail = "protected by DRM".to_string();
+                    return Check::new(path, Status::Encrypted, Some(detail));
+                }
+            }
+            Err(e) => return Check::new(path, Status::Corrupt, Some(format!("{e:#}"))),
+        }
+    }
+
+    // A file that trips up a parser is as broken as one it rejects
+    let extracted =
+        panic::catch_unwind(AssertUnwindSafe(|| extract::extract_source(file, options)))
+            .unwrap_or_else(|_| Err(anyhow::anyhow!("extraction panicked")));
+    match extracted {
+        Ok(extracted) => {
+            let chars = extracted.document.text_chars();
+            if chars < min_chars {
+                let detail = format!("{chars} characters of text");
+                Check::new(path, Status::ImageOnly, Some(detail))
+            } else {
+                Check::new(path, Status::Ok, None)
+            }
+        }
+        Err(e) => match e
+            .chain()
+            .find_map(|cause| cause.downcast_ref::<LockedPdf>())
+        {
+            Some(locked) => Check::new(path, Status::Encrypted, Some(locked.to_string())),
+            None => Check::new(path, Status::Corrupt, Some(format!("{e:#}"))),
+        },
+    }
+}
@@ -0,0 +1,70 @@
+//! Watching a folder for documents that arrive or change, such as a downloads folder, so they
+//! are extracted as they appear instead of on the next run.
+//!
+//! A file is handed on once it has gone `settle` without further changes, so documents still
+//! being downloaded or copied are not read half-written.
+
+use anyhow::{Context, Result};
+use notify::{EventKind, RecursiveMode, Watcher};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+/// Longest wait for news before settled files are looked for again.
+const POLL: Duration = Duration::from_millis(500);
+
+/// Watches `folder` and everything under it until the watch fails, calling `ready` with the
+/// files that were created or changed and have since settled, in name order. Only files that
+/// `wanted` accepts are followed. `ready` is called after every event and at least twice a
+/// second, with no files when none has settled, so callers can look at work of their own.
+pub fn watch<W, R>(folder: &Path, settle: Duration, wanted: W, mut ready: R) -> Result<()>
+where
+    W: Fn(&Path) -> bool,
+    R: FnMut(Vec<PathBuf>),
+{
+    let (sender, events) = mpsc::channel();
+    let mut watcher =
+        notify::recommended_watcher(sender).context("Failed to start watching for files")?;
+    watcher
+        .watch(folder, RecursiveMode::Recursive)
+        .context(format!("Failed to watch: {}", folder.display()))?;
+
+    // When each file last changed
+    let mut changed: HashMap<PathBuf, Instant> = HashMap::new();
+    loop {
+        match events.recv_timeout(POLL) {
+            Ok(Ok(event)) => match event.kind {
+                EventKind::Create(_) | EventKind::Modify(_) => {
+                    for path in event.paths.into_iter().filter(|path| wanted(path)) {
+                        changed.insert(path, Instant::now());
+                    }
+                }
+                EventKind::Remove(_) => {
+                    for path in &event.paths {
+                        changed.remove(path);
+                    }
+                }
+                _ => {}
+            },
+            Ok(Err(e)) => eprintln!("Error watching for files: {e}"),
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                anyhow::bail!("Stopped watching: {}", folder.display())
+            }
+        }
+
+        let mut settled: Vec<PathBuf> = changed
+            .iter()
+            .filter(|(_, at)| at.elapsed() >= settle)
+            .map(|(path, _)| path.clone())
+            .collect();
+        for path in &settled {
+            changed.remove(path);
+        }
+        // Renamed away or deleted before it settled
+        settled.retain(|path| path.is_file());
+        settled.sort();
+        ready(settled);
+    }
+}
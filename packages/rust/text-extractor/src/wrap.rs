@@ -0,0 +1,92 @@
+//! Reflowing paragraphs to a fixed display width.
+
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+/// How lines are laid out in text output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Wrap {
+    /// Keep the line breaks produced by the extractor
+    #[default]
+    Preserve,
+    /// Reflow paragraphs so no line exceeds this many terminal columns
+    Width(usize),
+    /// Put each paragraph on a single line
+    Paragraph,
+}
+
+/// A piece of text that must not be split, and whether a space separates it from the previous one.
+struct Token<'a> {
+    text: &'a str,
+    spaced: bool,
+}
+
+/// Wraps a single paragraph to `width` columns, counting wide (e.g. CJK) characters as two.
+///
+/// Breaks happen at spaces and between wide characters, which is where CJK text may break.
+/// Words longer than the width are kept whole on their own line.
+pub fn wrap_paragraph(paragraph: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    let mut line_width = 0;
+
+    for token in tokenize(paragraph) {
+        let token_width = token.text.width();
+        let gap = usize::from(token.spaced);
+
+        if !line.is_empty() && line_width + gap + token_width > width {
+            lines.push(std::mem::take(&mut line));
+            line_width = 0;
+        }
+
+        if !line.is_empty() && token.spaced {
+            line.push(' ');
+            line_width += 1;
+        }
+        line.push_str(token.text);
+        line_width += token_width;
+    }
+
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+fn tokenize(paragraph: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+
+    for word in paragraph.split_whitespace() {
+        let mut spaced = true;
+        let mut start = 0;
+
+        for (i, ch) in word.char_indices() {
+            if ch.width().unwrap_or(0) < 2 {
+                continue;
+            }
+            // Narrow text before the wide character stays one token
+            if start < i {
+                tokens.push(Token {
+                    text: &word[start..i],
+                    spaced,
+                });
+                spaced = false;
+            }
+            let end = i + ch.len_utf8();
+            tokens.push(Token {
+                text: &word[i..end],
+                spaced,
+            });
+            spaced = false;
+            start = end;
+        }
+
+        if start < word.len() {
+            tokens.push(Token {
+                text: &word[start..],
+                spaced,
+            });
+        }
+    }
+
+    tokens
+}
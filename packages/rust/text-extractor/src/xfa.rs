@@ -0,0 +1,289 @@
+//! XFA form content.
+//!
+//! Dynamic XFA forms keep their content in XML packets under the AcroForm dictionary and draw
+//! nothing but a "please wait" notice on the PDF pages. The form's template is walked in order,
+//! pairing each field's caption with its value from the datasets packet.
+
+use pdf_extract::{Document, Object};
+use roxmltree::Node;
+use std::collections::HashMap;
+
+/// XFA packets of the document, if it has any.
+pub struct XfaForm {
+    xml: String,
+    /// Set by the catalog's `/NeedsRendering`: the pages hold no real content
+    pub needs_rendering: bool,
+}
+
+impl XfaForm {
+    pub fn read(doc: &Document) -> Option<XfaForm> {
+        let catalog = doc.catalog().ok()?;
+        let acro_form = catalog
+            .get(b"AcroForm")
+            .and_then(|o| doc.dereference(o))
+            .and_then(|(_, o)| o.as_dict())
+            .ok()?;
+        let xfa = acro_form
+            .get(b"XFA")
+            .and_then(|o| doc.dereference(o))
+            .ok()?
+            .1;
+
+        // Either one stream with the whole XDP document, or name/stream pairs for its packets
+        let mut xml = String::new();
+        match xfa {
+            Object::Array(parts) => {
+                for part in parts.iter().skip(1).step_by(2) {
+                    xml.push_str(&packet(doc, part)?);
+                }
+            }
+            stream => xml.push_str(&packet(doc, stream)?),
+        }
+
+        let needs_rendering = catalog
+            .get(b"NeedsRendering")
+            .and_then(Object::as_bool)
+            .unwrap_or(false);
+        Some(XfaForm {
+            xml,
+            needs_rendering,
+        })
+    }
+
+    /// The form as text: one line per caption or field, `Label: value`, with blank lines
+    /// between subforms.
+    pub fn text(&self) -> Option<String> {
+        let xml = roxmltree::Document::parse(&self.xml).ok()?;
+        let template = xml
+            .descendants()
+            .find(|n| n.tag_name().name() == "template")?;
+        let data = xml
+            .descendants()
+            .find(|n| n.tag_name().name() == "datasets")
+            .and_then(|datasets| datasets.children().find(|n| n.tag_name().name() == "data"));
+
+        let mut writer = FormWriter::default();
+        let mut scope = Scope::new(data);
+        for child in template.children().filter(Node::is_element) {
+            writer.visit(child, &mut scope);
+        }
+
+        let text = writer.lines.join("\n").trim().to_string();
+        (!text.is_empty()).then_some(text)
+    }
+}
+
+/// A packet's XML, with its XML declaration dropped so packets can be joined into one document.
+fn packet(doc: &Document, object: &Object) -> Option<String> {
+    let stream = doc.dereference(object).ok()?.1.as_stream().ok()?;
+    let content = stream.get_plain_content().ok()?;
+    let xml = String::from_utf8_lossy(&content);
+
+    let xml = xml.trim_start_matches('\u{feff}').trim_start();
+    Some(match xml.strip_prefix("<?xml") {
+        Some(rest) => rest
+            .split_once("?>")
+            .map_or("", |(_, body)| body)
+            .to_string(),
+        None => xml.to_string(),
+    })
+}
+
+/// Data node the current container binds to, with the number of times each child name has
+/// already been bound so repeated fields take successive values.
+struct Scope<'a, 'input> {
+    node: Option<Node<'a, 'input>>,
+    used: HashMap<String, usize>,
+}
+
+impl<'a, 'input> Scope<'a, 'input> {
+    fn new(node: Option<Node<'a, 'input>>) -> Self {
+        Scope {
+            node,
+            used: HashMap::new(),
+        }
+    }
+
+    /// The next unbound data element named `name`.
+    fn bind(&mut self, name: &str) -> Option<Node<'a, 'input>> {
+        let used = self.used.entry(name.to_string()).or_default();
+        let node = self
+            .node?
+            .children()
+            .filter(|n| n.tag_name().name() == name)
+            .nth(*used);
+        *used += 1;
+        node
+    }
+
+    /// Number of data elements named `name` not bound yet.
+    fn remaining(&self, name: &str) -> usize {
+        let total = self.node.map_or(0, |node| {
+            node.children()
+                .filter(|n| n.tag_name().name() == name)
+                .count()
+        });
+        total.saturating_sub(self.used.get(name).copied().unwrap_or(0))
+    }
+}
+
+#[derive(Default)]
+struct FormWriter {
+    lines: Vec<String>,
+}
+
+impl FormWriter {
+    fn visit(&mut self, node: Node, scope: &mut Scope) {
+        match node.tag_name().name() {
+            "subform" | "subformSet" | "area" => self.visit_subform(node, scope),
+            "field" => self.visit_field(node, scope),
+            "exclGroup" => self.visit_group(node, scope),
+            "draw" => {
+                if let Some(text) = node_child(node, "value").map(text_content) {
+                    self.push(text);
+                }
+            }
+            // Page layout, scripts and prototypes hold no form content
+            _ => {}
+        }
+    }
+
+    fn visit_subform(&mut self, node: Node, scope: &mut Scope) {
+        let name = node.attribute("name");
+        // Repeating subforms appear once per data record
+        let instances = match name {
+            Some(name) if node.tag_name().name() == "subform" => scope.remaining(name).max(1),
+            _ => 1,
+        };
+
+        for _ in 0..instances {
+            self.break_paragraph();
+            match name {
+                Some(name) if node.tag_name().name() == "subform" => {
+                    let mut inner = Scope::new(scope.bind(name));
+                    self.visit_children(node, &mut inner);
+                }
+                _ => self.visit_children(node, scope),
+            }
+            self.break_paragraph();
+        }
+    }
+
+    fn visit_children(&mut self, node: Node, scope: &mut Scope) {
+        for child in node.children().filter(Node::is_element) {
+            self.visit(child, scope);
+        }
+    }
+
+    fn visit_field(&mut self, node: Node, scope: &mut Scope) {
+        // Buttons and signatures carry no value worth reading
+        let ui = node_child(node, "ui").and_then(|ui| ui.children().find(Node::is_element));
+        if ui.is_some_and(|ui| matches!(ui.tag_name().name(), "button" | "signature")) {
+            return;
+        }
+
+        let data = node.attribute("name").and_then(|name| scope.bind(name));
+        let value = data
+            .map(text_content)
+            .or_else(|| node_child(node, "value").map(text_content))
+            .map(|value| display_value(node, &value));
+        self.push_field(label(node), value);
+    }
+
+    /// A group of radio buttons, whose value is the chosen button's caption.
+    fn visit_group(&mut self, node: Node, scope: &mut Scope) {
+        let data = node.attribute("name").and_then(|name| scope.bind(name));
+        let value = data.map(text_content).map(|value| {
+            node.children()
+                .filter(|n| n.tag_name().name() == "field")
+                .find(|option| {
+                    node_child(*option, "items")
+                        .and_then(|items| items.children().find(Node::is_element))
+                        .is_some_and(|on| text_content(on) == value)
+                })
+                .and_then(caption)
+                .unwrap_or(value)
+        });
+        self.push_field(label(node), value);
+    }
+
+    fn push_field(&mut self, label: Option<String>, value: Option<String>) {
+        let value = value.filter(|v| !v.is_empty());
+        match (label, value) {
+            (Some(label), Some(value)) => self.push(format!("{}: {}", label, value)),
+            (Some(label), None) => self.push(format!("{}:", label)),
+            (None, Some(value)) => self.push(value),
+            (None, None) => {}
+        }
+    }
+
+    fn push(&mut self, text: String) {
+        let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+        if !text.is_empty() {
+            self.lines.push(text);
+        }
+    }
+
+    fn break_paragraph(&mut self) {
+        if self.lines.last().is_some_and(|line| !line.is_empty()) {
+            self.lines.push(String::new());
+        }
+    }
+}
+
+/// The field's caption, falling back to its tooltip and then its name.
+fn label(node: Node) -> Option<String> {
+    caption(node)
+        .or_else(|| {
+            node_child(node, "assist")
+                .and_then(|assist| node_child(assist, "toolTip").or(node_child(assist, "speak")))
+                .map(text_content)
+        })
+        .filter(|label| !label.is_empty())
+        .or_else(|| node.attribute("name").map(str::to_string))
+}
+
+fn caption(node: Node) -> Option<String> {
+    node_child(node, "caption")
+        .and_then(|caption| node_child(caption, "value"))
+        .map(text_content)
+        .filter(|text| !text.is_empty())
+}
+
+/// Maps a saved choice-list value to the text shown for it.
+fn display_value(field: Node, value: &str) -> String {
+    let lists: Vec<Node> = field
+        .children()
+        .filter(|n| n.tag_name().name() == "items")
+        .collect();
+    let [first, second] = lists.as_slice() else {
+        return value.to_string();
+    };
+    let (shown, saved) = if first.attribute("save") == Some("1") {
+        (second, first)
+    } else {
+        (first, second)
+    };
+
+    saved
+        .children()
+        .filter(Node::is_element)
+        .position(|item| text_content(item) == value)
+        .and_then(|i| shown.children().filter(Node::is_element).nth(i))
+        .map_or_else(|| value.to_string(), text_content)
+}
+
+fn node_child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children().find(|n| n.tag_name().name() == name)
+}
+
+/// All text below the node, rich text included, with whitespace collapsed.
+fn text_content(node: Node) -> String {
+    let text: String = node
+        .descendants()
+        .filter(Node::is_text)
+        .filter_map(|n| n.text())
+        .collect::<Vec<_>>()
+        .join(" ");
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}